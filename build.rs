@@ -0,0 +1,12 @@
+//! Compiles `proto/liath.proto` for the `grpc` feature
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/liath.proto");
+    #[cfg(feature = "grpc")]
+    {
+        // Use the vendored protoc so the build needs no system install
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        tonic_build::compile_protos("proto/liath.proto")?;
+    }
+    Ok(())
+}
@@ -26,7 +26,9 @@ impl Conversation {
     }
 
     /// Create a conversation with a specific ID
-    fn create_with_id(id: &str, agent_id: &str, db: Arc<EmbeddedLiath>) -> Result<Self> {
+    ///
+    /// An existing conversation with the same ID is reset; use [`load`](Self::load) to resume one.
+    pub fn create_with_id(id: &str, agent_id: &str, db: Arc<EmbeddedLiath>) -> Result<Self> {
         let namespace = format!("agent_{}_conv_{}", agent_id, id);
 
         // Create namespace if it doesn't exist
@@ -120,6 +120,21 @@ impl Memory {
         Ok(entries)
     }
 
+    /// Get a stored memory by ID, with a distance of 0
+    pub fn get(&self, id: MemoryId) -> Result<Option<MemoryEntry>> {
+        self.get_memory_entry(id, 0.0)
+    }
+
+    /// Every stored memory, oldest first, each with a distance of 0
+    pub fn list(&self) -> Result<Vec<MemoryEntry>> {
+        let next_id = self.next_id.load(std::sync::atomic::Ordering::SeqCst);
+        let mut entries = Vec::new();
+        for id in 1..next_id {
+            entries.extend(self.get_memory_entry(id, 0.0)?);
+        }
+        Ok(entries)
+    }
+
     /// Get a specific memory by ID
     fn get_memory_entry(&self, id: MemoryId, distance: f32) -> Result<Option<MemoryEntry>> {
         // Get content
@@ -130,11 +130,7 @@ impl Agent {
 
     /// Ensure the agents namespace exists
     fn ensure_agents_namespace(db: &EmbeddedLiath) -> Result<()> {
-        #[cfg(feature = "vector")]
-        if !db.namespace_exists(AGENTS_NAMESPACE) {
-            db.create_namespace(AGENTS_NAMESPACE, 1, usearch::MetricKind::Cos, usearch::ScalarKind::F32)?;
-        }
-        Ok(())
+        db.query_executor().ensure_system_namespace(AGENTS_NAMESPACE)
     }
 
     /// List all registered agents
@@ -250,6 +246,17 @@ impl Agent {
         }
     }
 
+    /// IDs of the agent's conversations, sorted
+    pub fn conversations(&self) -> Result<Vec<ConversationId>> {
+        let prefix = format!("agent_{}_conv_", self.id);
+        let mut ids: Vec<ConversationId> = self.db.list_namespaces()
+            .into_iter()
+            .filter_map(|ns| ns.strip_prefix(&prefix).map(String::from))
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
     /// Get tool state storage for a specific tool
     pub fn tool_state(&self, tool_name: &str) -> Result<ToolState> {
         ToolState::new(&self.id, tool_name, self.db.clone())
@@ -1,6 +1,8 @@
 use fastembed::{TextEmbedding, InitOptions, EmbeddingModel};
 use anyhow::{Result, anyhow};
 use std::sync::Arc;
+use std::time::Instant;
+use crate::metrics;
 
 /// A wrapper around fastembed TextEmbedding for generating text embeddings
 pub struct EmbeddingWrapper {
@@ -37,17 +39,26 @@ impl EmbeddingWrapper {
 
     /// Generate embeddings for a list of texts
     pub fn generate(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
-        self.model.embed(texts, None)
+        self.embed(texts)
             .map_err(|e| anyhow!("Failed to generate embeddings: {}", e))
     }
 
     /// Generate embeddings for a single text
     pub fn generate_one(&self, text: &str) -> Result<Vec<f32>> {
-        let embeddings = self.model.embed(vec![text], None)
+        let embeddings = self.embed(vec![text])
             .map_err(|e| anyhow!("Failed to generate embedding: {}", e))?;
         Ok(embeddings.into_iter().next().unwrap_or_default())
     }
 
+    /// Embed one batch, recording its size and duration
+    fn embed(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        metrics::observe(metrics::EMBEDDING_BATCH_SIZE, metrics::BATCH_BUCKETS, &[], texts.len() as f64);
+        let started = Instant::now();
+        let embeddings = self.model.embed(texts, None);
+        metrics::observe_duration(metrics::EMBEDDING_DURATION, &[], started.elapsed());
+        embeddings
+    }
+
     /// Get a reference to the underlying model
     pub fn model(&self) -> &TextEmbedding {
         &self.model
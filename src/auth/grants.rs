@@ -0,0 +1,139 @@
+//! Permission grants scoped to namespaces
+//!
+//! A grant is written as a permission string with two optional parts:
+//!
+//! - `select` allows `select` everywhere
+//! - `select@docs_*` allows `select` only in namespaces matching the glob `docs_*`
+//! - `*@agent_7_*` allows every permission in the matching namespaces
+//! - `!delete@prod_*` denies `delete` in the matching namespaces, overriding any grant
+//!
+//! Globs support `*` (any run of characters) and `?` (one character).
+
+use std::fmt;
+
+/// One parsed permission grant or deny rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    /// Permission name, or `*` for all permissions
+    pub permission: String,
+    /// Namespace glob the grant is limited to; `None` applies everywhere
+    pub namespace: Option<String>,
+    /// Whether this is a deny rule
+    pub deny: bool,
+}
+
+impl Grant {
+    /// Parse a grant from its string form
+    pub fn parse(spec: &str) -> Self {
+        let (deny, rest) = match spec.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (permission, namespace) = match rest.rsplit_once('@') {
+            Some((permission, pattern)) if pattern != "*" => (permission, Some(pattern.to_string())),
+            Some((permission, _)) => (permission, None),
+            None => (rest, None),
+        };
+        Self { permission: permission.to_string(), namespace, deny }
+    }
+
+    /// Whether the grant covers `permission`, in `namespace` if one is given
+    ///
+    /// Without a namespace only unscoped grants match, so a grant over `docs_*`
+    /// never authorizes an operation that is not tied to a namespace.
+    pub fn matches(&self, permission: &str, namespace: Option<&str>) -> bool {
+        if self.permission != "*" && self.permission != permission {
+            return false;
+        }
+        match (&self.namespace, namespace) {
+            (None, _) => true,
+            (Some(pattern), Some(namespace)) => glob_match(pattern, namespace),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Whether the grant covers `permission` in at least one namespace
+    pub fn matches_anywhere(&self, permission: &str) -> bool {
+        self.permission == "*" || self.permission == permission
+    }
+}
+
+impl fmt::Display for Grant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.deny {
+            write!(f, "!")?;
+        }
+        write!(f, "{}", self.permission)?;
+        if let Some(ref namespace) = self.namespace {
+            write!(f, "@{}", namespace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Match `text` against a glob of `*` and `?` wildcards
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it is currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("docs_*", "docs_api"));
+        assert!(glob_match("docs_*", "docs_"));
+        assert!(!glob_match("docs_*", "doc"));
+        assert!(glob_match("agent_?_*", "agent_7_memory"));
+        assert!(!glob_match("agent_?_*", "agent_17_memory"));
+        assert!(glob_match("*_memory", "agent_7_memory"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbY"));
+        assert!(glob_match("exact", "exact"));
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let grant = Grant::parse("!delete@prod_*");
+        assert!(grant.deny);
+        assert_eq!(grant.to_string(), "!delete@prod_*");
+        assert!(grant.matches("delete", Some("prod_users")));
+        assert!(!grant.matches("delete", Some("dev_users")));
+        assert!(!grant.matches("delete", None));
+
+        let grant = Grant::parse("*@agent_7_*");
+        assert!(grant.matches("insert", Some("agent_7_memory")));
+        assert!(!grant.matches("insert", None));
+        assert!(grant.matches_anywhere("insert"));
+
+        assert_eq!(Grant::parse("select@*"), Grant::parse("select"));
+        assert!(Grant::parse("call_procedure:greet").matches("call_procedure:greet", None));
+    }
+}
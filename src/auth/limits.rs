@@ -0,0 +1,406 @@
+//! Per-user rate limits and daily quotas
+//!
+//! Every user may have a token bucket on requests and daily quotas on requests,
+//! embedding tokens and stored bytes. Limits a user has not been given come from
+//! the defaults. Buckets are kept in memory and start full whenever the process
+//! starts. Daily usage is also written to the `_quotas` system namespace, so a
+//! restart does not hand out a fresh quota; quotas reset at midnight UTC.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+
+/// System namespace holding each user's usage for the day, keyed by user ID
+pub const QUOTAS_NAMESPACE: &str = "_quotas";
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// What a daily quota counts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Requests,
+    /// Estimated tokens of the texts sent to the embedding model
+    EmbeddingTokens,
+    /// Bytes of keys and values written
+    StorageBytes,
+}
+
+impl Resource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Requests => "requests",
+            Resource::EmbeddingTokens => "embedding_tokens",
+            Resource::StorageBytes => "storage_bytes",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Resource::Requests => 0,
+            Resource::EmbeddingTokens => 1,
+            Resource::StorageBytes => 2,
+        }
+    }
+}
+
+/// A user's rate limit and daily quotas; `None` means unlimited
+///
+/// Written as `key=value` pairs separated by commas, such as
+/// `requests_per_second=5,burst=20,daily_embedding_tokens=100000`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// Rate at which the request bucket refills
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+    /// Requests the bucket holds; defaults to one second's worth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_requests: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_embedding_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_storage_bytes: Option<u64>,
+}
+
+impl Limits {
+    /// The daily quota on `resource`, if any
+    pub fn quota(&self, resource: Resource) -> Option<u64> {
+        match resource {
+            Resource::Requests => self.daily_requests,
+            Resource::EmbeddingTokens => self.daily_embedding_tokens,
+            Resource::StorageBytes => self.daily_storage_bytes,
+        }
+    }
+
+    /// Fail unless the limits can be enforced
+    pub fn validate(&self) -> Result<()> {
+        if let Some(rate) = self.requests_per_second {
+            if !valid_rate(rate) {
+                return Err(anyhow!("requests_per_second must be positive, got {}", rate));
+            }
+        }
+        Ok(())
+    }
+
+    /// Requests the bucket holds when full
+    fn capacity(&self, rate: f64) -> f64 {
+        self.burst.map_or(rate.ceil(), f64::from).max(1.0)
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pairs = Vec::new();
+        if let Some(rate) = self.requests_per_second {
+            pairs.push(format!("requests_per_second={}", rate));
+        }
+        if let Some(burst) = self.burst {
+            pairs.push(format!("burst={}", burst));
+        }
+        for resource in [Resource::Requests, Resource::EmbeddingTokens, Resource::StorageBytes] {
+            if let Some(quota) = self.quota(resource) {
+                pairs.push(format!("daily_{}={}", resource.as_str(), quota));
+            }
+        }
+        if pairs.is_empty() {
+            write!(f, "unlimited")
+        } else {
+            write!(f, "{}", pairs.join(","))
+        }
+    }
+}
+
+impl FromStr for Limits {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut limits = Limits::default();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty() && *p != "unlimited") {
+            let (key, value) = pair.split_once('=')
+                .ok_or_else(|| anyhow!("Expected key=value, got '{}'", pair))?;
+            let invalid = |e: &dyn fmt::Display| anyhow!("Invalid value for '{}': {}", key, e);
+            match key.trim() {
+                "requests_per_second" => limits.requests_per_second = Some(value.trim().parse().map_err(|e| invalid(&e))?),
+                "burst" => limits.burst = Some(value.trim().parse().map_err(|e| invalid(&e))?),
+                "daily_requests" => limits.daily_requests = Some(value.trim().parse().map_err(|e| invalid(&e))?),
+                "daily_embedding_tokens" => limits.daily_embedding_tokens = Some(value.trim().parse().map_err(|e| invalid(&e))?),
+                "daily_storage_bytes" => limits.daily_storage_bytes = Some(value.trim().parse().map_err(|e| invalid(&e))?),
+                other => return Err(anyhow!(
+                    "Unknown limit '{}'; expected requests_per_second, burst, daily_requests, daily_embedding_tokens or daily_storage_bytes",
+                    other
+                )),
+            }
+        }
+        limits.validate()?;
+        Ok(limits)
+    }
+}
+
+fn valid_rate(rate: f64) -> bool {
+    rate > 0.0 && rate.is_finite()
+}
+
+/// A request or operation refused by a limit
+///
+/// Returned inside the `anyhow::Error` of a refused operation; downcast to tell it
+/// apart, e.g. to answer 429 with `retry_after`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitExceeded {
+    pub user_id: String,
+    /// The daily quota that ran out, or `None` for the rate limit
+    pub resource: Option<Resource>,
+    /// When the request can succeed again
+    pub retry_after: Duration,
+}
+
+impl LimitExceeded {
+    /// `retry_after` in whole seconds, rounded up as the `Retry-After` header wants
+    pub fn retry_after_secs(&self) -> u64 {
+        let secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 { secs + 1 } else { secs.max(1) }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.resource {
+            None => write!(f, "'{}' exceeded the request rate limit", self.user_id)?,
+            Some(resource) => write!(f, "'{}' used up the daily {} quota", self.user_id, resource.as_str().replace('_', " "))?,
+        }
+        write!(f, "; retry after {}s", self.retry_after_secs())
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Rough token count of a text sent to the embedding model
+///
+/// Tokenizers differ, so quotas count one token per four bytes, rounded up.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.len() as u64).div_ceil(4).max(1)
+}
+
+/// Today's usage of one user
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    /// Days since the epoch, UTC
+    pub day: u64,
+    pub requests: u64,
+    pub embedding_tokens: u64,
+    pub storage_bytes: u64,
+}
+
+#[derive(Default)]
+struct Meter {
+    /// Tokens left in the request bucket, and when it was last refilled
+    bucket: Option<(f64, SystemTime)>,
+    day: u64,
+    used: [u64; 3],
+}
+
+impl Meter {
+    /// Start a new day's counts if the day changed
+    fn roll(&mut self, day: u64) {
+        if self.day != day {
+            self.day = day;
+            self.used = [0; 3];
+        }
+    }
+}
+
+/// Buckets and daily counts of every user who made a request
+#[derive(Default)]
+pub(crate) struct Meters {
+    meters: HashMap<String, Meter>,
+}
+
+impl Meters {
+    /// Take a token from the user's bucket and count one request
+    pub(crate) fn admit(&mut self, user_id: &str, limits: &Limits, now: SystemTime) -> Result<(), LimitExceeded> {
+        let meter = self.meters.entry(user_id.to_string()).or_default();
+        let (day, until_tomorrow) = day_of(now);
+        meter.roll(day);
+
+        if let Some(quota) = limits.daily_requests {
+            if meter.used[Resource::Requests.index()] >= quota {
+                return Err(exceeded(user_id, Some(Resource::Requests), until_tomorrow));
+            }
+        }
+        // Limits are validated when set; a bad rate that slipped through is not enforced
+        if let Some(rate) = limits.requests_per_second.filter(|rate| valid_rate(*rate)) {
+            let capacity = limits.capacity(rate);
+            let (tokens, refilled) = meter.bucket.unwrap_or((capacity, now));
+            let elapsed = now.duration_since(refilled).unwrap_or_default().as_secs_f64();
+            let tokens = (tokens + elapsed * rate).min(capacity);
+            if tokens < 1.0 {
+                meter.bucket = Some((tokens, now));
+                return Err(exceeded(user_id, None, Duration::from_secs_f64((1.0 - tokens) / rate)));
+            }
+            meter.bucket = Some((tokens - 1.0, now));
+        }
+        meter.used[Resource::Requests.index()] += 1;
+        Ok(())
+    }
+
+    /// Fail if the user's quota on `resource` is already used up
+    pub(crate) fn check(&mut self, user_id: &str, limits: &Limits, resource: Resource, now: SystemTime) -> Result<(), LimitExceeded> {
+        self.charge(user_id, limits, resource, 0, now)
+    }
+
+    /// Count `amount` of `resource`, unless that would exceed the user's quota
+    ///
+    /// A charge of zero fails only once the quota is used up.
+    pub(crate) fn charge(&mut self, user_id: &str, limits: &Limits, resource: Resource, amount: u64, now: SystemTime) -> Result<(), LimitExceeded> {
+        let meter = self.meters.entry(user_id.to_string()).or_default();
+        let (day, until_tomorrow) = day_of(now);
+        meter.roll(day);
+        let used = &mut meter.used[resource.index()];
+        if let Some(quota) = limits.quota(resource) {
+            let exhausted = if amount == 0 { *used >= quota } else { used.saturating_add(amount) > quota };
+            if exhausted {
+                return Err(exceeded(user_id, Some(resource), until_tomorrow));
+            }
+        }
+        *used = used.saturating_add(amount);
+        Ok(())
+    }
+
+    /// Give back `amount` of `resource` counted today
+    pub(crate) fn refund(&mut self, user_id: &str, resource: Resource, amount: u64, now: SystemTime) {
+        let (day, _) = day_of(now);
+        if let Some(meter) = self.meters.get_mut(user_id).filter(|meter| meter.day == day) {
+            let used = &mut meter.used[resource.index()];
+            *used = used.saturating_sub(amount);
+        }
+    }
+
+    pub(crate) fn usage(&self, user_id: &str, now: SystemTime) -> Usage {
+        let (day, _) = day_of(now);
+        match self.meters.get(user_id).filter(|meter| meter.day == day) {
+            Some(meter) => Usage {
+                day,
+                requests: meter.used[Resource::Requests.index()],
+                embedding_tokens: meter.used[Resource::EmbeddingTokens.index()],
+                storage_bytes: meter.used[Resource::StorageBytes.index()],
+            },
+            None => Usage { day, ..Default::default() },
+        }
+    }
+
+    /// Pick up usage recorded before a restart; usage of an earlier day is dropped
+    pub(crate) fn restore(&mut self, user_id: &str, usage: &Usage) {
+        let meter = self.meters.entry(user_id.to_string()).or_default();
+        meter.day = usage.day;
+        meter.used = [usage.requests, usage.embedding_tokens, usage.storage_bytes];
+    }
+
+    pub(crate) fn forget(&mut self, user_id: &str) {
+        self.meters.remove(user_id);
+    }
+}
+
+fn exceeded(user_id: &str, resource: Option<Resource>, retry_after: Duration) -> LimitExceeded {
+    LimitExceeded { user_id: user_id.to_string(), resource, retry_after }
+}
+
+/// The UTC day of `now` and the time left until the next one
+fn day_of(now: SystemTime) -> (u64, Duration) {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let day = since_epoch.as_secs() / SECONDS_PER_DAY;
+    (day, Duration::from_secs((day + 1) * SECONDS_PER_DAY) - since_epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: f64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn test_limits_spec() {
+        let limits: Limits = "requests_per_second=2.5, burst=10,daily_storage_bytes=1000".parse().unwrap();
+        assert_eq!(limits.requests_per_second, Some(2.5));
+        assert_eq!(limits.burst, Some(10));
+        assert_eq!(limits.quota(Resource::StorageBytes), Some(1000));
+        assert_eq!(limits.quota(Resource::Requests), None);
+        assert_eq!(limits.to_string().parse::<Limits>().unwrap(), limits);
+
+        assert_eq!("unlimited".parse::<Limits>().unwrap(), Limits::default());
+        assert_eq!(Limits::default().to_string(), "unlimited");
+        assert!("burst".parse::<Limits>().is_err());
+        assert!("daily_bytes=1".parse::<Limits>().is_err());
+        assert!("requests_per_second=0".parse::<Limits>().is_err());
+        assert!("requests_per_second=inf".parse::<Limits>().is_err());
+        assert!(Limits { requests_per_second: Some(-1.0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limits = Limits { requests_per_second: Some(2.0), burst: Some(3), ..Default::default() };
+        let mut meters = Meters::default();
+        let start = 1_000_000.0;
+        for _ in 0..3 {
+            meters.admit("bob", &limits, at(start)).unwrap();
+        }
+        let error = meters.admit("bob", &limits, at(start)).unwrap_err();
+        assert_eq!(error.resource, None);
+        assert_eq!(error.retry_after, Duration::from_millis(500));
+        assert_eq!(error.retry_after_secs(), 1);
+
+        // Half a second refills one token; other users have their own bucket
+        meters.admit("bob", &limits, at(start + 0.5)).unwrap();
+        assert!(meters.admit("bob", &limits, at(start + 0.5)).is_err());
+        meters.admit("alice", &limits, at(start + 0.5)).unwrap();
+        assert_eq!(meters.usage("bob", at(start + 0.5)).requests, 4);
+
+        // A rate that was never validated is not enforced rather than dividing by zero
+        let zero = Limits { requests_per_second: Some(0.0), ..Default::default() };
+        meters.admit("carol", &zero, at(start)).unwrap();
+        meters.admit("carol", &zero, at(start)).unwrap();
+    }
+
+    #[test]
+    fn test_daily_quotas() {
+        let limits = Limits { daily_requests: Some(1), daily_embedding_tokens: Some(10), ..Default::default() };
+        let mut meters = Meters::default();
+        let noon = 20_000.0 * SECONDS_PER_DAY as f64 + 43_200.0;
+
+        meters.admit("bob", &limits, at(noon)).unwrap();
+        let error = meters.admit("bob", &limits, at(noon)).unwrap_err();
+        assert_eq!(error.resource, Some(Resource::Requests));
+        assert_eq!(error.retry_after, Duration::from_secs(43_200));
+
+        meters.charge("bob", &limits, Resource::EmbeddingTokens, 8, at(noon)).unwrap();
+        // A charge that would cross the quota is refused whole
+        assert!(meters.charge("bob", &limits, Resource::EmbeddingTokens, 3, at(noon)).is_err());
+        meters.check("bob", &limits, Resource::EmbeddingTokens, at(noon)).unwrap();
+        meters.charge("bob", &limits, Resource::EmbeddingTokens, 2, at(noon)).unwrap();
+        assert!(meters.check("bob", &limits, Resource::EmbeddingTokens, at(noon)).is_err());
+        // Unlimited resources are still counted
+        meters.charge("bob", &limits, Resource::StorageBytes, 100, at(noon)).unwrap();
+        assert_eq!(meters.usage("bob", at(noon)).storage_bytes, 100);
+        // Refunds give back what a failed write was charged
+        meters.refund("bob", Resource::StorageBytes, 40, at(noon));
+        assert_eq!(meters.usage("bob", at(noon)).storage_bytes, 60);
+
+        // The next day starts fresh
+        let tomorrow = noon + SECONDS_PER_DAY as f64;
+        meters.admit("bob", &limits, at(tomorrow)).unwrap();
+        meters.charge("bob", &limits, Resource::EmbeddingTokens, 10, at(tomorrow)).unwrap();
+        assert_eq!(meters.usage("bob", at(tomorrow)).storage_bytes, 0);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 1);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("hello world"), 3);
+    }
+}
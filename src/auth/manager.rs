@@ -1,20 +1,89 @@
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{Result, anyhow, Context};
+use rand::RngCore;
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
 use crate::core::FjallWrapper;
+use crate::metrics;
+use super::grants::Grant;
+use super::limits::{LimitExceeded, Limits, Meters, Resource, Usage};
+
+/// Prefix of API key records in the auth store
+const API_KEY_PREFIX: &str = "apikey:";
+
+/// Prefix of role records in the auth store; keys without a prefix are user IDs
+const ROLE_PREFIX: &str = "role:";
+
+/// Prefix of the bearer tokens handed out for API keys
+const TOKEN_PREFIX: &str = "liath";
 
 /// Persisted user permissions
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct UserPermissions {
     user_id: String,
     permissions: Vec<String>,
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    limits: Option<Limits>,
+}
+
+/// A named set of grants that users can be assigned
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleInfo {
+    pub name: String,
+    /// Grants in their string form, such as `select@docs_*` or `!delete`
+    pub grants: Vec<String>,
+}
+
+/// An API key as shown to administrators; the secret is never stored
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiKeyInfo {
+    /// Public identifier, used to list and revoke the key
+    pub id: String,
+    pub user_id: String,
+    pub description: Option<String>,
+    pub created_at: u64,
+}
+
+/// A user and the permissions they hold
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserInfo {
+    pub user_id: String,
+    pub permissions: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// The user's own limits, which replace the defaults
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<Limits>,
+}
+
+/// Persisted API key: its public info and the SHA-256 hash of its secret
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoredApiKey {
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+    secret_hash: String,
 }
 
 pub struct AuthManager {
     user_permissions: HashMap<String, HashSet<String>>,
+    user_roles: HashMap<String, HashSet<String>>,
+    roles: HashMap<String, HashSet<String>>,
+    api_keys: HashMap<String, StoredApiKey>,
+    /// Read-only namespaces and the unscoped permission needed to read them
+    protected: HashMap<String, String>,
+    user_limits: HashMap<String, Limits>,
+    /// Limits of users without their own
+    default_limits: Limits,
+    /// Rate and quota usage
+    meters: Mutex<Meters>,
     store: Option<Arc<FjallWrapper>>,
+    /// Where today's quota usage is kept across restarts
+    usage_store: Option<Arc<FjallWrapper>>,
 }
 
 impl Default for AuthManager {
@@ -28,7 +97,15 @@ impl AuthManager {
     pub fn new() -> Self {
         Self {
             user_permissions: HashMap::new(),
+            user_roles: HashMap::new(),
+            roles: HashMap::new(),
+            api_keys: HashMap::new(),
+            protected: HashMap::new(),
+            user_limits: HashMap::new(),
+            default_limits: Limits::default(),
+            meters: Mutex::new(Meters::default()),
             store: None,
+            usage_store: None,
         }
     }
 
@@ -42,7 +119,15 @@ impl AuthManager {
 
         let mut manager = Self {
             user_permissions: HashMap::new(),
+            user_roles: HashMap::new(),
+            roles: HashMap::new(),
+            api_keys: HashMap::new(),
+            protected: HashMap::new(),
+            user_limits: HashMap::new(),
+            default_limits: Limits::default(),
+            meters: Mutex::new(Meters::default()),
             store: Some(Arc::new(store)),
+            usage_store: None,
         };
 
         manager.load_all()?;
@@ -57,6 +142,20 @@ impl AuthManager {
                 let user_id = String::from_utf8(key)
                     .context("Invalid user ID in auth store")?;
 
+                if let Some(key_id) = user_id.strip_prefix(API_KEY_PREFIX) {
+                    let api_key: StoredApiKey = serde_json::from_slice(&value)
+                        .context(format!("Failed to deserialize API key '{}'", key_id))?;
+                    self.api_keys.insert(key_id.to_string(), api_key);
+                    continue;
+                }
+
+                if let Some(name) = user_id.strip_prefix(ROLE_PREFIX) {
+                    let role: RoleInfo = serde_json::from_slice(&value)
+                        .context(format!("Failed to deserialize role '{}'", name))?;
+                    self.roles.insert(role.name, role.grants.into_iter().collect());
+                    continue;
+                }
+
                 let user_perms: UserPermissions = serde_json::from_slice(&value)
                     .context(format!("Failed to deserialize permissions for user '{}'", user_id))?;
 
@@ -64,6 +163,12 @@ impl AuthManager {
                     user_id.clone(),
                     user_perms.permissions.into_iter().collect(),
                 );
+                if !user_perms.roles.is_empty() {
+                    self.user_roles.insert(user_id.clone(), user_perms.roles.into_iter().collect());
+                }
+                if let Some(limits) = user_perms.limits {
+                    self.user_limits.insert(user_id.clone(), limits);
+                }
                 tracing::debug!("Loaded auth for user '{}'", user_id);
             }
         }
@@ -72,11 +177,18 @@ impl AuthManager {
 
     /// Persist user permissions to disk
     fn persist_user(&self, user_id: &str) -> Result<()> {
+        for prefix in [API_KEY_PREFIX, ROLE_PREFIX] {
+            if user_id.starts_with(prefix) {
+                return Err(anyhow!("User IDs may not start with '{}'", prefix));
+            }
+        }
         if let Some(ref store) = self.store {
             if let Some(perms) = self.user_permissions.get(user_id) {
                 let user_perms = UserPermissions {
                     user_id: user_id.to_string(),
                     permissions: perms.iter().cloned().collect(),
+                    roles: self.roles_of(user_id),
+                    limits: self.user_limits.get(user_id).cloned(),
                 };
                 let value = serde_json::to_vec(&user_perms)
                     .context("Failed to serialize user permissions")?;
@@ -103,20 +215,282 @@ impl AuthManager {
         }
     }
 
+    /// Whether a user holds a permission outside of any namespace
+    ///
+    /// Only grants without a namespace scope count; use `is_authorized_for` for
+    /// operations on a namespace.
     pub fn is_authorized(&self, user_id: &str, permission: &str) -> bool {
-        self.user_permissions
+        self.check(user_id, permission, None)
+    }
+
+    /// Whether a user holds the unscoped `*` grant, with no deny rules
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        let grants = self.grants(user_id);
+        !grants.iter().any(|g| g.deny) && grants.iter().any(|g| g.permission == "*" && g.namespace.is_none())
+    }
+
+    /// Whether a user holds a permission in a namespace
+    ///
+    /// Deny rules matching the namespace take precedence over any grant.
+    pub fn is_authorized_for(&self, user_id: &str, permission: &str, namespace: &str) -> bool {
+        if let Some(read_permission) = self.protected.get(namespace) {
+            return matches!(permission, "select" | "list_namespaces") && self.check(user_id, read_permission, None);
+        }
+        self.check(user_id, permission, Some(namespace))
+    }
+
+    /// Make a namespace read-only for every user
+    ///
+    /// Reading it then takes the unscoped `read_permission` rather than `select`,
+    /// and writes are refused whatever grants the user holds.
+    pub fn protect_namespace(&mut self, namespace: &str, read_permission: &str) {
+        self.protected.insert(namespace.to_string(), read_permission.to_string());
+    }
+
+    /// Whether a user holds a permission in at least one namespace
+    ///
+    /// Used to admit requests whose namespaces are only known later, such as a
+    /// listing filtered by `is_authorized_for`.
+    pub fn is_authorized_anywhere(&self, user_id: &str, permission: &str) -> bool {
+        let grants = self.grants(user_id);
+        let denied = grants.iter().any(|g| g.deny && g.namespace.is_none() && g.matches_anywhere(permission));
+        !denied && grants.iter().any(|g| !g.deny && g.matches_anywhere(permission))
+    }
+
+    fn check(&self, user_id: &str, permission: &str, namespace: Option<&str>) -> bool {
+        let grants = self.grants(user_id);
+        let mut allowed = false;
+        for grant in grants.iter().filter(|g| g.matches(permission, namespace)) {
+            if grant.deny {
+                return false;
+            }
+            allowed = true;
+        }
+        allowed
+    }
+
+    /// The user's own grants followed by those of their roles
+    fn grants(&self, user_id: &str) -> Vec<Grant> {
+        let Some(permissions) = self.user_permissions.get(user_id) else {
+            return Vec::new();
+        };
+        let role_grants = self.user_roles
             .get(user_id)
-            .map(|permissions| permissions.contains(permission))
-            .unwrap_or(false)
+            .into_iter()
+            .flatten()
+            .filter_map(|role| self.roles.get(role))
+            .flatten();
+        permissions.iter().chain(role_grants).map(|spec| Grant::parse(spec)).collect()
+    }
+
+    fn roles_of(&self, user_id: &str) -> Vec<String> {
+        let mut roles: Vec<String> = self.user_roles.get(user_id).into_iter().flatten().cloned().collect();
+        roles.sort();
+        roles
     }
 
     pub fn remove_user(&mut self, user_id: &str) -> Result<()> {
         self.user_permissions.remove(user_id)
             .ok_or_else(|| anyhow!("User not found"))?;
+        self.user_roles.remove(user_id);
+        self.user_limits.remove(user_id);
+        self.meters.lock().unwrap().forget(user_id);
+        if let Some(ref usage_store) = self.usage_store {
+            usage_store.delete(user_id.as_bytes())
+                .context("Failed to delete quota usage")?;
+        }
         self.delete_user_from_store(user_id)?;
+        let key_ids: Vec<String> = self.list_api_keys(Some(user_id)).into_iter().map(|k| k.id).collect();
+        for key_id in key_ids {
+            self.revoke_api_key(&key_id)?;
+        }
         Ok(())
     }
 
+    /// Create `user_id` with every permission if no users exist yet
+    ///
+    /// Returns whether the user was created. Later changes to the admin's
+    /// permissions are kept, since an existing store is never bootstrapped again.
+    pub fn bootstrap_admin(&mut self, user_id: &str) -> Result<bool> {
+        if !self.user_permissions.is_empty() {
+            return Ok(false);
+        }
+        self.user_permissions.insert(user_id.to_string(), HashSet::from(["*".to_string()]));
+        self.persist_user(user_id)?;
+        Ok(true)
+    }
+
+    /// Whether a user exists
+    pub fn has_user(&self, user_id: &str) -> bool {
+        self.user_permissions.contains_key(user_id)
+    }
+
+    /// All users with their permissions, ordered by user ID
+    pub fn list_users(&self) -> Vec<UserInfo> {
+        let mut users: Vec<UserInfo> = self.user_permissions
+            .iter()
+            .map(|(user_id, permissions)| {
+                let mut permissions: Vec<String> = permissions.iter().cloned().collect();
+                permissions.sort();
+                UserInfo {
+                    user_id: user_id.clone(),
+                    permissions,
+                    roles: self.roles_of(user_id),
+                    limits: self.user_limits.get(user_id).cloned(),
+                }
+            })
+            .collect();
+        users.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+        users
+    }
+
+    /// Create or replace a role
+    pub fn define_role(&mut self, name: &str, grants: Vec<String>) -> Result<()> {
+        if name.is_empty() {
+            return Err(anyhow!("Role name must not be empty"));
+        }
+        let role = RoleInfo { name: name.to_string(), grants };
+        if let Some(ref store) = self.store {
+            let value = serde_json::to_vec(&role)
+                .context("Failed to serialize role")?;
+            store.put(format!("{}{}", ROLE_PREFIX, name).as_bytes(), &value)
+                .context("Failed to persist role")?;
+        }
+        self.roles.insert(role.name, role.grants.into_iter().collect());
+        Ok(())
+    }
+
+    /// Delete a role and unassign it from every user
+    pub fn remove_role(&mut self, name: &str) -> Result<()> {
+        self.roles.remove(name)
+            .ok_or_else(|| anyhow!("Role not found"))?;
+        if let Some(ref store) = self.store {
+            store.delete(format!("{}{}", ROLE_PREFIX, name).as_bytes())
+                .context("Failed to delete role from store")?;
+        }
+        let holders: Vec<String> = self.user_roles
+            .iter()
+            .filter(|(_, roles)| roles.contains(name))
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        for user_id in holders {
+            self.unassign_role(&user_id, name)?;
+        }
+        Ok(())
+    }
+
+    /// All roles with their grants, ordered by name
+    pub fn list_roles(&self) -> Vec<RoleInfo> {
+        let mut roles: Vec<RoleInfo> = self.roles
+            .iter()
+            .map(|(name, grants)| {
+                let mut grants: Vec<String> = grants.iter().cloned().collect();
+                grants.sort();
+                RoleInfo { name: name.clone(), grants }
+            })
+            .collect();
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+        roles
+    }
+
+    /// Give a user the grants of a role
+    pub fn assign_role(&mut self, user_id: &str, role: &str) -> Result<()> {
+        if !self.has_user(user_id) {
+            return Err(anyhow!("User not found"));
+        }
+        if !self.roles.contains_key(role) {
+            return Err(anyhow!("Role not found"));
+        }
+        self.user_roles.entry(user_id.to_string()).or_default().insert(role.to_string());
+        self.persist_user(user_id)
+    }
+
+    /// Take a role away from a user
+    pub fn unassign_role(&mut self, user_id: &str, role: &str) -> Result<()> {
+        if !self.has_user(user_id) {
+            return Err(anyhow!("User not found"));
+        }
+        if let Some(roles) = self.user_roles.get_mut(user_id) {
+            roles.remove(role);
+        }
+        self.persist_user(user_id)
+    }
+
+    /// Issue an API key for an existing user
+    ///
+    /// Returns the key's info and the bearer token. The token is only available
+    /// here: the store keeps a hash of its secret.
+    pub fn create_api_key(&mut self, user_id: &str, description: Option<&str>) -> Result<(ApiKeyInfo, String)> {
+        if !self.has_user(user_id) {
+            return Err(anyhow!("User not found"));
+        }
+
+        let mut id_bytes = [0u8; 6];
+        let mut secret_bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut id_bytes);
+        rand::thread_rng().fill_bytes(&mut secret_bytes);
+        let id = to_hex(&id_bytes);
+        let secret = to_hex(&secret_bytes);
+
+        let api_key = StoredApiKey {
+            info: ApiKeyInfo {
+                id: id.clone(),
+                user_id: user_id.to_string(),
+                description: description.map(String::from),
+                created_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            },
+            secret_hash: hash_secret(&secret),
+        };
+        if let Some(ref store) = self.store {
+            let value = serde_json::to_vec(&api_key)
+                .context("Failed to serialize API key")?;
+            store.put(format!("{}{}", API_KEY_PREFIX, id).as_bytes(), &value)
+                .context("Failed to persist API key")?;
+        }
+
+        let info = api_key.info.clone();
+        self.api_keys.insert(id.clone(), api_key);
+        tracing::info!("Issued API key '{}' for user '{}'", id, user_id);
+        Ok((info, format!("{}_{}_{}", TOKEN_PREFIX, id, secret)))
+    }
+
+    /// Revoke an API key by its ID
+    pub fn revoke_api_key(&mut self, key_id: &str) -> Result<()> {
+        self.api_keys.remove(key_id)
+            .ok_or_else(|| anyhow!("API key not found"))?;
+        if let Some(ref store) = self.store {
+            store.delete(format!("{}{}", API_KEY_PREFIX, key_id).as_bytes())
+                .context("Failed to delete API key from store")?;
+        }
+        tracing::info!("Revoked API key '{}'", key_id);
+        Ok(())
+    }
+
+    /// API keys, optionally only those of one user, oldest first
+    pub fn list_api_keys(&self, user_id: Option<&str>) -> Vec<ApiKeyInfo> {
+        let mut keys: Vec<ApiKeyInfo> = self.api_keys
+            .values()
+            .filter(|k| user_id.map(|u| k.info.user_id == u).unwrap_or(true))
+            .map(|k| k.info.clone())
+            .collect();
+        keys.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        keys
+    }
+
+    /// Resolve a bearer token to the user it was issued for
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        let rest = token.strip_prefix(TOKEN_PREFIX)?.strip_prefix('_')?;
+        let (id, secret) = rest.split_once('_')?;
+        let api_key = self.api_keys.get(id)?;
+        if api_key.secret_hash != hash_secret(secret) || !self.has_user(&api_key.info.user_id) {
+            return None;
+        }
+        Some(api_key.info.user_id.clone())
+    }
+
     pub fn update_permissions(&mut self, user_id: &str, permissions: Vec<String>) -> Result<()> {
         self.user_permissions.get_mut(user_id)
             .ok_or_else(|| anyhow!("User not found"))?
@@ -142,6 +516,109 @@ impl AuthManager {
         Ok(())
     }
 
+    /// Give a user their own limits, or with `None` put them back on the defaults
+    pub fn set_limits(&mut self, user_id: &str, limits: Option<Limits>) -> Result<()> {
+        if !self.has_user(user_id) {
+            return Err(anyhow!("User not found"));
+        }
+        match limits {
+            Some(limits) => {
+                limits.validate()?;
+                self.user_limits.insert(user_id.to_string(), limits)
+            }
+            None => self.user_limits.remove(user_id),
+        };
+        self.persist_user(user_id)
+    }
+
+    /// Set the limits of users without their own; these are not persisted
+    pub fn set_default_limits(&mut self, limits: Limits) -> Result<()> {
+        limits.validate()?;
+        self.default_limits = limits;
+        Ok(())
+    }
+
+    /// The limits that apply to a user
+    pub fn limits(&self, user_id: &str) -> &Limits {
+        self.user_limits.get(user_id).unwrap_or(&self.default_limits)
+    }
+
+    /// Keep quota usage in `store` and pick up what it recorded for today
+    pub fn persist_usage(&mut self, store: Arc<FjallWrapper>) -> Result<()> {
+        let mut meters = self.meters.lock().unwrap();
+        for result in store.iter() {
+            let (key, value) = result?;
+            let user_id = String::from_utf8(key)
+                .context("Invalid user ID in quota usage")?;
+            let usage: Usage = serde_json::from_slice(&value)
+                .context(format!("Failed to deserialize quota usage of '{}'", user_id))?;
+            meters.restore(&user_id, &usage);
+        }
+        drop(meters);
+        self.usage_store = Some(store);
+        Ok(())
+    }
+
+    /// Count a request against the user's rate limit and daily request quota
+    pub fn admit(&self, user_id: &str) -> Result<(), LimitExceeded> {
+        let now = SystemTime::now();
+        let mut meters = self.meters.lock().unwrap();
+        meters.admit(user_id, self.limits(user_id), now).inspect_err(count_refusal)?;
+        self.save_usage(user_id, meters.usage(user_id, now));
+        Ok(())
+    }
+
+    /// Fail if the user's daily quota on `resource` is already used up
+    pub fn check_quota(&self, user_id: &str, resource: Resource) -> Result<(), LimitExceeded> {
+        self.meters.lock().unwrap().check(user_id, self.limits(user_id), resource, SystemTime::now())
+            .inspect_err(count_refusal)
+    }
+
+    /// Count `amount` of `resource` against the user's daily quota
+    ///
+    /// Nothing is counted when the charge would exceed the quota.
+    pub fn charge(&self, user_id: &str, resource: Resource, amount: u64) -> Result<(), LimitExceeded> {
+        let now = SystemTime::now();
+        let mut meters = self.meters.lock().unwrap();
+        meters.charge(user_id, self.limits(user_id), resource, amount, now).inspect_err(count_refusal)?;
+        if amount > 0 {
+            self.save_usage(user_id, meters.usage(user_id, now));
+        }
+        Ok(())
+    }
+
+    /// Give back a charge for work that did not happen, such as a write that failed
+    pub fn refund(&self, user_id: &str, resource: Resource, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut meters = self.meters.lock().unwrap();
+        meters.refund(user_id, resource, amount, now);
+        self.save_usage(user_id, meters.usage(user_id, now));
+    }
+
+    /// What the user has used of their quotas today
+    pub fn usage(&self, user_id: &str) -> Usage {
+        self.meters.lock().unwrap().usage(user_id, SystemTime::now())
+    }
+
+    /// Write a user's usage to the usage store, if there is one
+    ///
+    /// Called with the meters locked, so writes land in the order they were counted.
+    /// A failed write is logged; the request itself was already counted.
+    fn save_usage(&self, user_id: &str, usage: Usage) {
+        let Some(ref store) = self.usage_store else {
+            return;
+        };
+        let result = serde_json::to_vec(&usage)
+            .map_err(anyhow::Error::from)
+            .and_then(|value| store.put(user_id.as_bytes(), &value));
+        if let Err(e) = result {
+            tracing::warn!("Failed to persist quota usage of '{}': {:#}", user_id, e);
+        }
+    }
+
     /// Flush auth data to disk
     pub fn flush(&self) -> Result<()> {
         if let Some(ref store) = self.store {
@@ -151,6 +628,19 @@ impl AuthManager {
     }
 }
 
+fn count_refusal(refusal: &LimitExceeded) {
+    let limit = refusal.resource.map_or("rate", |resource| resource.as_str());
+    metrics::increment(metrics::RATE_LIMITED, &[("limit", limit)]);
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hash_secret(secret: &str) -> String {
+    to_hex(&Sha256::digest(secret.as_bytes()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,4 +695,150 @@ mod tests {
             assert!(!manager.is_authorized("persistent_user", "admin"));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_scoped_grants_and_roles() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+            manager.add_user("agent", vec!["*@agent_7_*".to_string(), "!delete@agent_7_audit".to_string()]);
+            manager.define_role("docs_reader", vec!["select@docs_*".to_string()]).unwrap();
+            manager.assign_role("agent", "docs_reader").unwrap();
+            assert!(manager.assign_role("agent", "missing").is_err());
+        }
+
+        let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+        assert!(manager.is_authorized_for("agent", "insert", "agent_7_memory"));
+        assert!(manager.is_authorized_for("agent", "delete", "agent_7_memory"));
+        assert!(!manager.is_authorized_for("agent", "delete", "agent_7_audit"));
+        assert!(!manager.is_authorized_for("agent", "insert", "agent_8_memory"));
+        assert!(manager.is_authorized_for("agent", "select", "docs_api"));
+        assert!(!manager.is_authorized_for("agent", "insert", "docs_api"));
+        // Scoped grants never authorize operations outside a namespace
+        assert!(!manager.is_authorized("agent", "select"));
+        assert!(manager.is_authorized_anywhere("agent", "select"));
+        assert!(!manager.is_authorized_anywhere("nobody", "select"));
+        assert_eq!(manager.list_users()[0].roles, vec!["docs_reader".to_string()]);
+
+        manager.remove_role("docs_reader").unwrap();
+        assert!(!manager.is_authorized_for("agent", "select", "docs_api"));
+        assert!(manager.list_users()[0].roles.is_empty());
+    }
+
+    #[test]
+    fn test_bootstrap_admin() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+            assert!(manager.bootstrap_admin("root").unwrap());
+            assert!(manager.is_authorized("root", "manage_users"));
+            assert!(manager.is_admin("root"));
+            manager.update_permissions("root", vec!["manage_users".to_string()]).unwrap();
+        }
+
+        // An existing store keeps its users as they are
+        let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+        assert!(!manager.bootstrap_admin("root").unwrap());
+        assert!(!manager.bootstrap_admin("admin").unwrap());
+        assert!(!manager.is_authorized("root", "delete"));
+        assert!(!manager.is_admin("root"));
+        assert!(!manager.has_user("admin"));
+        assert!(!manager.is_admin("admin"));
+
+        // Scoped or denied wildcards do not make an admin
+        manager.add_user("scoped", vec!["*@docs".to_string()]);
+        assert!(!manager.is_admin("scoped"));
+        manager.add_user("fenced", vec!["*".to_string(), "!delete@audit".to_string()]);
+        assert!(!manager.is_admin("fenced"));
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let token = {
+            let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+            assert!(manager.create_api_key("agent", None).is_err());
+            manager.add_user("agent", vec!["select".to_string()]);
+            let (info, token) = manager.create_api_key("agent", Some("ci")).unwrap();
+            assert_eq!(manager.authenticate(&token).as_deref(), Some("agent"));
+            assert_eq!(manager.list_api_keys(Some("agent")), vec![info]);
+            token
+        };
+
+        // Keys survive a restart, and only the exact token authenticates
+        let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+        assert!(manager.is_authorized("agent", "select"));
+        assert_eq!(manager.authenticate(&token).as_deref(), Some("agent"));
+        assert!(manager.authenticate(&format!("{}0", token)).is_none());
+        assert!(manager.authenticate("agent").is_none());
+
+        let id = manager.list_api_keys(None)[0].id.clone();
+        manager.revoke_api_key(&id).unwrap();
+        assert!(manager.authenticate(&token).is_none());
+    }
+
+    #[test]
+    fn test_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let agent_limits: Limits = "daily_storage_bytes=10".parse().unwrap();
+        {
+            let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+            manager.add_user("agent", vec!["*".to_string()]);
+            assert!(manager.set_limits("nobody", Some(agent_limits.clone())).is_err());
+            manager.set_limits("agent", Some(agent_limits.clone())).unwrap();
+        }
+
+        // Own limits survive a restart and replace the defaults entirely
+        let mut manager = AuthManager::with_persistence(temp_dir.path()).unwrap();
+        manager.add_user("other", vec!["*".to_string()]);
+        manager.set_default_limits("daily_requests=1".parse().unwrap()).unwrap();
+        assert_eq!(manager.limits("agent"), &agent_limits);
+        assert_eq!(manager.list_users()[0].limits, Some(agent_limits));
+        manager.admit("agent").unwrap();
+        manager.admit("agent").unwrap();
+        manager.admit("other").unwrap();
+        assert_eq!(manager.admit("other").unwrap_err().resource, Some(Resource::Requests));
+
+        manager.charge("agent", Resource::StorageBytes, 6).unwrap();
+        assert!(manager.charge("agent", Resource::StorageBytes, 6).is_err());
+        assert_eq!(manager.usage("agent").storage_bytes, 6);
+        assert_eq!(manager.usage("agent").requests, 2);
+
+        manager.set_limits("agent", None).unwrap();
+        assert!(manager.admit("agent").is_err());
+
+        let zero = Limits { requests_per_second: Some(0.0), ..Default::default() };
+        assert!(manager.set_limits("agent", Some(zero.clone())).is_err());
+        assert!(manager.set_default_limits(zero).is_err());
+        assert_eq!(manager.limits("agent"), &"daily_requests=1".parse().unwrap());
+    }
+
+    #[test]
+    fn test_quota_usage_survives_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let open = || {
+            let mut manager = AuthManager::new();
+            manager.add_user("agent", vec!["*".to_string()]);
+            manager.set_default_limits("daily_requests=2".parse().unwrap()).unwrap();
+            let store = FjallWrapper::new(temp_dir.path().join("quotas")).unwrap();
+            manager.persist_usage(Arc::new(store)).unwrap();
+            manager
+        };
+        {
+            let manager = open();
+            manager.admit("agent").unwrap();
+            manager.charge("agent", Resource::StorageBytes, 6).unwrap();
+        }
+
+        let mut manager = open();
+        assert_eq!(manager.usage("agent").requests, 1);
+        assert_eq!(manager.usage("agent").storage_bytes, 6);
+        manager.admit("agent").unwrap();
+        assert_eq!(manager.admit("agent").unwrap_err().resource, Some(Resource::Requests));
+
+        // Removed users leave no usage behind
+        manager.remove_user("agent").unwrap();
+        drop(manager);
+        assert_eq!(open().usage("agent").requests, 0);
+    }
+}
@@ -1,3 +1,7 @@
+mod grants;
+mod limits;
 mod manager;
 
-pub use manager::AuthManager;
+pub use grants::{glob_match, Grant};
+pub use limits::{estimate_tokens, LimitExceeded, Limits, Resource, Usage, QUOTAS_NAMESPACE};
+pub use manager::{ApiKeyInfo, AuthManager, RoleInfo, UserInfo};
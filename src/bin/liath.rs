@@ -4,12 +4,16 @@
 //! vector search, embeddings, and a Lua scripting interface.
 
 use clap::{Parser, Subcommand, Args};
-use liath::{EmbeddedLiath, Config};
+use liath::{AuditConfig, AuditFilter, EmbeddedLiath, EncryptionConfig, Config, KeyRing, Limits};
+use liath::cli::Executor;
+use liath::core::crypto;
+use liath::query::audit;
+use liath::scheduler::{self, JobAction, Schedule};
 use anyhow::Result;
 use std::path::PathBuf;
 
 #[cfg(feature = "server")]
-use liath::server::run_server;
+use liath::server::{run_server_with_config, ServerConfig};
 
 /// Liath - AI-First Database with vector search and Lua scripting
 #[derive(Parser)]
@@ -35,7 +39,14 @@ Examples:
   liath server              Start HTTP API server on port 3000
   liath server --port 8080  Start server on custom port
   liath mcp                 Start MCP server (for AI assistants)
+  liath mcp --http          Serve MCP over HTTP to several assistants at once
   liath execute "print('hello')"  Execute a Lua script
+  liath --remote http://localhost:3000  Open the console on a running server
+  liath procedure call rag '{"q":"hi"}'  Call a stored procedure
+  liath query "SELECT key FROM docs LIMIT 5"  Run a declarative query
+  liath key create agent    Issue an API key for the HTTP server
+  liath audit --since 1h    Show the last hour of the audit log
+  liath --key-file keys encryption rotate  Re-encrypt data under the newest key
 "#
 )]
 struct Cli {
@@ -49,6 +60,34 @@ struct Cli {
     /// User ID for authentication
     #[arg(short, long, global = true, default_value = "admin")]
     user: String,
+
+    /// Admin user created with every permission when the data directory has no users yet
+    #[arg(long, global = true, default_value = "admin")]
+    admin_user: String,
+
+    /// Delete audit entries older than this, e.g. 30d (default: keep forever)
+    #[arg(long, global = true)]
+    audit_retention: Option<String>,
+
+    /// Encryption key file: one `<id> <hex key>` line per key, the highest id is active
+    #[arg(long, global = true)]
+    key_file: Option<PathBuf>,
+
+    /// Encrypt new namespaces whose names match these patterns, e.g. agent_*
+    #[arg(long, global = true, value_delimiter = ',')]
+    encrypt_namespaces: Vec<String>,
+
+    /// Encrypt files stored with upload_file
+    #[arg(long, global = true)]
+    encrypt_files: bool,
+
+    /// Work on a running `liath server` instead of the data directory, e.g. http://localhost:3000
+    #[arg(long, global = true)]
+    remote: Option<String>,
+
+    /// API key for --remote (default: $LIATH_API_KEY)
+    #[arg(long, global = true)]
+    api_key: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -65,12 +104,38 @@ enum Commands {
     #[command(alias = "exec", alias = "run")]
     Execute(ExecuteArgs),
 
+    /// Run a declarative query (SELECT ... FROM ...) and exit
+    #[command(alias = "sql")]
+    Query(QueryArgs),
+
     /// Manage namespaces
     #[command(alias = "ns")]
     Namespace(NamespaceArgs),
 
+    /// Manage stored procedures
+    #[command(alias = "proc")]
+    Procedure(ProcedureArgs),
+
+    /// Manage scheduled jobs (run by `liath server`)
+    Job(JobArgs),
+
+    /// Manage users
+    User(UserArgs),
+
+    /// Manage roles: named sets of grants assigned to users
+    Role(RoleArgs),
+
+    /// Issue and revoke API keys for the HTTP server
+    Key(KeyArgs),
+
+    /// Show the audit log of mutations and denied operations
+    Audit(AuditArgs),
+
+    /// Manage encryption keys for data at rest
+    Encryption(EncryptionArgs),
+
     /// Start MCP server for AI assistant integration
-    Mcp,
+    Mcp(McpArgs),
 
     /// Display version and build information
     Info,
@@ -92,6 +157,34 @@ struct ServerArgs {
     /// Host to bind to
     #[arg(short = 'H', long, default_value = "127.0.0.1")]
     host: String,
+
+    /// Worker threads handling requests (default: number of CPUs)
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Requests that may wait for a worker before the server answers 503
+    #[arg(long, default_value = "64")]
+    queue_size: usize,
+
+    /// Seconds a request may take before the server answers 504
+    #[arg(long, default_value = "30")]
+    request_timeout: u64,
+
+    /// Rate limit and daily quotas of users without their own, e.g.
+    /// requests_per_second=5,burst=20,daily_embedding_tokens=100000
+    #[arg(long)]
+    limits: Option<Limits>,
+}
+
+#[derive(Args)]
+struct McpArgs {
+    /// Serve MCP over HTTP at /mcp instead of stdio; clients authenticate with API keys
+    #[arg(long)]
+    http: bool,
+
+    /// Port to listen on with --http
+    #[arg(short, long, default_value = "3000", requires = "http")]
+    port: u16,
 }
 
 #[derive(Args)]
@@ -102,6 +195,20 @@ struct ExecuteArgs {
     /// Execute from file instead of command line
     #[arg(short, long)]
     file: Option<PathBuf>,
+
+    /// Print a profile of the database functions called to stderr
+    #[arg(long)]
+    explain: bool,
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// Query to run, e.g. "SELECT name FROM users WHERE age > 30"
+    query: String,
+
+    /// Print rows as JSON objects instead of a table
+    #[arg(long)]
+    json: bool,
 }
 
 #[derive(Args)]
@@ -140,6 +247,321 @@ enum NamespaceAction {
     },
 }
 
+#[derive(Args)]
+struct ProcedureArgs {
+    #[command(subcommand)]
+    action: ProcedureAction,
+}
+
+#[derive(Subcommand)]
+enum ProcedureAction {
+    /// List stored procedures
+    List,
+
+    /// Show the code of a procedure
+    Show {
+        /// Name of the procedure
+        name: String,
+
+        /// Show a specific version instead of the current one
+        #[arg(short, long)]
+        version: Option<u32>,
+    },
+
+    /// Register a new procedure, or a new version of an existing one
+    Register {
+        /// Name of the procedure
+        name: String,
+
+        /// Lua code of the procedure body
+        code: Option<String>,
+
+        /// Read the procedure body from a file
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Human-readable description (also used for the MCP tool)
+        #[arg(long)]
+        description: Option<String>,
+    },
+
+    /// Call a procedure with JSON arguments
+    Call {
+        /// Name of the procedure
+        name: String,
+
+        /// Arguments as JSON (default: null)
+        args: Option<String>,
+    },
+
+    /// Show the version history of a procedure
+    History {
+        /// Name of the procedure
+        name: String,
+    },
+
+    /// Delete a procedure and its history
+    Delete {
+        /// Name of the procedure
+        name: String,
+
+        /// Skip confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Args)]
+struct UserArgs {
+    #[command(subcommand)]
+    action: UserCommand,
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// List users and their permissions
+    List,
+
+    /// Create a user, or replace an existing user's permissions
+    Add {
+        /// ID of the user
+        user_id: String,
+
+        /// Permissions to grant, e.g. select,insert@docs_*,!delete@prod_*
+        #[arg(short, long, value_delimiter = ',')]
+        permissions: Vec<String>,
+
+        /// Roles to assign, e.g. reader,writer
+        #[arg(short, long, value_delimiter = ',')]
+        roles: Vec<String>,
+    },
+
+    /// Grant permissions to a user
+    Grant {
+        /// ID of the user
+        user_id: String,
+
+        /// Permissions to grant, e.g. insert 'select@docs_*'
+        #[arg(required = true)]
+        permissions: Vec<String>,
+    },
+
+    /// Revoke permissions granted directly to a user
+    Revoke {
+        /// ID of the user
+        user_id: String,
+
+        /// Permissions to revoke, exactly as they were granted
+        #[arg(required = true)]
+        permissions: Vec<String>,
+    },
+
+    /// Delete a user and revoke their API keys
+    Remove {
+        /// ID of the user
+        user_id: String,
+    },
+
+    /// Show a user's own rate limit and quotas, or set them
+    Limits {
+        /// ID of the user
+        user_id: String,
+
+        /// Limits replacing the server's defaults, e.g.
+        /// requests_per_second=5,burst=20,daily_storage_bytes=1000000
+        limits: Option<Limits>,
+
+        /// Put the user back on the server's defaults
+        #[arg(long, conflicts_with = "limits")]
+        clear: bool,
+    },
+}
+
+#[derive(Args)]
+struct RoleArgs {
+    #[command(subcommand)]
+    action: RoleCommand,
+}
+
+#[derive(Subcommand)]
+enum RoleCommand {
+    /// List roles and their grants
+    List,
+
+    /// Create a role, or replace an existing role's grants
+    Define {
+        /// Name of the role
+        name: String,
+
+        /// Grants, e.g. select@docs_*,similarity_search@docs_*
+        #[arg(short, long, value_delimiter = ',')]
+        grants: Vec<String>,
+    },
+
+    /// Delete a role and unassign it from every user
+    Delete {
+        /// Name of the role
+        name: String,
+    },
+
+    /// Assign a role to a user
+    Assign {
+        /// ID of the user
+        user_id: String,
+
+        /// Name of the role
+        role: String,
+    },
+
+    /// Take a role away from a user
+    Unassign {
+        /// ID of the user
+        user_id: String,
+
+        /// Name of the role
+        role: String,
+    },
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    #[command(subcommand)]
+    action: KeyCommand,
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Issue an API key; the key is printed once and cannot be shown again
+    Create {
+        /// User the key authenticates as
+        user_id: String,
+
+        /// What the key is for
+        #[arg(long)]
+        description: Option<String>,
+    },
+
+    /// List API keys (IDs only, never the secrets)
+    List {
+        /// Only list the keys of this user
+        #[arg(long = "for")]
+        owner: Option<String>,
+    },
+
+    /// Revoke an API key by its ID
+    Revoke {
+        /// ID of the key, as shown by `liath key list`
+        id: String,
+    },
+}
+
+#[derive(Args)]
+struct EncryptionArgs {
+    #[command(subcommand)]
+    action: EncryptionCommand,
+}
+
+#[derive(Subcommand)]
+enum EncryptionCommand {
+    /// Generate a key and append it to --key-file as the new active key (or print it)
+    GenerateKey,
+
+    /// Re-encrypt all encrypted data under the active key
+    Rotate,
+
+    /// Show the configured keys and which namespaces are encrypted
+    Status,
+}
+
+#[derive(Args)]
+struct AuditArgs {
+    /// Start of the range: milliseconds since the epoch, or a duration ago like 1h
+    #[arg(long)]
+    since: Option<String>,
+
+    /// End of the range, in the same forms as --since
+    #[arg(long)]
+    until: Option<String>,
+
+    /// Only show entries for this user
+    #[arg(long = "for")]
+    user_id: Option<String>,
+
+    /// Show at most this many entries
+    #[arg(long, default_value_t = audit::DEFAULT_QUERY_LIMIT)]
+    limit: usize,
+
+    /// Print entries as JSON lines
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct JobArgs {
+    #[command(subcommand)]
+    action: JobCommand,
+}
+
+#[derive(Subcommand)]
+enum JobCommand {
+    /// List jobs with their last-run status
+    List,
+
+    /// Create or replace a job
+    Add {
+        /// Name of the job
+        name: String,
+
+        /// Run on an interval, e.g. 30s, 5m, 1h, 1d
+        #[arg(long, conflicts_with = "cron", required_unless_present = "cron")]
+        every: Option<String>,
+
+        /// Run on a cron schedule (UTC), e.g. "0 3 * * *"
+        #[arg(long)]
+        cron: Option<String>,
+
+        /// Lua code to run
+        #[arg(long, conflicts_with_all = ["file", "procedure"])]
+        lua: Option<String>,
+
+        /// Read the Lua code from a file
+        #[arg(short, long, conflicts_with = "procedure")]
+        file: Option<PathBuf>,
+
+        /// Stored procedure to call
+        #[arg(short, long)]
+        procedure: Option<String>,
+
+        /// JSON arguments for the procedure
+        #[arg(long, requires = "procedure")]
+        args: Option<String>,
+    },
+
+    /// Run a job immediately
+    Run {
+        /// Name of the job
+        name: String,
+    },
+
+    /// Resume a paused job
+    Enable {
+        /// Name of the job
+        name: String,
+    },
+
+    /// Pause a job
+    Disable {
+        /// Name of the job
+        name: String,
+    },
+
+    /// Delete a job
+    Remove {
+        /// Name of the job
+        name: String,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging (only in debug mode or when RUST_LOG is set)
@@ -176,48 +598,57 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if let Some(Commands::Encryption(EncryptionArgs { action: EncryptionCommand::GenerateKey })) = &cli.command {
+        if let Err(e) = generate_key(cli.key_file.as_deref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(url) = cli.remote.clone() {
+        return run_remote(cli, url).await;
+    }
+
     // Create config with data directory
+    let retention = cli.audit_retention.as_deref().map(scheduler::parse_duration).transpose()?;
     let config = Config {
         data_dir: cli.data_dir.clone(),
+        admin_user: cli.admin_user.clone(),
+        audit: AuditConfig { retention, ..Default::default() },
+        encryption: EncryptionConfig {
+            key_file: cli.key_file.clone(),
+            namespaces: cli.encrypt_namespaces.clone(),
+            encrypt_files: cli.encrypt_files,
+            ..Default::default()
+        },
+        limits: match &cli.command {
+            Some(Commands::Server(args)) => args.limits.clone().unwrap_or_default(),
+            _ => Limits::default(),
+        },
         ..Default::default()
     };
+    let encryption = config.encryption.clone();
 
     let liath = EmbeddedLiath::new(config)?;
     let query_executor = liath.query_executor();
 
     match cli.command {
-        // Default: start TUI console
-        None => {
-            #[cfg(feature = "tui")]
-            {
-                liath::cli::tui::run(query_executor, cli.user, cli.data_dir).await?;
-            }
-            #[cfg(not(feature = "tui"))]
-            {
-                liath::cli::console::run(query_executor).await?;
-            }
-        }
-
-        Some(Commands::Cli(args)) => {
-            if args.simple {
-                liath::cli::console::run(query_executor).await?;
-            } else {
-                #[cfg(feature = "tui")]
-                {
-                    liath::cli::tui::run(query_executor, cli.user, cli.data_dir).await?;
-                }
-                #[cfg(not(feature = "tui"))]
-                {
-                    liath::cli::console::run(query_executor).await?;
-                }
-            }
+        command @ (None | Some(Commands::Cli(_) | Commands::Execute(_) | Commands::Query(_))) => {
+            run_session(command, query_executor.into(), cli.user, cli.data_dir).await?;
         }
 
         Some(Commands::Server(args)) => {
             #[cfg(feature = "server")]
             {
                 println!("Starting Liath server on {}:{}", args.host, args.port);
-                run_server(args.port, query_executor).await?;
+                let defaults = ServerConfig::default();
+                let config = ServerConfig {
+                    workers: args.workers.unwrap_or(defaults.workers),
+                    queue_size: args.queue_size,
+                    request_timeout: std::time::Duration::from_secs(args.request_timeout),
+                };
+                run_server_with_config(args.port, std::sync::Arc::new(liath), config).await?;
             }
             #[cfg(not(feature = "server"))]
             {
@@ -228,26 +659,6 @@ async fn main() -> Result<()> {
             }
         }
 
-        Some(Commands::Execute(args)) => {
-            let code = if let Some(file) = args.file {
-                std::fs::read_to_string(&file)?
-            } else {
-                args.code
-            };
-
-            match query_executor.execute(&code, &cli.user).await {
-                Ok(result) => {
-                    if !result.is_empty() {
-                        println!("{}", result);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-
         Some(Commands::Namespace(ns_args)) => {
             match ns_args.action {
                 NamespaceAction::List => {
@@ -312,7 +723,71 @@ async fn main() -> Result<()> {
             }
         }
 
-        Some(Commands::Mcp) => {
+        Some(Commands::Procedure(proc_args)) => {
+            if let Err(e) = run_procedure_action(&liath, proc_args.action, &cli.user).await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Job(job_args)) => {
+            if let Err(e) = run_job_command(&liath, job_args.action, &cli.user).await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::User(user_args)) => {
+            if let Err(e) = run_user_command(&liath, user_args.action, &cli.user) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Role(role_args)) => {
+            if let Err(e) = run_role_command(&liath, role_args.action, &cli.user) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Key(key_args)) => {
+            if let Err(e) = run_key_command(&liath, key_args.action, &cli.user) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Encryption(args)) => {
+            if let Err(e) = run_encryption_command(&liath, &encryption, args.action) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Audit(args)) => {
+            if let Err(e) = run_audit_command(&liath, args, &cli.user) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Mcp(args)) if args.http => {
+            #[cfg(all(feature = "mcp", feature = "server"))]
+            {
+                eprintln!("Starting Liath MCP server on http://127.0.0.1:{}/mcp", args.port);
+                run_server_with_config(args.port, std::sync::Arc::new(liath), ServerConfig::default()).await?;
+            }
+            #[cfg(not(all(feature = "mcp", feature = "server")))]
+            {
+                let _ = args;
+                eprintln!("Error: MCP over HTTP needs the mcp and server features.");
+                eprintln!("Rebuild with: cargo build --features mcp,server");
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Mcp(_)) => {
             #[cfg(feature = "mcp")]
             {
                 eprintln!("Starting Liath MCP server...");
@@ -334,3 +809,545 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Print query rows as an aligned table
+/// Run the console commands, on the data directory or a server
+async fn run_session(command: Option<Commands>, query_executor: Executor, user: String, data_dir: PathBuf) -> Result<()> {
+    // Only the TUI keeps its history in the data directory
+    #[cfg(not(feature = "tui"))]
+    let _ = data_dir;
+
+    match command {
+        // Default: start TUI console
+        None => {
+            #[cfg(feature = "tui")]
+            {
+                liath::cli::tui::run(query_executor, user, data_dir).await?;
+            }
+            #[cfg(not(feature = "tui"))]
+            {
+                liath::cli::console::run(query_executor).await?;
+            }
+        }
+
+        Some(Commands::Cli(args)) => {
+            if args.simple {
+                liath::cli::console::run(query_executor).await?;
+            } else {
+                #[cfg(feature = "tui")]
+                {
+                    liath::cli::tui::run(query_executor, user, data_dir).await?;
+                }
+                #[cfg(not(feature = "tui"))]
+                {
+                    liath::cli::console::run(query_executor).await?;
+                }
+            }
+        }
+
+        Some(Commands::Execute(args)) => {
+            let code = if let Some(file) = args.file {
+                std::fs::read_to_string(&file)?
+            } else {
+                args.code
+            };
+
+            let outcome = if args.explain {
+                query_executor
+                    .execute_profiled(&code, &user)
+                    .await
+                    .map(|(result, profile)| (result, Some(profile)))
+            } else {
+                query_executor.execute(&code, &user).await.map(|result| (result, None))
+            };
+            match outcome {
+                Ok((result, profile)) => {
+                    if !result.is_empty() {
+                        println!("{}", result);
+                    }
+                    if let Some(profile) = profile {
+                        eprint!("{}", profile.format_table());
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Some(Commands::Query(args)) => {
+            match query_executor.query(&args.query, &user).await {
+                Ok(result) if args.json => {
+                    println!("{}", serde_json::to_string_pretty(&result.to_objects())?);
+                }
+                Ok(result) => print_query_result(&result),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Some(_) => unreachable!("Not a console command"),
+    }
+    Ok(())
+}
+
+/// Run the console commands against a server given with --remote
+#[cfg(feature = "client")]
+async fn run_remote(cli: Cli, url: String) -> Result<()> {
+    use liath::client::{Client, RemoteExecutor};
+
+    let mut client = Client::new(url);
+    if let Some(api_key) = cli.api_key.or_else(|| std::env::var("LIATH_API_KEY").ok()) {
+        client = client.with_api_key(api_key);
+    }
+    if let Err(e) = client.health().await {
+        eprintln!("Error: Cannot reach {}: {}", client.base_url(), e);
+        std::process::exit(1);
+    }
+    match cli.command {
+        command @ (None | Some(Commands::Cli(_) | Commands::Execute(_) | Commands::Query(_))) => {
+            run_session(command, RemoteExecutor::new(client).into(), cli.user, cli.data_dir).await
+        }
+        Some(_) => {
+            eprintln!("Error: --remote works with the console, execute and query; run other commands on the server host");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "client"))]
+async fn run_remote(_cli: Cli, _url: String) -> Result<()> {
+    eprintln!("Error: Client feature not enabled.");
+    eprintln!("Rebuild with: cargo build --features client");
+    std::process::exit(1);
+}
+
+fn print_query_result(result: &liath::QueryResult) {
+    let cells: Vec<Vec<String>> = result.rows.iter()
+        .map(|row| row.iter().map(|v| match v {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }).collect())
+        .collect();
+    let widths: Vec<usize> = result.columns.iter().enumerate()
+        .map(|(i, name)| cells.iter().map(|row| row[i].chars().count()).chain([name.len()]).max().unwrap_or(0))
+        .collect();
+
+    let line = |values: Vec<&str>| {
+        let padded: Vec<String> = values.iter().zip(&widths).map(|(v, w)| format!("{:<w$}", v, w = w)).collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(result.columns.iter().map(String::as_str).collect());
+    line(widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().iter().map(String::as_str).collect());
+    for row in &cells {
+        line(row.iter().map(String::as_str).collect());
+    }
+    println!("({} row{})", cells.len(), if cells.len() == 1 { "" } else { "s" });
+}
+
+async fn run_procedure_action(liath: &EmbeddedLiath, action: ProcedureAction, user: &str) -> Result<()> {
+    let query_executor = liath.query_executor();
+    match action {
+        ProcedureAction::List => {
+            let procedures = query_executor.list_procedures()?;
+            if procedures.is_empty() {
+                println!("No procedures found.");
+            } else {
+                println!("Procedures:");
+                for p in procedures {
+                    match p.description {
+                        Some(desc) => println!("  - {} (v{}, owner {}): {}", p.name, p.version, p.owner, desc),
+                        None => println!("  - {} (v{}, owner {})", p.name, p.version, p.owner),
+                    }
+                }
+            }
+        }
+
+        ProcedureAction::Show { name, version } => {
+            let procedure = match version {
+                Some(v) => query_executor.get_procedure_version(&name, v)?,
+                None => query_executor.get_procedure(&name)?,
+            };
+            let p = procedure.ok_or_else(|| anyhow::anyhow!("Procedure '{}' not found", name))?;
+            println!("-- {} v{} (owner {})", p.name, p.version, p.owner);
+            if let Some(desc) = &p.description {
+                println!("-- {}", desc);
+            }
+            println!("{}", p.code);
+        }
+
+        ProcedureAction::Register { name, code, file, description } => {
+            let code = match (file, code) {
+                (Some(file), _) => std::fs::read_to_string(&file)?,
+                (None, Some(code)) => code,
+                (None, None) => return Err(anyhow::anyhow!("Provide the procedure code or --file")),
+            };
+            let p = query_executor.register_procedure(&name, &code, description.as_deref(), user)?;
+            liath.save()?;
+            println!("Registered procedure '{}' version {}", p.name, p.version);
+        }
+
+        ProcedureAction::Call { name, args } => {
+            let args = match args {
+                Some(raw) => serde_json::from_str(&raw)?,
+                None => serde_json::Value::Null,
+            };
+            let result = query_executor.call_procedure(&name, args, user).await?;
+            liath.save()?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        }
+
+        ProcedureAction::History { name } => {
+            let history = query_executor.procedure_history(&name)?;
+            if history.is_empty() {
+                println!("No versions found for '{}'.", name);
+            }
+            for p in history {
+                println!("  v{}  created_at={}  owner={}", p.version, p.created_at, p.owner);
+            }
+        }
+
+        ProcedureAction::Delete { name, force } => {
+            if !force {
+                println!("Are you sure you want to delete procedure '{}'? [y/N]", name);
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input)?;
+                if !input.trim().eq_ignore_ascii_case("y") {
+                    println!("Aborted.");
+                    return Ok(());
+                }
+            }
+            query_executor.delete_procedure(&name, user)?;
+            liath.save()?;
+            println!("Deleted procedure '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+async fn run_job_command(liath: &EmbeddedLiath, command: JobCommand, user: &str) -> Result<()> {
+    let scheduler = liath.scheduler();
+    match command {
+        JobCommand::List => {
+            let jobs = scheduler.list_jobs()?;
+            if jobs.is_empty() {
+                println!("No jobs found.");
+            } else {
+                println!("Jobs:");
+                for (job, status) in jobs {
+                    let schedule = match &job.schedule {
+                        Schedule::Interval { seconds } => format!("every {}s", seconds),
+                        Schedule::Cron { expression } => format!("cron '{}'", expression),
+                    };
+                    let last = match (status.last_success, &status.last_error) {
+                        (Some(true), _) => "ok".to_string(),
+                        (Some(false), Some(e)) => format!("failed: {}", e),
+                        _ => "never run".to_string(),
+                    };
+                    println!(
+                        "  - {} ({}{}) runs={} last={}",
+                        job.name,
+                        schedule,
+                        if job.enabled { "" } else { ", disabled" },
+                        status.run_count,
+                        last
+                    );
+                }
+            }
+        }
+
+        JobCommand::Add { name, every, cron, lua, file, procedure, args } => {
+            let schedule = match (every, cron) {
+                (Some(every), _) => Schedule::every(&every)?,
+                (None, Some(cron)) => Schedule::cron(&cron)?,
+                (None, None) => return Err(anyhow::anyhow!("Provide --every or --cron")),
+            };
+            let action = match (lua, file, procedure) {
+                (Some(code), _, _) => JobAction::Lua { code },
+                (None, Some(file), _) => JobAction::Lua { code: std::fs::read_to_string(&file)? },
+                (None, None, Some(name)) => JobAction::Procedure {
+                    name,
+                    args: match args {
+                        Some(raw) => serde_json::from_str(&raw)?,
+                        None => serde_json::Value::Null,
+                    },
+                },
+                (None, None, None) => return Err(anyhow::anyhow!("Provide --lua, --file or --procedure")),
+            };
+            scheduler.add_job(&name, schedule, action, user)?;
+            liath.save()?;
+            println!("Scheduled job '{}'", name);
+        }
+
+        JobCommand::Run { name } => {
+            let status = scheduler.run_job(&name, user).await?;
+            liath.save()?;
+            match status.last_error {
+                Some(e) => return Err(anyhow::anyhow!("Job '{}' failed: {}", name, e)),
+                None => println!("{}", serde_json::to_string_pretty(&status.last_result)?),
+            }
+        }
+
+        JobCommand::Enable { name } => {
+            scheduler.set_enabled(&name, true, user)?;
+            liath.save()?;
+            println!("Enabled job '{}'", name);
+        }
+
+        JobCommand::Disable { name } => {
+            scheduler.set_enabled(&name, false, user)?;
+            liath.save()?;
+            println!("Disabled job '{}'", name);
+        }
+
+        JobCommand::Remove { name } => {
+            scheduler.remove_job(&name, user)?;
+            liath.save()?;
+            println!("Removed job '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+fn run_user_command(liath: &EmbeddedLiath, command: UserCommand, user: &str) -> Result<()> {
+    let query_executor = liath.query_executor();
+    match command {
+        UserCommand::List => {
+            let users = query_executor.list_users(user)?;
+            if users.is_empty() {
+                println!("No users found.");
+            } else {
+                println!("Users:");
+                for u in users {
+                    let mut line = format!("  - {}: {}", u.user_id, u.permissions.join(", "));
+                    if !u.roles.is_empty() {
+                        line.push_str(&format!(" (roles: {})", u.roles.join(", ")));
+                    }
+                    if let Some(limits) = &u.limits {
+                        line.push_str(&format!(" (limits: {})", limits));
+                    }
+                    println!("{}", line);
+                }
+            }
+        }
+
+        UserCommand::Add { user_id, permissions, roles } => {
+            query_executor.add_user(&user_id, permissions, user)?;
+            for role in roles {
+                query_executor.assign_role(&user_id, &role, user)?;
+            }
+            liath.save()?;
+            println!("Saved user '{}'", user_id);
+        }
+
+        UserCommand::Grant { user_id, permissions } => {
+            for permission in &permissions {
+                query_executor.grant_permission(&user_id, permission, user)?;
+            }
+            liath.save()?;
+            println!("Granted {} to '{}'", permissions.join(", "), user_id);
+        }
+
+        UserCommand::Revoke { user_id, permissions } => {
+            for permission in &permissions {
+                query_executor.revoke_permission(&user_id, permission, user)?;
+            }
+            liath.save()?;
+            println!("Revoked {} from '{}'", permissions.join(", "), user_id);
+        }
+
+        UserCommand::Remove { user_id } => {
+            query_executor.remove_user(&user_id, user)?;
+            liath.save()?;
+            println!("Removed user '{}'", user_id);
+        }
+
+        UserCommand::Limits { user_id, limits: None, clear: false } => {
+            let users = query_executor.list_users(user)?;
+            let member = users.iter().find(|u| u.user_id == user_id)
+                .ok_or_else(|| anyhow::anyhow!("User '{}' not found", user_id))?;
+            match &member.limits {
+                Some(limits) => println!("{}", limits),
+                None => println!("'{}' has the server's default limits", user_id),
+            }
+        }
+
+        UserCommand::Limits { user_id, limits, .. } => {
+            let cleared = limits.is_none();
+            query_executor.set_limits(&user_id, limits, user)?;
+            liath.save()?;
+            if cleared {
+                println!("'{}' now has the server's default limits", user_id);
+            } else {
+                println!("Saved limits of '{}'", user_id);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_role_command(liath: &EmbeddedLiath, command: RoleCommand, user: &str) -> Result<()> {
+    let query_executor = liath.query_executor();
+    match command {
+        RoleCommand::List => {
+            let roles = query_executor.list_roles(user)?;
+            if roles.is_empty() {
+                println!("No roles found.");
+            } else {
+                println!("Roles:");
+                for r in roles {
+                    println!("  - {}: {}", r.name, r.grants.join(", "));
+                }
+            }
+        }
+
+        RoleCommand::Define { name, grants } => {
+            query_executor.define_role(&name, grants, user)?;
+            liath.save()?;
+            println!("Saved role '{}'", name);
+        }
+
+        RoleCommand::Delete { name } => {
+            query_executor.remove_role(&name, user)?;
+            liath.save()?;
+            println!("Deleted role '{}'", name);
+        }
+
+        RoleCommand::Assign { user_id, role } => {
+            query_executor.assign_role(&user_id, &role, user)?;
+            liath.save()?;
+            println!("Assigned role '{}' to '{}'", role, user_id);
+        }
+
+        RoleCommand::Unassign { user_id, role } => {
+            query_executor.unassign_role(&user_id, &role, user)?;
+            liath.save()?;
+            println!("Removed role '{}' from '{}'", role, user_id);
+        }
+    }
+    Ok(())
+}
+
+fn run_key_command(liath: &EmbeddedLiath, command: KeyCommand, user: &str) -> Result<()> {
+    let query_executor = liath.query_executor();
+    match command {
+        KeyCommand::Create { user_id, description } => {
+            let (info, token) = query_executor.create_api_key(&user_id, description.as_deref(), user)?;
+            liath.save()?;
+            println!("Issued key '{}' for user '{}'. Store it now; it will not be shown again:", info.id, info.user_id);
+            println!("{}", token);
+        }
+
+        KeyCommand::List { owner } => {
+            let keys = query_executor.list_api_keys(owner.as_deref(), user)?;
+            if keys.is_empty() {
+                println!("No API keys found.");
+            } else {
+                println!("API keys:");
+                for k in keys {
+                    match k.description {
+                        Some(desc) => println!("  - {} (user {}, created {}): {}", k.id, k.user_id, k.created_at, desc),
+                        None => println!("  - {} (user {}, created {})", k.id, k.user_id, k.created_at),
+                    }
+                }
+            }
+        }
+
+        KeyCommand::Revoke { id } => {
+            query_executor.revoke_api_key(&id, user)?;
+            liath.save()?;
+            println!("Revoked key '{}'", id);
+        }
+    }
+    Ok(())
+}
+
+/// Append a new key to the key file, numbered after its highest key, or print one
+fn generate_key(key_file: Option<&std::path::Path>) -> Result<()> {
+    let key = KeyRing::generate_key();
+    let Some(path) = key_file else {
+        println!("{}", crypto::format_key_line(1, &key));
+        return Ok(());
+    };
+    let existing = if path.exists() { crypto::read_key_file(path)? } else { Vec::new() };
+    let id = existing.iter().map(|(id, _)| id + 1).max().unwrap_or(1);
+
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", crypto::format_key_line(id, &key))?;
+    println!("Added key {} to {}", id, path.display());
+    if !existing.is_empty() {
+        println!("Run `liath --key-file {} encryption rotate` to re-encrypt existing data with it", path.display());
+    }
+    Ok(())
+}
+
+fn run_encryption_command(liath: &EmbeddedLiath, encryption: &EncryptionConfig, action: EncryptionCommand) -> Result<()> {
+    match action {
+        EncryptionCommand::GenerateKey => unreachable!("handled before the database is opened"),
+        EncryptionCommand::Rotate => {
+            let Some(keyring) = encryption.keyring()? else {
+                anyhow::bail!("No encryption keys configured; pass --key-file");
+            };
+            let rewritten = liath.rotate_encryption_keys()?;
+            println!("Re-encrypted {} values and files under key {}", rewritten, keyring.active_key_id());
+        }
+        EncryptionCommand::Status => {
+            match encryption.keyring()? {
+                Some(keyring) => println!("Keys: {:?} (active: {})", keyring.key_ids(), keyring.active_key_id()),
+                None => println!("Keys: none"),
+            }
+            let mut encrypted: Vec<String> = liath.list_namespaces()
+                .into_iter()
+                .filter(|name| liath.is_encrypted(name))
+                .collect();
+            encrypted.sort();
+            if encrypted.is_empty() {
+                println!("Encrypted namespaces: none");
+            } else {
+                println!("Encrypted namespaces: {}", encrypted.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_audit_command(liath: &EmbeddedLiath, args: AuditArgs, user: &str) -> Result<()> {
+    let filter = AuditFilter {
+        since_ms: args.since.as_deref().map(audit::parse_time_bound).transpose()?,
+        until_ms: args.until.as_deref().map(audit::parse_time_bound).transpose()?,
+        user_id: args.user_id,
+        limit: args.limit,
+    };
+    let entries = liath.query_executor().audit_log(&filter, user)?;
+    if args.json {
+        for entry in entries {
+            println!("{}", serde_json::to_string(&entry)?);
+        }
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No audit entries found.");
+        return Ok(());
+    }
+    for e in entries {
+        let target = match (e.namespace, e.key.or(e.key_hash)) {
+            (Some(ns), Some(key)) => format!("{}/{}", ns, key),
+            (Some(ns), None) => ns,
+            (None, Some(key)) => key,
+            (None, None) => "-".to_string(),
+        };
+        match e.detail {
+            Some(detail) => println!("{}  {}  {}  {}  {}: {}", e.timestamp_ms, e.user_id, e.operation, target, e.outcome, detail),
+            None => println!("{}  {}  {}  {}  {}", e.timestamp_ms, e.user_id, e.operation, target, e.outcome),
+        }
+    }
+    Ok(())
+}
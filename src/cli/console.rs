@@ -1,17 +1,23 @@
 use std::io::{self, Write};
-use crate::query::QueryExecutor;
+use super::Executor;
 use anyhow::Result;
 #[cfg(feature = "vector")]
 use usearch::{MetricKind, ScalarKind};
 
-pub async fn run(query_executor: QueryExecutor) -> Result<()> {
+pub async fn run(query_executor: Executor) -> Result<()> {
     println!("Welcome to AI-First DB CLI");
     println!("Enter your queries or type 'exit' to quit");
 
+    // A server runs requests as the owner of the API key
     let mut user_id = String::new();
-    print!("Enter your user ID: ");
-    io::stdout().flush()?;
-    io::stdin().read_line(&mut user_id)?;
+    match query_executor.remote_url() {
+        Some(url) => println!("Connected to {}", url),
+        None => {
+            print!("Enter your user ID: ");
+            io::stdout().flush()?;
+            io::stdin().read_line(&mut user_id)?;
+        }
+    }
     let user_id = user_id.trim();
 
     loop {
@@ -36,10 +42,10 @@ pub async fn run(query_executor: QueryExecutor) -> Result<()> {
             match parts[0] {
                 "ns" if parts.len() >= 2 => {
                     match parts[1] {
-                        "list" => {
-                            let nss = query_executor.list_namespaces();
-                            println!("Namespaces: {:?}", nss);
-                        }
+                        "list" => match query_executor.list_namespaces().await {
+                            Ok(nss) => println!("Namespaces: {:?}", nss),
+                            Err(e) => eprintln!("Error: {}", e),
+                        },
                         "create" if parts.len() == 6 => {
                             let name = parts[2];
                             let dims: usize = parts[3].parse().unwrap_or(384);
@@ -55,9 +61,9 @@ pub async fn run(query_executor: QueryExecutor) -> Result<()> {
                                 _ => ScalarKind::F32,
                             };
                             #[cfg(feature = "vector")]
-                            let res = query_executor.create_namespace(name, dims, metric, scalar);
+                            let res = query_executor.create_namespace(name, dims, metric, scalar).await;
                             #[cfg(not(feature = "vector"))]
-                            let res = query_executor.create_namespace_basic(name);
+                            let res = query_executor.create_namespace_basic(name).await;
                             if let Err(e) = res {
                                 eprintln!("Error: {}", e);
                             } else {
@@ -71,7 +77,7 @@ pub async fn run(query_executor: QueryExecutor) -> Result<()> {
                     let ns = parts[1];
                     let key = parts[2].as_bytes();
                     let value = parts[3..].join(" ");
-                    if let Err(e) = query_executor.put(ns, key, value.as_bytes()) {
+                    if let Err(e) = query_executor.put(ns, key, value.as_bytes()).await {
                         eprintln!("Error: {}", e);
                     } else {
                         println!("OK");
@@ -80,7 +86,7 @@ pub async fn run(query_executor: QueryExecutor) -> Result<()> {
                 "get" if parts.len() == 3 => {
                     let ns = parts[1];
                     let key = parts[2].as_bytes();
-                    match query_executor.get(ns, key) {
+                    match query_executor.get(ns, key).await {
                         Ok(Some(v)) => println!("{}", String::from_utf8_lossy(&v)),
                         Ok(None) => println!("(nil)"),
                         Err(e) => eprintln!("Error: {}", e),
@@ -89,13 +95,26 @@ pub async fn run(query_executor: QueryExecutor) -> Result<()> {
                 "del" if parts.len() == 3 => {
                     let ns = parts[1];
                     let key = parts[2].as_bytes();
-                    if let Err(e) = query_executor.delete(ns, key) {
+                    if let Err(e) = query_executor.delete(ns, key).await {
                         eprintln!("Error: {}", e);
                     } else {
                         println!("OK");
                     }
                 }
-                _ => eprintln!("Unknown command. Available: :ns, :put, :get, :del"),
+                "sql" if parts.len() >= 2 => {
+                    let query = cmd.trim_start()["sql".len()..].trim();
+                    match query_executor.query(query, user_id).await {
+                        Ok(result) => {
+                            println!("{}", result.columns.join(" | "));
+                            for row in &result.rows {
+                                let cells: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                                println!("{}", cells.join(" | "));
+                            }
+                        }
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                }
+                _ => eprintln!("Unknown command. Available: :ns, :put, :get, :del, :sql"),
             }
             continue;
         }
@@ -0,0 +1,125 @@
+//! The database a console works on: a data directory opened by this process, or
+//! a running server reached with `--remote`
+
+use anyhow::Result;
+
+use crate::query::{Profile, QueryExecutor, QueryResult};
+#[cfg(feature = "client")]
+use crate::client::RemoteExecutor;
+#[cfg(feature = "vector")]
+use usearch::{MetricKind, ScalarKind};
+#[cfg(not(feature = "vector"))]
+use crate::core::{MetricKind, ScalarKind};
+
+pub enum Executor {
+    Local(QueryExecutor),
+    #[cfg(feature = "client")]
+    Remote(RemoteExecutor),
+}
+
+impl From<QueryExecutor> for Executor {
+    fn from(executor: QueryExecutor) -> Self {
+        Executor::Local(executor)
+    }
+}
+
+#[cfg(feature = "client")]
+impl From<RemoteExecutor> for Executor {
+    fn from(executor: RemoteExecutor) -> Self {
+        Executor::Remote(executor)
+    }
+}
+
+impl Executor {
+    /// URL of the server, when working remotely
+    pub fn remote_url(&self) -> Option<&str> {
+        match self {
+            Executor::Local(_) => None,
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => Some(remote.client().base_url()),
+        }
+    }
+
+    /// Execute Lua code; a server runs it as the owner of the API key instead of `user_id`
+    pub async fn execute(&self, query: &str, user_id: &str) -> Result<String> {
+        match self {
+            Executor::Local(local) => local.execute(query, user_id).await,
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.execute(query).await,
+        }
+    }
+
+    pub async fn execute_profiled(&self, query: &str, user_id: &str) -> Result<(String, Profile)> {
+        match self {
+            Executor::Local(local) => local.execute_profiled(query, user_id).await,
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.execute_profiled(query).await,
+        }
+    }
+
+    pub async fn query(&self, query: &str, user_id: &str) -> Result<QueryResult> {
+        match self {
+            Executor::Local(local) => local.query(query, user_id),
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.query(query).await,
+        }
+    }
+
+    pub async fn list_namespaces(&self) -> Result<Vec<String>> {
+        match self {
+            Executor::Local(local) => Ok(local.list_namespaces()),
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.list_namespaces().await,
+        }
+    }
+
+    /// Create a namespace; a server always stores `f32` vectors
+    pub async fn create_namespace(&self, name: &str, dimensions: usize, metric: MetricKind, scalar: ScalarKind) -> Result<()> {
+        match self {
+            Executor::Local(local) => local.create_namespace(name, dimensions, metric, scalar),
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => {
+                let metric = if matches!(metric, MetricKind::L2sq) { "euclidean" } else { "cosine" };
+                remote.create_namespace(name, dimensions, metric).await
+            }
+        }
+    }
+
+    #[cfg(not(feature = "vector"))]
+    pub async fn create_namespace_basic(&self, name: &str) -> Result<()> {
+        self.create_namespace(name, 128, MetricKind::Cos, ScalarKind::F32).await
+    }
+
+    pub async fn put(&self, namespace: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        match self {
+            Executor::Local(local) => local.put(namespace, key, value),
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.put(namespace, key, value).await,
+        }
+    }
+
+    pub async fn get(&self, namespace: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self {
+            Executor::Local(local) => local.get(namespace, key),
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.get(namespace, key).await,
+        }
+    }
+
+    pub async fn delete(&self, namespace: &str, key: &[u8]) -> Result<()> {
+        match self {
+            Executor::Local(local) => local.delete(namespace, key),
+            #[cfg(feature = "client")]
+            Executor::Remote(remote) => remote.delete(namespace, key).await,
+        }
+    }
+
+    /// Save all data to disk; a server saves its own
+    pub fn save_all(&self) -> Result<()> {
+        match self {
+            Executor::Local(local) => local.save_all(),
+            #[cfg(feature = "client")]
+            Executor::Remote(_) => Err(anyhow::anyhow!("The server saves its own data")),
+        }
+    }
+}
@@ -1,4 +1,7 @@
 pub mod console;
+mod executor;
+
+pub use executor::Executor;
 
 #[cfg(feature = "tui")]
-pub mod tui;
\ No newline at end of file
+pub mod tui;
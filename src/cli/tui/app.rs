@@ -14,7 +14,8 @@ use ratatui::{
 };
 use anyhow::Result;
 
-use crate::query::QueryExecutor;
+use crate::cli::Executor;
+use crate::query::Profile;
 use super::ui;
 use super::events::InputMode;
 
@@ -31,6 +32,8 @@ pub struct ResultEntry {
     pub result: String,
     pub is_error: bool,
     pub timestamp: Instant,
+    /// Execution profile, when explain mode was on
+    pub profile: Option<Profile>,
 }
 
 /// Main application state
@@ -53,6 +56,8 @@ pub struct App {
     pub current_page: usize,
     /// Current user ID
     pub user_id: String,
+    /// URL of the server, when connected with `--remote`
+    pub remote: Option<String>,
     /// Current namespace (if selected)
     pub current_namespace: Option<String>,
     /// Available namespaces
@@ -61,6 +66,8 @@ pub struct App {
     pub show_help: bool,
     /// Show namespace browser
     pub show_namespaces: bool,
+    /// Profile executed queries and show where their time went
+    pub explain: bool,
     /// Selected namespace index (for browser)
     pub namespace_index: usize,
     /// Status message
@@ -69,15 +76,15 @@ pub struct App {
     pub start_time: Instant,
     /// Should quit
     pub should_quit: bool,
-    /// Query executor reference
-    query_executor: QueryExecutor,
+    /// Local database or remote server
+    query_executor: Executor,
     /// Data directory for history persistence
     data_dir: PathBuf,
 }
 
 impl App {
-    pub fn new(query_executor: QueryExecutor, user_id: String, data_dir: PathBuf) -> Self {
-        let namespaces = query_executor.list_namespaces();
+    pub fn new(query_executor: Executor, user_id: String, data_dir: PathBuf) -> Self {
+        let remote = query_executor.remote_url().map(str::to_string);
         let history = Self::load_history(&data_dir).unwrap_or_default();
         Self {
             input: String::new(),
@@ -89,10 +96,12 @@ impl App {
             results_scroll: 0,
             current_page: 0,
             user_id,
+            remote,
             current_namespace: None,
-            namespaces,
+            namespaces: Vec::new(),
             show_help: false,
             show_namespaces: false,
+            explain: false,
             namespace_index: 0,
             status_message: None,
             start_time: Instant::now(),
@@ -248,8 +257,11 @@ impl App {
     }
 
     /// Refresh namespace list
-    pub fn refresh_namespaces(&mut self) {
-        self.namespaces = self.query_executor.list_namespaces();
+    pub async fn refresh_namespaces(&mut self) {
+        match self.query_executor.list_namespaces().await {
+            Ok(namespaces) => self.namespaces = namespaces,
+            Err(e) => self.set_status(&format!("Failed to list namespaces: {}", e)),
+        }
     }
 
     /// Execute the current input
@@ -270,13 +282,22 @@ impl App {
             self.handle_command(&input).await;
         } else {
             // Execute as Lua query
-            match self.query_executor.execute(&input, &self.user_id).await {
-                Ok(result) => {
+            let outcome = if self.explain {
+                self.query_executor
+                    .execute_profiled(&input, &self.user_id)
+                    .await
+                    .map(|(result, profile)| (result, Some(profile)))
+            } else {
+                self.query_executor.execute(&input, &self.user_id).await.map(|result| (result, None))
+            };
+            match outcome {
+                Ok((result, profile)) => {
                     self.results.push(ResultEntry {
                         query: input,
                         result,
                         is_error: false,
                         timestamp: Instant::now(),
+                        profile,
                     });
                 }
                 Err(e) => {
@@ -285,6 +306,7 @@ impl App {
                         result: format!("Error: {}", e),
                         is_error: true,
                         timestamp: Instant::now(),
+                        profile: None,
                     });
                 }
             }
@@ -327,13 +349,21 @@ impl App {
                 }
             }
             "put" if parts.len() >= 3 => {
-                self.handle_put_command(&parts[1..]);
+                self.handle_put_command(&parts[1..]).await;
             }
             "get" if parts.len() >= 2 => {
-                self.handle_get_command(&parts[1..]);
+                self.handle_get_command(&parts[1..]).await;
             }
             "del" if parts.len() >= 2 => {
-                self.handle_del_command(&parts[1..]);
+                self.handle_del_command(&parts[1..]).await;
+            }
+            "explain" => {
+                match parts.get(1) {
+                    Some(&"on") => self.explain = true,
+                    Some(&"off") => self.explain = false,
+                    _ => self.explain = !self.explain,
+                }
+                self.set_status(if self.explain { "Explain mode on" } else { "Explain mode off" });
             }
             "save" => {
                 match self.query_executor.save_all() {
@@ -347,6 +377,7 @@ impl App {
                     result: format!("Unknown command: {}. Type :help for available commands.", parts[0]),
                     is_error: true,
                     timestamp: Instant::now(),
+                    profile: None,
                 });
             }
         }
@@ -355,7 +386,7 @@ impl App {
     async fn handle_namespace_command(&mut self, parts: &[&str]) {
         match parts[0] {
             "list" | "ls" => {
-                self.refresh_namespaces();
+                self.refresh_namespaces().await;
                 let ns_list = if self.namespaces.is_empty() {
                     "(no namespaces)".to_string()
                 } else {
@@ -366,6 +397,7 @@ impl App {
                     result: format!("Namespaces: {}", ns_list),
                     is_error: false,
                     timestamp: Instant::now(),
+                    profile: None,
                 });
             }
             "create" if parts.len() >= 2 => {
@@ -382,9 +414,9 @@ impl App {
                         Some("f16") => ScalarKind::F16,
                         _ => ScalarKind::F32,
                     };
-                    match self.query_executor.create_namespace(name, dims, metric, scalar) {
+                    match self.query_executor.create_namespace(name, dims, metric, scalar).await {
                         Ok(_) => {
-                            self.refresh_namespaces();
+                            self.refresh_namespaces().await;
                             self.set_status(&format!("Created namespace: {}", name));
                         }
                         Err(e) => self.set_status(&format!("Failed: {}", e)),
@@ -392,9 +424,9 @@ impl App {
                 }
                 #[cfg(not(feature = "vector"))]
                 {
-                    match self.query_executor.create_namespace_basic(name) {
+                    match self.query_executor.create_namespace_basic(name).await {
                         Ok(_) => {
-                            self.refresh_namespaces();
+                            self.refresh_namespaces().await;
                             self.set_status(&format!("Created namespace: {}", name));
                         }
                         Err(e) => self.set_status(&format!("Failed: {}", e)),
@@ -407,7 +439,7 @@ impl App {
         }
     }
 
-    fn handle_put_command(&mut self, parts: &[&str]) {
+    async fn handle_put_command(&mut self, parts: &[&str]) {
         let (ns, key, value) = if parts.len() >= 3 {
             (parts[0], parts[1], parts[2..].join(" "))
         } else if let Some(ref ns) = self.current_namespace {
@@ -422,13 +454,13 @@ impl App {
             return;
         };
 
-        match self.query_executor.put(ns, key.as_bytes(), value.as_bytes()) {
+        match self.query_executor.put(ns, key.as_bytes(), value.as_bytes()).await {
             Ok(_) => self.set_status("OK"),
             Err(e) => self.set_status(&format!("Error: {}", e)),
         }
     }
 
-    fn handle_get_command(&mut self, parts: &[&str]) {
+    async fn handle_get_command(&mut self, parts: &[&str]) {
         let (ns, key) = if parts.len() >= 2 {
             (parts[0], parts[1])
         } else if let Some(ref ns) = self.current_namespace {
@@ -438,13 +470,14 @@ impl App {
             return;
         };
 
-        match self.query_executor.get(ns, key.as_bytes()) {
+        match self.query_executor.get(ns, key.as_bytes()).await {
             Ok(Some(v)) => {
                 self.results.push(ResultEntry {
                     query: format!(":get {} {}", ns, key),
                     result: String::from_utf8_lossy(&v).to_string(),
                     is_error: false,
                     timestamp: Instant::now(),
+                    profile: None,
                 });
             }
             Ok(None) => {
@@ -453,13 +486,14 @@ impl App {
                     result: "(nil)".to_string(),
                     is_error: false,
                     timestamp: Instant::now(),
+                    profile: None,
                 });
             }
             Err(e) => self.set_status(&format!("Error: {}", e)),
         }
     }
 
-    fn handle_del_command(&mut self, parts: &[&str]) {
+    async fn handle_del_command(&mut self, parts: &[&str]) {
         let (ns, key) = if parts.len() >= 2 {
             (parts[0], parts[1])
         } else if let Some(ref ns) = self.current_namespace {
@@ -469,7 +503,7 @@ impl App {
             return;
         };
 
-        match self.query_executor.delete(ns, key.as_bytes()) {
+        match self.query_executor.delete(ns, key.as_bytes()).await {
             Ok(_) => self.set_status("Deleted"),
             Err(e) => self.set_status(&format!("Error: {}", e)),
         }
@@ -550,7 +584,7 @@ impl App {
 }
 
 /// Run the TUI application
-pub async fn run(query_executor: QueryExecutor, user_id: String, data_dir: PathBuf) -> Result<()> {
+pub async fn run(query_executor: Executor, user_id: String, data_dir: PathBuf) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -560,6 +594,7 @@ pub async fn run(query_executor: QueryExecutor, user_id: String, data_dir: PathB
 
     // Create app state
     let mut app = App::new(query_executor, user_id, data_dir);
+    app.refresh_namespaces().await;
 
     // Main loop
     let tick_rate = Duration::from_millis(100);
@@ -591,6 +626,10 @@ pub async fn run(query_executor: QueryExecutor, user_id: String, data_dir: PathB
                             KeyCode::Char('n') => {
                                 app.show_namespaces = !app.show_namespaces;
                             }
+                            KeyCode::Char('e') => {
+                                app.explain = !app.explain;
+                                app.set_status(if app.explain { "Explain mode on" } else { "Explain mode off" });
+                            }
                             KeyCode::Up | KeyCode::Char('k') => {
                                 if app.show_namespaces {
                                     app.namespace_index = app.namespace_index.saturating_sub(1);
@@ -46,7 +46,7 @@ fn draw_title_bar(f: &mut Frame, app: &App, area: Rect) {
     let namespace = app.current_namespace.as_deref().unwrap_or("(none)");
     let uptime = app.start_time.elapsed().as_secs();
 
-    let title = Line::from(vec![
+    let mut spans = vec![
         mode,
         Span::raw(" "),
         Span::styled("Liath", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
@@ -54,11 +54,25 @@ fn draw_title_bar(f: &mut Frame, app: &App, area: Rect) {
         Span::styled("ns:", Style::default().fg(Color::DarkGray)),
         Span::styled(namespace, Style::default().fg(Color::Yellow)),
         Span::raw(" | "),
-        Span::styled("user:", Style::default().fg(Color::DarkGray)),
-        Span::styled(&app.user_id, Style::default().fg(Color::Magenta)),
-        Span::raw(" | "),
-        Span::styled(format!("uptime: {}s", uptime), Style::default().fg(Color::DarkGray)),
-    ]);
+    ];
+    // Against a server the API key decides the user
+    match &app.remote {
+        Some(url) => {
+            spans.push(Span::styled("remote:", Style::default().fg(Color::DarkGray)));
+            spans.push(Span::styled(url.as_str(), Style::default().fg(Color::Magenta)));
+        }
+        None => {
+            spans.push(Span::styled("user:", Style::default().fg(Color::DarkGray)));
+            spans.push(Span::styled(app.user_id.as_str(), Style::default().fg(Color::Magenta)));
+        }
+    }
+    spans.push(Span::raw(" | "));
+    spans.push(Span::styled(format!("uptime: {}s", uptime), Style::default().fg(Color::DarkGray)));
+    if app.explain {
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled("explain", Style::default().fg(Color::Green)));
+    }
+    let title = Line::from(spans);
 
     let title_bar = Paragraph::new(title)
         .style(Style::default().bg(Color::Rgb(30, 30, 30)));
@@ -121,7 +135,7 @@ fn draw_main_area(f: &mut Frame, app: &App, area: Rect) {
                 Style::default().fg(Color::Cyan)
             };
 
-            let lines = vec![
+            let mut lines = vec![
                 Line::from(vec![
                     Span::styled("› ", query_style),
                     Span::styled(&entry.query, query_style),
@@ -130,8 +144,16 @@ fn draw_main_area(f: &mut Frame, app: &App, area: Rect) {
                     Span::styled("  ", base_style),
                     Span::styled(&entry.result, base_style),
                 ]),
-                Line::from(""),
             ];
+            if let Some(profile) = &entry.profile {
+                for row in profile.format_table().lines() {
+                    lines.push(Line::from(Span::styled(
+                        format!("  {}", row),
+                        Style::default().fg(Color::DarkGray),
+                    )));
+                }
+            }
+            lines.push(Line::from(""));
 
             ListItem::new(lines)
         }).collect();
@@ -190,7 +212,7 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
         msg.clone()
     } else {
         match app.input_mode {
-            InputMode::Normal => " i:insert  ?:help  n:namespaces  j/k:scroll  PgUp/PgDn:page  Ctrl+Q:quit ".to_string(),
+            InputMode::Normal => " i:insert  ?:help  n:namespaces  e:explain  j/k:scroll  PgUp/PgDn:page  Ctrl+Q:quit ".to_string(),
             InputMode::Insert => " Enter:execute  Esc:normal  ↑↓:history  PgUp/PgDn:page  Ctrl+C:clear ".to_string(),
         }
     };
@@ -251,6 +273,7 @@ fn draw_help_popup(f: &mut Frame, _app: &App) {
         Line::from("  g           Go to top"),
         Line::from("  G           Go to bottom"),
         Line::from("  n           Toggle namespace browser"),
+        Line::from("  e           Toggle explain (profile queries)"),
         Line::from("  ?, F1       Toggle this help"),
         Line::from("  Ctrl+C      Clear results"),
         Line::from("  Ctrl+Q      Quit"),
@@ -272,6 +295,7 @@ fn draw_help_popup(f: &mut Frame, _app: &App) {
         Line::from("  :put [ns] <key> <value>   Store value"),
         Line::from("  :get [ns] <key>           Get value"),
         Line::from("  :del [ns] <key>           Delete value"),
+        Line::from("  :explain [on|off]         Profile queries"),
         Line::from("  :save                     Persist to disk"),
         Line::from("  :clear                    Clear results"),
         Line::from("  :quit                     Exit"),
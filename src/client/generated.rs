@@ -0,0 +1,954 @@
+//! Generated from the server's OpenAPI document by `server::codegen`; do not edit.
+//!
+//! Regenerate with `LIATH_UPDATE_CLIENT=1 cargo test --features server generated_client`.
+
+use anyhow::Result;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use super::{ndjson_body, segment, Client};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AddMessageRequest {
+    pub content: String,
+    /// `user`, `assistant`, `system` or `tool`
+    pub role: String,
+    /// Name of the tool, required when `role` is `tool`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+}
+
+/// Metadata for a registered agent
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentMetadata {
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentResponse {
+    #[serde(flatten)]
+    pub agent_metadata: AgentMetadata,
+    pub conversations: Vec<String>,
+}
+
+/// One audited operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Error message for denied and failed operations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Key the operation touched, unless keys are hashed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Hex SHA-256 of the key when `hash_keys` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Operation name, such as `insert` or `register_procedure`
+    pub operation: String,
+    pub outcome: AuditOutcome,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub user_id: String,
+}
+
+/// How an audited operation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditOutcome {
+    #[serde(rename = "ok")]
+    Ok,
+    #[serde(rename = "denied")]
+    Denied,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuditQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    /// Milliseconds since the epoch, or a duration like `1h` meaning that long ago
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuditResponse {
+    pub entries: Vec<AuditEntry>,
+}
+
+/// A failed record, the running totals, or the final totals
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkLine {
+    /// Set on the last line when every record was processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done: Option<bool>,
+    /// Why a record failed, or why the ingest stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Records failed so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<u64>,
+    /// Key of a record that failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Line of a record that failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    /// Records processed so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processed: Option<u64>,
+    /// Records written so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkQuery {
+    /// Records written per batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<u64>,
+    /// Vectors to reserve index capacity for before the first batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<u64>,
+}
+
+/// One record of a bulk ingest
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkRecord {
+    /// Vector id; derived from the key when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Embedded and indexed unless `vector` is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Stored under `key`: strings as they are, other JSON serialized. Defaults to `text`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallProcedureRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallProcedureResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    pub success: bool,
+}
+
+/// One change to the database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    /// Id of the vector added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// Key written or deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub kind: ChangeKind,
+    pub namespace: String,
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+}
+
+/// What a change did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    #[serde(rename = "put")]
+    Put,
+    #[serde(rename = "delete")]
+    Delete,
+    #[serde(rename = "add_vector")]
+    AddVector,
+    #[serde(rename = "create_namespace")]
+    CreateNamespace,
+    #[serde(rename = "delete_namespace")]
+    DeleteNamespace,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConversationResponse {
+    pub agent_id: String,
+    pub id: String,
+    pub message_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreateAgentRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreateConversationRequest {
+    /// ID for the conversation; a UUID is generated when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreateNamespaceRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<u64>,
+    /// Encrypt values and the saved vector index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbedRequest {
+    pub texts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbedResponse {
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// Calls of one database function during an execution
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FunctionProfile {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub calls: u64,
+    pub max_us: u64,
+    pub name: String,
+    pub total_us: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub uptime_secs: u64,
+    pub version: String,
+}
+
+/// Id of a created memory or message
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IdResponse {
+    pub id: u64,
+}
+
+/// A scheduled job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub action: JobAction,
+    pub created_at: u64,
+    pub enabled: bool,
+    pub name: String,
+    /// User the job runs as
+    pub owner: String,
+    pub schedule: Schedule,
+}
+
+/// What a job does when it runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum JobAction {
+    /// Run a Lua script
+    #[serde(rename = "lua")]
+    Lua {
+        code: String,
+    },
+    /// Call a stored procedure
+    #[serde(rename = "procedure")]
+    Procedure {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        args: Option<serde_json::Value>,
+        name: String,
+    },
+    /// Call a Rust callback registered with the scheduler
+    #[serde(rename = "callback")]
+    Callback {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobInfo {
+    #[serde(flatten)]
+    pub job: Job,
+    pub status: JobStatus,
+}
+
+/// Outcome of the most recent run of a job
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run_at: Option<u64>,
+    pub run_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobsResponse {
+    pub jobs: Vec<JobInfo>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KvGetResponse {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KvPutRequest {
+    pub value: String,
+}
+
+/// An entry in an agent's long-term memory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub content: String,
+    pub created_at: u64,
+    /// Distance to the recall query; 0 when not recalled by similarity
+    pub distance: f32,
+    pub id: u64,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageResponse {
+    pub content: String,
+    pub id: u64,
+    pub role: String,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessagesParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<u64>,
+    /// Return only the most recent messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<u64>,
+    /// Return the messages most similar to this text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsResponse {
+    pub busy_workers: u64,
+    pub namespaces: u64,
+    pub queue_capacity: u64,
+    /// Requests waiting for a worker
+    pub queue_depth: u64,
+    pub requests_total: u64,
+    pub uptime_secs: u64,
+    pub workers: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NamespacesResponse {
+    pub namespaces: Vec<String>,
+}
+
+/// A version of a stored procedure
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Procedure {
+    pub code: String,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub name: String,
+    /// User that registered this version; the procedure body runs with their permissions
+    pub owner: String,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProceduresResponse {
+    pub procedures: Vec<Procedure>,
+}
+
+/// Where the time of one execution went
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Per-function statistics, slowest first
+    pub functions: Vec<FunctionProfile>,
+    /// Time spent in Lua itself, outside database functions
+    pub lua_us: u64,
+    /// Wall time of the whole execution
+    pub total_us: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PutJobRequest {
+    pub action: JobAction,
+    pub schedule: Schedule,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryRequest {
+    /// Profile the execution and return the profile with the result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<bool>,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    pub result: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecallParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<u64>,
+    /// Text to recall semantically similar memories for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Comma-separated tags, used when `query` is absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegisterProcedureRequest {
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunJobResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<JobStatus>,
+    pub success: bool,
+}
+
+/// An entry, the cursor to resume from, or an error
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanLine {
+    /// Present on the last line when `limit` cut the scan short
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// Present on the last line when the scan failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// Stop after this many entries and end the stream with a cursor line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Schedule {
+    /// Every `seconds` seconds
+    #[serde(rename = "interval")]
+    Interval {
+        seconds: u64,
+    },
+    /// Cron expression, evaluated in UTC
+    #[serde(rename = "cron")]
+    Cron {
+        expression: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SemanticSearchRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<u64>,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SemanticSearchResponse {
+    pub results: Vec<SemanticSearchResult>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SemanticSearchResult {
+    pub content: String,
+    pub distance: f32,
+    pub id: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SqlRequest {
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SqlResponse {
+    pub columns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StoreMemoryRequest {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SuccessResponse {
+    pub message: String,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolStateRequest {
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolStateResponse {
+    pub key: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WatchQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Resume after this sequence number; SSE clients may send `Last-Event-ID` instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+}
+
+impl Client {
+    /// List agents
+    ///
+    /// `GET /agents`
+    pub async fn list_agents(&self) -> Result<Vec<AgentMetadata>> {
+        let request = self.request(Method::GET, "/agents");
+        Self::json(request).await
+    }
+
+    /// Register an agent
+    ///
+    /// `POST /agents`
+    pub async fn create_agent(&self, body: &CreateAgentRequest) -> Result<AgentMetadata> {
+        let request = self.request(Method::POST, "/agents").json(body);
+        Self::json(request).await
+    }
+
+    /// Delete an agent
+    ///
+    /// `DELETE /agents/{agent}`
+    pub async fn delete_agent(&self, agent: &str) -> Result<SuccessResponse> {
+        let path = format!("/agents/{}", segment(agent));
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// Read an agent and its conversations
+    ///
+    /// `GET /agents/{agent}`
+    pub async fn get_agent(&self, agent: &str) -> Result<AgentResponse> {
+        let path = format!("/agents/{}", segment(agent));
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// List an agent's conversations
+    ///
+    /// `GET /agents/{agent}/conversations`
+    pub async fn list_conversations(&self, agent: &str) -> Result<Vec<String>> {
+        let path = format!("/agents/{}/conversations", segment(agent));
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// Start a conversation
+    ///
+    /// `POST /agents/{agent}/conversations`
+    pub async fn create_conversation(&self, agent: &str, body: &CreateConversationRequest) -> Result<ConversationResponse> {
+        let path = format!("/agents/{}/conversations", segment(agent));
+        let request = self.request(Method::POST, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Read a conversation
+    ///
+    /// `GET /agents/{agent}/conversations/{conversation}`
+    pub async fn get_conversation(&self, agent: &str, conversation: &str) -> Result<ConversationResponse> {
+        let path = format!("/agents/{}/conversations/{}", segment(agent), segment(conversation));
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// Read messages
+    ///
+    /// `GET /agents/{agent}/conversations/{conversation}/messages`
+    pub async fn list_messages(&self, agent: &str, conversation: &str, query: &MessagesParams) -> Result<Vec<MessageResponse>> {
+        let path = format!("/agents/{}/conversations/{}/messages", segment(agent), segment(conversation));
+        let request = self.request(Method::GET, &path).query(query);
+        Self::json(request).await
+    }
+
+    /// Add a message
+    ///
+    /// `POST /agents/{agent}/conversations/{conversation}/messages`
+    pub async fn add_message(&self, agent: &str, conversation: &str, body: &AddMessageRequest) -> Result<IdResponse> {
+        let path = format!("/agents/{}/conversations/{}/messages", segment(agent), segment(conversation));
+        let request = self.request(Method::POST, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Recall memories by similarity or tags
+    ///
+    /// `GET /agents/{agent}/memories`
+    pub async fn recall_memories(&self, agent: &str, query: &RecallParams) -> Result<Vec<MemoryEntry>> {
+        let path = format!("/agents/{}/memories", segment(agent));
+        let request = self.request(Method::GET, &path).query(query);
+        Self::json(request).await
+    }
+
+    /// Store a memory
+    ///
+    /// `POST /agents/{agent}/memories`
+    pub async fn store_memory(&self, agent: &str, body: &StoreMemoryRequest) -> Result<IdResponse> {
+        let path = format!("/agents/{}/memories", segment(agent));
+        let request = self.request(Method::POST, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Forget a memory
+    ///
+    /// `DELETE /agents/{agent}/memories/{memory}`
+    pub async fn forget_memory(&self, agent: &str, memory: u64) -> Result<SuccessResponse> {
+        let path = format!("/agents/{}/memories/{}", segment(agent), memory);
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// Read a memory
+    ///
+    /// `GET /agents/{agent}/memories/{memory}`
+    pub async fn get_memory(&self, agent: &str, memory: u64) -> Result<MemoryEntry> {
+        let path = format!("/agents/{}/memories/{}", segment(agent), memory);
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// Delete a tool's state
+    ///
+    /// `DELETE /agents/{agent}/tools/{tool}/state/{key}`
+    pub async fn delete_tool_state(&self, agent: &str, tool: &str, key: &str) -> Result<SuccessResponse> {
+        let path = format!("/agents/{}/tools/{}/state/{}", segment(agent), segment(tool), segment(key));
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// Read a tool's state
+    ///
+    /// `GET /agents/{agent}/tools/{tool}/state/{key}`
+    pub async fn get_tool_state(&self, agent: &str, tool: &str, key: &str) -> Result<ToolStateResponse> {
+        let path = format!("/agents/{}/tools/{}/state/{}", segment(agent), segment(tool), segment(key));
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// Write a tool's state
+    ///
+    /// `PUT /agents/{agent}/tools/{tool}/state/{key}`
+    pub async fn put_tool_state(&self, agent: &str, tool: &str, key: &str, body: &ToolStateRequest) -> Result<ToolStateResponse> {
+        let path = format!("/agents/{}/tools/{}/state/{}", segment(agent), segment(tool), segment(key));
+        let request = self.request(Method::PUT, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Read the audit log
+    ///
+    /// `GET /audit`; requires the `read_audit` permission.
+    pub async fn audit(&self, query: &AuditQuery) -> Result<AuditResponse> {
+        let request = self.request(Method::GET, "/audit").query(query);
+        Self::json(request).await
+    }
+
+    /// Ingest records in batches, streaming the progress
+    ///
+    /// `POST /bulk/{namespace}`; requires the `insert` permission.
+    pub async fn bulk_ingest(&self, namespace: &str, query: &BulkQuery, records: &[BulkRecord]) -> Result<Vec<BulkLine>> {
+        let path = format!("/bulk/{}", segment(namespace));
+        let request = ndjson_body(self.request(Method::POST, &path).query(query), records)?;
+        Self::ndjson(request).await
+    }
+
+    /// Embed texts
+    ///
+    /// `POST /embed`; requires the `generate_embedding` permission.
+    pub async fn embed(&self, body: &EmbedRequest) -> Result<EmbedResponse> {
+        let request = self.request(Method::POST, "/embed").json(body);
+        Self::json(request).await
+    }
+
+    /// Check that the server is up
+    ///
+    /// `GET /health`
+    pub async fn health(&self) -> Result<HealthResponse> {
+        let request = self.request(Method::GET, "/health");
+        Self::json(request).await
+    }
+
+    /// List scheduled jobs and their status
+    ///
+    /// `GET /jobs`; requires the `manage_jobs` permission.
+    pub async fn list_jobs(&self) -> Result<JobsResponse> {
+        let request = self.request(Method::GET, "/jobs");
+        Self::json(request).await
+    }
+
+    /// Delete a job
+    ///
+    /// `DELETE /jobs/{name}`
+    pub async fn delete_job(&self, name: &str) -> Result<SuccessResponse> {
+        let path = format!("/jobs/{}", segment(name));
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// Create or replace a job
+    ///
+    /// `PUT /jobs/{name}`
+    pub async fn put_job(&self, name: &str, body: &PutJobRequest) -> Result<SuccessResponse> {
+        let path = format!("/jobs/{}", segment(name));
+        let request = self.request(Method::PUT, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Disable a job
+    ///
+    /// `POST /jobs/{name}/disable`
+    pub async fn disable_job(&self, name: &str) -> Result<SuccessResponse> {
+        let path = format!("/jobs/{}/disable", segment(name));
+        let request = self.request(Method::POST, &path);
+        Self::json(request).await
+    }
+
+    /// Enable a job
+    ///
+    /// `POST /jobs/{name}/enable`
+    pub async fn enable_job(&self, name: &str) -> Result<SuccessResponse> {
+        let path = format!("/jobs/{}/enable", segment(name));
+        let request = self.request(Method::POST, &path);
+        Self::json(request).await
+    }
+
+    /// Run a job now
+    ///
+    /// `POST /jobs/{name}/run`
+    pub async fn run_job(&self, name: &str) -> Result<RunJobResponse> {
+        let path = format!("/jobs/{}/run", segment(name));
+        let request = self.request(Method::POST, &path);
+        Self::json(request).await
+    }
+
+    /// Stream the entries under a prefix
+    ///
+    /// `GET /kv/{namespace}`; requires the `select` permission.
+    pub async fn kv_scan(&self, namespace: &str, query: &ScanQuery) -> Result<Vec<ScanLine>> {
+        let path = format!("/kv/{}", segment(namespace));
+        let request = self.request(Method::GET, &path).query(query);
+        Self::ndjson(request).await
+    }
+
+    /// Delete a value
+    ///
+    /// `DELETE /kv/{namespace}/{key}`; requires the `delete` permission.
+    pub async fn kv_delete(&self, namespace: &str, key: &str) -> Result<SuccessResponse> {
+        let path = format!("/kv/{}/{}", segment(namespace), segment(key));
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// Read a value
+    ///
+    /// `GET /kv/{namespace}/{key}`; requires the `select` permission.
+    pub async fn kv_get(&self, namespace: &str, key: &str) -> Result<KvGetResponse> {
+        let path = format!("/kv/{}/{}", segment(namespace), segment(key));
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// Write a value
+    ///
+    /// `PUT /kv/{namespace}/{key}`; requires the `insert` permission.
+    pub async fn kv_put(&self, namespace: &str, key: &str, body: &KvPutRequest) -> Result<SuccessResponse> {
+        let path = format!("/kv/{}/{}", segment(namespace), segment(key));
+        let request = self.request(Method::PUT, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Request, queue and worker counters
+    ///
+    /// `GET /metrics`
+    pub async fn metrics(&self) -> Result<MetricsResponse> {
+        let request = self.request(Method::GET, "/metrics");
+        Self::json(request).await
+    }
+
+    /// Metrics in the Prometheus text format
+    ///
+    /// `GET /metrics/prometheus`
+    pub async fn metrics_prometheus(&self) -> Result<String> {
+        let request = self.request(Method::GET, "/metrics/prometheus");
+        Self::text(request).await
+    }
+
+    /// List the namespaces the caller may read
+    ///
+    /// `GET /namespaces`; requires the `list_namespaces` permission.
+    pub async fn list_namespaces(&self) -> Result<NamespacesResponse> {
+        let request = self.request(Method::GET, "/namespaces");
+        Self::json(request).await
+    }
+
+    /// Create a namespace
+    ///
+    /// `POST /namespaces`; requires the `create_namespace` permission.
+    pub async fn create_namespace(&self, body: &CreateNamespaceRequest) -> Result<SuccessResponse> {
+        let request = self.request(Method::POST, "/namespaces").json(body);
+        Self::json(request).await
+    }
+
+    /// Delete a namespace and its data
+    ///
+    /// `DELETE /namespaces/{name}`; requires the `delete_namespace` permission.
+    pub async fn delete_namespace(&self, name: &str) -> Result<SuccessResponse> {
+        let path = format!("/namespaces/{}", segment(name));
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// The OpenAPI document of this API
+    ///
+    /// `GET /openapi.json`
+    pub async fn openapi(&self) -> Result<serde_json::Value> {
+        let request = self.request(Method::GET, "/openapi.json");
+        Self::json(request).await
+    }
+
+    /// List stored procedures
+    ///
+    /// `GET /procedures`; requires the `call_procedure` permission.
+    pub async fn list_procedures(&self) -> Result<ProceduresResponse> {
+        let request = self.request(Method::GET, "/procedures");
+        Self::json(request).await
+    }
+
+    /// Delete a procedure and its versions
+    ///
+    /// `DELETE /procedures/{name}`
+    pub async fn delete_procedure(&self, name: &str) -> Result<SuccessResponse> {
+        let path = format!("/procedures/{}", segment(name));
+        let request = self.request(Method::DELETE, &path);
+        Self::json(request).await
+    }
+
+    /// Read the latest version of a procedure
+    ///
+    /// `GET /procedures/{name}`; requires the `call_procedure` permission.
+    pub async fn get_procedure(&self, name: &str) -> Result<Option<Procedure>> {
+        let path = format!("/procedures/{}", segment(name));
+        let request = self.request(Method::GET, &path);
+        Self::json(request).await
+    }
+
+    /// Call a procedure
+    ///
+    /// `POST /procedures/{name}`
+    pub async fn call_procedure(&self, name: &str, body: &CallProcedureRequest) -> Result<CallProcedureResponse> {
+        let path = format!("/procedures/{}", segment(name));
+        let request = self.request(Method::POST, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Register a new version of a procedure
+    ///
+    /// `PUT /procedures/{name}`
+    pub async fn register_procedure(&self, name: &str, body: &RegisterProcedureRequest) -> Result<SuccessResponse> {
+        let path = format!("/procedures/{}", segment(name));
+        let request = self.request(Method::PUT, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Run a Lua script
+    ///
+    /// `POST /query`
+    pub async fn execute_query(&self, body: &QueryRequest) -> Result<QueryResponse> {
+        let request = self.request(Method::POST, "/query").json(body);
+        Self::json(request).await
+    }
+
+    /// Find the values closest in meaning to a text
+    ///
+    /// `POST /semantic/{namespace}`; requires the `similarity_search` permission.
+    pub async fn semantic_search(&self, namespace: &str, body: &SemanticSearchRequest) -> Result<SemanticSearchResponse> {
+        let path = format!("/semantic/{}", segment(namespace));
+        let request = self.request(Method::POST, &path).json(body);
+        Self::json(request).await
+    }
+
+    /// Run a declarative query
+    ///
+    /// `POST /sql`
+    pub async fn execute_sql(&self, body: &SqlRequest) -> Result<SqlResponse> {
+        let request = self.request(Method::POST, "/sql").json(body);
+        Self::json(request).await
+    }
+}
@@ -0,0 +1,162 @@
+//! Typed client for the HTTP API
+//!
+//! The request and response types and one method per route are generated from
+//! the server's OpenAPI document into `generated.rs`; this module holds the
+//! transport they share. `/watch` streams server-sent events and is left to an
+//! SSE or WebSocket client.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use liath::client::{Client, KvPutRequest};
+//!
+//! let client = Client::new("http://localhost:8080").with_api_key("lk_...");
+//! client.kv_put("docs", "greeting", &KvPutRequest { value: "hello".into() }).await?;
+//! let value = client.kv_get("docs", "greeting").await?.value;
+//! # Ok(())
+//! # }
+//! ```
+
+mod generated;
+mod remote;
+
+pub use generated::*;
+pub use remote::RemoteExecutor;
+
+use anyhow::Result;
+use reqwest::{header, Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A Liath server reached over HTTP
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+/// A request the server answered with an error status
+///
+/// Returned inside the `anyhow::Error` of a failed call; downcast to inspect the status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+    /// Seconds to wait before retrying, from the `Retry-After` header of a 429 or 503
+    pub retry_after: Option<u64>,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (HTTP {})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl Client {
+    /// A client for the server at `base_url`, such as `http://localhost:8080`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Send `api_key` as the bearer token of every request
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Use a configured `reqwest` client, for timeouts or proxies
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    async fn send(request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        #[derive(Deserialize)]
+        struct ErrorBody {
+            message: String,
+        }
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorBody>(&body).map_or(body, |error| error.message);
+        Err(ApiError { status: status.as_u16(), message, retry_after }.into())
+    }
+
+    async fn json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    async fn text(request: RequestBuilder) -> Result<String> {
+        Ok(Self::send(request).await?.text().await?)
+    }
+
+    /// Read a response of one JSON value per line
+    async fn ndjson<T: DeserializeOwned>(request: RequestBuilder) -> Result<Vec<T>> {
+        let body = Self::text(request).await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+}
+
+/// A request body of one JSON value per line
+fn ndjson_body<T: Serialize>(request: RequestBuilder, records: &[T]) -> Result<RequestBuilder> {
+    let mut body = String::new();
+    for record in records {
+        body.push_str(&serde_json::to_string(record)?);
+        body.push('\n');
+    }
+    Ok(request.header(header::CONTENT_TYPE, "application/x-ndjson").body(body))
+}
+
+/// Percent-encode a value for use as one path segment
+fn segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_encoding() {
+        assert_eq!(segment("docs"), "docs");
+        assert_eq!(segment("a/b c?"), "a%2Fb%20c%3F");
+        assert_eq!(segment("ü"), "%C3%BC");
+    }
+}
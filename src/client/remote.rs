@@ -0,0 +1,107 @@
+//! `QueryExecutor`'s operations against a running server
+//!
+//! The consoles use this to work on a live `liath server` instead of opening the
+//! data directory, which the server holds. Requests run as the owner of the API
+//! key, so unlike `QueryExecutor` no operation takes a user id.
+
+use anyhow::{anyhow, Result};
+
+use super::{Client, CreateNamespaceRequest, KvPutRequest, QueryRequest, SqlRequest, SuccessResponse};
+use crate::query::{Profile, QueryResult};
+
+/// Prefix the server puts on the result of a script that failed
+const SCRIPT_ERROR: &str = "Error: ";
+
+/// A Liath server used through the operations of `QueryExecutor`
+#[derive(Clone)]
+pub struct RemoteExecutor {
+    client: Client,
+}
+
+impl RemoteExecutor {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Execute Lua code on the server
+    pub async fn execute(&self, query: &str) -> Result<String> {
+        let request = QueryRequest { query: query.to_string(), explain: None };
+        script_result(self.client.execute_query(&request).await?.result)
+    }
+
+    /// Execute Lua code on the server and profile the database functions it calls
+    pub async fn execute_profiled(&self, query: &str) -> Result<(String, Profile)> {
+        let request = QueryRequest { query: query.to_string(), explain: Some(true) };
+        let response = self.client.execute_query(&request).await?;
+        let result = script_result(response.result)?;
+        let profile = response.profile.ok_or_else(|| anyhow!("The server returned no profile"))?;
+        Ok((result, serde_json::from_value(serde_json::to_value(profile)?)?))
+    }
+
+    /// Run a declarative query
+    pub async fn query(&self, query: &str) -> Result<QueryResult> {
+        let response = self.client.execute_sql(&SqlRequest { query: query.to_string() }).await?;
+        if !response.success {
+            return Err(anyhow!(response.error.unwrap_or_default()));
+        }
+        Ok(QueryResult { columns: response.columns, rows: response.rows })
+    }
+
+    pub async fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.client.list_namespaces().await?.namespaces)
+    }
+
+    /// Create a namespace; `metric` is `cosine` or `euclidean`
+    pub async fn create_namespace(&self, name: &str, dimensions: usize, metric: &str) -> Result<()> {
+        let request = CreateNamespaceRequest {
+            name: name.to_string(),
+            dimensions: Some(dimensions as u64),
+            metric: Some(metric.to_string()),
+            encrypted: None,
+        };
+        succeeded(self.client.create_namespace(&request).await?)
+    }
+
+    pub async fn delete_namespace(&self, name: &str) -> Result<()> {
+        succeeded(self.client.delete_namespace(name).await?)
+    }
+
+    pub async fn put(&self, namespace: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        let request = KvPutRequest { value: utf8(value, "Values")?.to_string() };
+        succeeded(self.client.kv_put(namespace, utf8(key, "Keys")?, &request).await?)
+    }
+
+    pub async fn get(&self, namespace: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let response = self.client.kv_get(namespace, utf8(key, "Keys")?).await?;
+        Ok(response.value.map(String::into_bytes))
+    }
+
+    pub async fn delete(&self, namespace: &str, key: &[u8]) -> Result<()> {
+        succeeded(self.client.kv_delete(namespace, utf8(key, "Keys")?).await?)
+    }
+}
+
+/// The server answers a failed script with its error as the result
+fn script_result(result: String) -> Result<String> {
+    match result.strip_prefix(SCRIPT_ERROR) {
+        Some(error) => Err(anyhow!(error.to_string())),
+        None => Ok(result),
+    }
+}
+
+fn succeeded(response: SuccessResponse) -> Result<()> {
+    if response.success {
+        Ok(())
+    } else {
+        Err(anyhow!(response.message))
+    }
+}
+
+/// Keys and values travel as JSON strings
+fn utf8<'a>(bytes: &'a [u8], what: &str) -> Result<&'a str> {
+    std::str::from_utf8(bytes).map_err(|_| anyhow!("{} sent to a server must be UTF-8", what))
+}
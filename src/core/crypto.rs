@@ -0,0 +1,270 @@
+//! Authenticated encryption of stored values, vector indexes and files
+//!
+//! Data is sealed with AES-256-GCM. Every ciphertext starts with a header
+//! naming the key it was sealed with, so a [`KeyRing`] can hold old keys for
+//! reading while new writes use the active (highest-numbered) key; rotating
+//! re-seals everything under the active key, after which old keys can be
+//! dropped.
+//!
+//! Layout: `"LE"`, format version, key id (u32, big endian), 96-bit nonce,
+//! ciphertext and tag. Associated data binds each ciphertext to where it is
+//! stored, so values cannot be swapped between keys or namespaces unnoticed.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::rand_core::RngCore;
+use anyhow::{Result, Context};
+
+/// Length of an encryption key in bytes
+pub const KEY_LEN: usize = 32;
+
+const MAGIC: &[u8] = b"LE";
+const FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + NONCE_LEN;
+
+/// Encryption settings
+#[derive(Clone, Default)]
+pub struct EncryptionConfig {
+    /// Keys given directly, as `(id, key)`
+    pub keys: Vec<(u32, [u8; KEY_LEN])>,
+    /// File with one `<id> <hex key>` line per key, read in addition to `keys`
+    pub key_file: Option<PathBuf>,
+    /// Name patterns (`agent_*`) of namespaces that are encrypted when created
+    pub namespaces: Vec<String>,
+    /// Encrypt blobs written by file storage
+    pub encrypt_files: bool,
+}
+
+impl fmt::Debug for EncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key_ids: Vec<u32> = self.keys.iter().map(|(id, _)| *id).collect();
+        f.debug_struct("EncryptionConfig")
+            .field("keys", &key_ids)
+            .field("key_file", &self.key_file)
+            .field("namespaces", &self.namespaces)
+            .field("encrypt_files", &self.encrypt_files)
+            .finish()
+    }
+}
+
+impl EncryptionConfig {
+    /// Build the key ring, or `None` when no keys are configured
+    pub fn keyring(&self) -> Result<Option<Arc<KeyRing>>> {
+        let mut keys = self.keys.clone();
+        if let Some(path) = &self.key_file {
+            keys.extend(read_key_file(path)?);
+        }
+        if keys.is_empty() {
+            if !self.namespaces.is_empty() || self.encrypt_files {
+                anyhow::bail!("Encryption is requested but no encryption keys are configured");
+            }
+            return Ok(None);
+        }
+        Ok(Some(Arc::new(KeyRing::new(keys)?)))
+    }
+}
+
+/// Encryption keys by id; the highest id seals new data
+pub struct KeyRing {
+    ciphers: BTreeMap<u32, Aes256Gcm>,
+}
+
+impl KeyRing {
+    pub fn new(keys: Vec<(u32, [u8; KEY_LEN])>) -> Result<Self> {
+        let mut ciphers = BTreeMap::new();
+        for (id, key) in keys {
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+            if ciphers.insert(id, cipher).is_some() {
+                anyhow::bail!("Encryption key {} is defined twice", id);
+            }
+        }
+        if ciphers.is_empty() {
+            anyhow::bail!("A key ring needs at least one key");
+        }
+        Ok(Self { ciphers })
+    }
+
+    /// A new random key
+    pub fn generate_key() -> [u8; KEY_LEN] {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        key
+    }
+
+    /// Id of the key new data is sealed with
+    pub fn active_key_id(&self) -> u32 {
+        *self.ciphers.keys().next_back().expect("key ring is never empty")
+    }
+
+    /// Ids of every key in the ring, in ascending order
+    pub fn key_ids(&self) -> Vec<u32> {
+        self.ciphers.keys().copied().collect()
+    }
+
+    /// Encrypt `plaintext` with the active key, authenticating `aad` alongside it
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let key_id = self.active_key_id();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.ciphers[&key_id]
+            .encrypt(&nonce, Payload { msg: plaintext, aad })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt data"))?;
+
+        let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.push(FORMAT_VERSION);
+        sealed.extend_from_slice(&key_id.to_be_bytes());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt data produced by [`seal`](Self::seal) with the same `aad`
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        let key_id = Self::key_id(sealed)
+            .ok_or_else(|| anyhow::anyhow!("Data is not encrypted or uses an unknown format"))?;
+        let cipher = self.ciphers.get(&key_id)
+            .ok_or_else(|| anyhow::anyhow!("Encryption key {} is not configured", key_id))?;
+        let nonce = Nonce::from_slice(&sealed[HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+        cipher
+            .decrypt(nonce, Payload { msg: &sealed[HEADER_LEN..], aad })
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt data: wrong key or tampered ciphertext"))
+    }
+
+    /// Id of the key `sealed` was encrypted with, or `None` if it is not sealed data
+    pub fn key_id(sealed: &[u8]) -> Option<u32> {
+        if sealed.len() < HEADER_LEN || !sealed.starts_with(MAGIC) || sealed[MAGIC.len()] != FORMAT_VERSION {
+            return None;
+        }
+        let id = &sealed[MAGIC.len() + 1..MAGIC.len() + 5];
+        Some(u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+    }
+
+    /// Whether `sealed` needs re-sealing to be under the active key
+    pub fn is_stale(&self, sealed: &[u8]) -> bool {
+        Self::key_id(sealed) != Some(self.active_key_id())
+    }
+}
+
+/// Seals the values of one namespace, binding each to its key
+#[derive(Clone)]
+pub struct ValueCipher {
+    keyring: Arc<KeyRing>,
+    namespace: String,
+}
+
+impl ValueCipher {
+    pub fn new(keyring: Arc<KeyRing>, namespace: &str) -> Self {
+        Self { keyring, namespace: namespace.to_string() }
+    }
+
+    fn aad(&self, key: &[u8]) -> Vec<u8> {
+        let mut aad = format!("value:{}:", self.namespace).into_bytes();
+        aad.extend_from_slice(key);
+        aad
+    }
+
+    pub fn seal(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        self.keyring.seal(&self.aad(key), value)
+    }
+
+    pub fn open(&self, key: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        self.keyring.open(&self.aad(key), sealed)
+            .with_context(|| format!("Failed to decrypt value in namespace '{}'", self.namespace))
+    }
+
+    pub fn keyring(&self) -> &KeyRing {
+        &self.keyring
+    }
+}
+
+/// Parse a key file: one `<id> <64 hex digits>` line per key, `#` starts a comment
+pub fn read_key_file(path: &Path) -> Result<Vec<(u32, [u8; KEY_LEN])>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file '{}'", path.display()))?;
+    let mut keys = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parse = || -> Option<(u32, [u8; KEY_LEN])> {
+            let (id, hex) = line.split_once(char::is_whitespace)?;
+            Some((id.parse().ok()?, parse_hex_key(hex.trim())?))
+        };
+        let key = parse().ok_or_else(|| anyhow::anyhow!(
+            "Invalid key on line {} of '{}': expected '<id> <64 hex digits>'", number + 1, path.display()
+        ))?;
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// A key-file line for `key`
+pub fn format_key_line(id: u32, key: &[u8; KEY_LEN]) -> String {
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{} {}", id, hex)
+}
+
+/// Parse a key written as 64 hex digits
+pub fn parse_hex_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_and_rotation() {
+        let old = KeyRing::new(vec![(1, [7u8; KEY_LEN])]).unwrap();
+        let sealed = old.seal(b"value:docs:k", b"secret").unwrap();
+        assert_eq!(KeyRing::key_id(&sealed), Some(1));
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+        assert_eq!(old.open(b"value:docs:k", &sealed).unwrap(), b"secret");
+
+        // Different associated data or a flipped bit fails authentication
+        assert!(old.open(b"value:docs:other", &sealed).is_err());
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(old.open(b"value:docs:k", &tampered).is_err());
+
+        // A ring with a newer key still reads old data but seals with the new key
+        let rotated = KeyRing::new(vec![(1, [7u8; KEY_LEN]), (2, KeyRing::generate_key())]).unwrap();
+        assert_eq!(rotated.active_key_id(), 2);
+        assert!(rotated.is_stale(&sealed));
+        assert_eq!(rotated.open(b"value:docs:k", &sealed).unwrap(), b"secret");
+        assert_eq!(KeyRing::key_id(&rotated.seal(b"", b"x").unwrap()), Some(2));
+
+        // Once the old key is dropped its data is unreadable
+        let newest = KeyRing::new(vec![(2, [9u8; KEY_LEN])]).unwrap();
+        assert!(newest.open(b"value:docs:k", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_key_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("keys");
+        let key = KeyRing::generate_key();
+        std::fs::write(&path, format!("# liath keys\n{}\n\n", format_key_line(3, &key))).unwrap();
+        assert_eq!(read_key_file(&path).unwrap(), vec![(3, key)]);
+
+        std::fs::write(&path, "3 not-hex\n").unwrap();
+        assert!(read_key_file(&path).is_err());
+
+        let config = EncryptionConfig { namespaces: vec!["agent_*".to_string()], ..Default::default() };
+        assert!(config.keyring().is_err());
+        assert!(EncryptionConfig::default().keyring().unwrap().is_none());
+    }
+}
@@ -1,10 +1,61 @@
 use fjall::{Config, Keyspace, PartitionHandle, PartitionCreateOptions};
+use std::cell::Cell;
+use std::ops::Bound;
 use std::path::Path;
 use anyhow::{Result, Context};
+use crate::core::crypto::ValueCipher;
+
+/// Boxed iterator over key-value pairs read from a partition
+pub type KvIter<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+thread_local! {
+    static IO_STATS: Cell<IoStats> = const { Cell::new(IoStats { bytes_read: 0, bytes_written: 0 }) };
+}
+
+/// Bytes moved through storage on the current thread
+///
+/// The counters only grow; take the difference of two snapshots to measure a piece of work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl IoStats {
+    /// Snapshot of the counters for the current thread
+    pub fn current() -> Self {
+        IO_STATS.with(|stats| stats.get())
+    }
+
+    /// Bytes moved between an earlier snapshot and this one
+    pub fn since(self, earlier: IoStats) -> Self {
+        Self {
+            bytes_read: self.bytes_read.saturating_sub(earlier.bytes_read),
+            bytes_written: self.bytes_written.saturating_sub(earlier.bytes_written),
+        }
+    }
+
+    fn add_read(bytes: usize) {
+        IO_STATS.with(|stats| {
+            let mut current = stats.get();
+            current.bytes_read += bytes as u64;
+            stats.set(current);
+        });
+    }
+
+    fn add_written(bytes: usize) {
+        IO_STATS.with(|stats| {
+            let mut current = stats.get();
+            current.bytes_written += bytes as u64;
+            stats.set(current);
+        });
+    }
+}
 
 pub struct FjallWrapper {
     keyspace: Keyspace,
     partition: PartitionHandle,
+    cipher: Option<ValueCipher>,
 }
 
 impl FjallWrapper {
@@ -20,12 +71,38 @@ impl FjallWrapper {
         Ok(Self { 
             keyspace,
             partition,
+            cipher: None,
         })
     }
 
+    /// Encrypt values on write and decrypt them on read; keys stay in plaintext
+    pub fn with_cipher(mut self, cipher: ValueCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Whether values are stored encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    fn seal(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.cipher.as_ref().map(|cipher| cipher.seal(key, value)).transpose()
+    }
+
+    fn decode(cipher: &Option<ValueCipher>, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        match cipher {
+            Some(cipher) => cipher.open(key, value),
+            None => Ok(value.to_vec()),
+        }
+    }
+
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let sealed = self.seal(key, value)?;
+        let value = sealed.as_deref().unwrap_or(value);
         self.partition.insert(key, value)
             .context("Failed to put value in DB")?;
+        IoStats::add_written(key.len() + value.len());
         Ok(())
     }
 
@@ -33,35 +110,123 @@ impl FjallWrapper {
         let res = self.partition
             .get(key)
             .context("Failed to get value from DB")?;
-        Ok(res.map(|slice| slice.to_vec()))
+        if let Some(slice) = &res {
+            IoStats::add_read(slice.len());
+        }
+        res.map(|slice| Self::decode(&self.cipher, key, &slice)).transpose()
     }
 
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         self.partition.remove(key)
             .context("Failed to delete value from DB")?;
+        IoStats::add_written(key.len());
         Ok(())
     }
 
     // Method to perform batch operations
     pub fn batch_put(&self, items: Vec<(&[u8], &[u8])>) -> Result<()> {
         let mut batch = self.keyspace.batch();
+        let mut bytes = 0;
         for (key, value) in items {
+            let sealed = self.seal(key, value)?;
+            let value = sealed.as_deref().unwrap_or(value);
+            bytes += key.len() + value.len();
             batch.insert(&self.partition, key, value);
         }
         batch.commit()
             .context("Failed to commit batch")?;
+        IoStats::add_written(bytes);
         Ok(())
     }
 
     /// Iterate over all key-value pairs in the partition
     pub fn iter(&self) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_ {
-        self.partition.iter().map(|result| {
-            result
-                .map(|(k, v)| (k.to_vec(), v.to_vec()))
-                .context("Failed to iterate over DB")
+        let cipher = self.cipher.clone();
+        self.partition.iter().map(move |result| {
+            let (k, v) = result.context("Failed to iterate over DB")?;
+            IoStats::add_read(k.len() + v.len());
+            Ok((k.to_vec(), Self::decode(&cipher, &k, &v)?))
         })
     }
 
+    /// Iterate over the key-value pairs whose key starts with `prefix`, in key order
+    pub fn scan_prefix(&self, prefix: &[u8]) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'static {
+        let cipher = self.cipher.clone();
+        self.partition.prefix(prefix.to_vec()).map(move |result| {
+            let (k, v) = result.context("Failed to scan DB prefix")?;
+            IoStats::add_read(k.len() + v.len());
+            Ok((k.to_vec(), Self::decode(&cipher, &k, &v)?))
+        })
+    }
+
+    /// The key-value pair with the greatest key starting with `prefix`
+    pub fn last_with_prefix(&self, prefix: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match self.partition.prefix(prefix.to_vec()).next_back() {
+            Some(result) => {
+                let (k, v) = result.context("Failed to scan DB prefix")?;
+                IoStats::add_read(k.len() + v.len());
+                Ok(Some((k.to_vec(), Self::decode(&self.cipher, &k, &v)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Iterate over the keys starting with `prefix` that sort after `after`, in key order
+    ///
+    /// Resumes a prefix scan from the last key already seen; with `after` unset this
+    /// is the same as [`scan_prefix`](Self::scan_prefix).
+    pub fn scan_prefix_after(
+        &self,
+        prefix: &[u8],
+        after: Option<&[u8]>,
+    ) -> KvIter<'static> {
+        let after = match after {
+            Some(after) if after >= prefix => after.to_vec(),
+            _ => return Box::new(self.scan_prefix(prefix)),
+        };
+        let prefix = prefix.to_vec();
+        let cipher = self.cipher.clone();
+        let range = (Bound::Excluded(after), Bound::Unbounded);
+        Box::new(
+            self.partition
+                .range::<Vec<u8>, _>(range)
+                .take_while(move |result| match result {
+                    Ok((k, _)) => k.starts_with(&prefix),
+                    Err(_) => true,
+                })
+                .map(move |result| {
+                    let (k, v) = result.context("Failed to scan DB prefix")?;
+                    IoStats::add_read(k.len() + v.len());
+                    Ok((k.to_vec(), Self::decode(&cipher, &k, &v)?))
+                }),
+        )
+    }
+
+    /// Re-encrypt values sealed with an older key under the active key
+    ///
+    /// Returns how many values were rewritten; does nothing for plaintext partitions.
+    pub fn reencrypt(&self) -> Result<usize> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(0);
+        };
+        let mut rewritten = 0;
+        for result in self.partition.iter() {
+            let (key, sealed) = result.context("Failed to iterate over DB")?;
+            if !cipher.keyring().is_stale(&sealed) {
+                continue;
+            }
+            let value = cipher.open(&key, &sealed)?;
+            self.put(&key, &value)?;
+            rewritten += 1;
+        }
+        Ok(rewritten)
+    }
+
+    /// Bytes the keyspace occupies on disk, journals included
+    pub fn disk_space(&self) -> u64 {
+        self.keyspace.disk_space()
+    }
+
     /// Flush all pending writes to disk
     pub fn flush(&self) -> Result<()> {
         self.keyspace.persist(fjall::PersistMode::SyncAll)
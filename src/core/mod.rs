@@ -1,7 +1,11 @@
+pub mod crypto;
 mod fjall_wrapper;
 mod namespace;
+mod trigger;
 
-pub use fjall_wrapper::FjallWrapper;
-pub use namespace::{Namespace, NamespaceManager};
+pub use crypto::{EncryptionConfig, KeyRing};
+pub use fjall_wrapper::{FjallWrapper, IoStats, KvIter};
+pub use namespace::{Namespace, NamespaceManager, NamespaceUsage};
+pub use trigger::{Trigger, TriggerEvent, TriggerTiming};
 #[cfg(not(feature = "vector"))]
 pub use namespace::{MetricKind, ScalarKind};
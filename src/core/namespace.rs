@@ -2,6 +2,8 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use crate::core::FjallWrapper;
+use crate::core::crypto::{KeyRing, ValueCipher};
+use crate::core::trigger::{Trigger, TriggerEvent, TriggerTiming};
 use crate::vector::UsearchWrapper;
 use anyhow::{Result, Context};
 use serde::{Serialize, Deserialize};
@@ -21,6 +23,11 @@ pub struct NamespaceMetadata {
     pub dimensions: usize,
     pub metric: String,
     pub scalar: String,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    /// Values and the saved vector index are encrypted
+    #[serde(default)]
+    pub encrypted: bool,
 }
 
 #[derive(Clone)]
@@ -38,15 +45,39 @@ impl Namespace {
     }
 }
 
+/// Resources held by one namespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceUsage {
+    pub name: String,
+    pub vectors: usize,
+    pub vector_capacity: usize,
+    pub disk_bytes: u64,
+}
+
 pub struct NamespaceManager {
     namespaces: Arc<RwLock<HashMap<String, Namespace>>>,
     data_dir: PathBuf,
     metadata_db: Arc<FjallWrapper>,
+    metadata: RwLock<HashMap<String, NamespaceMetadata>>,
+    keyring: Option<Arc<KeyRing>>,
+    encrypted_patterns: Vec<String>,
 }
 
 impl NamespaceManager {
     /// Create a new NamespaceManager with persistence
     pub fn new(data_dir: PathBuf) -> Result<Self> {
+        Self::with_encryption(data_dir, None, Vec::new())
+    }
+
+    /// Create a NamespaceManager that can open and create encrypted namespaces
+    ///
+    /// New namespaces whose names match one of `encrypted_patterns` are encrypted
+    /// with `keyring`; existing encrypted namespaces need their keys in it to load.
+    pub fn with_encryption(
+        data_dir: PathBuf,
+        keyring: Option<Arc<KeyRing>>,
+        encrypted_patterns: Vec<String>,
+    ) -> Result<Self> {
         std::fs::create_dir_all(&data_dir)
             .context("Failed to create data directory")?;
 
@@ -57,6 +88,9 @@ impl NamespaceManager {
             namespaces: Arc::new(RwLock::new(HashMap::new())),
             data_dir,
             metadata_db: Arc::new(metadata_db),
+            metadata: RwLock::new(HashMap::new()),
+            keyring,
+            encrypted_patterns,
         };
 
         manager.load_existing()?;
@@ -80,23 +114,24 @@ impl NamespaceManager {
             let scalar = Self::parse_scalar(&metadata.scalar)?;
 
             // Open existing Fjall database
-            let db = FjallWrapper::new(self.data_dir.join(&name))
+            let mut db = FjallWrapper::new(self.data_dir.join(&name))
                 .context(format!("Failed to open Fjall for namespace '{}'", name))?;
+            if metadata.encrypted {
+                db = db.with_cipher(ValueCipher::new(self.require_keyring(&name)?, &name));
+            }
 
             // Create vector index and try to load from disk
             let vector_db = UsearchWrapper::new(metadata.dimensions, metric, scalar)
                 .context(format!("Failed to create UsearchWrapper for namespace '{}'", name))?;
 
             // Try to load vector index if it exists
-            let vector_path = self.data_dir.join(&name).join("vectors.idx");
-            if vector_path.exists() {
-                if let Err(e) = vector_db.load(vector_path.to_str().unwrap()) {
-                    tracing::warn!("Failed to load vector index for '{}': {}", name, e);
-                }
+            if let Err(e) = self.load_index(&name, &vector_db, metadata.encrypted) {
+                tracing::warn!("Failed to load vector index for '{}': {}", name, e);
             }
 
             let mut namespaces = self.namespaces.write().unwrap();
             namespaces.insert(name.clone(), Namespace::new(db, vector_db));
+            self.metadata.write().unwrap().insert(name.clone(), metadata);
             loaded_count += 1;
             tracing::info!("Loaded namespace '{}' from disk", name);
         }
@@ -117,6 +152,44 @@ impl NamespaceManager {
         Ok(())
     }
 
+    fn require_keyring(&self, name: &str) -> Result<Arc<KeyRing>> {
+        self.keyring.clone().ok_or_else(|| anyhow::anyhow!(
+            "Namespace '{}' is encrypted but no encryption keys are configured", name
+        ))
+    }
+
+    fn index_path(&self, name: &str) -> PathBuf {
+        self.data_dir.join(name).join("vectors.idx")
+    }
+
+    /// Associated data binding an encrypted index file to its namespace
+    fn index_aad(name: &str) -> Vec<u8> {
+        format!("index:{}", name).into_bytes()
+    }
+
+    fn load_index(&self, name: &str, vector_db: &UsearchWrapper, encrypted: bool) -> Result<()> {
+        let vector_path = self.index_path(name);
+        if !vector_path.exists() {
+            return Ok(());
+        }
+        if encrypted {
+            let sealed = std::fs::read(&vector_path).context("Failed to read vector index")?;
+            let bytes = self.require_keyring(name)?.open(&Self::index_aad(name), &sealed)?;
+            return vector_db.load_bytes(&bytes);
+        }
+        vector_db.load(vector_path.to_str().unwrap())
+    }
+
+    /// Write a namespace's vector index, encrypted if the namespace is
+    fn save_index(&self, name: &str, ns: &Namespace) -> Result<()> {
+        let vector_path = self.index_path(name);
+        if ns.db.is_encrypted() {
+            let sealed = self.require_keyring(name)?.seal(&Self::index_aad(name), &ns.vector_db.to_bytes()?)?;
+            return std::fs::write(&vector_path, sealed).context("Failed to write vector index");
+        }
+        ns.vector_db.save(vector_path.to_str().unwrap())
+    }
+
     /// Delete namespace metadata from disk
     fn delete_metadata(&self, name: &str) -> Result<()> {
         self.metadata_db.delete(name.as_bytes())
@@ -162,7 +235,38 @@ impl NamespaceManager {
         }
     }
 
+    /// Reject names that are reserved or would escape the namespace's own directory
+    ///
+    /// Names starting with `_` belong to system namespaces and to stores such as
+    /// `_metadata` and `_auth` that share the data directory.
+    fn validate_name(name: &str) -> Result<()> {
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+            return Err(anyhow::anyhow!("Invalid namespace name '{}'", name));
+        }
+        if name.starts_with('_') {
+            return Err(anyhow::anyhow!("Namespace names starting with '_' are reserved: '{}'", name));
+        }
+        Ok(())
+    }
+
+    /// Create a namespace, encrypted if its name matches a configured pattern
     pub fn create_namespace(&self, name: &str, dimensions: usize, metric: MetricKind, scalar: ScalarKind) -> Result<()> {
+        Self::validate_name(name)?;
+        self.create(name, dimensions, metric, scalar, self.matches_encrypted_pattern(name))
+    }
+
+    /// Create a namespace whose values and saved vector index are encrypted
+    pub fn create_encrypted_namespace(&self, name: &str, dimensions: usize, metric: MetricKind, scalar: ScalarKind) -> Result<()> {
+        Self::validate_name(name)?;
+        self.create(name, dimensions, metric, scalar, true)
+    }
+
+    fn matches_encrypted_pattern(&self, name: &str) -> bool {
+        self.encrypted_patterns.iter().any(|pattern| crate::auth::glob_match(pattern, name))
+    }
+
+    fn create(&self, name: &str, dimensions: usize, metric: MetricKind, scalar: ScalarKind, encrypted: bool) -> Result<()> {
+        let keyring = if encrypted { Some(self.require_keyring(name)?) } else { None };
         let mut namespaces = self.namespaces.write().unwrap();
         if namespaces.contains_key(name) {
             return Err(anyhow::anyhow!("Namespace '{}' already exists", name));
@@ -173,8 +277,11 @@ impl NamespaceManager {
         std::fs::create_dir_all(&ns_dir)
             .context(format!("Failed to create namespace directory '{}'", name))?;
 
-        let db = FjallWrapper::new(&ns_dir)
+        let mut db = FjallWrapper::new(&ns_dir)
             .context(format!("Failed to create Fjall for namespace '{}'", name))?;
+        if let Some(keyring) = keyring {
+            db = db.with_cipher(ValueCipher::new(keyring, name));
+        }
         let vector_db = UsearchWrapper::new(dimensions, metric, scalar)
             .context(format!("Failed to create UsearchWrapper for namespace '{}'", name))?;
 
@@ -184,11 +291,14 @@ impl NamespaceManager {
             dimensions,
             metric: Self::metric_to_string(metric).to_string(),
             scalar: Self::scalar_to_string(scalar).to_string(),
+            triggers: Vec::new(),
+            encrypted,
         };
         self.persist_metadata(name, &metadata)?;
+        self.metadata.write().unwrap().insert(name.to_string(), metadata);
 
         namespaces.insert(name.to_string(), Namespace::new(db, vector_db));
-        tracing::info!("Created namespace '{}' with {} dimensions", name, dimensions);
+        tracing::info!("Created {}namespace '{}' with {} dimensions", if encrypted { "encrypted " } else { "" }, name, dimensions);
         Ok(())
     }
 
@@ -199,13 +309,29 @@ impl NamespaceManager {
             .ok_or_else(|| anyhow::anyhow!("Namespace '{}' not found", name))
     }
 
+    /// Get a system namespace (KV only, no meaningful vector index), creating it on first use
+    pub fn system_namespace(&self, name: &str) -> Result<Namespace> {
+        if let Ok(ns) = self.get_namespace(name) {
+            return Ok(ns);
+        }
+        if let Err(e) = self.create(name, 1, MetricKind::Cos, ScalarKind::F32, self.matches_encrypted_pattern(name)) {
+            // Another caller may have created it concurrently
+            if !self.namespace_exists(name) {
+                return Err(e);
+            }
+        }
+        self.get_namespace(name)
+    }
+
     pub fn delete_namespace(&self, name: &str) -> Result<()> {
+        Self::validate_name(name)?;
         let mut namespaces = self.namespaces.write().unwrap();
         namespaces.remove(name)
             .ok_or_else(|| anyhow::anyhow!("Namespace '{}' not found", name))?;
 
         // Delete metadata
         self.delete_metadata(name)?;
+        self.metadata.write().unwrap().remove(name);
 
         // Delete namespace directory
         let ns_dir = self.data_dir.join(name);
@@ -218,22 +344,104 @@ impl NamespaceManager {
         Ok(())
     }
 
+    /// Triggers attached to a namespace
+    pub fn triggers(&self, name: &str) -> Vec<Trigger> {
+        self.metadata.read().unwrap()
+            .get(name)
+            .map(|m| m.triggers.clone())
+            .unwrap_or_default()
+    }
+
+    /// Triggers of a namespace that fire for the given event and timing
+    pub fn matching_triggers(&self, name: &str, event: TriggerEvent, timing: TriggerTiming) -> Vec<Trigger> {
+        self.metadata.read().unwrap()
+            .get(name)
+            .map(|m| m.triggers.iter().filter(|t| t.matches(event, timing)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Attach a trigger to a namespace, replacing any trigger with the same name
+    pub fn set_trigger(&self, name: &str, trigger: Trigger) -> Result<()> {
+        let mut metadata = self.metadata.write().unwrap();
+        let entry = metadata.get_mut(name)
+            .ok_or_else(|| anyhow::anyhow!("Namespace '{}' not found", name))?;
+        let mut updated = entry.clone();
+        updated.triggers.retain(|t| t.name != trigger.name);
+        updated.triggers.push(trigger);
+        self.persist_metadata(name, &updated)?;
+        *entry = updated;
+        Ok(())
+    }
+
+    /// Remove a trigger from a namespace
+    pub fn remove_trigger(&self, name: &str, trigger_name: &str) -> Result<()> {
+        let mut metadata = self.metadata.write().unwrap();
+        let entry = metadata.get_mut(name)
+            .ok_or_else(|| anyhow::anyhow!("Namespace '{}' not found", name))?;
+        if !entry.triggers.iter().any(|t| t.name == trigger_name) {
+            return Err(anyhow::anyhow!("Trigger '{}' not found on namespace '{}'", trigger_name, name));
+        }
+        let mut updated = entry.clone();
+        updated.triggers.retain(|t| t.name != trigger_name);
+        self.persist_metadata(name, &updated)?;
+        *entry = updated;
+        Ok(())
+    }
+
     pub fn list_namespaces(&self) -> Vec<String> {
         let namespaces = self.namespaces.read().unwrap();
         namespaces.keys().cloned().collect()
     }
 
+    /// Vector index and disk usage of every namespace, sorted by name
+    pub fn usage(&self) -> Vec<NamespaceUsage> {
+        let namespaces = self.namespaces.read().unwrap();
+        let mut usage: Vec<NamespaceUsage> = namespaces
+            .iter()
+            .map(|(name, ns)| NamespaceUsage {
+                name: name.clone(),
+                vectors: ns.vector_db.size(),
+                vector_capacity: ns.vector_db.capacity(),
+                disk_bytes: ns.db.disk_space(),
+            })
+            .collect();
+        usage.sort_by(|a, b| a.name.cmp(&b.name));
+        usage
+    }
+
     pub fn namespace_exists(&self, name: &str) -> bool {
         let namespaces = self.namespaces.read().unwrap();
         namespaces.contains_key(name)
     }
 
+    /// Whether a namespace stores its data encrypted
+    pub fn is_encrypted(&self, name: &str) -> bool {
+        self.metadata.read().unwrap().get(name).is_some_and(|m| m.encrypted)
+    }
+
+    /// Re-encrypt every encrypted namespace under the active key
+    ///
+    /// Rewrites values sealed with older keys and re-saves the vector indexes;
+    /// returns how many values were rewritten. Afterwards the older keys can
+    /// be removed from the configuration.
+    pub fn rotate_keys(&self) -> Result<usize> {
+        let namespaces = self.namespaces.read().unwrap();
+        let mut rewritten = 0;
+        for (name, ns) in namespaces.iter().filter(|(_, ns)| ns.db.is_encrypted()) {
+            rewritten += ns.db.reencrypt()
+                .context(format!("Failed to re-encrypt namespace '{}'", name))?;
+            self.save_index(name, ns)
+                .context(format!("Failed to save vector index for namespace '{}'", name))?;
+            ns.db.flush()?;
+        }
+        Ok(rewritten)
+    }
+
     /// Save all vector indices to disk
     pub fn save_all(&self) -> Result<()> {
         let namespaces = self.namespaces.read().unwrap();
         for (name, ns) in namespaces.iter() {
-            let vector_path = self.data_dir.join(name).join("vectors.idx");
-            ns.vector_db.save(vector_path.to_str().unwrap())
+            self.save_index(name, ns)
                 .context(format!("Failed to save vector index for namespace '{}'", name))?;
         }
         self.metadata_db.flush()?;
@@ -245,8 +453,7 @@ impl NamespaceManager {
     pub fn save_namespace(&self, name: &str) -> Result<()> {
         let namespaces = self.namespaces.read().unwrap();
         if let Some(ns) = namespaces.get(name) {
-            let vector_path = self.data_dir.join(name).join("vectors.idx");
-            ns.vector_db.save(vector_path.to_str().unwrap())
+            self.save_index(name, ns)
                 .context(format!("Failed to save vector index for namespace '{}'", name))?;
         }
         Ok(())
@@ -311,4 +518,90 @@ mod tests {
             assert_eq!(ns.vector_db.dimensions(), 256);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_namespace_triggers_persist() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_path = temp_dir.path().to_path_buf();
+        let trigger = Trigger {
+            name: "audit".to_string(),
+            event: TriggerEvent::Put,
+            timing: TriggerTiming::After,
+            code: "return true".to_string(),
+            owner: "admin".to_string(),
+        };
+
+        {
+            let manager = NamespaceManager::new(data_path.clone()).unwrap();
+            manager.create_namespace("events", 8, MetricKind::Cos, ScalarKind::F32).unwrap();
+            manager.set_trigger("events", trigger.clone()).unwrap();
+            assert!(manager.set_trigger("missing", trigger.clone()).is_err());
+        }
+
+        {
+            let manager = NamespaceManager::new(data_path).unwrap();
+            assert_eq!(manager.triggers("events"), vec![trigger]);
+            assert_eq!(manager.matching_triggers("events", TriggerEvent::Put, TriggerTiming::After).len(), 1);
+            assert!(manager.matching_triggers("events", TriggerEvent::Delete, TriggerTiming::After).is_empty());
+            manager.remove_trigger("events", "audit").unwrap();
+            assert!(manager.triggers("events").is_empty());
+            assert!(manager.remove_trigger("events", "audit").is_err());
+        }
+    }
+
+    fn contains_on_disk(dir: &std::path::Path, needle: &[u8]) -> bool {
+        std::fs::read_dir(dir).unwrap().any(|entry| {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                return contains_on_disk(&path, needle);
+            }
+            std::fs::read(&path).unwrap().windows(needle.len()).any(|w| w == needle)
+        })
+    }
+
+    #[test]
+    fn test_encrypted_namespace() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_path = temp_dir.path().to_path_buf();
+        let old_key = (1, KeyRing::generate_key());
+        let keyring = |keys: Vec<(u32, [u8; 32])>| Some(Arc::new(KeyRing::new(keys).unwrap()));
+        let patterns = vec!["agent_*".to_string()];
+
+        {
+            let manager = NamespaceManager::with_encryption(data_path.clone(), keyring(vec![old_key]), patterns.clone()).unwrap();
+            manager.create_namespace("agent_1_memory", 2, MetricKind::Cos, ScalarKind::F32).unwrap();
+            manager.create_namespace("public", 2, MetricKind::Cos, ScalarKind::F32).unwrap();
+            assert!(manager.is_encrypted("agent_1_memory"));
+            assert!(!manager.is_encrypted("public"));
+
+            let ns = manager.get_namespace("agent_1_memory").unwrap();
+            ns.db.put(b"k1", b"patient-secret").unwrap();
+            ns.db.batch_put(vec![(b"k2".as_slice(), b"other-secret".as_slice())]).unwrap();
+            ns.vector_db.reserve(2).unwrap();
+            ns.vector_db.add(1, &[1.0, 0.0]).unwrap();
+            assert_eq!(ns.db.get(b"k1").unwrap().unwrap(), b"patient-secret");
+            assert_eq!(ns.db.scan_prefix(b"k").count(), 2);
+            manager.save_all().unwrap();
+            ns.db.flush().unwrap();
+        }
+        assert!(!contains_on_disk(&data_path, b"patient-secret"));
+        assert!(!contains_on_disk(&data_path, b"other-secret"));
+
+        // Without keys an encrypted namespace refuses to open
+        assert!(NamespaceManager::new(data_path.clone()).is_err());
+
+        // After rotation the old key is no longer needed
+        let new_key = (2, KeyRing::generate_key());
+        {
+            let manager = NamespaceManager::with_encryption(data_path.clone(), keyring(vec![old_key, new_key]), patterns.clone()).unwrap();
+            assert_eq!(manager.rotate_keys().unwrap(), 2);
+            assert_eq!(manager.rotate_keys().unwrap(), 0);
+        }
+        {
+            let manager = NamespaceManager::with_encryption(data_path, keyring(vec![new_key]), patterns).unwrap();
+            let ns = manager.get_namespace("agent_1_memory").unwrap();
+            assert_eq!(ns.db.get(b"k2").unwrap().unwrap(), b"other-secret");
+            assert_eq!(ns.vector_db.search(&[1.0, 0.0], 1).unwrap()[0].0, 1);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Namespace triggers: Lua hooks that fire around writes
+//!
+//! Trigger definitions are persisted with the namespace metadata; they are
+//! executed by the `QueryExecutor`, which owns the Lua VM.
+
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Write operation a trigger listens to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerEvent {
+    Put,
+    Delete,
+    StoreDocument,
+}
+
+/// Whether a trigger runs before or after the write
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerTiming {
+    Before,
+    After,
+}
+
+/// A Lua hook attached to a namespace
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trigger {
+    pub name: String,
+    pub event: TriggerEvent,
+    pub timing: TriggerTiming,
+    pub code: String,
+    /// User that created the trigger; the body runs with their permissions
+    pub owner: String,
+}
+
+impl Trigger {
+    /// Whether this trigger fires for the given event and timing
+    pub fn matches(&self, event: TriggerEvent, timing: TriggerTiming) -> bool {
+        self.event == event && self.timing == timing
+    }
+}
+
+impl fmt::Display for TriggerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerEvent::Put => write!(f, "put"),
+            TriggerEvent::Delete => write!(f, "delete"),
+            TriggerEvent::StoreDocument => write!(f, "store_document"),
+        }
+    }
+}
+
+impl FromStr for TriggerEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "put" | "insert" | "update" => Ok(TriggerEvent::Put),
+            "delete" => Ok(TriggerEvent::Delete),
+            "store_document" => Ok(TriggerEvent::StoreDocument),
+            _ => Err(anyhow::anyhow!("Unknown trigger event '{}': use put, delete or store_document", s)),
+        }
+    }
+}
+
+impl fmt::Display for TriggerTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerTiming::Before => write!(f, "before"),
+            TriggerTiming::After => write!(f, "after"),
+        }
+    }
+}
+
+impl FromStr for TriggerTiming {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "before" => Ok(TriggerTiming::Before),
+            "after" => Ok(TriggerTiming::After),
+            _ => Err(anyhow::anyhow!("Unknown trigger timing '{}': use before or after", s)),
+        }
+    }
+}
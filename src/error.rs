@@ -56,6 +56,10 @@ pub enum LiathError {
     /// Invalid input
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    /// Query language syntax or planning error
+    #[error("Query error: {0}")]
+    Query(String),
 }
 
 impl From<serde_json::Error> for LiathError {
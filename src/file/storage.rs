@@ -1,29 +1,53 @@
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::Arc;
 use anyhow::Result;
 use uuid::Uuid;
+use crate::core::KeyRing;
 
 pub struct FileStorage {
     base_path: PathBuf,
+    keyring: Option<Arc<KeyRing>>,
 }
 
 impl FileStorage {
     pub fn new<P: AsRef<Path>>(base_path: P) -> Result<Self> {
         let base_path = base_path.as_ref().to_path_buf();
         fs::create_dir_all(&base_path)?;
-        Ok(Self { base_path })
+        Ok(Self { base_path, keyring: None })
+    }
+
+    /// Encrypt stored files with `keyring`
+    ///
+    /// Files stored before encryption was enabled can't be read until
+    /// [`reencrypt`](Self::reencrypt) has sealed them.
+    pub fn with_keyring(mut self, keyring: Arc<KeyRing>) -> Self {
+        self.keyring = Some(keyring);
+        self
+    }
+
+    /// Associated data binding an encrypted file to its id
+    fn aad(file_id: &str) -> Vec<u8> {
+        format!("file:{}", file_id).into_bytes()
     }
 
     pub fn store(&self, content: &[u8]) -> Result<String> {
         let file_id = Uuid::new_v4().to_string();
         let file_path = self.base_path.join(&file_id);
-        fs::write(file_path, content)?;
+        match &self.keyring {
+            Some(keyring) => fs::write(file_path, keyring.seal(&Self::aad(&file_id), content)?)?,
+            None => fs::write(file_path, content)?,
+        }
         Ok(file_id)
     }
 
     pub fn retrieve(&self, file_id: &str) -> Result<Vec<u8>> {
         let file_path = self.base_path.join(file_id);
-        Ok(fs::read(file_path)?)
+        let content = fs::read(file_path)?;
+        match &self.keyring {
+            Some(keyring) => keyring.open(&Self::aad(file_id), &content),
+            None => Ok(content),
+        }
     }
 
     pub fn delete(&self, file_id: &str) -> Result<()> {
@@ -31,4 +55,32 @@ impl FileStorage {
         fs::remove_file(file_path)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Seal every file under the active key, including files stored in plaintext
+    ///
+    /// Returns how many files were rewritten; does nothing without a key ring.
+    pub fn reencrypt(&self) -> Result<usize> {
+        let Some(keyring) = &self.keyring else {
+            return Ok(0);
+        };
+        let mut rewritten = 0;
+        for entry in fs::read_dir(&self.base_path)? {
+            let path = entry?.path();
+            let Some(file_id) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let content = fs::read(&path)?;
+            if !keyring.is_stale(&content) {
+                continue;
+            }
+            let aad = Self::aad(file_id);
+            let plaintext = match KeyRing::key_id(&content) {
+                Some(_) => keyring.open(&aad, &content)?,
+                None => content,
+            };
+            fs::write(&path, keyring.seal(&aad, &plaintext)?)?;
+            rewritten += 1;
+        }
+        Ok(rewritten)
+    }
+}
@@ -80,7 +80,11 @@ pub mod query;
 pub mod auth;
 pub mod cli;
 pub mod agent;
+pub mod scheduler;
 pub mod error;
+pub mod metrics;
+#[cfg(feature = "client")]
+pub mod client;
 #[cfg(feature = "server")]
 pub mod server;
 #[cfg(feature = "mcp")]
@@ -89,14 +93,21 @@ pub mod mcp;
 pub mod python;
 
 // Re-export key types
-pub use crate::core::{FjallWrapper, NamespaceManager};
+pub use crate::core::{EncryptionConfig, FjallWrapper, KeyRing, NamespaceManager, Trigger, TriggerEvent, TriggerTiming};
 pub use crate::vector::UsearchWrapper;
 pub use crate::ai::EmbeddingWrapper;
 pub use crate::lua::LuaVM;
 pub use crate::file::FileStorage;
 pub use crate::query::executor::QueryExecutor;
-pub use crate::auth::AuthManager;
+pub use crate::query::procedures::Procedure;
+pub use crate::query::modules::LuaModule;
+pub use crate::query::planner::QueryResult;
+pub use crate::query::profile::Profile;
+pub use crate::query::audit::{AuditConfig, AuditEntry, AuditFilter, AuditOutcome};
+pub use crate::query::changes::{Change, ChangeFilter, ChangeKind, Subscription};
+pub use crate::auth::{ApiKeyInfo, AuthManager, Grant, LimitExceeded, Limits, Resource, RoleInfo, Usage, UserInfo};
 pub use crate::agent::Agent;
+pub use crate::scheduler::{Scheduler, SchedulerHandle};
 pub use crate::error::{LiathError, LiathResult};
 
 use anyhow::Result;
@@ -107,6 +118,16 @@ use std::path::PathBuf;
 pub struct Config {
     pub data_dir: PathBuf,
     pub luarocks_path: Option<PathBuf>,
+    /// User the embedded API acts as; created with every permission when the
+    /// auth store has no users yet, and warned about at startup if it is missing
+    /// or lacks `*`
+    pub admin_user: String,
+    /// What the audit log records and how long it keeps entries
+    pub audit: AuditConfig,
+    /// Keys and the namespaces and files to encrypt at rest
+    pub encryption: EncryptionConfig,
+    /// Rate limit and daily quotas of users without their own
+    pub limits: Limits,
 }
 
 impl Default for Config {
@@ -114,6 +135,10 @@ impl Default for Config {
         Self {
             data_dir: PathBuf::from("./data"),
             luarocks_path: None,
+            admin_user: "admin".to_string(),
+            audit: AuditConfig::default(),
+            encryption: EncryptionConfig::default(),
+            limits: Limits::default(),
         }
     }
 }
@@ -123,33 +148,45 @@ impl Default for Config {
 /// This struct provides a simplified interface for embedding Liath directly into Rust applications.
 pub struct EmbeddedLiath {
     query_executor: QueryExecutor,
+    scheduler: Scheduler,
     current_namespace: String,
+    admin_user: String,
 }
 
 impl EmbeddedLiath {
     /// Create a new embedded Liath instance
     pub fn new(config: Config) -> Result<Self> {
         std::fs::create_dir_all(&config.data_dir)?;
-        let namespace_manager = NamespaceManager::new(config.data_dir.clone())?;
+        let keyring = config.encryption.keyring()?;
+        let namespace_manager = NamespaceManager::with_encryption(
+            config.data_dir.clone(),
+            keyring.clone(),
+            config.encryption.namespaces.clone(),
+        )?;
         let embedding = EmbeddingWrapper::new()?;
         let lua_vm = LuaVM::new(config.luarocks_path.clone().unwrap_or_else(|| std::path::PathBuf::from("luarocks")))?; // Uses `luarocks` from PATH by default
         let file_storage_path = config.data_dir.join("files");
-        let file_storage = FileStorage::new(file_storage_path)?;
-        let mut auth_manager = AuthManager::new();
-
-        // Add a default admin user
-        auth_manager.add_user("admin", vec![
-            "select".to_string(),
-            "insert".to_string(),
-            "update".to_string(),
-            "delete".to_string(),
-            "create_namespace".to_string(),
-            "delete_namespace".to_string(),
-            "upload_file".to_string(),
-            "process_file".to_string(),
-            "generate_embedding".to_string(),
-            "similarity_search".to_string(),
-        ]);
+        let mut file_storage = FileStorage::new(file_storage_path)?;
+        if let Some(keyring) = keyring.filter(|_| config.encryption.encrypt_files) {
+            file_storage = file_storage.with_keyring(keyring);
+        }
+        let mut auth_manager = AuthManager::with_persistence(&config.data_dir)?;
+        auth_manager.set_default_limits(config.limits.clone())?;
+
+        if auth_manager.bootstrap_admin(&config.admin_user)? {
+            tracing::info!("Created admin user '{}'", config.admin_user);
+        } else if !auth_manager.has_user(&config.admin_user) {
+            tracing::warn!(
+                "Admin user '{}' does not exist, so the embedded API will be denied everything; \
+                 it is only created when the data directory has no users yet",
+                config.admin_user
+            );
+        } else if !auth_manager.is_admin(&config.admin_user) {
+            tracing::warn!(
+                "Admin user '{}' does not hold the '*' permission, so the embedded API may be denied",
+                config.admin_user
+            );
+        }
 
         let query_executor = QueryExecutor::new(
             namespace_manager,
@@ -158,18 +195,21 @@ impl EmbeddedLiath {
             file_storage,
             auth_manager,
             10, // max_concurrent_embedding
-        );
+        )
+        .with_audit_config(config.audit.clone());
 
         Ok(Self {
+            scheduler: Scheduler::new(query_executor.clone()),
             query_executor,
             current_namespace: String::from("default"),
+            admin_user: config.admin_user,
         })
     }
 
     /// Execute a Lua query and return the result as JSON
-    /// Uses "admin" user for authorization
+    /// Uses the admin user for authorization
     pub async fn execute_lua(&self, query: &str) -> Result<serde_json::Value> {
-        self.execute_lua_as(query, "admin").await
+        self.execute_lua_as(query, &self.admin_user).await
     }
 
     /// Execute a Lua query as a specific user and return the result as JSON
@@ -182,6 +222,111 @@ impl EmbeddedLiath {
         }
     }
 
+    /// Run a declarative query (`SELECT ... FROM ...`) as the admin user
+    pub fn query(&self, query: &str) -> Result<QueryResult> {
+        self.query_executor.query(query, &self.admin_user)
+    }
+
+    /// Register a new version of a stored procedure as the admin user
+    pub fn register_procedure(&self, name: &str, code: &str, description: Option<&str>) -> Result<Procedure> {
+        self.query_executor.register_procedure(name, code, description, &self.admin_user)
+    }
+
+    /// Call a stored procedure as the admin user
+    pub async fn call_procedure(&self, name: &str, args: serde_json::Value) -> Result<serde_json::Value> {
+        self.query_executor.call_procedure(name, args, &self.admin_user).await
+    }
+
+    /// Store a new version of a Lua module as the admin user
+    pub fn put_module(&self, name: &str, code: &str, description: Option<&str>) -> Result<LuaModule> {
+        self.query_executor.put_module(name, code, description, &self.admin_user)
+    }
+
+    /// Attach a Lua trigger to a namespace as the admin user
+    pub fn create_trigger(
+        &self,
+        namespace: &str,
+        name: &str,
+        event: TriggerEvent,
+        timing: TriggerTiming,
+        code: &str,
+    ) -> Result<()> {
+        self.query_executor.create_trigger(namespace, name, event, timing, code, &self.admin_user)
+    }
+
+    // ========== Users and Permissions ==========
+
+    /// Create a user, or replace the permissions of an existing one
+    ///
+    /// Permissions may be scoped to namespaces (`select@docs_*`) or denied (`!delete`).
+    pub fn add_user(&self, user_id: &str, permissions: Vec<String>) -> Result<()> {
+        self.query_executor.add_user(user_id, permissions, &self.admin_user)
+    }
+
+    /// Delete a user and revoke their API keys
+    pub fn remove_user(&self, user_id: &str) -> Result<()> {
+        self.query_executor.remove_user(user_id, &self.admin_user)
+    }
+
+    /// All users with their permissions and roles
+    pub fn list_users(&self) -> Result<Vec<UserInfo>> {
+        self.query_executor.list_users(&self.admin_user)
+    }
+
+    /// Grant a permission to a user
+    pub fn grant(&self, user_id: &str, permission: &str) -> Result<()> {
+        self.query_executor.grant_permission(user_id, permission, &self.admin_user)
+    }
+
+    /// Revoke a permission granted directly to a user
+    ///
+    /// Grants the user holds through a role are unaffected.
+    pub fn revoke(&self, user_id: &str, permission: &str) -> Result<()> {
+        self.query_executor.revoke_permission(user_id, permission, &self.admin_user)
+    }
+
+    /// Create or replace a role
+    pub fn define_role(&self, name: &str, grants: Vec<String>) -> Result<()> {
+        self.query_executor.define_role(name, grants, &self.admin_user)
+    }
+
+    /// Give a user the grants of a role
+    pub fn assign_role(&self, user_id: &str, role: &str) -> Result<()> {
+        self.query_executor.assign_role(user_id, role, &self.admin_user)
+    }
+
+    /// Give a user their own rate limit and quotas, or with `None` the defaults
+    pub fn set_limits(&self, user_id: &str, limits: Option<Limits>) -> Result<()> {
+        self.query_executor.set_limits(user_id, limits, &self.admin_user)
+    }
+
+    /// Issue an API key for a user; returns the key's info and its bearer token
+    pub fn create_api_key(&self, user_id: &str, description: Option<&str>) -> Result<(ApiKeyInfo, String)> {
+        self.query_executor.create_api_key(user_id, description, &self.admin_user)
+    }
+
+    /// Revoke an API key by its ID
+    pub fn revoke_api_key(&self, key_id: &str) -> Result<()> {
+        self.query_executor.revoke_api_key(key_id, &self.admin_user)
+    }
+
+    /// Audit log entries matching `filter`, oldest first
+    pub fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        self.query_executor.audit_log(filter, &self.admin_user)
+    }
+
+    /// Subscribe to the writes matching `filter`
+    ///
+    /// ```rust,ignore
+    /// let mut changes = db.subscribe(ChangeFilter { namespace: Some("docs".into()), ..Default::default() })?;
+    /// while let Some(change) = changes.next().await? {
+    ///     println!("{} {} {:?}", change.sequence, change.kind, change.key);
+    /// }
+    /// ```
+    pub fn subscribe(&self, filter: ChangeFilter) -> Result<Subscription> {
+        self.query_executor.subscribe(filter, &self.admin_user)
+    }
+
     /// Set the current namespace for operations that don't specify one
     pub fn set_namespace(&mut self, namespace: &str) {
         self.current_namespace = namespace.to_string();
@@ -197,6 +342,16 @@ impl EmbeddedLiath {
         self.query_executor.save_all()
     }
 
+    /// Re-encrypt stored values, vector indexes and files under the active key
+    ///
+    /// Run after adding a new key; once it returns, older keys can be removed
+    /// from the configuration. Returns how many values and files were rewritten.
+    pub fn rotate_encryption_keys(&self) -> Result<usize> {
+        let rewritten = self.query_executor.rotate_encryption_keys()?;
+        self.save()?;
+        Ok(rewritten)
+    }
+
     /// Close the database connection and save all data
     pub fn close(&self) -> Result<()> {
         self.save()?;
@@ -209,6 +364,18 @@ impl EmbeddedLiath {
         self.query_executor.clone()
     }
 
+    /// Access the job scheduler (cloned; clones share registered callbacks)
+    ///
+    /// Jobs only run while the scheduler is driven; see [`Self::start_scheduler`].
+    pub fn scheduler(&self) -> Scheduler {
+        self.scheduler.clone()
+    }
+
+    /// Run scheduled jobs on a background thread until the handle is dropped
+    pub fn start_scheduler(&self) -> Result<SchedulerHandle> {
+        self.scheduler.start()
+    }
+
     // Convenience APIs
     #[cfg(feature = "vector")]
     pub fn create_namespace(
@@ -221,6 +388,18 @@ impl EmbeddedLiath {
         self.query_executor.create_namespace(name, dimensions, metric, scalar)
     }
 
+    /// Create a namespace whose values and saved vector index are encrypted
+    #[cfg(feature = "vector")]
+    pub fn create_encrypted_namespace(
+        &self,
+        name: &str,
+        dimensions: usize,
+        metric: usearch::MetricKind,
+        scalar: usearch::ScalarKind,
+    ) -> Result<()> {
+        self.query_executor.create_encrypted_namespace(name, dimensions, metric, scalar)
+    }
+
     pub fn put(&self, namespace: &str, key: &[u8], value: &[u8]) -> Result<()> {
         self.query_executor.put(namespace, key, value)
     }
@@ -268,6 +447,11 @@ impl EmbeddedLiath {
         self.query_executor.namespace_exists(name)
     }
 
+    /// Whether a namespace stores its data encrypted
+    pub fn is_encrypted(&self, name: &str) -> bool {
+        self.query_executor.is_encrypted(name)
+    }
+
     /// List all namespaces
     pub fn list_namespaces(&self) -> Vec<String> {
         self.query_executor.list_namespaces()
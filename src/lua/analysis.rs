@@ -0,0 +1,577 @@
+//! Static analysis of parsed Lua code
+//!
+//! Walks a `full_moon` syntax tree with lexical scopes so that references can be
+//! resolved precisely: comments and strings never match, locals shadow globals,
+//! and locals that alias a forbidden module (`local o = os`) are followed to
+//! their uses.
+
+use crate::lua::errors::{blocked_functions, Span};
+use crate::lua::registry::{self, FunctionSpec};
+use full_moon::ast::{
+    Ast, Block, Call, Expression, Field, FunctionArgs, FunctionBody, FunctionCall, Index,
+    LastStmt, Parameter, Prefix, Stmt, Suffix, TableConstructor, Var,
+};
+use full_moon::tokenizer::{TokenReference, TokenType};
+use std::collections::HashSet;
+
+/// Globals provided by the Lua 5.4 standard library
+const LUA_GLOBALS: &[&str] = &[
+    "_G", "_ENV", "_VERSION", "assert", "collectgarbage", "coroutine", "debug", "dofile",
+    "error", "getmetatable", "io", "ipairs", "load", "loadfile", "math", "next", "os",
+    "package", "pairs", "pcall", "print", "rawequal", "rawget", "rawlen", "rawset", "require",
+    "select", "setmetatable", "string", "table", "tonumber", "tostring", "type", "utf8",
+    "warn", "xpcall",
+];
+
+/// Globals Liath adds besides the registered functions
+const LIATH_GLOBALS: &[&str] = &["liath"];
+
+/// Something the analysis found
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Finding {
+    /// Use of a forbidden module or function; `alias` is the local it was reached through
+    Forbidden { path: String, alias: Option<String>, call: bool, span: Span },
+    /// Reference to a global that is never defined
+    Undefined { name: String, span: Span },
+    /// Call to a registered function with the wrong number of arguments
+    ArgumentCount { function: &'static FunctionSpec, got: usize, span: Span },
+    /// Local that is assigned but never read
+    UnusedLocal { name: String, span: Span },
+}
+
+/// Result of analysing a chunk
+#[derive(Debug, Default)]
+pub(crate) struct Report {
+    pub findings: Vec<Finding>,
+    /// Whether the chunk itself (not a nested function) contains a `return`
+    pub has_return: bool,
+}
+
+/// Analyse a parsed chunk; `extra_globals` are names the caller provides (such as `args`)
+pub(crate) fn analyze(ast: &Ast, extra_globals: &[String]) -> Report {
+    let mut analyzer = Analyzer::new(extra_globals);
+    analyzer.block(ast.nodes());
+
+    let mut report = Report {
+        findings: analyzer.findings,
+        has_return: analyzer.has_return,
+    };
+    let mut seen = HashSet::new();
+    for (name, span) in analyzer.unresolved {
+        if !analyzer.assigned_globals.contains(&name) && seen.insert(name.clone()) {
+            report.findings.push(Finding::Undefined { name, span });
+        }
+    }
+    report
+}
+
+struct Local {
+    name: String,
+    span: Span,
+    used: bool,
+    /// Parameters and loop variables are not reported when unused
+    report_unused: bool,
+    /// Forbidden path this local holds, e.g. `os` or `os.execute`
+    alias: Option<String>,
+}
+
+/// What an expression refers to, when it matters for forbidden-module tracking
+type Target = Option<String>;
+
+struct Analyzer<'a> {
+    scopes: Vec<Vec<Local>>,
+    extra_globals: &'a [String],
+    forbidden: HashSet<&'static str>,
+    findings: Vec<Finding>,
+    assigned_globals: HashSet<String>,
+    unresolved: Vec<(String, Span)>,
+    function_depth: usize,
+    has_return: bool,
+}
+
+impl<'a> Analyzer<'a> {
+    fn new(extra_globals: &'a [String]) -> Self {
+        let forbidden = blocked_functions()
+            .into_iter()
+            .map(|(pattern, _)| pattern.split('.').next().unwrap_or(pattern))
+            .collect();
+        Self {
+            scopes: Vec::new(),
+            extra_globals,
+            forbidden,
+            findings: Vec::new(),
+            assigned_globals: HashSet::new(),
+            unresolved: Vec::new(),
+            function_depth: 0,
+            has_return: false,
+        }
+    }
+
+    // ---- scopes ----
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        for local in self.scopes.pop().unwrap_or_default() {
+            if local.report_unused && !local.used && !local.name.starts_with('_') {
+                self.findings.push(Finding::UnusedLocal { name: local.name, span: local.span });
+            }
+        }
+    }
+
+    fn declare(&mut self, token: &TokenReference, report_unused: bool, alias: Target) {
+        let local = Local {
+            name: name_of(token),
+            span: span_of(token),
+            used: false,
+            report_unused,
+            alias,
+        };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(local);
+        }
+    }
+
+    fn local_mut(&mut self, name: &str) -> Option<&mut Local> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name == name)
+    }
+
+    fn is_known_global(&self, name: &str) -> bool {
+        registry::lookup(name).is_some()
+            || LUA_GLOBALS.contains(&name)
+            || LIATH_GLOBALS.contains(&name)
+            || self.extra_globals.iter().any(|g| g == name)
+    }
+
+    // ---- statements ----
+
+    fn block(&mut self, block: &Block) {
+        self.push_scope();
+        self.block_body(block);
+        self.pop_scope();
+    }
+
+    /// Walk a block's statements in the current scope
+    fn block_body(&mut self, block: &Block) {
+        for stmt in block.stmts() {
+            self.stmt(stmt);
+        }
+        if let Some(LastStmt::Return(ret)) = block.last_stmt() {
+            if self.function_depth == 0 {
+                self.has_return = true;
+            }
+            for expr in ret.returns() {
+                self.expr(expr);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Assignment(assignment) => {
+                for expr in assignment.expressions() {
+                    self.expr(expr);
+                }
+                for var in assignment.variables() {
+                    match var {
+                        Var::Name(token) => {
+                            let name = name_of(token);
+                            if self.local_mut(&name).is_none() {
+                                self.assigned_globals.insert(name);
+                            }
+                        }
+                        other => {
+                            self.var(other);
+                        }
+                    }
+                }
+            }
+            Stmt::Do(block) => self.block(block.block()),
+            Stmt::FunctionCall(call) => {
+                self.call(call);
+            }
+            Stmt::FunctionDeclaration(declaration) => {
+                let name = declaration.name();
+                let mut names = name.names().iter();
+                let is_method = name.method_name().is_some();
+                if let Some(first) = names.next() {
+                    let first_name = name_of(first);
+                    let simple = name.names().len() == 1 && !is_method;
+                    if let Some(local) = self.local_mut(&first_name) {
+                        // Assigning a field of a local table reads the local
+                        if !simple {
+                            local.used = true;
+                        }
+                    } else if simple {
+                        self.assigned_globals.insert(first_name);
+                    } else {
+                        self.reference(first, &[]);
+                    }
+                }
+                self.function_body(declaration.body(), is_method);
+            }
+            Stmt::GenericFor(generic_for) => {
+                for expr in generic_for.expressions() {
+                    self.expr(expr);
+                }
+                self.push_scope();
+                for name in generic_for.names() {
+                    self.declare(name, false, None);
+                }
+                self.block(generic_for.block());
+                self.pop_scope();
+            }
+            Stmt::If(if_stmt) => {
+                self.expr(if_stmt.condition());
+                self.block(if_stmt.block());
+                for else_if in if_stmt.else_if().into_iter().flatten() {
+                    self.expr(else_if.condition());
+                    self.block(else_if.block());
+                }
+                if let Some(block) = if_stmt.else_block() {
+                    self.block(block);
+                }
+            }
+            Stmt::LocalAssignment(assignment) => {
+                let targets: Vec<Target> =
+                    assignment.expressions().iter().map(|expr| self.expr(expr)).collect();
+                for (i, name) in assignment.names().iter().enumerate() {
+                    self.declare(name, true, targets.get(i).cloned().flatten());
+                }
+            }
+            Stmt::LocalFunction(function) => {
+                self.declare(function.name(), true, None);
+                self.function_body(function.body(), false);
+            }
+            Stmt::NumericFor(numeric_for) => {
+                self.expr(numeric_for.start());
+                self.expr(numeric_for.end());
+                if let Some(step) = numeric_for.step() {
+                    self.expr(step);
+                }
+                self.push_scope();
+                self.declare(numeric_for.index_variable(), false, None);
+                self.block(numeric_for.block());
+                self.pop_scope();
+            }
+            Stmt::Repeat(repeat) => {
+                // The `until` condition can see the body's locals
+                self.push_scope();
+                self.block_body(repeat.block());
+                self.expr(repeat.until());
+                self.pop_scope();
+            }
+            Stmt::While(while_stmt) => {
+                self.expr(while_stmt.condition());
+                self.block(while_stmt.block());
+            }
+            _ => {}
+        }
+    }
+
+    fn function_body(&mut self, body: &FunctionBody, is_method: bool) {
+        self.function_depth += 1;
+        self.push_scope();
+        if is_method {
+            self.scopes.last_mut().unwrap().push(Local {
+                name: "self".to_string(),
+                span: span_of(body.parameters_parentheses().tokens().0),
+                used: false,
+                report_unused: false,
+                alias: None,
+            });
+        }
+        for parameter in body.parameters() {
+            if let Parameter::Name(token) = parameter {
+                self.declare(token, false, None);
+            }
+        }
+        self.block(body.block());
+        self.pop_scope();
+        self.function_depth -= 1;
+    }
+
+    // ---- expressions ----
+
+    fn expr(&mut self, expr: &Expression) -> Target {
+        match expr {
+            Expression::BinaryOperator { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                None
+            }
+            Expression::Parentheses { expression, .. } => self.expr(expression),
+            Expression::UnaryOperator { expression, .. } => {
+                self.expr(expression);
+                None
+            }
+            Expression::Function(function) => {
+                self.function_body(&function.1, false);
+                None
+            }
+            Expression::FunctionCall(call) => {
+                self.call(call);
+                None
+            }
+            Expression::TableConstructor(table) => {
+                self.table(table);
+                None
+            }
+            Expression::Var(var) => self.var(var),
+            _ => None,
+        }
+    }
+
+    fn table(&mut self, table: &TableConstructor) {
+        for field in table.fields() {
+            match field {
+                Field::ExpressionKey { key, value, .. } => {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                Field::NameKey { value, .. } => {
+                    self.expr(value);
+                }
+                Field::NoKey(value) => {
+                    self.expr(value);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn var(&mut self, var: &Var) -> Target {
+        match var {
+            Var::Name(token) => self.reference(token, &[]),
+            Var::Expression(var_expr) => {
+                let suffixes: Vec<&Suffix> = var_expr.suffixes().collect();
+                self.suffixed(var_expr.prefix(), &suffixes)
+            }
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, call: &FunctionCall) -> Target {
+        let suffixes: Vec<&Suffix> = call.suffixes().collect();
+        self.suffixed(call.prefix(), &suffixes)
+    }
+
+    fn suffixed(&mut self, prefix: &Prefix, suffixes: &[&Suffix]) -> Target {
+        match prefix {
+            Prefix::Name(token) => self.reference(token, suffixes),
+            Prefix::Expression(expr) => {
+                let target = self.expr(expr);
+                self.follow(target, None, None, suffixes)
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve a name followed by index and call suffixes
+    fn reference(&mut self, token: &TokenReference, suffixes: &[&Suffix]) -> Target {
+        let name = name_of(token);
+        let span = span_of(token);
+
+        if let Some(local) = self.local_mut(&name) {
+            local.used = true;
+            let alias = local.alias.clone();
+            let via = alias.as_ref().map(|_| name.clone());
+            return self.follow(alias, via, Some(span), suffixes);
+        }
+
+        // `_G.os` and `_ENV["os"]` reach the same globals as `os`
+        if name == "_G" || name == "_ENV" {
+            if let Some((Suffix::Index(index), rest)) = suffixes.split_first() {
+                if let Some(key) = self.index_key(index) {
+                    if self.forbidden.contains(key.as_str()) {
+                        return self.follow_forbidden(key, None, span, rest);
+                    }
+                }
+                return self.follow(None, None, None, rest);
+            }
+            return None;
+        }
+
+        if self.forbidden.contains(name.as_str()) {
+            return self.follow_forbidden(name, None, span, suffixes);
+        }
+
+        if let Some(spec) = registry::lookup(&name) {
+            if let Some(Suffix::Call(Call::AnonymousCall(args))) = suffixes.first() {
+                self.check_arguments(spec, args, span);
+            }
+        } else if !self.is_known_global(&name) {
+            self.unresolved.push((name, span));
+        }
+        self.follow(None, None, None, suffixes)
+    }
+
+    /// Follow a reference to something forbidden, reporting it unless it is only aliased
+    fn follow_forbidden(&mut self, path: String, via: Option<String>, span: Span, suffixes: &[&Suffix]) -> Target {
+        let reported = self.findings.len();
+        let target = self.follow(Some(path.clone()), via.clone(), Some(span), suffixes);
+        // A bare reference (`local o = os`) is reported here; calls were reported in `follow`
+        if self.findings.len() == reported && via.is_none() {
+            let path = target.clone().unwrap_or(path);
+            self.findings.push(Finding::Forbidden { path, alias: None, call: false, span });
+        }
+        target
+    }
+
+    /// Walk index and call suffixes, extending `target` through field accesses
+    fn follow(&mut self, mut target: Target, via: Option<String>, span: Option<Span>, suffixes: &[&Suffix]) -> Target {
+        for suffix in suffixes {
+            match suffix {
+                Suffix::Index(index) => {
+                    let key = self.index_key(index);
+                    if let Index::Brackets { expression, .. } = index {
+                        self.expr(expression);
+                    }
+                    target = match (target, key) {
+                        (Some(path), Some(key)) => Some(format!("{}.{}", path, key)),
+                        (path, _) => path,
+                    };
+                }
+                Suffix::Call(call) => {
+                    if let Some(path) = target.take() {
+                        let path = match call {
+                            Call::MethodCall(method) => format!("{}:{}", path, name_of(method.name())),
+                            _ => path,
+                        };
+                        if let Some(span) = span {
+                            self.findings.push(Finding::Forbidden { path, alias: via.clone(), call: true, span });
+                        }
+                    }
+                    let args = match call {
+                        Call::AnonymousCall(args) => args,
+                        Call::MethodCall(method) => method.args(),
+                        _ => continue,
+                    };
+                    self.arguments(args);
+                }
+                _ => {}
+            }
+        }
+        target
+    }
+
+    fn index_key(&self, index: &Index) -> Option<String> {
+        match index {
+            Index::Dot { name, .. } => Some(name_of(name)),
+            Index::Brackets { expression: Expression::String(token), .. } => string_literal(token),
+            _ => None,
+        }
+    }
+
+    fn arguments(&mut self, args: &FunctionArgs) {
+        match args {
+            FunctionArgs::Parentheses { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(arg);
+                }
+            }
+            FunctionArgs::TableConstructor(table) => self.table(table),
+            _ => {}
+        }
+    }
+
+    fn check_arguments(&mut self, spec: &'static FunctionSpec, args: &FunctionArgs, span: Span) {
+        let got = match args {
+            FunctionArgs::Parentheses { arguments, .. } => {
+                // A trailing call or `...` can expand to any number of values
+                let open_ended = arguments.iter().last().is_some_and(|last| match last {
+                    Expression::FunctionCall(_) => true,
+                    Expression::Symbol(token) => token.token().to_string() == "...",
+                    _ => false,
+                });
+                if open_ended {
+                    return;
+                }
+                arguments.len()
+            }
+            _ => 1,
+        };
+        if got < spec.min_args() || got > spec.max_args() {
+            self.findings.push(Finding::ArgumentCount { function: spec, got, span });
+        }
+    }
+}
+
+fn name_of(token: &TokenReference) -> String {
+    token.token().to_string()
+}
+
+fn string_literal(token: &TokenReference) -> Option<String> {
+    match token.token().token_type() {
+        TokenType::StringLiteral { literal, .. } => Some(literal.to_string()),
+        _ => None,
+    }
+}
+
+fn span_of(token: &TokenReference) -> Span {
+    let start = token.token().start_position();
+    let end = token.token().end_position();
+    Span {
+        start_line: start.line(),
+        start_column: start.character(),
+        end_line: end.line(),
+        end_column: end.character(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn findings(code: &str) -> Vec<Finding> {
+        let ast = full_moon::parse(code).expect("test code parses");
+        analyze(&ast, &[]).findings
+    }
+
+    #[test]
+    fn test_forbidden_alias() {
+        let found = findings("local o = os\nreturn o.execute('ls')");
+        assert_eq!(found.len(), 2);
+        assert!(matches!(&found[0], Finding::Forbidden { path, call: false, .. } if path == "os"));
+        assert!(matches!(
+            &found[1],
+            Finding::Forbidden { path, alias: Some(alias), call: true, span } if path == "os.execute" && alias == "o" && span.start_line == 2
+        ));
+
+        let found = findings("local run = _G['os'].execute\nrun('ls')");
+        assert!(found.iter().any(|f| matches!(f, Finding::Forbidden { path, alias: Some(_), .. } if path == "os.execute")));
+    }
+
+    #[test]
+    fn test_strings_and_shadowing() {
+        // Neither a string nor a local named like a forbidden module is a reference to it
+        assert!(findings("-- os.execute('x')\nlocal s = 'io.open'\nreturn s").is_empty());
+        assert!(findings("local os = {execute = print}\nos.execute('x')\nreturn 1").is_empty());
+    }
+
+    #[test]
+    fn test_undefined_globals_and_arguments() {
+        let found = findings("helper()\nfunction helper() return put('a', 'b', 'c') end\nreturn insert('ns', 'k')");
+        assert!(found.iter().any(|f| matches!(f, Finding::Undefined { name, .. } if name == "put")));
+        assert!(!found.iter().any(|f| matches!(f, Finding::Undefined { name, .. } if name == "helper")));
+        assert!(found.iter().any(|f| matches!(f, Finding::ArgumentCount { function, got: 2, .. } if function.name == "insert")));
+
+        // A trailing call may expand to several values
+        assert!(findings("return insert('ns', table.unpack({'k', 'v'}))").is_empty());
+    }
+
+    #[test]
+    fn test_unused_locals() {
+        let found = findings("local used, unused = 1, 2\nlocal _ignored = 3\nfor i, v in ipairs({}) do end\nreturn used");
+        assert_eq!(found, vec![Finding::UnusedLocal {
+            name: "unused".to_string(),
+            span: Span { start_line: 1, start_column: 13, end_line: 1, end_column: 19 },
+        }]);
+    }
+}
@@ -106,6 +106,21 @@ pub struct ValidationError {
     pub suggestion: String,
     /// Code snippet showing the error context
     pub code_snippet: Option<String>,
+    /// Exact location of the offending code, when known
+    pub span: Option<Span>,
+}
+
+/// A range of source code; lines and columns are 1-indexed and the end is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    /// Line the range starts on
+    pub start_line: usize,
+    /// Column the range starts at
+    pub start_column: usize,
+    /// Line the range ends on
+    pub end_line: usize,
+    /// Column just past the end of the range
+    pub end_column: usize,
 }
 
 impl ValidationError {
@@ -118,6 +133,7 @@ impl ValidationError {
             column: None,
             suggestion: suggestion.to_string(),
             code_snippet: None,
+            span: None,
         }
     }
 
@@ -130,6 +146,7 @@ impl ValidationError {
             column: None,
             suggestion: suggestion.to_string(),
             code_snippet: None,
+            span: None,
         }
     }
 
@@ -146,6 +163,20 @@ impl ValidationError {
             column: None,
             suggestion,
             code_snippet: None,
+            span: None,
+        }
+    }
+
+    /// Create an error for a call with the wrong number of arguments
+    pub fn argument_count(function: &str, signature: &str, expected: &str, got: usize) -> Self {
+        Self {
+            error_type: ErrorType::ArgumentCount,
+            message: format!("'{}' expects {} but was called with {}", function, expected, got),
+            line: None,
+            column: None,
+            suggestion: format!("Call it as {}", signature),
+            code_snippet: None,
+            span: None,
         }
     }
 
@@ -161,6 +192,14 @@ impl ValidationError {
         self.column = column;
         self
     }
+
+    /// Add an exact span (also sets line and column)
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.line = Some(span.start_line);
+        self.column = Some(span.start_column);
+        self.span = Some(span);
+        self
+    }
 }
 
 /// Types of validation errors
@@ -175,6 +214,8 @@ pub enum ErrorType {
     UndefinedVariable,
     /// Type mismatch in function call
     TypeMismatch,
+    /// Wrong number of arguments to a Liath function
+    ArgumentCount,
     /// Missing return statement
     MissingReturn,
     /// Code complexity exceeded limits
@@ -192,6 +233,8 @@ pub struct ValidationWarning {
     pub line: Option<usize>,
     /// Suggestion
     pub suggestion: String,
+    /// Exact location, when known
+    pub span: Option<Span>,
 }
 
 impl ValidationWarning {
@@ -202,6 +245,7 @@ impl ValidationWarning {
             message: "Code does not have an explicit return statement".to_string(),
             line: None,
             suggestion: "Add 'return <value>' at the end to return a result".to_string(),
+            span: None,
         }
     }
 
@@ -212,8 +256,16 @@ impl ValidationWarning {
             message: format!("Variable '{}' is defined but never used", name),
             line,
             suggestion: "Remove unused variables or use them in your code".to_string(),
+            span: None,
         }
     }
+
+    /// Add an exact span (also sets the line)
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.line = Some(span.start_line);
+        self.span = Some(span);
+        self
+    }
 }
 
 /// Types of warnings
@@ -273,7 +325,7 @@ impl RuntimeError {
             message: format!("Key '{}' not found in namespace '{}'", key, namespace),
             lua_traceback: None,
             suggestion: format!(
-                "Check that the key exists. Use get() which returns nil for missing keys, or store a value first with put()."
+                "Check that the key exists. Use select() which returns nil for missing keys, or store a value first with insert()."
             ),
         }
     }
@@ -364,7 +416,7 @@ pub enum RuntimeErrorType {
 pub struct FunctionInfo {
     /// Function name
     pub name: String,
-    /// Function signature (e.g., "put(namespace, key, value)")
+    /// Function signature (e.g., "insert(namespace, key, value)")
     pub signature: String,
     /// Short description
     pub description: String,
@@ -395,78 +447,19 @@ impl FunctionInfo {
 
 /// Get the list of available functions for Liath
 pub fn available_functions() -> Vec<FunctionInfo> {
-    vec![
-        // Storage
-        FunctionInfo::new("put", "put(namespace, key, value)", "Store a value", "nil")
-            .with_example("put('config', 'theme', 'dark')"),
-        FunctionInfo::new("get", "get(namespace, key)", "Retrieve a value", "string|nil")
-            .with_example("local theme = get('config', 'theme')"),
-        FunctionInfo::new("delete", "delete(namespace, key)", "Delete a key", "nil")
-            .with_example("delete('config', 'old_key')"),
-        // Semantic
-        FunctionInfo::new(
-            "store_with_embedding",
-            "store_with_embedding(namespace, id, content)",
-            "Store text with auto-generated embedding",
-            "nil",
-        )
-        .with_example("store_with_embedding('docs', 'doc1', 'Hello world')"),
-        FunctionInfo::new(
-            "semantic_search",
-            "semantic_search(namespace, query, limit)",
-            "Search by text similarity",
-            "list of {id, content, distance}",
-        )
-        .with_example("local results = semantic_search('docs', 'greeting', 5)"),
-        // Utilities
-        FunctionInfo::new(
-            "json.encode",
-            "json.encode(value)",
-            "Convert Lua value to JSON string",
-            "string",
-        )
-        .with_example("return json.encode({name = 'test'})"),
-        FunctionInfo::new(
-            "json.decode",
-            "json.decode(string)",
-            "Parse JSON string to Lua value",
-            "table",
-        )
-        .with_example("local data = json.decode('{\"a\": 1}')"),
-        FunctionInfo::new(
-            "filter",
-            "filter(list, fn)",
-            "Filter list by predicate",
-            "list",
-        )
-        .with_example("filter(items, function(x) return x.age > 18 end)"),
-        FunctionInfo::new("map", "map(list, fn)", "Transform each list element", "list")
-            .with_example("map(items, function(x) return x.name end)"),
-        FunctionInfo::new(
-            "reduce",
-            "reduce(list, fn, initial)",
-            "Reduce list to single value",
-            "any",
-        )
-        .with_example("reduce(nums, function(a, b) return a + b end, 0)"),
-        FunctionInfo::new("now", "now()", "Get current Unix timestamp", "number")
-            .with_example("local timestamp = now()"),
-        FunctionInfo::new("id", "id()", "Generate unique ID", "string")
-            .with_example("local unique_id = id()"),
-    ]
+    crate::lua::registry::function_info()
 }
 
 /// Blocked functions and their suggestions
 pub fn blocked_functions() -> Vec<(&'static str, &'static str)> {
     vec![
-        ("io.open", "File I/O is not allowed. Store data using put() instead."),
-        ("io.read", "File I/O is not allowed. Retrieve data using get() instead."),
-        ("io.write", "File I/O is not allowed. Store data using put() instead."),
+        ("io.open", "File I/O is not allowed. Store data using insert() instead."),
+        ("io.read", "File I/O is not allowed. Retrieve data using select() instead."),
+        ("io.write", "File I/O is not allowed. Store data using insert() instead."),
         ("os.execute", "System commands are not allowed for security."),
         ("os.remove", "File deletion is not allowed. Use delete() for keys."),
         ("os.rename", "File operations are not allowed."),
         ("os.exit", "Exiting is not allowed."),
-        ("require", "Loading external modules is not allowed."),
         ("loadfile", "Loading files is not allowed."),
         ("dofile", "Executing files is not allowed."),
         ("load", "Loading code strings is not allowed."),
@@ -1,12 +1,16 @@
 mod vm;
+mod analysis;
 mod luarocks;
 pub mod errors;
+pub mod registry;
 pub mod validator;
 
-pub use vm::LuaVM;
+pub use vm::{LuaVM, SharedLuaVM};
+pub(crate) use vm::LIATH_STDLIB;
 pub use luarocks::LuaRocks;
 pub use errors::{
     ExecutionResult, ValidationResult, ValidationError, ValidationWarning,
-    RuntimeError, ErrorType, RuntimeErrorType, FunctionInfo,
+    RuntimeError, ErrorType, RuntimeErrorType, FunctionInfo, Span,
 };
+pub use registry::FunctionSpec;
 pub use validator::LuaValidator;
\ No newline at end of file
@@ -0,0 +1,604 @@
+//! Registry of the functions Liath exposes to Lua
+//!
+//! This table is the single source of truth for the Lua API. The query executor
+//! binds its functions through [`Bindings`], which rejects names missing from the
+//! registry and reports registry entries that were never bound. The validator's
+//! suggestions, [`format_help`], the MCP tool description and Python `help()` are
+//! all generated from the same table, so the documented API cannot drift from the
+//! real one.
+
+use crate::lua::errors::FunctionInfo;
+use rlua::{Error as LuaError, Function as LuaFunction, Table as LuaTable};
+use std::collections::HashSet;
+
+/// Description of one Lua function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FunctionSpec {
+    /// Global name
+    pub name: &'static str,
+    /// Section the function is listed under in help output
+    pub category: &'static str,
+    /// Parameter names; optional parameters end with `?`
+    pub params: &'static [&'static str],
+    /// Return type
+    pub returns: &'static str,
+    /// Short description
+    pub description: &'static str,
+    /// Example usage
+    pub example: &'static str,
+}
+
+impl FunctionSpec {
+    /// Call signature, e.g. `scan(namespace, prefix, [limit])`
+    pub fn signature(&self) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|p| match p.strip_suffix('?') {
+                Some(optional) => format!("[{}]", optional),
+                None => p.to_string(),
+            })
+            .collect();
+        format!("{}({})", self.name, params.join(", "))
+    }
+
+    /// Fewest arguments the function accepts
+    pub fn min_args(&self) -> usize {
+        self.params.iter().filter(|p| !p.ends_with('?')).count()
+    }
+
+    /// Most arguments the function accepts
+    pub fn max_args(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Convert to the serializable form returned with validation results
+    pub fn info(&self) -> FunctionInfo {
+        FunctionInfo::new(self.name, &self.signature(), self.description, self.returns)
+            .with_example(self.example)
+    }
+}
+
+const NAMESPACES: &str = "Namespaces";
+const KEY_VALUE: &str = "Key-value";
+const JSON: &str = "JSON";
+const VECTORS: &str = "Vectors and semantic search";
+const MEMORY: &str = "Agent memory";
+const QUERIES: &str = "Queries";
+const PROCEDURES: &str = "Stored procedures";
+const TRIGGERS: &str = "Triggers";
+const MODULES: &str = "Modules";
+const FILES: &str = "Files and packages";
+const UTILITIES: &str = "Utilities";
+
+/// Every native function bound into the Lua environment, in help order
+pub static FUNCTIONS: &[FunctionSpec] = &[
+    // Namespaces
+    FunctionSpec {
+        name: "create_namespace",
+        category: NAMESPACES,
+        params: &["name", "dimensions", "metric", "scalar"],
+        returns: "nil",
+        description: "Create a namespace; metric is 'cosine' or 'euclidean', scalar is 'f32' or 'f16'",
+        example: "create_namespace('docs', 384, 'cosine', 'f32')",
+    },
+    FunctionSpec {
+        name: "delete_namespace",
+        category: NAMESPACES,
+        params: &["name"],
+        returns: "nil",
+        description: "Delete a namespace and all its data",
+        example: "delete_namespace('scratch')",
+    },
+    FunctionSpec {
+        name: "list_namespaces",
+        category: NAMESPACES,
+        params: &[],
+        returns: "list of string",
+        description: "List namespace names",
+        example: "local names = list_namespaces()",
+    },
+    FunctionSpec {
+        name: "namespace_exists",
+        category: NAMESPACES,
+        params: &["name"],
+        returns: "boolean",
+        description: "Check whether a namespace exists",
+        example: "if not namespace_exists('docs') then create_namespace('docs', 384, 'cosine', 'f32') end",
+    },
+    // Key-value
+    FunctionSpec {
+        name: "insert",
+        category: KEY_VALUE,
+        params: &["namespace", "key", "value"],
+        returns: "nil",
+        description: "Store a string value",
+        example: "insert('config', 'theme', 'dark')",
+    },
+    FunctionSpec {
+        name: "select",
+        category: KEY_VALUE,
+        params: &["namespace", "key"],
+        returns: "string|nil",
+        description: "Retrieve a value",
+        example: "local theme = select('config', 'theme')",
+    },
+    FunctionSpec {
+        name: "update",
+        category: KEY_VALUE,
+        params: &["namespace", "key", "value"],
+        returns: "nil",
+        description: "Overwrite a value",
+        example: "update('config', 'theme', 'light')",
+    },
+    FunctionSpec {
+        name: "delete",
+        category: KEY_VALUE,
+        params: &["namespace", "key"],
+        returns: "nil",
+        description: "Delete a key",
+        example: "delete('config', 'old_key')",
+    },
+    FunctionSpec {
+        name: "batch_insert",
+        category: KEY_VALUE,
+        params: &["namespace", "items"],
+        returns: "number",
+        description: "Store a list of {key, value} tables in one batch; returns the count",
+        example: "batch_insert('config', {{key = 'a', value = '1'}, {key = 'b', value = '2'}})",
+    },
+    FunctionSpec {
+        name: "batch_select",
+        category: KEY_VALUE,
+        params: &["namespace", "keys"],
+        returns: "table of key -> value",
+        description: "Retrieve several values at once",
+        example: "local values = batch_select('config', {'a', 'b'})",
+    },
+    FunctionSpec {
+        name: "scan",
+        category: KEY_VALUE,
+        params: &["namespace", "prefix", "limit?"],
+        returns: "list of {key, value}",
+        description: "List entries whose key starts with prefix (default limit 100)",
+        example: "local users = scan('app', 'user:', 10)",
+    },
+    FunctionSpec {
+        name: "iter",
+        category: KEY_VALUE,
+        params: &["namespace", "prefix", "cursor?"],
+        returns: "iterator of key, value",
+        description: "Lazily iterate over entries whose key starts with prefix, optionally resuming after a cursor",
+        example: "for key, value in iter('app', 'user:') do count = count + 1 end",
+    },
+    FunctionSpec {
+        name: "scan_page",
+        category: KEY_VALUE,
+        params: &["namespace", "prefix", "limit?", "cursor?"],
+        returns: "{entries = list of {key, value}, cursor}",
+        description: "One page of entries whose key starts with prefix; pass cursor back for the next page (nil when done)",
+        example: "local page = scan_page('app', 'user:', 50, cursor)",
+    },
+    // JSON
+    FunctionSpec {
+        name: "insert_json",
+        category: JSON,
+        params: &["namespace", "key", "value"],
+        returns: "nil",
+        description: "Store a Lua table as JSON",
+        example: "insert_json('users', 'user:1', {name = 'Ada', age = 36})",
+    },
+    FunctionSpec {
+        name: "select_json",
+        category: JSON,
+        params: &["namespace", "key"],
+        returns: "table|nil",
+        description: "Retrieve a JSON value as a Lua table",
+        example: "local user = select_json('users', 'user:1')",
+    },
+    FunctionSpec {
+        name: "json_encode",
+        category: JSON,
+        params: &["value"],
+        returns: "string",
+        description: "Convert a Lua value to a JSON string",
+        example: "return json_encode({name = 'test'})",
+    },
+    FunctionSpec {
+        name: "json_decode",
+        category: JSON,
+        params: &["json"],
+        returns: "any",
+        description: "Parse a JSON string into a Lua value",
+        example: "local data = json_decode('{\"a\": 1}')",
+    },
+    // Vectors and semantic search
+    FunctionSpec {
+        name: "store_document",
+        category: VECTORS,
+        params: &["namespace", "id", "key", "text"],
+        returns: "number",
+        description: "Store text under key and index its embedding under numeric id",
+        example: "store_document('docs', 1, 'doc:1', 'Hello world')",
+    },
+    FunctionSpec {
+        name: "semantic_search",
+        category: VECTORS,
+        params: &["namespace", "query", "k"],
+        returns: "list of {id, key, content, distance}",
+        description: "Find the k documents closest in meaning to query",
+        example: "local results = semantic_search('docs', 'greeting', 5)",
+    },
+    FunctionSpec {
+        name: "generate_embedding",
+        category: VECTORS,
+        params: &["texts"],
+        returns: "list of vectors",
+        description: "Embed a list of texts",
+        example: "local vectors = generate_embedding({'Hello world'})",
+    },
+    FunctionSpec {
+        name: "add_vector",
+        category: VECTORS,
+        params: &["namespace", "id", "vector"],
+        returns: "nil",
+        description: "Add a raw vector to the namespace index",
+        example: "add_vector('docs', 42, vectors[1])",
+    },
+    FunctionSpec {
+        name: "similarity_search",
+        category: VECTORS,
+        params: &["namespace", "vector", "k"],
+        returns: "list of {id, distance}",
+        description: "Find the k vectors nearest to a raw vector",
+        example: "local hits = similarity_search('docs', vectors[1], 5)",
+    },
+    // Agent memory
+    FunctionSpec {
+        name: "memory_store",
+        category: MEMORY,
+        params: &["namespace", "content", "tags?"],
+        returns: "number",
+        description: "Store a memory with its embedding and optional tags; returns its id",
+        example: "memory_store('agent:memory', 'User prefers dark mode', {'preferences'})",
+    },
+    FunctionSpec {
+        name: "memory_recall",
+        category: MEMORY,
+        params: &["namespace", "query", "k"],
+        returns: "list of {id, content, tags, created_at, distance}",
+        description: "Recall the k memories closest to query",
+        example: "local memories = memory_recall('agent:memory', 'ui preferences', 3)",
+    },
+    // Queries
+    FunctionSpec {
+        name: "sql",
+        category: QUERIES,
+        params: &["query"],
+        returns: "list of rows",
+        description: "Run a declarative SELECT query",
+        example: "local rows = sql(\"SELECT name FROM users WHERE age > 30\")",
+    },
+    // Stored procedures
+    FunctionSpec {
+        name: "register_procedure",
+        category: PROCEDURES,
+        params: &["name", "code", "description?"],
+        returns: "number",
+        description: "Register a new version of a stored procedure; returns the version",
+        example: "register_procedure('greet', \"return 'Hello, ' .. args.name\")",
+    },
+    FunctionSpec {
+        name: "call",
+        category: PROCEDURES,
+        params: &["name", "args?"],
+        returns: "any",
+        description: "Call a stored procedure",
+        example: "local greeting = call('greet', {name = 'Ada'})",
+    },
+    FunctionSpec {
+        name: "list_procedures",
+        category: PROCEDURES,
+        params: &[],
+        returns: "list of {name, version, description, owner}",
+        description: "List stored procedures",
+        example: "local procedures = list_procedures()",
+    },
+    FunctionSpec {
+        name: "delete_procedure",
+        category: PROCEDURES,
+        params: &["name"],
+        returns: "nil",
+        description: "Delete a stored procedure and its history",
+        example: "delete_procedure('greet')",
+    },
+    // Triggers
+    FunctionSpec {
+        name: "create_trigger",
+        category: TRIGGERS,
+        params: &["namespace", "name", "event", "timing", "code"],
+        returns: "nil",
+        description: "Run code before or after writes; event is 'put', 'delete' or 'store_document'",
+        example: "create_trigger('users', 'stamp', 'put', 'before', 'return event.value')",
+    },
+    FunctionSpec {
+        name: "drop_trigger",
+        category: TRIGGERS,
+        params: &["namespace", "name"],
+        returns: "nil",
+        description: "Remove a trigger",
+        example: "drop_trigger('users', 'stamp')",
+    },
+    FunctionSpec {
+        name: "list_triggers",
+        category: TRIGGERS,
+        params: &["namespace"],
+        returns: "list of {name, event, timing, owner}",
+        description: "List the triggers on a namespace",
+        example: "local triggers = list_triggers('users')",
+    },
+    // Modules
+    FunctionSpec {
+        name: "require",
+        category: MODULES,
+        params: &["name", "version?"],
+        returns: "any",
+        description: "Load a stored or bundled module (latest version unless one is given)",
+        example: "local text = require('text.utils')",
+    },
+    FunctionSpec {
+        name: "module_put",
+        category: MODULES,
+        params: &["name", "code", "description?"],
+        returns: "number",
+        description: "Store a new version of a module; returns the version",
+        example: "module_put('text.utils', 'local M = {} function M.upper(s) return s:upper() end return M')",
+    },
+    FunctionSpec {
+        name: "list_modules",
+        category: MODULES,
+        params: &[],
+        returns: "list of {name, version, description, owner}",
+        description: "List stored modules",
+        example: "local modules = list_modules()",
+    },
+    FunctionSpec {
+        name: "module_delete",
+        category: MODULES,
+        params: &["name"],
+        returns: "nil",
+        description: "Delete a module and its history",
+        example: "module_delete('text.utils')",
+    },
+    // Files and packages
+    FunctionSpec {
+        name: "upload_file",
+        category: FILES,
+        params: &["name", "content"],
+        returns: "string",
+        description: "Store file content; returns its file id",
+        example: "local file_id = upload_file('notes.txt', 'Remember the milk')",
+    },
+    FunctionSpec {
+        name: "retrieve_file",
+        category: FILES,
+        params: &["file_id"],
+        returns: "string",
+        description: "Retrieve file content by id",
+        example: "local content = retrieve_file(file_id)",
+    },
+    FunctionSpec {
+        name: "install_package",
+        category: FILES,
+        params: &["name"],
+        returns: "nil",
+        description: "Install a LuaRocks package",
+        example: "install_package('inspect')",
+    },
+    FunctionSpec {
+        name: "list_packages",
+        category: FILES,
+        params: &[],
+        returns: "list of string",
+        description: "List installed packages",
+        example: "local packages = list_packages()",
+    },
+    // Utilities
+    FunctionSpec {
+        name: "save",
+        category: UTILITIES,
+        params: &[],
+        returns: "nil",
+        description: "Flush all data to disk",
+        example: "save()",
+    },
+    FunctionSpec {
+        name: "uuid",
+        category: UTILITIES,
+        params: &[],
+        returns: "string",
+        description: "Generate a random UUID",
+        example: "local id = uuid()",
+    },
+    FunctionSpec {
+        name: "timestamp",
+        category: UTILITIES,
+        params: &[],
+        returns: "number",
+        description: "Current Unix timestamp in seconds",
+        example: "local now = timestamp()",
+    },
+    FunctionSpec {
+        name: "sleep",
+        category: UTILITIES,
+        params: &["ms"],
+        returns: "nil",
+        description: "Pause for ms milliseconds",
+        example: "sleep(100)",
+    },
+];
+
+/// Names agents often guess, and the function they meant
+pub static MISNAMED: &[(&str, &str)] = &[
+    ("put", "insert"),
+    ("get", "select"),
+    ("set", "insert"),
+    ("store", "store_document"),
+    ("store_with_embedding", "store_document"),
+    ("search", "semantic_search"),
+    ("json", "json_encode"),
+    ("now", "timestamp"),
+    ("id", "uuid"),
+];
+
+/// Look up a function by name
+pub fn lookup(name: &str) -> Option<&'static FunctionSpec> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// Function info for every registered function
+pub fn function_info() -> Vec<FunctionInfo> {
+    FUNCTIONS.iter().map(FunctionSpec::info).collect()
+}
+
+/// Help text listing every function by category
+pub fn format_help() -> String {
+    let mut help = String::from("Available Liath functions:\n");
+    let mut category = "";
+
+    for func in FUNCTIONS {
+        if func.category != category {
+            category = func.category;
+            help.push_str(&format!("\n{}:\n", category));
+        }
+        help.push_str(&format!("  {} -> {}\n", func.signature(), func.returns));
+        help.push_str(&format!("    {}\n", func.description));
+        help.push_str(&format!("    Example: {}\n", func.example));
+    }
+
+    help.push_str("\nThe bundled 'liath' module adds helpers on top: liath.kv, liath.docs, liath.memory, liath.conversation, liath.rag and liath.util.\n");
+    help
+}
+
+/// Compact function list for tool descriptions (one line per category)
+pub fn summary() -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut category = "";
+
+    for func in FUNCTIONS {
+        if func.category != category {
+            category = func.category;
+            lines.push(format!("{}:", category));
+        }
+        let line = lines.last_mut().expect("category line pushed above");
+        line.push(' ');
+        line.push_str(&func.signature());
+        line.push(';');
+    }
+
+    lines
+        .into_iter()
+        .map(|line| line.trim_end_matches(';').to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Binds native functions into a Lua table, checked against the registry
+pub struct Bindings<'a, 'lua> {
+    target: &'a LuaTable<'lua>,
+    bound: HashSet<&'static str>,
+    wrapper: Option<Box<BindingWrapper<'a, 'lua>>>,
+}
+
+/// Replaces a function before it is bound, e.g. to instrument it
+pub type BindingWrapper<'a, 'lua> =
+    dyn Fn(&'static str, LuaFunction<'lua>) -> Result<LuaFunction<'lua>, LuaError> + 'a;
+
+impl<'a, 'lua> Bindings<'a, 'lua> {
+    /// Bind into `target` (the globals or a sandbox environment)
+    pub fn new(target: &'a LuaTable<'lua>) -> Self {
+        Self {
+            target,
+            bound: HashSet::new(),
+            wrapper: None,
+        }
+    }
+
+    /// Pass every function through `wrapper` before binding it
+    pub fn with_wrapper(
+        mut self,
+        wrapper: impl Fn(&'static str, LuaFunction<'lua>) -> Result<LuaFunction<'lua>, LuaError> + 'a,
+    ) -> Self {
+        self.wrapper = Some(Box::new(wrapper));
+        self
+    }
+
+    /// Bind a function; fails if `name` is not in the registry
+    pub fn set(&mut self, name: &str, function: LuaFunction<'lua>) -> Result<(), LuaError> {
+        let spec = lookup(name).ok_or_else(|| {
+            LuaError::RuntimeError(format!("Function '{}' is not in the function registry", name))
+        })?;
+        let function = match &self.wrapper {
+            Some(wrapper) => wrapper(spec.name, function)?,
+            None => function,
+        };
+        self.bound.insert(spec.name);
+        self.target.set(spec.name, function)
+    }
+
+    /// Finish binding; fails if any registered function was not bound
+    pub fn finish(self) -> Result<(), LuaError> {
+        let missing: Vec<&str> = FUNCTIONS
+            .iter()
+            .map(|f| f.name)
+            .filter(|name| !self.bound.contains(name))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(LuaError::RuntimeError(format!(
+                "Registered functions were not bound: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_are_unique() {
+        let mut seen = HashSet::new();
+        for func in FUNCTIONS {
+            assert!(seen.insert(func.name), "duplicate registry entry '{}'", func.name);
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(lookup("scan").unwrap().signature(), "scan(namespace, prefix, [limit])");
+        assert_eq!(lookup("uuid").unwrap().signature(), "uuid()");
+        assert!(lookup("put").is_none());
+    }
+
+    #[test]
+    fn test_arity() {
+        let scan = lookup("scan").unwrap();
+        assert_eq!((scan.min_args(), scan.max_args()), (2, 3));
+        for (_, meant) in MISNAMED {
+            assert!(lookup(meant).is_some(), "'{}' is not registered", meant);
+        }
+    }
+
+    #[test]
+    fn test_help_lists_every_function() {
+        let help = format_help();
+        let summary = summary();
+        for func in FUNCTIONS {
+            assert!(help.contains(&func.signature()));
+            assert!(summary.contains(&func.signature()));
+        }
+    }
+}
@@ -2,25 +2,33 @@
 //!
 //! Validates Lua code before execution to:
 //! 1. Check syntax errors
-//! 2. Detect forbidden functions (io, os, debug, etc.)
-//! 3. Provide LLM-friendly error messages with suggestions
+//! 2. Detect forbidden functions (io, os, debug, etc.), including through aliases
+//! 3. Resolve globals against the function registry and check argument counts
+//! 4. Report unused locals
+//! 5. Provide LLM-friendly error messages with suggestions
+//!
+//! Checks 2-4 work on a parsed syntax tree, so comments and strings never match.
+//! When the code does not parse, forbidden functions are found by pattern instead.
 
 use crate::lua::errors::{
-    available_functions, blocked_functions, ErrorType, FunctionInfo, ValidationError,
-    ValidationResult, ValidationWarning,
+    blocked_functions, ErrorType, FunctionInfo, ValidationError, ValidationResult,
+    ValidationWarning,
 };
+use crate::lua::analysis::{self, Finding};
+use crate::lua::registry;
 use regex::Regex;
 use rlua::{Lua, Result as LuaResult};
-use std::collections::HashSet;
 
 /// Lua code validator
 pub struct LuaValidator {
     /// Set of blocked function patterns
     blocked_patterns: Vec<(Regex, String)>,
-    /// Set of available function names (for suggestions)
-    available_functions: HashSet<String>,
+    /// Available function names, in registry order (for suggestions)
+    available_functions: Vec<String>,
     /// Function info for help
     function_info: Vec<FunctionInfo>,
+    /// Globals the caller provides besides the standard ones (e.g. `args`)
+    extra_globals: Vec<String>,
 }
 
 impl Default for LuaValidator {
@@ -49,39 +57,43 @@ impl LuaValidator {
             }
         }
 
-        let function_info = available_functions();
-        let available_functions: HashSet<String> =
-            function_info.iter().map(|f| f.name.clone()).collect();
+        let function_info = registry::function_info();
+        let available_functions: Vec<String> =
+            registry::FUNCTIONS.iter().map(|f| f.name.to_string()).collect();
 
         Self {
             blocked_patterns,
             available_functions,
             function_info,
+            extra_globals: Vec::new(),
         }
     }
 
+    /// Treat `names` as defined globals, for code run with extra variables in scope
+    pub fn with_globals(mut self, names: &[&str]) -> Self {
+        self.extra_globals.extend(names.iter().map(|n| n.to_string()));
+        self
+    }
+
     /// Validate Lua code without executing it
     pub fn validate(&self, code: &str) -> ValidationResult {
         let mut errors = Vec::new();
         let mut warnings = Vec::new();
 
-        // Step 1: Check for forbidden functions
-        for (pattern, suggestion) in &self.blocked_patterns {
-            if let Some(m) = pattern.find(code) {
-                let line = Self::line_number(code, m.start());
-                let snippet = Self::extract_snippet(code, m.start(), 40);
-
-                let error = ValidationError {
-                    error_type: ErrorType::ForbiddenFunction,
-                    message: format!("Forbidden function call detected: '{}'", m.as_str().trim_end_matches('(')),
-                    line: Some(line),
-                    column: None,
-                    suggestion: suggestion.clone(),
-                    code_snippet: Some(snippet),
-                };
-                errors.push(error);
+        // Step 1: Static analysis on the syntax tree
+        let has_return = match full_moon::parse(code) {
+            Ok(ast) => {
+                let report = analysis::analyze(&ast, &self.extra_globals);
+                for finding in report.findings {
+                    self.add_finding(code, finding, &mut errors, &mut warnings);
+                }
+                report.has_return
             }
-        }
+            Err(_) => {
+                self.scan_blocked_patterns(code, &mut errors);
+                self.has_return_statement(code)
+            }
+        };
 
         // Step 2: Check syntax by trying to parse
         if let Err(syntax_error) = self.check_syntax(code) {
@@ -95,12 +107,13 @@ impl LuaValidator {
                 column: None,
                 suggestion,
                 code_snippet: line.map(|l| Self::get_line(code, l)),
+                span: None,
             };
             errors.push(error);
         }
 
         // Step 3: Check for missing return (warning only)
-        if !self.has_return_statement(code) && !code.trim().is_empty() {
+        if !has_return && !code.trim().is_empty() {
             warnings.push(ValidationWarning::missing_return());
         }
 
@@ -117,6 +130,94 @@ impl LuaValidator {
         }
     }
 
+    /// Turn an analysis finding into an error or warning
+    fn add_finding(
+        &self,
+        code: &str,
+        finding: Finding,
+        errors: &mut Vec<ValidationError>,
+        warnings: &mut Vec<ValidationWarning>,
+    ) {
+        match finding {
+            Finding::Forbidden { path, alias, call, span } => {
+                let message = match (&alias, call) {
+                    (Some(alias), _) => format!("Forbidden function call detected: '{}' (through alias '{}')", path, alias),
+                    (None, true) => format!("Forbidden function call detected: '{}'", path),
+                    (None, false) => format!("Forbidden reference detected: '{}'", path),
+                };
+                let error = ValidationError {
+                    error_type: ErrorType::ForbiddenFunction,
+                    message,
+                    line: None,
+                    column: None,
+                    suggestion: Self::blocked_suggestion(&path),
+                    code_snippet: Some(Self::get_line(code, span.start_line)),
+                    span: None,
+                };
+                errors.push(error.with_span(span));
+            }
+            Finding::Undefined { name, span } => {
+                let did_you_mean = self.find_similar_function(&name);
+                errors.push(
+                    ValidationError::undefined_variable(&name, did_you_mean.as_deref())
+                        .with_snippet(&Self::get_line(code, span.start_line))
+                        .with_span(span),
+                );
+            }
+            Finding::ArgumentCount { function, got, span } => {
+                let expected = match (function.min_args(), function.max_args()) {
+                    (min, max) if min == max => format!("{} argument{}", min, if min == 1 { "" } else { "s" }),
+                    (min, max) => format!("{} to {} arguments", min, max),
+                };
+                errors.push(
+                    ValidationError::argument_count(function.name, &function.signature(), &expected, got)
+                        .with_snippet(&Self::get_line(code, span.start_line))
+                        .with_span(span),
+                );
+            }
+            Finding::UnusedLocal { name, span } => {
+                warnings.push(ValidationWarning::unused_variable(&name, None).with_span(span));
+            }
+        }
+    }
+
+    /// Suggestion for a forbidden path such as `os.execute` or `io`
+    fn blocked_suggestion(path: &str) -> String {
+        let blocked = blocked_functions();
+        if let Some((_, suggestion)) = blocked.iter().find(|(pattern, _)| *pattern == path) {
+            return suggestion.to_string();
+        }
+        let module = path.split(['.', ':']).next().unwrap_or(path);
+        match blocked.iter().find(|(pattern, _)| *pattern == module || pattern.starts_with(&format!("{}.", module))) {
+            Some((pattern, suggestion)) if pattern.contains('.') => {
+                format!("The '{}' module is not available. {}", module, suggestion)
+            }
+            Some((_, suggestion)) => suggestion.to_string(),
+            None => "This function is not available in the sandbox.".to_string(),
+        }
+    }
+
+    /// Find forbidden functions by pattern, for code that does not parse
+    fn scan_blocked_patterns(&self, code: &str, errors: &mut Vec<ValidationError>) {
+        for (pattern, suggestion) in &self.blocked_patterns {
+            if let Some(m) = pattern.find(code) {
+                let line = Self::line_number(code, m.start());
+                let snippet = Self::extract_snippet(code, m.start(), 40);
+
+                let error = ValidationError {
+                    error_type: ErrorType::ForbiddenFunction,
+                    message: format!("Forbidden function call detected: '{}'", m.as_str().trim_end_matches('(')),
+                    line: Some(line),
+                    column: None,
+                    suggestion: suggestion.clone(),
+                    code_snippet: Some(snippet),
+                    span: None,
+                };
+                errors.push(error);
+            }
+        }
+    }
+
     /// Check Lua syntax by parsing the code
     fn check_syntax(&self, code: &str) -> LuaResult<()> {
         let lua = Lua::new();
@@ -258,6 +359,9 @@ impl LuaValidator {
 
     /// Find a similar function name (for "did you mean" suggestions)
     fn find_similar_function(&self, name: &str) -> Option<String> {
+        if let Some((_, meant)) = registry::MISNAMED.iter().find(|(guess, _)| *guess == name) {
+            return Some(meant.to_string());
+        }
         let name_lower = name.to_lowercase();
 
         // Simple similarity: look for functions that start with same letters
@@ -316,17 +420,7 @@ impl LuaValidator {
 
     /// Format available functions as a help string
     pub fn format_help(&self) -> String {
-        let mut help = String::from("Available Liath functions:\n\n");
-
-        for func in &self.function_info {
-            help.push_str(&format!("  {} - {}\n", func.signature, func.description));
-            if let Some(example) = &func.example {
-                help.push_str(&format!("    Example: {}\n", example));
-            }
-            help.push('\n');
-        }
-
-        help
+        registry::format_help()
     }
 }
 
@@ -358,7 +452,7 @@ mod tests {
         assert!(!result.valid);
         assert!(!result.errors.is_empty());
         assert_eq!(result.errors[0].error_type, ErrorType::ForbiddenFunction);
-        assert!(result.errors[0].suggestion.contains("put"));
+        assert!(result.errors[0].suggestion.contains("insert"));
     }
 
     #[test]
@@ -378,6 +472,44 @@ mod tests {
         assert!(!result.warnings.is_empty());
     }
 
+    #[test]
+    fn test_forbidden_alias_and_comments() {
+        let validator = LuaValidator::new();
+        let result = validator.validate("local o = os\no.execute('rm -rf /')\nreturn 1");
+        assert!(!result.valid);
+        assert!(result.errors.iter().any(|e| e.message.contains("'os.execute' (through alias 'o')") && e.line == Some(2)));
+
+        // Mentions inside comments and strings are not calls
+        let result = validator.validate("-- io.open is not allowed here\nreturn 'os.execute'");
+        assert!(result.valid);
+    }
+
+    #[test]
+    fn test_undefined_global_and_argument_count() {
+        let validator = LuaValidator::new();
+        let result = validator.validate("put('config', 'theme', 'dark')\nreturn select('config')");
+        assert!(!result.valid);
+        let undefined = result.errors.iter().find(|e| e.error_type == ErrorType::UndefinedVariable).unwrap();
+        assert!(undefined.suggestion.contains("insert"));
+        let arity = result.errors.iter().find(|e| e.error_type == ErrorType::ArgumentCount).unwrap();
+        assert_eq!(arity.line, Some(2));
+        assert!(arity.suggestion.contains("select(namespace, key)"));
+
+        let result = LuaValidator::new().with_globals(&["args"]).validate("return args.name");
+        assert!(result.valid);
+    }
+
+    #[test]
+    fn test_unused_local_span() {
+        let validator = LuaValidator::new();
+        let result = validator.validate("local unused = 1\nreturn 2");
+        assert!(result.valid);
+        let warning = &result.warnings[0];
+        assert_eq!(warning.warning_type, crate::lua::errors::WarningType::UnusedVariable);
+        let span = warning.span.unwrap();
+        assert_eq!((span.start_line, span.start_column, span.end_column), (1, 7, 13));
+    }
+
     #[test]
     fn test_levenshtein() {
         assert_eq!(LuaValidator::levenshtein("put", "put"), 0);
@@ -390,7 +522,8 @@ mod tests {
         let validator = LuaValidator::new();
         let funcs = validator.get_available_functions();
         assert!(!funcs.is_empty());
-        assert!(funcs.iter().any(|f| f.name == "put"));
+        assert!(funcs.iter().any(|f| f.name == "insert"));
+        assert!(!funcs.iter().any(|f| f.name == "put"));
         assert!(funcs.iter().any(|f| f.name == "semantic_search"));
     }
 }
@@ -6,7 +6,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 /// Embedded Liath standard library
-const LIATH_STDLIB: &str = include_str!("../../lua/liath.lua");
+pub(crate) const LIATH_STDLIB: &str = include_str!("../../lua/liath.lua");
 
 pub struct LuaVM {
     lua: Lua,
@@ -16,8 +16,12 @@ pub struct LuaVM {
 }
 
 impl LuaVM {
-    #[allow(deprecated)]
     pub fn new(luarocks_path: PathBuf) -> LuaResult<Self> {
+        Self::with_packages(luarocks_path, Arc::new(RwLock::new(HashMap::new())))
+    }
+
+    #[allow(deprecated)]
+    fn with_packages(luarocks_path: PathBuf, installed_packages: Arc<RwLock<HashMap<String, String>>>) -> LuaResult<Self> {
         let lua = Lua::new();
 
         // Register print function and initialize standard library
@@ -56,7 +60,7 @@ impl LuaVM {
 
         Ok(Self {
             lua,
-            installed_packages: Arc::new(RwLock::new(HashMap::new())),
+            installed_packages,
             luarocks_path,
         })
     }
@@ -90,6 +94,33 @@ impl LuaVM {
     }
 }
 
+/// A handle giving each request its own [`LuaVM`]
+///
+/// A Lua state cannot move between threads, and a script may change any global
+/// or library table, which must not reach the next script since it may run as
+/// another user. So a `SharedLuaVM` (which is `Send + Sync`) creates a fresh VM
+/// for every request; VMs share only the LuaRocks path and installed packages.
+#[derive(Clone)]
+pub struct SharedLuaVM {
+    luarocks_path: PathBuf,
+    installed_packages: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl SharedLuaVM {
+    /// Share the LuaRocks path and installed packages of `vm`
+    pub fn new(vm: LuaVM) -> Self {
+        Self {
+            luarocks_path: vm.luarocks_path.clone(),
+            installed_packages: vm.installed_packages.clone(),
+        }
+    }
+
+    /// A new VM for one request
+    pub fn fresh(&self) -> LuaResult<LuaVM> {
+        LuaVM::with_packages(self.luarocks_path.clone(), self.installed_packages.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -212,4 +243,23 @@ mod tests {
         let packages = vm.list_installed_packages().unwrap();
         assert!(packages.contains(&"test-package".to_string()));
     }
+
+    #[test]
+    fn test_shared_vm_fresh_per_request() {
+        let shared = SharedLuaVM::new(LuaVM::new(PathBuf::from("luarocks")).unwrap());
+        shared.fresh().unwrap().install_package("shared-package").unwrap();
+
+        // Globals and library tables a script changes do not outlive its VM
+        shared.fresh().unwrap().execute("marker = 1 string.upper = nil").unwrap();
+        let vm = shared.fresh().unwrap();
+        let marker = vm.execute_with_context(|ctx| ctx.load("return marker == nil and string.upper ~= nil").eval::<bool>()).unwrap();
+        assert!(marker);
+
+        // VMs made on other threads share the packages
+        let handle = shared.clone();
+        std::thread::spawn(move || {
+            let vm = handle.fresh().unwrap();
+            assert!(vm.list_installed_packages().unwrap().contains(&"shared-package".to_string()));
+        }).join().unwrap();
+    }
 }
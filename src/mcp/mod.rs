@@ -2,7 +2,14 @@
 //!
 //! Exposes Liath's database capabilities as MCP tools for AI assistants.
 
+mod resources;
 mod server;
 mod tools;
 
 pub use server::run_mcp_server;
+#[cfg(feature = "server")]
+pub(crate) use server::{handle_message, parse_error};
+#[cfg(feature = "server")]
+pub(crate) use resources::Subscriptions;
+#[cfg(feature = "server")]
+pub(crate) use tools::LiathService;
@@ -0,0 +1,393 @@
+//! MCP resources: stored values, agent memories and conversations
+//!
+//! Resources are addressed by `liath://` URIs whose path segments are
+//! percent-encoded. Listing pages through them in URI order; subscribing to one
+//! sends `notifications/resources/updated` whenever the change feed touches it.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use crate::agent::{Agent, Conversation, Memory};
+use crate::query::{scan, Change, ChangeFilter, ChangeKind, Subscription};
+use super::tools::LiathService;
+
+/// Resources returned by one `resources/list` call
+const PAGE_SIZE: usize = 100;
+
+/// A resource a client can read or subscribe to
+///
+/// Variants are declared in listing order.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ResourceUri {
+    /// Every memory of an agent, oldest first
+    Memories { agent: String },
+    /// Every message of a conversation, oldest first
+    Conversation { agent: String, conversation: String },
+    /// One stored value
+    Key { namespace: String, key: String },
+}
+
+impl ResourceUri {
+    pub(crate) fn parse(uri: &str) -> Result<Self, String> {
+        let unknown = || format!("Unknown resource URI: {}", uri);
+        let path = uri.strip_prefix("liath://").ok_or_else(unknown)?;
+        let segments = path.split('/').map(decode).collect::<Option<Vec<_>>>().ok_or_else(unknown)?;
+        match segments.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+            ["namespace", namespace, "key", key] => {
+                Ok(ResourceUri::Key { namespace: namespace.to_string(), key: key.to_string() })
+            }
+            ["agent", agent, "memories"] => Ok(ResourceUri::Memories { agent: agent.to_string() }),
+            ["agent", agent, "conversation", conversation] => {
+                Ok(ResourceUri::Conversation { agent: agent.to_string(), conversation: conversation.to_string() })
+            }
+            _ => Err(unknown()),
+        }
+    }
+
+    /// Namespace holding the resource's data
+    fn namespace(&self) -> String {
+        match self {
+            ResourceUri::Key { namespace, .. } => namespace.clone(),
+            ResourceUri::Memories { agent } => format!("agent_{}_memory", agent),
+            ResourceUri::Conversation { agent, conversation } => format!("agent_{}_conv_{}", agent, conversation),
+        }
+    }
+
+    /// Whether `change` alters what reading the resource returns
+    ///
+    /// Memories write their metadata last when stored and when forgotten, and
+    /// conversations write one key per message.
+    fn is_changed_by(&self, change: &Change) -> bool {
+        if change.namespace != self.namespace() {
+            return false;
+        }
+        if change.kind == ChangeKind::DeleteNamespace {
+            return true;
+        }
+        let Some(key) = change.key.as_deref() else {
+            return false;
+        };
+        match self {
+            ResourceUri::Key { key: resource_key, .. } => key == resource_key,
+            ResourceUri::Memories { .. } => key.starts_with("meta:"),
+            ResourceUri::Conversation { .. } => key.starts_with("msg:"),
+        }
+    }
+}
+
+impl fmt::Display for ResourceUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceUri::Key { namespace, key } => write!(f, "liath://namespace/{}/key/{}", encode(namespace), encode(key)),
+            ResourceUri::Memories { agent } => write!(f, "liath://agent/{}/memories", encode(agent)),
+            ResourceUri::Conversation { agent, conversation } => {
+                write!(f, "liath://agent/{}/conversation/{}", encode(agent), encode(conversation))
+            }
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved characters, so a segment cannot contain `/`
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = segment.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// JSON values are served as JSON, anything else as plain text
+fn mime_type(text: &str) -> &'static str {
+    if serde_json::from_str::<Value>(text).is_ok() {
+        "application/json"
+    } else {
+        "text/plain"
+    }
+}
+
+/// The URI templates of the resources `resources/read` understands
+pub(crate) fn templates() -> Value {
+    json!([
+        {
+            "uriTemplate": "liath://namespace/{namespace}/key/{key}",
+            "name": "Stored value",
+            "description": "A value in a namespace, as JSON if it parses as JSON and as text otherwise"
+        },
+        {
+            "uriTemplate": "liath://agent/{agent}/memories",
+            "name": "Agent memories",
+            "description": "Every memory of an agent with its tags, oldest first",
+            "mimeType": "application/json"
+        },
+        {
+            "uriTemplate": "liath://agent/{agent}/conversation/{conversation}",
+            "name": "Conversation",
+            "description": "Every message of an agent's conversation, oldest first",
+            "mimeType": "application/json"
+        }
+    ])
+}
+
+fn may_select(service: &LiathService, namespace: &str) -> bool {
+    service.query_executor.is_authorized_for(&service.user_id, "select", namespace)
+}
+
+/// The agent resources the caller may read, in listing order
+fn agent_resources(service: &LiathService) -> Result<Vec<ResourceUri>, String> {
+    let Some(db) = &service.db else {
+        return Ok(Vec::new());
+    };
+    let mut resources = Vec::new();
+    for metadata in Agent::list_agents(db).map_err(|e| e.to_string())? {
+        let agent = Agent::new(&metadata.id, db.clone());
+        let memories = ResourceUri::Memories { agent: metadata.id.clone() };
+        if db.namespace_exists(&memories.namespace()) {
+            resources.push(memories);
+        }
+        for conversation in agent.conversations().map_err(|e| e.to_string())? {
+            resources.push(ResourceUri::Conversation { agent: metadata.id.clone(), conversation });
+        }
+    }
+    resources.retain(|resource| may_select(service, &resource.namespace()));
+    resources.sort();
+    Ok(resources)
+}
+
+/// One page of resources after `cursor`, the URI that ended the previous page
+pub(crate) fn list(service: &LiathService, cursor: Option<&str>) -> Result<Value, String> {
+    let after = cursor
+        .map(|cursor| ResourceUri::parse(cursor).map_err(|_| format!("Invalid cursor: {}", cursor)))
+        .transpose()?;
+    let describe = |resource: &ResourceUri, mime_type: &str| {
+        let name = match resource {
+            ResourceUri::Key { namespace, key } => format!("{}/{}", namespace, key),
+            ResourceUri::Memories { agent } => format!("Memories of agent '{}'", agent),
+            ResourceUri::Conversation { agent, conversation } => format!("Conversation '{}' of agent '{}'", conversation, agent),
+        };
+        json!({ "uri": resource.to_string(), "name": name, "mimeType": mime_type })
+    };
+
+    let agents = agent_resources(service)?;
+    let mut page: Vec<Value> = agents
+        .iter()
+        .filter(|resource| after.as_ref().is_none_or(|after| *resource > after))
+        .take(PAGE_SIZE + 1)
+        .map(|resource| describe(resource, "application/json"))
+        .collect();
+
+    // System namespaces and keys start with '_'; agents' namespaces are listed above
+    let listed = |namespace: &str| {
+        !namespace.starts_with('_') && !agents.iter().any(|resource| resource.namespace() == namespace)
+    };
+    let mut namespaces: Vec<String> = service
+        .query_executor
+        .visible_namespaces(&service.user_id)
+        .into_iter()
+        .filter(|namespace| listed(namespace) && may_select(service, namespace))
+        .collect();
+    namespaces.sort();
+    for namespace in namespaces {
+        if page.len() > PAGE_SIZE {
+            break;
+        }
+        let start = match &after {
+            Some(ResourceUri::Key { namespace: last, key }) if *last == namespace => Some(scan::encode_cursor(key.as_bytes())),
+            Some(ResourceUri::Key { namespace: last, .. }) if *last > namespace => continue,
+            _ => None,
+        };
+        let entries = service
+            .query_executor
+            .scan_iter(&namespace, "", start.as_deref(), &service.user_id)
+            .map_err(|e| e.to_string())?;
+        let entries = entries.filter(|entry| entry.as_ref().map_or(true, |entry| !entry.key.starts_with('_')));
+        for entry in entries.take(PAGE_SIZE + 1 - page.len()) {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let resource = ResourceUri::Key { namespace: namespace.clone(), key: entry.key };
+            page.push(describe(&resource, mime_type(&entry.value)));
+        }
+    }
+
+    let next_cursor = if page.len() > PAGE_SIZE {
+        page.truncate(PAGE_SIZE);
+        page.last().map(|resource| resource["uri"].clone())
+    } else {
+        None
+    };
+    let mut result = json!({ "resources": page });
+    if let Some(next_cursor) = next_cursor {
+        result["nextCursor"] = next_cursor;
+    }
+    Ok(result)
+}
+
+/// The contents of the resource at `uri`
+pub(crate) fn read(service: &LiathService, uri: &str) -> Result<Value, String> {
+    let resource = ResourceUri::parse(uri)?;
+    let namespace = resource.namespace();
+    let (mime_type, text) = match &resource {
+        ResourceUri::Key { key, .. } => {
+            let value = service
+                .query_executor
+                .get_as(&namespace, key.as_bytes(), &service.user_id)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Resource not found: {}", uri))?;
+            let text = String::from_utf8_lossy(&value).into_owned();
+            (mime_type(&text), text)
+        }
+        ResourceUri::Memories { agent } | ResourceUri::Conversation { agent, .. } => {
+            let db = service.db.as_ref().ok_or("Agent resources need a server started with the database")?;
+            if !may_select(service, &namespace) {
+                return Err(format!("'{}' lacks the 'select' permission on '{}'", service.user_id, namespace));
+            }
+            if !db.namespace_exists(&namespace) {
+                return Err(format!("Resource not found: {}", uri));
+            }
+            let contents = match &resource {
+                ResourceUri::Conversation { conversation, .. } => Conversation::load(conversation, agent, db.clone())
+                    .and_then(|conversation| conversation.messages())
+                    .map(|messages| json!(messages)),
+                _ => Memory::new(agent, db.clone()).and_then(|memory| memory.list()).map(|memories| json!(memories)),
+            };
+            ("application/json", contents.map_err(|e| e.to_string())?.to_string())
+        }
+    };
+    Ok(json!({ "contents": [{ "uri": uri, "mimeType": mime_type, "text": text }] }))
+}
+
+/// One client's resource subscriptions, and where their update notifications go
+#[derive(Clone)]
+pub(crate) struct Subscriptions {
+    notify: mpsc::Sender<Value>,
+    runtime: tokio::runtime::Handle,
+    active: Arc<Mutex<HashMap<String, AbortHandle>>>,
+}
+
+impl Subscriptions {
+    /// Subscriptions whose notifications go to `notify`, forwarded on the current runtime
+    ///
+    /// Notifications that find `notify` full are dropped.
+    pub(crate) fn new(notify: mpsc::Sender<Value>) -> Self {
+        Self { notify, runtime: tokio::runtime::Handle::current(), active: Arc::default() }
+    }
+
+    /// Watch the resource at `uri` for the caller of `service`
+    pub(crate) fn subscribe(&self, service: &LiathService, uri: &str) -> Result<(), String> {
+        let resource = ResourceUri::parse(uri)?;
+        let filter = ChangeFilter { namespace: Some(resource.namespace()), ..Default::default() };
+        let subscription = service.query_executor.subscribe(filter, &service.user_id).map_err(|e| e.to_string())?;
+        let task = self.runtime.spawn(forward_updates(subscription, resource, uri.to_string(), self.notify.clone()));
+        if let Some(previous) = self.active.lock().unwrap().insert(uri.to_string(), task.abort_handle()) {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unsubscribe(&self, uri: &str) {
+        if let Some(task) = self.active.lock().unwrap().remove(uri) {
+            task.abort();
+        }
+    }
+
+    /// Stop every subscription, e.g. when the client's session ends
+    #[cfg(feature = "server")]
+    pub(crate) fn clear(&self) {
+        for (_, task) in self.active.lock().unwrap().drain() {
+            task.abort();
+        }
+    }
+}
+
+/// Notify `notify` of changes to `resource` until nobody receives them any more
+async fn forward_updates(mut subscription: Subscription, resource: ResourceUri, uri: String, notify: mpsc::Sender<Value>) {
+    loop {
+        let change = tokio::select! {
+            change = subscription.next() => change,
+            () = notify.closed() => return,
+        };
+        match change {
+            Ok(Some(change)) if resource.is_changed_by(&change) => {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/resources/updated",
+                    "params": { "uri": uri }
+                });
+                if let Err(mpsc::error::TrySendError::Closed(_)) = notify.try_send(notification) {
+                    return;
+                }
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => return,
+            Err(e) => {
+                tracing::debug!("Subscription to {} ended: {:#}", uri, e);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_uris() {
+        let key = ResourceUri::Key { namespace: "docs".to_string(), key: "user:7/a b".to_string() };
+        assert_eq!(key.to_string(), "liath://namespace/docs/key/user%3A7%2Fa%20b");
+        assert_eq!(ResourceUri::parse(&key.to_string()).unwrap(), key);
+        assert_eq!(
+            ResourceUri::parse("liath://agent/helper/conversation/c1").unwrap(),
+            ResourceUri::Conversation { agent: "helper".to_string(), conversation: "c1".to_string() }
+        );
+        assert_eq!(ResourceUri::parse("liath://agent/helper/memories").unwrap().namespace(), "agent_helper_memory");
+        for invalid in ["liath://namespace/docs", "file:///etc", "liath://agent/a/memories/x", "liath://namespace/d/key/%zz"] {
+            assert!(ResourceUri::parse(invalid).is_err(), "{}", invalid);
+        }
+        // Agent resources list before stored values
+        assert!(ResourceUri::Memories { agent: "z".to_string() } < key);
+    }
+
+    #[test]
+    fn test_resource_changes() {
+        let change = |namespace: &str, kind: ChangeKind, key: Option<&str>| Change {
+            sequence: 1,
+            timestamp_ms: 0,
+            kind,
+            namespace: namespace.to_string(),
+            key: key.map(String::from),
+            id: None,
+        };
+        let key = ResourceUri::parse("liath://namespace/docs/key/a").unwrap();
+        assert!(key.is_changed_by(&change("docs", ChangeKind::Put, Some("a"))));
+        assert!(key.is_changed_by(&change("docs", ChangeKind::DeleteNamespace, None)));
+        assert!(!key.is_changed_by(&change("docs", ChangeKind::Put, Some("ab"))));
+        assert!(!key.is_changed_by(&change("other", ChangeKind::Put, Some("a"))));
+
+        let memories = ResourceUri::parse("liath://agent/helper/memories").unwrap();
+        assert!(memories.is_changed_by(&change("agent_helper_memory", ChangeKind::Put, Some("meta:3"))));
+        assert!(!memories.is_changed_by(&change("agent_helper_memory", ChangeKind::Put, Some("_next_id"))));
+        assert!(!memories.is_changed_by(&change("agent_helper_memory", ChangeKind::AddVector, None)));
+    }
+}
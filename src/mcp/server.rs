@@ -1,6 +1,7 @@
 //! MCP server implementation for Liath
 //!
-//! Implements the Model Context Protocol over stdio using JSON-RPC 2.0
+//! Implements the Model Context Protocol over stdio using JSON-RPC 2.0. The
+//! HTTP server answers the same messages through [`handle_message`].
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -8,8 +9,15 @@ use serde_json::{json, Value};
 use std::io::{BufRead, BufReader, Write};
 
 use crate::query::QueryExecutor;
+use super::resources::{self, Subscriptions};
 use super::tools::{get_tools, LiathService};
 
+/// Protocol versions the server speaks, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-03-26", "2024-11-05"];
+
+/// Notifications waiting to be written to stdout
+const NOTIFICATION_BUFFER: usize = 64;
+
 /// JSON-RPC request
 #[derive(Debug, Deserialize)]
 struct JsonRpcRequest {
@@ -66,7 +74,17 @@ impl JsonRpcResponse {
 
 /// Run the MCP server over stdio
 pub async fn run_mcp_server(query_executor: QueryExecutor, user_id: String) -> Result<()> {
-    let service = LiathService::new(query_executor, user_id);
+    // Resource updates are written as they happen, between replies
+    let (notify, mut notifications) = tokio::sync::mpsc::channel::<Value>(NOTIFICATION_BUFFER);
+    tokio::spawn(async move {
+        while let Some(notification) = notifications.recv().await {
+            let mut stdout = std::io::stdout();
+            if writeln!(stdout, "{}", notification).and_then(|()| stdout.flush()).is_err() {
+                break;
+            }
+        }
+    });
+    let service = LiathService::new(query_executor, user_id).with_subscriptions(Subscriptions::new(notify));
 
     let stdin = std::io::stdin();
     let mut stdout = std::io::stdout();
@@ -84,43 +102,62 @@ pub async fn run_mcp_server(query_executor: QueryExecutor, user_id: String) -> R
             continue;
         }
 
-        let request: JsonRpcRequest = match serde_json::from_str(&line) {
-            Ok(r) => r,
-            Err(e) => {
-                let response = JsonRpcResponse::error(
-                    Value::Null,
-                    -32700,
-                    format!("Parse error: {}", e),
-                );
-                writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
-                stdout.flush()?;
-                continue;
-            }
-        };
-
-        let id = request.id.clone().unwrap_or(Value::Null);
-        let response = handle_request(&service, &request).await;
-
-        let json_response = match response {
-            Ok(result) => JsonRpcResponse::success(id, result),
-            Err(e) => JsonRpcResponse::error(id, -32603, e),
+        let response = match serde_json::from_str(&line) {
+            Ok(message) => handle_message(&service, message).await,
+            Err(e) => Some(parse_error(e)),
         };
 
-        writeln!(stdout, "{}", serde_json::to_string(&json_response)?)?;
-        stdout.flush()?;
+        if let Some(response) = response {
+            writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
+            stdout.flush()?;
+        }
     }
 
     Ok(())
 }
 
+/// The reply to a message that is not valid JSON
+pub(crate) fn parse_error(error: serde_json::Error) -> Value {
+    json!(JsonRpcResponse::error(Value::Null, -32700, format!("Parse error: {}", error)))
+}
+
+/// Answer one JSON-RPC message
+///
+/// Notifications, and responses to requests the server never sends, get no reply.
+pub(crate) async fn handle_message(service: &LiathService, message: Value) -> Option<Value> {
+    message.get("method")?;
+    let request: JsonRpcRequest = match serde_json::from_value(message) {
+        Ok(request) => request,
+        Err(e) => return Some(json!(JsonRpcResponse::error(Value::Null, -32600, format!("Invalid request: {}", e)))),
+    };
+    let id = request.id.clone()?;
+    let response = match handle_request(service, &request).await {
+        Ok(result) => JsonRpcResponse::success(id, result),
+        Err(e) => JsonRpcResponse::error(id, -32603, e),
+    };
+    Some(json!(response))
+}
+
+/// The `uri` parameter of a resources request
+fn resource_uri(request: &JsonRpcRequest) -> Result<&str, String> {
+    request.params.get("uri").and_then(|v| v.as_str()).ok_or_else(|| "Missing uri".to_string())
+}
+
 async fn handle_request(service: &LiathService, request: &JsonRpcRequest) -> Result<Value, String> {
     match request.method.as_str() {
         "initialize" => {
+            // Answer with the client's version if we speak it, otherwise our newest
+            let requested = request.params.get("protocolVersion").and_then(|v| v.as_str());
+            let version = PROTOCOL_VERSIONS.iter().find(|v| Some(**v) == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
             Ok(json!({
-                "protocolVersion": "2024-11-05",
+                "protocolVersion": version,
                 "capabilities": {
                     "tools": {
                         "listChanged": false
+                    },
+                    "resources": {
+                        "subscribe": true,
+                        "listChanged": false
                     }
                 },
                 "serverInfo": {
@@ -131,14 +168,10 @@ async fn handle_request(service: &LiathService, request: &JsonRpcRequest) -> Res
             }))
         }
 
-        "initialized" => {
-            // Notification, no response needed but we return empty for consistency
-            Ok(json!({}))
-        }
-
         "tools/list" => {
             let tools: Vec<Value> = get_tools()
                 .into_iter()
+                .chain(service.procedure_tools())
                 .map(|t| {
                     json!({
                         "name": t.name,
@@ -182,40 +215,25 @@ async fn handle_request(service: &LiathService, request: &JsonRpcRequest) -> Res
         }
 
         "resources/list" => {
-            // List namespaces as browsable resources
-            let namespaces = service.query_executor.list_namespaces();
-            let resources: Vec<Value> = namespaces.iter().map(|ns| {
-                json!({
-                    "uri": format!("liath://namespace/{}", ns),
-                    "name": ns,
-                    "mimeType": "application/json",
-                    "description": format!("Liath namespace: {}", ns)
-                })
-            }).collect();
-            Ok(json!({ "resources": resources }))
+            let cursor = request.params.get("cursor").and_then(|v| v.as_str());
+            resources::list(service, cursor)
         }
 
-        "resources/read" => {
-            let uri = request.params.get("uri")
-                .and_then(|v| v.as_str())
-                .ok_or("Missing uri")?;
+        "resources/templates/list" => Ok(json!({ "resourceTemplates": resources::templates() })),
 
-            // Parse the URI to get namespace
-            if let Some(ns) = uri.strip_prefix("liath://namespace/") {
-                if service.query_executor.namespace_exists(ns) {
-                    Ok(json!({
-                        "contents": [{
-                            "uri": uri,
-                            "mimeType": "application/json",
-                            "text": format!("Namespace '{}' exists. Use liath_kv_get to read specific keys.", ns)
-                        }]
-                    }))
-                } else {
-                    Err(format!("Namespace '{}' not found", ns))
-                }
-            } else {
-                Err(format!("Unknown resource URI: {}", uri))
+        "resources/read" => resources::read(service, resource_uri(request)?),
+
+        "resources/subscribe" => {
+            let subscriptions = service.subscriptions.as_ref().ok_or("Subscriptions are not available")?;
+            subscriptions.subscribe(service, resource_uri(request)?)?;
+            Ok(json!({}))
+        }
+
+        "resources/unsubscribe" => {
+            if let Some(subscriptions) = &service.subscriptions {
+                subscriptions.unsubscribe(resource_uri(request)?);
             }
+            Ok(json!({}))
         }
 
         "prompts/list" => {
@@ -4,9 +4,12 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
-use crate::query::QueryExecutor;
+use crate::auth::{estimate_tokens, Resource};
+use crate::query::{AuditOutcome, QueryExecutor};
 use crate::EmbeddedLiath;
 use crate::agent::{Agent, Role};
+use crate::lua::registry;
+use super::resources::Subscriptions;
 
 /// Tool definition for MCP
 #[derive(Debug, Clone, Serialize)]
@@ -80,6 +83,8 @@ pub struct LiathService {
     pub query_executor: Arc<QueryExecutor>,
     pub db: Option<Arc<EmbeddedLiath>>,
     pub user_id: String,
+    /// Where `resources/subscribe` registers; without it, subscribing fails
+    pub(crate) subscriptions: Option<Subscriptions>,
 }
 
 impl LiathService {
@@ -88,6 +93,7 @@ impl LiathService {
             query_executor: Arc::new(query_executor),
             db: None,
             user_id,
+            subscriptions: None,
         }
     }
 
@@ -97,8 +103,15 @@ impl LiathService {
             query_executor: Arc::new(db.query_executor()),
             db: Some(db),
             user_id,
+            subscriptions: None,
         }
     }
+
+    /// Serve resource subscriptions through `subscriptions`
+    pub(crate) fn with_subscriptions(mut self, subscriptions: Subscriptions) -> Self {
+        self.subscriptions = Some(subscriptions);
+        self
+    }
 }
 
 // ============================================================
@@ -108,6 +121,8 @@ impl LiathService {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExecuteLuaInput {
     pub code: String,
+    #[serde(default)]
+    pub explain: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -136,6 +151,8 @@ pub struct CreateNamespaceInput {
     pub dimensions: Option<usize>,
     #[serde(default)]
     pub metric: Option<String>,
+    #[serde(default)]
+    pub encrypted: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -218,13 +235,21 @@ pub fn get_tools() -> Vec<Tool> {
     vec![
         Tool::new(
             "liath_execute_lua",
-            "Execute Lua code against the Liath database. Use for complex queries or custom operations.",
+            &format!(
+                "Execute Lua code against the Liath database. Use for complex queries or custom operations. \
+                 Return the result with 'return'. Available functions:\n{}",
+                registry::summary()
+            ),
             serde_json::json!({
                 "type": "object",
                 "properties": {
                     "code": {
                         "type": "string",
                         "description": "Lua code to execute"
+                    },
+                    "explain": {
+                        "type": "boolean",
+                        "description": "Also return a profile of calls, time and bytes per database function"
                     }
                 },
                 "required": ["code"]
@@ -280,7 +305,8 @@ pub fn get_tools() -> Vec<Tool> {
                 "properties": {
                     "name": { "type": "string", "description": "Namespace name" },
                     "dimensions": { "type": "integer", "description": "Vector dimensions (default: 384)" },
-                    "metric": { "type": "string", "description": "Distance metric: cosine or euclidean" }
+                    "metric": { "type": "string", "description": "Distance metric: cosine or euclidean" },
+                    "encrypted": { "type": "boolean", "description": "Encrypt stored values (requires configured keys)" }
                 },
                 "required": ["name"]
             }),
@@ -423,6 +449,38 @@ pub fn get_tools() -> Vec<Tool> {
     ]
 }
 
+/// Prefix of the tools generated for stored procedures
+pub const PROCEDURE_TOOL_PREFIX: &str = "liath_proc_";
+
+impl LiathService {
+    /// One tool per stored procedure; tool arguments are passed to the procedure as `args`
+    pub fn procedure_tools(&self) -> Vec<Tool> {
+        let procedures = match self.query_executor.list_procedures() {
+            Ok(procedures) => procedures,
+            Err(e) => {
+                tracing::warn!("Failed to list procedures for MCP tools: {}", e);
+                return Vec::new();
+            }
+        };
+        procedures
+            .into_iter()
+            .map(|p| {
+                let description = p.description.clone().unwrap_or_else(|| {
+                    format!("Call the '{}' stored procedure (version {})", p.name, p.version)
+                });
+                Tool::new(
+                    &format!("{}{}", PROCEDURE_TOOL_PREFIX, p.name),
+                    &description,
+                    serde_json::json!({
+                        "type": "object",
+                        "additionalProperties": true
+                    }),
+                )
+            })
+            .collect()
+    }
+}
+
 // ============================================================
 // Tool Handler
 // ============================================================
@@ -522,11 +580,56 @@ impl LiathService {
                     Err(e) => CallToolResult::error(vec![Content::text(format!("Invalid params: {}", e))]),
                 }
             }
-            _ => CallToolResult::error(vec![Content::text(format!("Unknown tool: {}", name))]),
+            _ => match name.strip_prefix(PROCEDURE_TOOL_PREFIX) {
+                Some(procedure) => self.call_procedure(procedure, arguments).await,
+                None => CallToolResult::error(vec![Content::text(format!("Unknown tool: {}", name))]),
+            },
+        }
+    }
+
+    /// Check that the service's user holds `permission` in `namespace`, auditing a denial
+    fn authorize(&self, permission: &str, namespace: &str) -> Result<(), CallToolResult> {
+        if self.query_executor.is_authorized_for(&self.user_id, permission, namespace) {
+            return Ok(());
+        }
+        let message = format!("'{}' lacks the '{}' permission on '{}'", self.user_id, permission, namespace);
+        self.query_executor.audit().record(&self.user_id, permission, Some(namespace), None, AuditOutcome::Denied, Some(&message));
+        Err(CallToolResult::error(vec![Content::text(format!("Unauthorized: {}", message))]))
+    }
+
+    /// Audit a mutation made through the agent API rather than the executor
+    /// Count the tokens of `text` against the caller's daily embedding quota
+    fn charge_embedding(&self, text: &str) -> Result<(), CallToolResult> {
+        self.query_executor
+            .charge(&self.user_id, Resource::EmbeddingTokens, estimate_tokens(text))
+            .map_err(|e| CallToolResult::error(vec![Content::text(format!("Error: {}", e))]))
+    }
+
+    fn record<T>(&self, operation: &str, namespace: &str, result: &anyhow::Result<T>) {
+        let (outcome, detail) = match result {
+            Ok(_) => (AuditOutcome::Ok, None),
+            Err(e) => (AuditOutcome::Failed, Some(e.to_string())),
+        };
+        self.query_executor.audit().record(&self.user_id, operation, Some(namespace), None, outcome, detail.as_deref());
+    }
+
+    async fn call_procedure(&self, name: &str, arguments: Value) -> CallToolResult {
+        match self.query_executor.call_procedure(name, arguments, &self.user_id).await {
+            Ok(result) => CallToolResult::success(vec![Content::text(result.to_string())]),
+            Err(e) => CallToolResult::error(vec![Content::text(format!("Error: {}", e))]),
         }
     }
 
     async fn execute_lua(&self, input: ExecuteLuaInput) -> CallToolResult {
+        if input.explain {
+            return match self.query_executor.execute_profiled(&input.code, &self.user_id).await {
+                Ok((result, profile)) => CallToolResult::success(vec![
+                    Content::text(result),
+                    Content::text(profile.format_table()),
+                ]),
+                Err(e) => CallToolResult::error(vec![Content::text(format!("Error: {}", e))]),
+            };
+        }
         match self.query_executor.execute(&input.code, &self.user_id).await {
             Ok(result) => CallToolResult::success(vec![Content::text(result)]),
             Err(e) => CallToolResult::error(vec![Content::text(format!("Error: {}", e))]),
@@ -534,7 +637,10 @@ impl LiathService {
     }
 
     async fn kv_get(&self, input: KvGetInput) -> CallToolResult {
-        match self.query_executor.get(&input.namespace, input.key.as_bytes()) {
+        if let Err(denied) = self.authorize("select", &input.namespace) {
+            return denied;
+        }
+        match self.query_executor.get_as(&input.namespace, input.key.as_bytes(), &self.user_id) {
             Ok(Some(value)) => {
                 let text = String::from_utf8_lossy(&value).to_string();
                 CallToolResult::success(vec![Content::text(text)])
@@ -545,21 +651,32 @@ impl LiathService {
     }
 
     async fn kv_put(&self, input: KvPutInput) -> CallToolResult {
-        match self.query_executor.put(&input.namespace, input.key.as_bytes(), input.value.as_bytes()) {
+        if let Err(denied) = self.authorize("insert", &input.namespace) {
+            return denied;
+        }
+        match self.query_executor.put_as(&input.namespace, input.key.as_bytes(), input.value.as_bytes(), &self.user_id) {
             Ok(_) => CallToolResult::success(vec![Content::text("OK")]),
             Err(e) => CallToolResult::error(vec![Content::text(format!("Error: {}", e))]),
         }
     }
 
     async fn kv_delete(&self, input: KvDeleteInput) -> CallToolResult {
-        match self.query_executor.delete(&input.namespace, input.key.as_bytes()) {
+        if let Err(denied) = self.authorize("delete", &input.namespace) {
+            return denied;
+        }
+        match self.query_executor.delete_as(&input.namespace, input.key.as_bytes(), &self.user_id) {
             Ok(_) => CallToolResult::success(vec![Content::text("Deleted")]),
             Err(e) => CallToolResult::error(vec![Content::text(format!("Error: {}", e))]),
         }
     }
 
     async fn list_namespaces(&self) -> CallToolResult {
-        let namespaces = self.query_executor.list_namespaces();
+        if !self.query_executor.auth_manager().read().unwrap().is_authorized_anywhere(&self.user_id, "list_namespaces") {
+            return CallToolResult::error(vec![Content::text(format!(
+                "Unauthorized: '{}' lacks the 'list_namespaces' permission", self.user_id
+            ))]);
+        }
+        let namespaces = self.query_executor.visible_namespaces(&self.user_id);
         let result = if namespaces.is_empty() {
             "No namespaces found.".to_string()
         } else {
@@ -569,6 +686,9 @@ impl LiathService {
     }
 
     async fn create_namespace(&self, input: CreateNamespaceInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("create_namespace", &input.name) {
+            return denied;
+        }
         let dims = input.dimensions.unwrap_or(384);
         let metric = input.metric.as_deref().unwrap_or("cosine");
 
@@ -579,7 +699,7 @@ impl LiathService {
                 "euclidean" | "l2" => MetricKind::L2sq,
                 _ => MetricKind::Cos,
             };
-            match self.query_executor.create_namespace(&input.name, dims, metric_kind, ScalarKind::F32) {
+            match self.query_executor.create_namespace_as(&input.name, dims, metric_kind, ScalarKind::F32, input.encrypted, &self.user_id) {
                 Ok(_) => CallToolResult::success(vec![Content::text(
                     format!("Created namespace '{}' ({}D, {})", input.name, dims, metric)
                 )]),
@@ -594,7 +714,10 @@ impl LiathService {
     }
 
     async fn delete_namespace(&self, input: DeleteNamespaceInput) -> CallToolResult {
-        match self.query_executor.delete_namespace(&input.name) {
+        if let Err(denied) = self.authorize("delete_namespace", &input.name) {
+            return denied;
+        }
+        match self.query_executor.delete_namespace_as(&input.name, &self.user_id) {
             Ok(_) => CallToolResult::success(vec![Content::text(
                 format!("Deleted namespace '{}'", input.name)
             )]),
@@ -610,6 +733,12 @@ impl LiathService {
     }
 
     async fn semantic_search(&self, input: SemanticSearchInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("similarity_search", &input.namespace) {
+            return denied;
+        }
+        if let Err(refused) = self.charge_embedding(&input.query) {
+            return refused;
+        }
         let k = input.k.unwrap_or(5);
 
         let embeddings = match self.query_executor.generate_embedding(vec![input.query.as_str()]) {
@@ -640,6 +769,12 @@ impl LiathService {
     }
 
     async fn store_document(&self, input: StoreDocumentInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("insert", &input.namespace) {
+            return denied;
+        }
+        if let Err(refused) = self.charge_embedding(&input.text) {
+            return refused;
+        }
         let embeddings = match self.query_executor.generate_embedding(vec![input.text.as_str()]) {
             Ok(e) => e,
             Err(e) => return CallToolResult::error(vec![Content::text(format!("Embedding error: {}", e))]),
@@ -650,7 +785,7 @@ impl LiathService {
             None => return CallToolResult::error(vec![Content::text("Failed to generate embedding")]),
         };
 
-        if let Err(e) = self.query_executor.put(&input.namespace, input.key.as_bytes(), input.text.as_bytes()) {
+        if let Err(e) = self.query_executor.put_as(&input.namespace, input.key.as_bytes(), input.text.as_bytes(), &self.user_id) {
             return CallToolResult::error(vec![Content::text(format!("Storage error: {}", e))]);
         }
 
@@ -723,6 +858,12 @@ impl LiathService {
     }
 
     async fn agent_store_memory(&self, input: AgentStoreMemoryInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("insert", &format!("agent_{}_memory", input.agent_id)) {
+            return denied;
+        }
+        if let Err(refused) = self.charge_embedding(&input.content) {
+            return refused;
+        }
         let db = match self.require_db() {
             Ok(db) => db,
             Err(err) => return err,
@@ -739,7 +880,9 @@ impl LiathService {
             .map(|t| t.iter().map(|s| s.as_str()).collect())
             .unwrap_or_default();
 
-        match memory.store(&input.content, &tags) {
+        let result = memory.store(&input.content, &tags);
+        self.record("agent_store_memory", &format!("agent_{}_memory", input.agent_id), &result);
+        match result {
             Ok(id) => CallToolResult::success(vec![Content::text(
                 format!("Stored memory with ID {}", id)
             )]),
@@ -748,6 +891,12 @@ impl LiathService {
     }
 
     async fn agent_recall_memory(&self, input: AgentRecallMemoryInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("select", &format!("agent_{}_memory", input.agent_id)) {
+            return denied;
+        }
+        if let Err(refused) = self.charge_embedding(&input.query) {
+            return refused;
+        }
         let db = match self.require_db() {
             Ok(db) => db,
             Err(err) => return err,
@@ -776,6 +925,9 @@ impl LiathService {
     }
 
     async fn agent_recall_by_tags(&self, input: AgentRecallByTagsInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("select", &format!("agent_{}_memory", input.agent_id)) {
+            return denied;
+        }
         let db = match self.require_db() {
             Ok(db) => db,
             Err(err) => return err,
@@ -806,6 +958,9 @@ impl LiathService {
     }
 
     async fn agent_add_message(&self, input: AgentAddMessageInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("insert", &format!("agent_{}_conv_{}", input.agent_id, input.conversation_id)) {
+            return denied;
+        }
         let db = match self.require_db() {
             Ok(db) => db,
             Err(err) => return err,
@@ -824,13 +979,18 @@ impl LiathService {
             other => Role::Tool(other.to_string()),
         };
 
-        match conversation.add_message(role, &input.content) {
+        let result = conversation.add_message(role, &input.content);
+        self.record("agent_add_message", &format!("agent_{}_conv_{}", input.agent_id, input.conversation_id), &result);
+        match result {
             Ok(_) => CallToolResult::success(vec![Content::text("Message added")]),
             Err(e) => CallToolResult::error(vec![Content::text(format!("Error: {}", e))]),
         }
     }
 
     async fn agent_get_messages(&self, input: AgentGetMessagesInput) -> CallToolResult {
+        if let Err(denied) = self.authorize("select", &format!("agent_{}_conv_{}", input.agent_id, input.conversation_id)) {
+            return denied;
+        }
         let db = match self.require_db() {
             Ok(db) => db,
             Err(err) => return err,
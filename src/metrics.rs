@@ -0,0 +1,234 @@
+//! Process-wide metrics in the Prometheus text format
+//!
+//! Counters and histograms are recorded where the work happens and kept in one
+//! registry for the whole process. Values that describe current state, such as
+//! index sizes or disk usage, are read when the metrics are rendered and added as
+//! gauges by whoever renders them.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use rlua::{Context as LuaContext, Error as LuaError, Function as LuaFunction, MultiValue};
+
+/// Bucket bounds for durations, in seconds
+pub const LATENCY_BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Bucket bounds for batch sizes
+pub const BATCH_BUCKETS: &[f64] = &[1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0, 512.0, 1024.0];
+
+pub const HTTP_REQUESTS: &str = "liath_http_requests_total";
+pub const HTTP_DURATION: &str = "liath_http_request_duration_seconds";
+pub const DB_FUNCTION_DURATION: &str = "liath_db_function_duration_seconds";
+pub const EMBEDDING_BATCH_SIZE: &str = "liath_embedding_batch_size";
+pub const EMBEDDING_DURATION: &str = "liath_embedding_duration_seconds";
+pub const LUA_ERRORS: &str = "liath_lua_errors_total";
+pub const AUTH_DENIALS: &str = "liath_auth_denials_total";
+pub const RATE_LIMITED: &str = "liath_rate_limited_total";
+
+/// Help text of the recorded metrics
+const HELP: &[(&str, &str)] = &[
+    (HTTP_REQUESTS, "HTTP requests by method, route and status"),
+    (HTTP_DURATION, "Time to answer HTTP requests, until the response headers"),
+    (DB_FUNCTION_DURATION, "Time spent in database functions called from Lua"),
+    (EMBEDDING_BATCH_SIZE, "Texts per embedding call"),
+    (EMBEDDING_DURATION, "Time to embed one batch of texts"),
+    (LUA_ERRORS, "Lua executions that failed, by error type"),
+    (AUTH_DENIALS, "Requests refused for a missing or invalid key or a missing permission"),
+    (RATE_LIMITED, "Requests and operations refused by a rate limit or daily quota, by limit"),
+];
+
+#[derive(Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket, not cumulative
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<&'static str, BTreeMap<String, u64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<String, Histogram>>,
+}
+
+static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(|| Mutex::new(Registry::default()));
+
+/// Add one to a counter
+pub fn increment(name: &'static str, labels: &[(&str, &str)]) {
+    let mut registry = REGISTRY.lock().unwrap();
+    *registry.counters.entry(name).or_default().entry(format_labels(labels)).or_default() += 1;
+}
+
+/// Record a value in a histogram with the given bucket bounds
+pub fn observe(name: &'static str, bounds: &'static [f64], labels: &[(&str, &str)], value: f64) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let histogram = registry
+        .histograms
+        .entry(name)
+        .or_default()
+        .entry(format_labels(labels))
+        .or_insert_with(|| Histogram { bounds, counts: vec![0; bounds.len()], sum: 0.0, count: 0 });
+    if let Some(bucket) = histogram.bounds.iter().position(|&bound| value <= bound) {
+        histogram.counts[bucket] += 1;
+    }
+    histogram.sum += value;
+    histogram.count += 1;
+}
+
+/// Record a duration in a latency histogram
+pub fn observe_duration(name: &'static str, labels: &[(&str, &str)], elapsed: Duration) {
+    observe(name, LATENCY_BUCKETS, labels, elapsed.as_secs_f64());
+}
+
+/// Count a failed Lua execution by the kind of error
+pub fn record_lua_error(error: &LuaError) {
+    increment(LUA_ERRORS, &[("type", lua_error_type(error))]);
+}
+
+fn lua_error_type(error: &LuaError) -> &'static str {
+    match error {
+        LuaError::SyntaxError { .. } => "syntax",
+        LuaError::RuntimeError(_) => "runtime",
+        LuaError::MemoryError(_) => "memory",
+        LuaError::CallbackError { cause, .. } => lua_error_type(cause),
+        LuaError::FromLuaConversionError { .. } | LuaError::ToLuaConversionError { .. } => "conversion",
+        LuaError::ExternalError(_) => "external",
+        _ => "other",
+    }
+}
+
+/// Wrap a bound database function so its calls are timed under `name`
+pub(crate) fn wrap<'lua>(
+    lua_ctx: LuaContext<'lua>,
+    name: &'static str,
+    function: LuaFunction<'lua>,
+) -> Result<LuaFunction<'lua>, LuaError> {
+    let key = lua_ctx.create_registry_value(function)?;
+    lua_ctx.create_function(move |lua_ctx, args: MultiValue| {
+        let function: LuaFunction = lua_ctx.registry_value(&key)?;
+        let started = Instant::now();
+        let result = function.call::<_, MultiValue>(args);
+        observe_duration(DB_FUNCTION_DURATION, &[("function", name)], started.elapsed());
+        result
+    })
+}
+
+/// Render metrics in the Prometheus text exposition format
+///
+/// Add gauges for current state, then `finish` to append the recorded counters
+/// and histograms.
+#[derive(Default)]
+pub struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a gauge with one sample per label set
+    pub fn gauge<'a>(&mut self, name: &str, help: &str, samples: impl IntoIterator<Item = (&'a [(&'a str, &'a str)], f64)>) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} gauge", name);
+        for (labels, value) in samples {
+            let _ = writeln!(self.out, "{}{} {}", name, format_labels(labels), value);
+        }
+    }
+
+    /// Add a gauge with a single unlabeled value
+    pub fn value(&mut self, name: &str, help: &str, value: f64) {
+        self.gauge(name, help, [(&[][..], value)]);
+    }
+
+    pub fn finish(mut self) -> String {
+        let registry = REGISTRY.lock().unwrap();
+        for (name, samples) in &registry.counters {
+            self.header(name, "counter");
+            for (labels, value) in samples {
+                let _ = writeln!(self.out, "{}{} {}", name, labels, value);
+            }
+        }
+        for (name, samples) in &registry.histograms {
+            self.header(name, "histogram");
+            for (labels, histogram) in samples {
+                let mut cumulative = 0;
+                for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+                    cumulative += count;
+                    let _ = writeln!(self.out, "{}_bucket{} {}", name, with_label(labels, "le", &bound.to_string()), cumulative);
+                }
+                let _ = writeln!(self.out, "{}_bucket{} {}", name, with_label(labels, "le", "+Inf"), histogram.count);
+                let _ = writeln!(self.out, "{}_sum{} {}", name, labels, histogram.sum);
+                let _ = writeln!(self.out, "{}_count{} {}", name, labels, histogram.count);
+            }
+        }
+        self.out
+    }
+
+    fn header(&mut self, name: &str, kind: &str) {
+        let help = HELP.iter().find(|(n, _)| *n == name).map_or("", |(_, help)| help);
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+}
+
+/// `{a="1",b="2"}`, or nothing without labels
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// Append one more label to a formatted label set
+fn with_label(labels: &str, name: &str, value: &str) -> String {
+    let label = format!("{}=\"{}\"", name, escape(value));
+    match labels.strip_suffix('}') {
+        Some(labels) => format!("{},{}}}", labels, label),
+        None => format!("{{{}}}", label),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counters_and_histograms() {
+        increment("liath_test_events_total", &[("kind", "a\"b")]);
+        increment("liath_test_events_total", &[("kind", "a\"b")]);
+        observe("liath_test_sizes", &[1.0, 10.0], &[], 5.0);
+        observe("liath_test_sizes", &[1.0, 10.0], &[], 50.0);
+
+        let mut exposition = Exposition::new();
+        exposition.gauge("liath_test_gauge", "A gauge", [(&[("ns", "docs")][..], 3.0)]);
+        let text = exposition.finish();
+
+        assert!(text.contains("# TYPE liath_test_gauge gauge\nliath_test_gauge{ns=\"docs\"} 3\n"));
+        assert!(text.contains("liath_test_events_total{kind=\"a\\\"b\"} 2\n"));
+        assert!(text.contains("liath_test_sizes_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("liath_test_sizes_bucket{le=\"10\"} 1\n"));
+        assert!(text.contains("liath_test_sizes_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("liath_test_sizes_sum 55\n"));
+        assert!(text.contains("liath_test_sizes_count 2\n"));
+    }
+
+    #[test]
+    fn test_lua_error_types() {
+        let lua = rlua::Lua::new();
+        let error = lua.load("return (").exec().unwrap_err();
+        assert_eq!(lua_error_type(&error), "syntax");
+        let error = lua.load("error('boom')").exec().unwrap_err();
+        assert_eq!(lua_error_type(&error), "runtime");
+    }
+}
@@ -0,0 +1,251 @@
+//! Syntax tree for the declarative query language
+//!
+//! Produced by [`QueryParser::parse_sql`](super::QueryParser::parse_sql) and
+//! consumed by the [`Planner`](super::Planner).
+
+use std::fmt;
+
+/// A parsed statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(Select),
+    /// `EXPLAIN SELECT ...`: describe the plan instead of running it
+    Explain(Select),
+}
+
+/// `SELECT ... FROM ... [JOIN ...] [WHERE ...] [GROUP BY ...] [HAVING ...] [ORDER BY ...] [LIMIT ...]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub items: Vec<SelectItem>,
+    pub from: TableRef,
+    pub join: Option<Join>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl Select {
+    /// Whether the query produces one row per group rather than per record
+    pub fn is_aggregate(&self) -> bool {
+        !self.group_by.is_empty()
+            || self.having.is_some()
+            || self.items.iter().any(|item| match item {
+                SelectItem::Expr { expr, .. } => expr.contains_aggregate(),
+                SelectItem::Wildcard => false,
+            })
+    }
+}
+
+/// An entry of the select list
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`
+    Wildcard,
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+/// A namespace in `FROM` or `JOIN`, with an optional alias
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRef {
+    pub namespace: String,
+    pub alias: Option<String>,
+}
+
+impl TableRef {
+    /// Name columns are qualified with: the alias if given, else the namespace
+    pub fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.namespace)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+}
+
+/// `[LEFT] JOIN table ON condition`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: TableRef,
+    pub on: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Like,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "COUNT" => Some(Aggregate::Count),
+            "SUM" => Some(Aggregate::Sum),
+            "AVG" => Some(Aggregate::Avg),
+            "MIN" => Some(Aggregate::Min),
+            "MAX" => Some(Aggregate::Max),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    /// A column: `key`, `value`, `distance`, a JSON field path like
+    /// `address.city`, optionally qualified by a table name or alias
+    Column(Vec<String>),
+    Not(Box<Expr>),
+    /// Arithmetic negation
+    Neg(Box<Expr>),
+    Binary { left: Box<Expr>, op: BinaryOp, right: Box<Expr> },
+    IsNull { expr: Box<Expr>, negated: bool },
+    InList { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
+    /// Aggregate call; `arg` is `None` for `COUNT(*)`
+    Aggregate { func: Aggregate, arg: Option<Box<Expr>> },
+    /// `NEAR(text, k)`: the `k` records whose embeddings are closest to `text`
+    Near { text: String, k: usize },
+}
+
+impl Expr {
+    pub fn contains_aggregate(&self) -> bool {
+        match self {
+            Expr::Aggregate { .. } => true,
+            Expr::Not(expr) | Expr::Neg(expr) | Expr::IsNull { expr, .. } => expr.contains_aggregate(),
+            Expr::Binary { left, right, .. } => left.contains_aggregate() || right.contains_aggregate(),
+            Expr::InList { expr, list, .. } => {
+                expr.contains_aggregate() || list.iter().any(Expr::contains_aggregate)
+            }
+            Expr::Literal(_) | Expr::Column(_) | Expr::Near { .. } => false,
+        }
+    }
+
+    pub fn contains_near(&self) -> bool {
+        match self {
+            Expr::Near { .. } => true,
+            Expr::Not(expr) | Expr::Neg(expr) | Expr::IsNull { expr, .. } => expr.contains_near(),
+            Expr::Binary { left, right, .. } => left.contains_near() || right.contains_near(),
+            Expr::InList { expr, list, .. } => expr.contains_near() || list.iter().any(Expr::contains_near),
+            Expr::Aggregate { arg, .. } => arg.as_ref().is_some_and(|a| a.contains_near()),
+            Expr::Literal(_) | Expr::Column(_) => false,
+        }
+    }
+
+    /// Split a condition into its top-level `AND` terms
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Binary { left, op: BinaryOp::And, right } => {
+                let mut terms = left.conjuncts();
+                terms.extend(right.conjuncts());
+                terms
+            }
+            other => vec![other],
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Null => write!(f, "NULL"),
+            Literal::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Literal::Number(n) => write!(f, "{}", n),
+            Literal::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Like => "LIKE",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Aggregate::Count => "COUNT",
+            Aggregate::Sum => "SUM",
+            Aggregate::Avg => "AVG",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(lit) => write!(f, "{}", lit),
+            Expr::Column(path) => write!(f, "{}", path.join(".")),
+            Expr::Not(expr) => write!(f, "NOT {}", expr),
+            Expr::Neg(expr) => write!(f, "-{}", expr),
+            Expr::Binary { left, op, right } => write!(f, "({} {} {})", left, op, right),
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            Expr::InList { expr, list, negated } => {
+                let items: Vec<String> = list.iter().map(|e| e.to_string()).collect();
+                write!(f, "{} {}IN ({})", expr, if *negated { "NOT " } else { "" }, items.join(", "))
+            }
+            Expr::Aggregate { func, arg: Some(arg) } => write!(f, "{}({})", func, arg),
+            Expr::Aggregate { func, arg: None } => write!(f, "{}(*)", func),
+            Expr::Near { text, k } => write!(f, "NEAR({}, {})", Literal::String(text.clone()), k),
+        }
+    }
+}
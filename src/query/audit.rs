@@ -0,0 +1,314 @@
+//! Audit log of mutations and denied authorizations
+//!
+//! Entries live in the `_audit` system namespace under
+//! `entry:<timestamp_ms>:<sequence>`, so a range scan returns them in time order.
+//! The namespace is append-only: the executor writes entries, users can only
+//! read them with the `read_audit` permission, and only retention removes them.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::core::{Namespace, NamespaceManager};
+use crate::metrics;
+
+/// System namespace holding audit entries
+pub const AUDIT_NAMESPACE: &str = "_audit";
+
+/// Permission needed to read the audit log
+pub const READ_AUDIT_PERMISSION: &str = "read_audit";
+
+/// Entries returned by a query when no limit is given
+pub const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+/// How many entries are written between retention sweeps
+const PRUNE_INTERVAL: u64 = 10_000;
+
+const ENTRY_PREFIX: &str = "entry:";
+
+/// Audit settings
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// Whether entries are recorded at all
+    pub enabled: bool,
+    /// Store the SHA-256 of each key instead of the key itself
+    pub hash_keys: bool,
+    /// Entries older than this are deleted; `None` keeps them forever
+    pub retention: Option<Duration>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hash_keys: false,
+            retention: None,
+        }
+    }
+}
+
+/// How an audited operation ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    /// The operation was authorized and succeeded
+    Ok,
+    /// The user lacked the permission
+    Denied,
+    /// The operation was authorized but failed
+    Failed,
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOutcome::Ok => write!(f, "ok"),
+            AuditOutcome::Denied => write!(f, "denied"),
+            AuditOutcome::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// One audit log entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub user_id: String,
+    /// Operation name, such as `insert` or `register_procedure`
+    pub operation: String,
+    pub namespace: Option<String>,
+    /// Key the operation touched, unless keys are hashed
+    pub key: Option<String>,
+    /// Hex SHA-256 of the key when `hash_keys` is set
+    pub key_hash: Option<String>,
+    pub outcome: AuditOutcome,
+    /// Error message for denied and failed operations
+    pub detail: Option<String>,
+}
+
+/// Which entries an audit query returns
+#[derive(Debug, Clone)]
+pub struct AuditFilter {
+    /// Earliest timestamp (ms, inclusive)
+    pub since_ms: Option<u64>,
+    /// Latest timestamp (ms, inclusive)
+    pub until_ms: Option<u64>,
+    pub user_id: Option<String>,
+    pub limit: usize,
+}
+
+impl Default for AuditFilter {
+    fn default() -> Self {
+        Self {
+            since_ms: None,
+            until_ms: None,
+            user_id: None,
+            limit: DEFAULT_QUERY_LIMIT,
+        }
+    }
+}
+
+/// Writer and reader for the audit namespace
+#[derive(Clone)]
+pub struct AuditLog {
+    namespace_manager: Arc<RwLock<NamespaceManager>>,
+    config: AuditConfig,
+    sequence: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+    pub fn new(namespace_manager: Arc<RwLock<NamespaceManager>>, config: AuditConfig) -> Self {
+        Self {
+            namespace_manager,
+            config,
+            sequence: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn namespace(&self) -> Result<Namespace> {
+        self.namespace_manager.read().unwrap().system_namespace(AUDIT_NAMESPACE)
+    }
+
+    fn entry_key(timestamp_ms: u64, sequence: u64) -> String {
+        format!("{}{:020}:{:020}", ENTRY_PREFIX, timestamp_ms, sequence)
+    }
+
+    /// Record an operation
+    ///
+    /// Failing to write the entry is logged and otherwise ignored, so auditing
+    /// never changes the outcome of the operation itself.
+    pub fn record(
+        &self,
+        user_id: &str,
+        operation: &str,
+        namespace: Option<&str>,
+        key: Option<&str>,
+        outcome: AuditOutcome,
+        detail: Option<&str>,
+    ) {
+        if outcome == AuditOutcome::Denied {
+            metrics::increment(metrics::AUTH_DENIALS, &[("operation", operation)]);
+        }
+        if !self.config.enabled {
+            return;
+        }
+        let (key, key_hash) = match key {
+            Some(key) if self.config.hash_keys => (None, Some(hash_key(key))),
+            key => (key.map(String::from), None),
+        };
+        let entry = AuditEntry {
+            timestamp_ms: now_ms(),
+            user_id: user_id.to_string(),
+            operation: operation.to_string(),
+            namespace: namespace.map(String::from),
+            key,
+            key_hash,
+            outcome,
+            detail: detail.map(String::from),
+        };
+        if let Err(e) = self.append(&entry) {
+            tracing::warn!("Failed to write audit entry for '{}': {:#}", operation, e);
+        }
+    }
+
+    fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let value = serde_json::to_vec(entry).context("Failed to serialize audit entry")?;
+        self.namespace()?
+            .db
+            .put(Self::entry_key(entry.timestamp_ms, sequence).as_bytes(), &value)?;
+        if sequence > 0 && sequence.is_multiple_of(PRUNE_INTERVAL) {
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    /// Entries matching `filter`, oldest first
+    pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let ns = self.namespace()?;
+        // Keys sort by timestamp, so start just before the first millisecond wanted
+        let after = filter
+            .since_ms
+            .filter(|&since| since > 0)
+            .map(|since| format!("{}{:020}", ENTRY_PREFIX, since - 1));
+
+        let mut entries = Vec::new();
+        for result in ns.db.scan_prefix_after(ENTRY_PREFIX.as_bytes(), after.as_ref().map(|a| a.as_bytes())) {
+            if entries.len() >= filter.limit {
+                break;
+            }
+            let (_, value) = result?;
+            let entry: AuditEntry = serde_json::from_slice(&value)
+                .context("Failed to deserialize audit entry")?;
+            if filter.since_ms.is_some_and(|since| entry.timestamp_ms < since) {
+                continue;
+            }
+            if filter.until_ms.is_some_and(|until| entry.timestamp_ms > until) {
+                break;
+            }
+            if filter.user_id.as_deref().is_some_and(|user| user != entry.user_id) {
+                continue;
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Delete entries older than the retention period; returns how many were removed
+    pub fn prune(&self) -> Result<usize> {
+        let Some(retention) = self.config.retention else {
+            return Ok(0);
+        };
+        let cutoff = Self::entry_key(now_ms().saturating_sub(retention.as_millis() as u64), 0);
+        let ns = self.namespace()?;
+        let mut removed = 0;
+        for result in ns.db.scan_prefix(ENTRY_PREFIX.as_bytes()) {
+            let (key, _) = result?;
+            if key.as_slice() >= cutoff.as_bytes() {
+                break;
+            }
+            ns.db.delete(&key)?;
+            removed += 1;
+        }
+        if removed > 0 {
+            tracing::info!("Pruned {} audit entries", removed);
+        }
+        Ok(removed)
+    }
+}
+
+/// Parse a query bound: milliseconds since the epoch, or a duration like `1h`
+/// meaning that long ago
+pub fn parse_time_bound(value: &str) -> Result<u64> {
+    if let Ok(timestamp_ms) = value.trim().parse::<u64>() {
+        return Ok(timestamp_ms);
+    }
+    let ago = crate::scheduler::parse_duration(value)?;
+    Ok(now_ms().saturating_sub(ago.as_millis() as u64))
+}
+
+/// Hex SHA-256 of a key, as stored when `hash_keys` is set
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn audit_log(config: AuditConfig) -> (TempDir, AuditLog) {
+        let dir = TempDir::new().unwrap();
+        let manager = NamespaceManager::new(dir.path().to_path_buf()).unwrap();
+        (dir, AuditLog::new(Arc::new(RwLock::new(manager)), config))
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let (_dir, log) = audit_log(AuditConfig::default());
+        log.record("alice", "insert", Some("docs"), Some("k1"), AuditOutcome::Ok, None);
+        log.record("bob", "delete", Some("docs"), Some("k1"), AuditOutcome::Denied, Some("Unauthorized"));
+
+        let all = log.query(&AuditFilter::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].operation, "insert");
+        assert_eq!(all[1].outcome, AuditOutcome::Denied);
+
+        let bob = log.query(&AuditFilter { user_id: Some("bob".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(bob.len(), 1);
+        assert_eq!(bob[0].key.as_deref(), Some("k1"));
+
+        let future = log.query(&AuditFilter { since_ms: Some(now_ms() + 60_000), ..Default::default() }).unwrap();
+        assert!(future.is_empty());
+        let past = log.query(&AuditFilter { until_ms: Some(1), ..Default::default() }).unwrap();
+        assert!(past.is_empty());
+        let recent = log.query(&AuditFilter { since_ms: Some(parse_time_bound("1h").unwrap()), ..Default::default() }).unwrap();
+        assert_eq!(recent.len(), 2);
+    }
+
+    #[test]
+    fn test_hash_keys_and_retention() {
+        let config = AuditConfig { hash_keys: true, retention: Some(Duration::ZERO), ..Default::default() };
+        let (_dir, log) = audit_log(config);
+        log.record("alice", "insert", Some("docs"), Some("secret"), AuditOutcome::Ok, None);
+
+        let entries = log.query(&AuditFilter::default()).unwrap();
+        assert_eq!(entries[0].key, None);
+        assert_eq!(entries[0].key_hash.as_deref(), Some(hash_key("secret").as_str()));
+
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(log.prune().unwrap(), 1);
+        assert!(log.query(&AuditFilter::default()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Bulk ingest of NDJSON records
+//!
+//! Each line of a bulk request is one record. Records are written in batches: one
+//! Fjall batch per chunk, one embedding call for the texts that arrive without a
+//! vector, and index capacity reserved before any vector is added. A record that
+//! fails is reported by its line number while the rest of its batch still lands.
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::error::LiathError;
+
+/// Default number of records written per batch
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
+/// Largest batch a bulk request may ask for
+pub const MAX_BATCH_SIZE: usize = 10_000;
+
+/// Prefix of the key under which a record's metadata is stored as JSON
+pub const METADATA_PREFIX: &str = "_meta:";
+
+/// One record of a bulk ingest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkRecord {
+    pub key: String,
+    /// Stored under `key`: strings as they are, other JSON serialized. Defaults to `text`
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+    /// Embedded and indexed unless `vector` is given
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub vector: Option<Vec<f32>>,
+    /// Vector id; derived from the key when absent
+    #[serde(default)]
+    pub id: Option<u64>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl BulkRecord {
+    /// Parse one NDJSON line
+    pub fn parse(line: &str) -> Result<Self> {
+        let record: Self = serde_json::from_str(line)
+            .map_err(|e| LiathError::InvalidInput(format!("Invalid record: {}", e)))?;
+        record.validate()?;
+        Ok(record)
+    }
+
+    /// Check that the record has a key and something to store under it
+    pub fn validate(&self) -> Result<()> {
+        if self.key.is_empty() {
+            return Err(LiathError::InvalidInput("Record key is empty".to_string()).into());
+        }
+        if self.value.is_none() && self.text.is_none() {
+            return Err(LiathError::InvalidInput(format!("Record '{}' has neither a value nor text", self.key)).into());
+        }
+        Ok(())
+    }
+
+    /// The value written under the record's key
+    pub fn stored_value(&self) -> String {
+        match &self.value {
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => self.text.clone().unwrap_or_default(),
+        }
+    }
+
+    /// The id of the record's vector in the namespace index
+    pub fn vector_id(&self) -> u64 {
+        self.id.unwrap_or_else(|| vector_id(&self.key))
+    }
+}
+
+/// Vector id for a key that was not given one
+///
+/// The id is stable, so ingesting a key again replaces its vector, and fits in
+/// 53 bits so JSON and Lua numbers carry it intact.
+pub fn vector_id(key: &str) -> u64 {
+    let digest = Sha256::digest(key.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes) & ((1 << 53) - 1)
+}
+
+/// A record that could not be written
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkError {
+    /// Line of the record in the request, counting from 1
+    pub line: usize,
+    pub key: Option<String>,
+    pub error: String,
+}
+
+/// Outcome of one bulk batch
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BulkReport {
+    pub written: usize,
+    pub errors: Vec<BulkError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        let record = BulkRecord::parse(r#"{"key": "a", "value": {"n": 1}, "metadata": {"tag": "x"}}"#).unwrap();
+        assert_eq!(record.stored_value(), r#"{"n":1}"#);
+        assert!(record.vector.is_none());
+
+        let record = BulkRecord::parse(r#"{"key": "b", "text": "hello"}"#).unwrap();
+        assert_eq!(record.stored_value(), "hello");
+        assert_eq!(record.vector_id(), vector_id("b"));
+
+        assert!(BulkRecord::parse(r#"{"key": "c"}"#).is_err());
+        assert!(BulkRecord::parse(r#"{"key": "", "value": "v"}"#).is_err());
+        assert!(BulkRecord::parse("not json").is_err());
+    }
+
+    #[test]
+    fn test_vector_id_is_stable_and_small() {
+        assert_eq!(vector_id("doc-1"), vector_id("doc-1"));
+        assert_ne!(vector_id("doc-1"), vector_id("doc-2"));
+        assert!(vector_id("doc-1") < 1 << 53);
+    }
+}
@@ -0,0 +1,356 @@
+//! Change feed of writes
+//!
+//! Every write through the query executor is appended to the `_changes` system
+//! namespace under `change:<sequence>` and broadcast to live subscribers. Sequence
+//! numbers only grow, so a subscriber that remembers the last one it saw can resume
+//! right after it for as long as the feed retains that change.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+use crate::core::{Namespace, NamespaceManager};
+use crate::error::LiathError;
+
+/// System namespace holding the retained changes
+pub const CHANGES_NAMESPACE: &str = "_changes";
+
+/// Number of most recent changes kept for resuming subscribers
+pub const DEFAULT_RETENTION: u64 = 100_000;
+
+/// How many changes are written between retention sweeps
+const PRUNE_INTERVAL: u64 = 1_000;
+
+/// Changes buffered for a live subscriber before it has to catch up from storage
+const LIVE_BUFFER: usize = 1024;
+
+/// Changes read from storage at a time while a subscriber catches up
+const REPLAY_BATCH: usize = 256;
+
+const ENTRY_PREFIX: &str = "change:";
+
+/// What a change did
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Put,
+    Delete,
+    AddVector,
+    CreateNamespace,
+    DeleteNamespace,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::Put => write!(f, "put"),
+            ChangeKind::Delete => write!(f, "delete"),
+            ChangeKind::AddVector => write!(f, "add_vector"),
+            ChangeKind::CreateNamespace => write!(f, "create_namespace"),
+            ChangeKind::DeleteNamespace => write!(f, "delete_namespace"),
+        }
+    }
+}
+
+/// One write, as seen by subscribers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub kind: ChangeKind,
+    pub namespace: String,
+    /// Key written or deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Id of the vector added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+}
+
+/// Which changes a subscription delivers
+#[derive(Debug, Clone, Default)]
+pub struct ChangeFilter {
+    pub namespace: Option<String>,
+    /// Only changes to keys starting with this; namespace and vector changes have no key
+    pub prefix: Option<String>,
+    /// Resume after this sequence number; `None` starts with the next change
+    pub since: Option<u64>,
+}
+
+impl ChangeFilter {
+    pub fn matches(&self, change: &Change) -> bool {
+        if self.namespace.as_deref().is_some_and(|ns| ns != change.namespace) {
+            return false;
+        }
+        match (&self.prefix, &change.key) {
+            (Some(prefix), Some(key)) => key.starts_with(prefix.as_str()),
+            (Some(prefix), None) => prefix.is_empty(),
+            (None, _) => true,
+        }
+    }
+}
+
+/// Writer of the change feed and source of subscriptions
+#[derive(Clone)]
+pub struct ChangeFeed {
+    namespace_manager: Arc<RwLock<NamespaceManager>>,
+    /// Last sequence number handed out, loaded from storage on first use
+    ///
+    /// Held while a change is stored and broadcast, so both happen in sequence order.
+    last: Arc<Mutex<Option<u64>>>,
+    live: broadcast::Sender<Change>,
+    retention: u64,
+}
+
+impl ChangeFeed {
+    pub fn new(namespace_manager: Arc<RwLock<NamespaceManager>>) -> Self {
+        Self {
+            namespace_manager,
+            last: Arc::new(Mutex::new(None)),
+            live: broadcast::channel(LIVE_BUFFER).0,
+            retention: DEFAULT_RETENTION,
+        }
+    }
+
+    fn namespace(&self) -> Result<Namespace> {
+        self.namespace_manager.read().unwrap().system_namespace(CHANGES_NAMESPACE)
+    }
+
+    fn entry_key(sequence: u64) -> String {
+        format!("{}{:020}", ENTRY_PREFIX, sequence)
+    }
+
+    fn load_last(&self, last: &mut Option<u64>) -> Result<u64> {
+        if let Some(sequence) = *last {
+            return Ok(sequence);
+        }
+        let sequence = match self.namespace()?.db.last_with_prefix(ENTRY_PREFIX.as_bytes())? {
+            Some((_, value)) => serde_json::from_slice::<Change>(&value)
+                .context("Failed to deserialize change")?
+                .sequence,
+            None => 0,
+        };
+        *last = Some(sequence);
+        Ok(sequence)
+    }
+
+    /// Sequence number of the latest change, 0 before the first
+    pub fn latest(&self) -> Result<u64> {
+        let mut last = self.last.lock().unwrap();
+        self.load_last(&mut last)
+    }
+
+    /// Record a write and notify subscribers
+    ///
+    /// Failing to store the change is logged and otherwise ignored, so the feed
+    /// never changes the outcome of the write itself.
+    pub fn emit(&self, kind: ChangeKind, namespace: &str, key: Option<&str>, id: Option<u64>) {
+        let mut last = self.last.lock().unwrap();
+        let sequence = match self.load_last(&mut last) {
+            Ok(sequence) => sequence + 1,
+            Err(e) => {
+                tracing::warn!("Failed to read the change feed: {:#}", e);
+                return;
+            }
+        };
+        *last = Some(sequence);
+        let change = Change {
+            sequence,
+            timestamp_ms: now_ms(),
+            kind,
+            namespace: namespace.to_string(),
+            key: key.map(String::from),
+            id,
+        };
+        if let Err(e) = self.append(&change) {
+            tracing::warn!("Failed to store change {}: {:#}", sequence, e);
+        }
+        // No receivers just means nobody is watching
+        let _ = self.live.send(change);
+    }
+
+    fn append(&self, change: &Change) -> Result<()> {
+        let value = serde_json::to_vec(change).context("Failed to serialize change")?;
+        let ns = self.namespace()?;
+        ns.db.put(Self::entry_key(change.sequence).as_bytes(), &value)?;
+        if change.sequence.is_multiple_of(PRUNE_INTERVAL) && change.sequence > self.retention {
+            let cutoff = Self::entry_key(change.sequence - self.retention);
+            for result in ns.db.scan_prefix(ENTRY_PREFIX.as_bytes()) {
+                let (key, _) = result?;
+                if key.as_slice() > cutoff.as_bytes() {
+                    break;
+                }
+                ns.db.delete(&key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Retained changes after `sequence`, oldest first
+    pub fn read_after(&self, sequence: u64, limit: usize) -> Result<Vec<Change>> {
+        let after = Self::entry_key(sequence);
+        self.namespace()?
+            .db
+            .scan_prefix_after(ENTRY_PREFIX.as_bytes(), Some(after.as_bytes()))
+            .take(limit)
+            .map(|result| {
+                let (_, value) = result?;
+                serde_json::from_slice(&value).context("Failed to deserialize change")
+            })
+            .collect()
+    }
+
+    /// Subscribe to the changes matching `filter` that `visible` accepts
+    ///
+    /// `visible` is asked for the namespace of every change. Resuming from a
+    /// sequence number the feed no longer retains is an error; the subscriber has
+    /// missed changes and must resynchronize.
+    pub fn subscribe(
+        &self,
+        filter: ChangeFilter,
+        visible: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Result<Subscription> {
+        // Listen before reading the position, so no change falls in between
+        let live = self.live.subscribe();
+        let latest = self.latest()?;
+        let last = match filter.since {
+            Some(since) if since < latest => {
+                let oldest = self.read_after(0, 1)?.first().map_or(latest, |change| change.sequence);
+                if since + 1 < oldest {
+                    return Err(LiathError::InvalidInput(format!(
+                        "Changes after {} are no longer retained; the oldest is {}", since, oldest
+                    )).into());
+                }
+                since
+            }
+            _ => latest,
+        };
+        Ok(Subscription {
+            feed: self.clone(),
+            filter,
+            visible: Box::new(visible),
+            live,
+            backlog: VecDeque::new(),
+            last,
+        })
+    }
+}
+
+/// A stream of changes, starting after the filter's `since` or the latest change
+pub struct Subscription {
+    feed: ChangeFeed,
+    filter: ChangeFilter,
+    visible: Box<dyn Fn(&str) -> bool + Send + Sync>,
+    live: broadcast::Receiver<Change>,
+    /// Stored changes still to deliver while catching up
+    backlog: VecDeque<Change>,
+    /// Sequence number of the last change delivered or skipped
+    last: u64,
+}
+
+impl Subscription {
+    /// Sequence number of the last change seen, to resume from later
+    pub fn position(&self) -> u64 {
+        self.last
+    }
+
+    fn accepts(&self, change: &Change) -> bool {
+        self.filter.matches(change) && (self.visible)(&change.namespace)
+    }
+
+    /// Wait for the next matching change; `None` once the feed is gone
+    pub async fn next(&mut self) -> Result<Option<Change>> {
+        loop {
+            if self.backlog.is_empty() && self.last < self.feed.latest()? {
+                self.backlog.extend(self.feed.read_after(self.last, REPLAY_BATCH)?);
+            }
+            if let Some(change) = self.backlog.pop_front() {
+                self.last = change.sequence;
+                if self.accepts(&change) {
+                    return Ok(Some(change));
+                }
+                continue;
+            }
+            match self.live.recv().await {
+                Ok(change) if change.sequence <= self.last => {}
+                Ok(change) => {
+                    self.last = change.sequence;
+                    if self.accepts(&change) {
+                        return Ok(Some(change));
+                    }
+                }
+                // Fell behind the live buffer; the next pass catches up from storage
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn feed() -> (TempDir, ChangeFeed) {
+        let dir = TempDir::new().unwrap();
+        let manager = NamespaceManager::new(dir.path().to_path_buf()).unwrap();
+        (dir, ChangeFeed::new(Arc::new(RwLock::new(manager))))
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_live_and_resume() {
+        let (_dir, feed) = feed();
+        feed.emit(ChangeKind::CreateNamespace, "docs", None, None);
+        let filter = ChangeFilter { namespace: Some("docs".to_string()), prefix: Some("a".to_string()), since: None };
+        let mut live = feed.subscribe(filter.clone(), |_| true).unwrap();
+
+        feed.emit(ChangeKind::Put, "docs", Some("b1"), None);
+        feed.emit(ChangeKind::Put, "other", Some("a1"), None);
+        feed.emit(ChangeKind::Put, "docs", Some("a1"), None);
+        feed.emit(ChangeKind::Delete, "docs", Some("a1"), None);
+
+        let change = live.next().await.unwrap().unwrap();
+        assert_eq!((change.sequence, change.kind), (4, ChangeKind::Put));
+        assert_eq!(live.next().await.unwrap().unwrap().kind, ChangeKind::Delete);
+
+        // Resuming replays the stored changes after the given sequence
+        let mut resumed = feed.subscribe(ChangeFilter { since: Some(1), ..Default::default() }, |ns| ns == "docs").unwrap();
+        let sequences: Vec<u64> = [
+            resumed.next().await.unwrap().unwrap(),
+            resumed.next().await.unwrap().unwrap(),
+            resumed.next().await.unwrap().unwrap(),
+        ].iter().map(|c| c.sequence).collect();
+        assert_eq!(sequences, vec![2, 4, 5]);
+        assert_eq!(resumed.position(), 5);
+    }
+
+    #[test]
+    fn test_sequence_survives_restart() {
+        let dir = TempDir::new().unwrap();
+        {
+            let manager = NamespaceManager::new(dir.path().to_path_buf()).unwrap();
+            let feed = ChangeFeed::new(Arc::new(RwLock::new(manager)));
+            feed.emit(ChangeKind::Put, "docs", Some("k"), None);
+            feed.emit(ChangeKind::AddVector, "docs", None, Some(7));
+        }
+        let manager = NamespaceManager::new(dir.path().to_path_buf()).unwrap();
+        let feed = ChangeFeed::new(Arc::new(RwLock::new(manager)));
+        assert_eq!(feed.latest().unwrap(), 2);
+        feed.emit(ChangeKind::Delete, "docs", Some("k"), None);
+        let changes = feed.read_after(0, 10).unwrap();
+        assert_eq!(changes.iter().map(|c| c.sequence).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(changes[1].id, Some(7));
+    }
+}
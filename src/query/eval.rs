@@ -0,0 +1,332 @@
+//! Expression evaluation for the declarative query language
+//!
+//! Records are key-value pairs whose values are parsed as JSON where possible;
+//! a bare column name refers to a field of the JSON value unless it is one of
+//! the pseudo-columns `key`, `value` or `distance`.
+
+use std::cmp::Ordering;
+use anyhow::Result;
+use serde_json::Value;
+use crate::error::LiathError;
+use super::ast::{Aggregate, BinaryOp, Expr, Literal};
+
+/// One key-value pair read from a namespace
+#[derive(Debug, Clone)]
+pub(crate) struct Record {
+    pub key: String,
+    pub value: Value,
+    /// Distance from the `NEAR` query, for records found by vector search
+    pub distance: Option<f32>,
+}
+
+impl Record {
+    pub fn new(key: &[u8], value: &[u8]) -> Self {
+        let value = serde_json::from_slice(value)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(value).into_owned()));
+        Self { key: String::from_utf8_lossy(key).into_owned(), value, distance: None }
+    }
+
+    fn column(&self, path: &[String]) -> Value {
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Value::Null,
+        };
+        match first.as_str() {
+            "key" if rest.is_empty() => Value::String(self.key.clone()),
+            "distance" if rest.is_empty() => self.distance.map(|d| number(d as f64)).unwrap_or(Value::Null),
+            "value" => lookup(&self.value, rest),
+            _ => lookup(&self.value, path),
+        }
+    }
+}
+
+fn lookup(value: &Value, path: &[String]) -> Value {
+    let mut current = value;
+    for segment in path {
+        current = match current {
+            Value::Object(map) => match map.get(segment) {
+                Some(v) => v,
+                None => return Value::Null,
+            },
+            Value::Array(items) => match segment.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                Some(v) => v,
+                None => return Value::Null,
+            },
+            _ => return Value::Null,
+        };
+    }
+    current.clone()
+}
+
+/// A row being evaluated: one record per table in `FROM`/`JOIN` order
+/// (`None` for the unmatched side of a `LEFT JOIN`)
+pub(crate) type Row = Vec<Option<Record>>;
+
+/// Names that qualify columns of each table in a row
+pub(crate) struct Scope {
+    pub tables: Vec<String>,
+}
+
+impl Scope {
+    fn resolve(&self, row: &Row, path: &[String]) -> Value {
+        if path.len() > 1 {
+            if let Some(i) = self.tables.iter().position(|t| t == &path[0]) {
+                return row.get(i)
+                    .and_then(|r| r.as_ref())
+                    .map(|r| r.column(&path[1..]))
+                    .unwrap_or(Value::Null);
+            }
+        }
+        // Unqualified: the first table that has the column
+        for record in row.iter().flatten() {
+            let value = record.column(path);
+            if !value.is_null() {
+                return value;
+            }
+        }
+        Value::Null
+    }
+}
+
+/// What an expression is evaluated against
+pub(crate) enum Context<'a> {
+    Row(&'a Row),
+    /// All rows of a group; aggregates range over them, plain columns
+    /// take the value from the first row
+    Group(&'a [Row]),
+}
+
+pub(crate) fn eval(expr: &Expr, ctx: &Context, scope: &Scope) -> Result<Value> {
+    Ok(match expr {
+        Expr::Literal(lit) => literal(lit),
+        Expr::Column(path) => match ctx {
+            Context::Row(row) => scope.resolve(row, path),
+            Context::Group(rows) => rows.first().map(|row| scope.resolve(row, path)).unwrap_or(Value::Null),
+        },
+        Expr::Not(inner) => match eval(inner, ctx, scope)? {
+            Value::Null => Value::Null,
+            v => Value::Bool(!truthy(&v)),
+        },
+        Expr::Neg(inner) => match eval(inner, ctx, scope)?.as_f64() {
+            Some(n) => number(-n),
+            None => Value::Null,
+        },
+        Expr::Binary { left, op: BinaryOp::And, right } => {
+            Value::Bool(truthy(&eval(left, ctx, scope)?) && truthy(&eval(right, ctx, scope)?))
+        }
+        Expr::Binary { left, op: BinaryOp::Or, right } => {
+            Value::Bool(truthy(&eval(left, ctx, scope)?) || truthy(&eval(right, ctx, scope)?))
+        }
+        Expr::Binary { left, op, right } => {
+            binary(&eval(left, ctx, scope)?, *op, &eval(right, ctx, scope)?)
+        }
+        Expr::IsNull { expr, negated } => Value::Bool(eval(expr, ctx, scope)?.is_null() != *negated),
+        Expr::InList { expr, list, negated } => {
+            let value = eval(expr, ctx, scope)?;
+            if value.is_null() {
+                return Ok(Value::Null);
+            }
+            let mut found = false;
+            for item in list {
+                if equals(&value, &eval(item, ctx, scope)?) {
+                    found = true;
+                    break;
+                }
+            }
+            Value::Bool(found != *negated)
+        }
+        Expr::Aggregate { func, arg } => match ctx {
+            Context::Group(rows) => aggregate(*func, arg.as_deref(), rows, scope)?,
+            Context::Row(_) => {
+                return Err(LiathError::Query(format!("{} is not allowed here", expr)).into());
+            }
+        },
+        // Satisfied by the vector search that produced the row
+        Expr::Near { .. } => Value::Bool(true),
+    })
+}
+
+/// Whether a value counts as true in `WHERE`, `ON` and `HAVING`
+pub(crate) fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(_) | Value::Object(_) => true,
+    }
+}
+
+fn literal(lit: &Literal) -> Value {
+    match lit {
+        Literal::Null => Value::Null,
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Number(n) => number(*n),
+        Literal::String(s) => Value::String(s.clone()),
+    }
+}
+
+/// JSON number, as an integer when it has no fractional part
+pub(crate) fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < 9.0e15 {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n).map(Value::Number).unwrap_or(Value::Null)
+    }
+}
+
+fn binary(left: &Value, op: BinaryOp, right: &Value) -> Value {
+    if left.is_null() || right.is_null() {
+        return Value::Null;
+    }
+    match op {
+        BinaryOp::Eq => Value::Bool(equals(left, right)),
+        BinaryOp::NotEq => Value::Bool(!equals(left, right)),
+        BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => match compare(left, right) {
+            Some(ordering) => Value::Bool(match op {
+                BinaryOp::Lt => ordering == Ordering::Less,
+                BinaryOp::LtEq => ordering != Ordering::Greater,
+                BinaryOp::Gt => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }),
+            None => Value::Null,
+        },
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+            match (left.as_f64(), right.as_f64()) {
+                (Some(a), Some(b)) => match op {
+                    BinaryOp::Add => number(a + b),
+                    BinaryOp::Sub => number(a - b),
+                    BinaryOp::Mul => number(a * b),
+                    _ if b == 0.0 => Value::Null,
+                    _ => number(a / b),
+                },
+                _ => Value::Null,
+            }
+        }
+        BinaryOp::Like => match (left, right) {
+            (Value::String(s), Value::String(pattern)) => Value::Bool(like(s, pattern)),
+            _ => Value::Null,
+        },
+        BinaryOp::And | BinaryOp::Or => unreachable!("handled in eval"),
+    }
+}
+
+fn equals(left: &Value, right: &Value) -> bool {
+    compare(left, right) == Some(Ordering::Equal)
+}
+
+/// Compare two values of the same type; `None` if they are not comparable
+pub(crate) fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// Total order used by `ORDER BY`, `MIN` and `MAX`:
+/// null < booleans < numbers < strings < arrays and objects
+pub(crate) fn sort_order(left: &Value, right: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) | Value::Object(_) => 4,
+        }
+    }
+    compare(left, right)
+        .unwrap_or_else(|| rank(left).cmp(&rank(right)).then_with(|| left.to_string().cmp(&right.to_string())))
+}
+
+/// SQL `LIKE`: `%` matches any run of characters, `_` exactly one
+pub(crate) fn like(s: &str, pattern: &str) -> bool {
+    let s: Vec<char> = s.chars().collect();
+    let p: Vec<char> = pattern.chars().collect();
+    let (mut i, mut j) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if j < p.len() && (p[j] == '_' || p[j] == s[i]) {
+            i += 1;
+            j += 1;
+        } else if j < p.len() && p[j] == '%' {
+            backtrack = Some((j, i));
+            j += 1;
+        } else if let Some((pj, si)) = backtrack {
+            j = pj + 1;
+            i = si + 1;
+            backtrack = Some((pj, si + 1));
+        } else {
+            return false;
+        }
+    }
+    p[j..].iter().all(|&c| c == '%')
+}
+
+fn aggregate(func: Aggregate, arg: Option<&Expr>, rows: &[Row], scope: &Scope) -> Result<Value> {
+    let arg = match arg {
+        Some(arg) => arg,
+        // COUNT(*)
+        None => return Ok(Value::from(rows.len())),
+    };
+
+    let mut values = Vec::with_capacity(rows.len());
+    for row in rows {
+        let value = eval(arg, &Context::Row(row), scope)?;
+        if !value.is_null() {
+            values.push(value);
+        }
+    }
+
+    Ok(match func {
+        Aggregate::Count => Value::from(values.len()),
+        Aggregate::Sum | Aggregate::Avg => {
+            let numbers: Vec<f64> = values.iter().filter_map(Value::as_f64).collect();
+            if numbers.is_empty() {
+                Value::Null
+            } else if func == Aggregate::Sum {
+                number(numbers.iter().sum())
+            } else {
+                number(numbers.iter().sum::<f64>() / numbers.len() as f64)
+            }
+        }
+        Aggregate::Min => values.into_iter().min_by(sort_order).unwrap_or(Value::Null),
+        Aggregate::Max => values.into_iter().max_by(sort_order).unwrap_or(Value::Null),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_like() {
+        assert!(like("user:42", "user:%"));
+        assert!(like("user:42", "%:4_"));
+        assert!(like("abc", "%"));
+        assert!(like("", "%"));
+        assert!(like("a%c", "a%c"));
+        assert!(like("aXbXc", "a%b%c"));
+        assert!(!like("user:42", "post:%"));
+        assert!(!like("abc", "ab"));
+        assert!(!like("abc", "a_"));
+    }
+
+    #[test]
+    fn test_record_columns() {
+        let record = Record::new(b"u1", br#"{"name": "Ada", "address": {"city": "London"}, "tags": ["a", "b"]}"#);
+        assert_eq!(record.column(&["key".to_string()]), Value::from("u1"));
+        assert_eq!(record.column(&["name".to_string()]), Value::from("Ada"));
+        assert_eq!(record.column(&["address".to_string(), "city".to_string()]), Value::from("London"));
+        assert_eq!(record.column(&["value".to_string(), "tags".to_string(), "1".to_string()]), Value::from("b"));
+        assert_eq!(record.column(&["missing".to_string()]), Value::Null);
+
+        // Values that are not JSON are plain strings
+        let record = Record::new(b"k", b"hello world");
+        assert_eq!(record.column(&["value".to_string()]), Value::from("hello world"));
+    }
+}
@@ -1,11 +1,27 @@
-use crate::core::NamespaceManager;
+use crate::core::{NamespaceManager, NamespaceUsage, Trigger, TriggerEvent, TriggerTiming};
 use crate::ai::EmbeddingWrapper;
-use crate::lua::LuaVM;
+use crate::lua::{LuaVM, SharedLuaVM, LIATH_STDLIB};
 use crate::file::FileStorage;
-use crate::auth::AuthManager;
+use crate::auth::{estimate_tokens, ApiKeyInfo, AuthManager, Limits, Resource, RoleInfo, UserInfo, QUOTAS_NAMESPACE};
+use crate::lua::LuaValidator;
+use crate::lua::registry::Bindings;
+use crate::query::modules::{self, LuaModule, ModuleStore};
+use crate::query::procedures::{self, Procedure, ProcedureStore};
+use crate::query::profile::{self, Profile, ProfileGuard};
+use crate::query::scan::{self, ScanIter, ScanPage};
+use crate::query::bulk::{self, BulkError, BulkRecord, BulkReport};
+use crate::query::changes::{self, ChangeFeed, ChangeFilter, ChangeKind, Subscription};
+use crate::query::ast::Statement;
+use crate::query::audit::{self, AuditConfig, AuditEntry, AuditFilter, AuditLog, AuditOutcome};
+use crate::query::parser::QueryParser;
+use crate::query::planner::{Access, Planner, QueryResult};
+use crate::scheduler::SCHEDULER_NAMESPACE;
+use crate::error::LiathError;
+use crate::metrics;
 use anyhow::Result;
 use tokio::sync::Semaphore;
-use std::sync::{Arc, RwLock};
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, RwLock, Weak};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::instrument;
 use rlua::{Context as LuaContext, Error as LuaError, Value as LuaValue, Table as LuaTable};
@@ -14,14 +30,175 @@ use usearch::{MetricKind, ScalarKind};
 #[cfg(not(feature = "vector"))]
 use crate::core::{MetricKind, ScalarKind};
 
+/// Maximum nesting of stored procedure calls, guarding against runaway recursion
+const MAX_PROCEDURE_DEPTH: usize = 16;
+
+/// Maximum nesting of triggers firing from writes made by other triggers
+const MAX_TRIGGER_DEPTH: usize = 8;
+
+/// Lua registry entry caching loaded modules by `name@version`
+const MODULE_CACHE: &str = "liath.modules";
+
+/// Lua registry entry caching each user's DB functions and stdlib by user ID
+const USER_BINDINGS: &str = "liath.users";
+
+thread_local! {
+    static PROCEDURE_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static ACTIVE_TRIGGERS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Tracks procedure nesting on the current thread for the lifetime of a call
+struct ProcedureDepthGuard;
+
+impl ProcedureDepthGuard {
+    fn enter() -> Result<Self, LuaError> {
+        let depth = PROCEDURE_DEPTH.with(|d| d.get());
+        if depth >= MAX_PROCEDURE_DEPTH {
+            return Err(LuaError::RuntimeError(format!(
+                "Procedure call depth exceeded ({})", MAX_PROCEDURE_DEPTH
+            )));
+        }
+        PROCEDURE_DEPTH.with(|d| d.set(depth + 1));
+        Ok(Self)
+    }
+}
+
+impl Drop for ProcedureDepthGuard {
+    fn drop(&mut self) {
+        PROCEDURE_DEPTH.with(|d| d.set(d.get() - 1));
+    }
+}
+
+/// Marks a namespace's triggers as running on the current thread
+///
+/// Writes made by a trigger into a namespace whose triggers are already running
+/// do not fire them again, so a trigger can safely write to its own namespace.
+struct TriggerGuard;
+
+impl TriggerGuard {
+    /// Returns `None` if triggers for `namespace` are already running
+    fn enter(namespace: &str) -> Result<Option<Self>, LuaError> {
+        ACTIVE_TRIGGERS.with(|active| {
+            let mut active = active.borrow_mut();
+            if active.iter().any(|ns| ns == namespace) {
+                return Ok(None);
+            }
+            if active.len() >= MAX_TRIGGER_DEPTH {
+                return Err(LuaError::RuntimeError(format!(
+                    "Trigger depth exceeded ({})", MAX_TRIGGER_DEPTH
+                )));
+            }
+            active.push(namespace.to_string());
+            Ok(Some(Self))
+        })
+    }
+}
+
+impl Drop for TriggerGuard {
+    fn drop(&mut self) {
+        ACTIVE_TRIGGERS.with(|active| {
+            active.borrow_mut().pop();
+        });
+    }
+}
+
+/// Permission checks for one user's Lua bindings that feed the audit log
+///
+/// Denials are recorded and surface in Lua as `Unauthorized`; `record` logs
+/// whether an authorized mutation succeeded.
+#[derive(Clone)]
+struct Gate {
+    auth_manager: Arc<RwLock<AuthManager>>,
+    audit: AuditLog,
+    user_id: String,
+}
+
+impl Gate {
+    /// Check a permission on a namespace
+    fn check(&self, operation: &str, permission: &str, namespace: &str, key: Option<&str>) -> Result<(), LuaError> {
+        if self.auth_manager.read().unwrap().is_authorized_for(&self.user_id, permission, namespace) {
+            return Ok(());
+        }
+        self.deny(operation, permission, Some(namespace), key)
+    }
+
+    /// Check a permission that is not tied to a namespace
+    fn check_global(&self, operation: &str, permission: &str) -> Result<(), LuaError> {
+        if self.auth_manager.read().unwrap().is_authorized(&self.user_id, permission) {
+            return Ok(());
+        }
+        self.deny(operation, permission, None, None)
+    }
+
+    /// Count usage against the user's daily quota
+    fn charge(&self, resource: Resource, amount: u64) -> Result<(), LuaError> {
+        self.auth_manager.read().unwrap().charge(&self.user_id, resource, amount)
+            .map_err(|e| LuaError::ExternalError(Arc::new(e)))
+    }
+
+    /// Give back a charge when the write it paid for failed
+    fn refund_on_error<T>(&self, resource: Resource, amount: u64, result: Result<T, LuaError>) -> Result<T, LuaError> {
+        if result.is_err() {
+            self.auth_manager.read().unwrap().refund(&self.user_id, resource, amount);
+        }
+        result
+    }
+
+    fn deny(&self, operation: &str, permission: &str, namespace: Option<&str>, key: Option<&str>) -> Result<(), LuaError> {
+        let detail = format!("missing '{}'", permission);
+        self.audit.record(&self.user_id, operation, namespace, key, AuditOutcome::Denied, Some(&detail));
+        Err(LuaError::RuntimeError("Unauthorized".to_string()))
+    }
+
+    /// Record the outcome of a mutation and pass it through
+    fn record<T>(&self, operation: &str, namespace: Option<&str>, key: Option<&str>, result: Result<T, LuaError>) -> Result<T, LuaError> {
+        match &result {
+            Ok(_) => self.audit.record(&self.user_id, operation, namespace, key, AuditOutcome::Ok, None),
+            Err(e) => {
+                let detail = e.to_string();
+                self.audit.record(&self.user_id, operation, namespace, key, AuditOutcome::Failed, Some(&detail));
+            }
+        }
+        result
+    }
+}
+
+/// Runs scripts, queries, procedures and triggers against the database
+///
+/// Cheap to clone; clones share the same state.
 #[derive(Clone)]
 pub struct QueryExecutor {
+    state: Arc<ExecutorState>,
+}
+
+#[derive(Clone)]
+struct ExecutorState {
     namespace_manager: Arc<RwLock<NamespaceManager>>,
     embedding: Arc<RwLock<EmbeddingWrapper>>,
-    lua_vm: Arc<RwLock<LuaVM>>,
+    lua_vm: SharedLuaVM,
     file_storage: Arc<RwLock<FileStorage>>,
     auth_manager: Arc<RwLock<AuthManager>>,
     embedding_semaphore: Arc<Semaphore>,
+    procedures: ProcedureStore,
+    modules: ModuleStore,
+    audit: AuditLog,
+    changes: ChangeFeed,
+}
+
+/// An executor handle for Lua bindings
+///
+/// Bindings live in the executor's own VM, so a strong handle there would keep
+/// the executor, its VM and the open database alive forever.
+#[derive(Clone)]
+struct WeakExecutor(Weak<ExecutorState>);
+
+impl WeakExecutor {
+    fn upgrade(&self) -> Result<QueryExecutor, LuaError> {
+        self.0
+            .upgrade()
+            .map(|state| QueryExecutor { state })
+            .ok_or_else(|| LuaError::RuntimeError("The database has been closed".to_string()))
+    }
 }
 
 impl QueryExecutor {
@@ -30,29 +207,75 @@ impl QueryExecutor {
         embedding: EmbeddingWrapper,
         lua_vm: LuaVM,
         file_storage: FileStorage,
-        auth_manager: AuthManager,
+        mut auth_manager: AuthManager,
         max_concurrent_embedding: usize,
     ) -> Self {
-        Self {
-            namespace_manager: Arc::new(RwLock::new(namespace_manager)),
+        auth_manager.protect_namespace(audit::AUDIT_NAMESPACE, audit::READ_AUDIT_PERMISSION);
+        auth_manager.protect_namespace(changes::CHANGES_NAMESPACE, audit::READ_AUDIT_PERMISSION);
+        auth_manager.protect_namespace(QUOTAS_NAMESPACE, audit::READ_AUDIT_PERMISSION);
+        // Forged procedures and jobs would run as the owner they name
+        auth_manager.protect_namespace(procedures::PROCEDURES_NAMESPACE, "register_procedure");
+        auth_manager.protect_namespace(SCHEDULER_NAMESPACE, "manage_jobs");
+        // A planted module would run as every user who requires it
+        auth_manager.protect_namespace(modules::MODULES_NAMESPACE, "manage_modules");
+        let usage = namespace_manager.system_namespace(QUOTAS_NAMESPACE)
+            .and_then(|namespace| auth_manager.persist_usage(namespace.db));
+        if let Err(e) = usage {
+            tracing::warn!("Failed to load quota usage, so quotas restart from zero: {:#}", e);
+        }
+        let namespace_manager = Arc::new(RwLock::new(namespace_manager));
+        let state = ExecutorState {
+            procedures: ProcedureStore::new(namespace_manager.clone()),
+            modules: ModuleStore::new(namespace_manager.clone()),
+            audit: AuditLog::new(namespace_manager.clone(), AuditConfig::default()),
+            changes: ChangeFeed::new(namespace_manager.clone()),
+            namespace_manager,
             embedding: Arc::new(RwLock::new(embedding)),
-            lua_vm: Arc::new(RwLock::new(lua_vm)),
+            lua_vm: SharedLuaVM::new(lua_vm),
             file_storage: Arc::new(RwLock::new(file_storage)),
             auth_manager: Arc::new(RwLock::new(auth_manager)),
             embedding_semaphore: Arc::new(Semaphore::new(max_concurrent_embedding)),
+        };
+        Self { state: Arc::new(state) }
+    }
+
+    fn downgrade(&self) -> WeakExecutor {
+        WeakExecutor(Arc::downgrade(&self.state))
+    }
+
+    /// Replace the audit settings and apply their retention right away
+    pub fn with_audit_config(mut self, config: AuditConfig) -> Self {
+        let namespace_manager = self.state.namespace_manager.clone();
+        Arc::make_mut(&mut self.state).audit = AuditLog::new(namespace_manager, config);
+        if let Err(e) = self.state.audit.prune() {
+            tracing::warn!("Failed to prune the audit log: {:#}", e);
         }
+        self
     }
 
     #[instrument(skip(self, query))]
     pub async fn execute(&self, query: &str, user_id: &str) -> Result<String> {
-        let res: String = self
+        self.run_script(query, user_id)
+    }
+
+    /// Execute Lua code and profile the database functions it calls
+    ///
+    /// Returns the result together with per-function call counts, timings and
+    /// storage bytes read and written.
+    pub async fn execute_profiled(&self, query: &str, user_id: &str) -> Result<(String, Profile)> {
+        let guard = ProfileGuard::start()
+            .ok_or_else(|| anyhow::anyhow!("A profile is already being recorded on this thread"))?;
+        let result = self.run_script(query, user_id)?;
+        Ok((result, guard.finish()))
+    }
+
+    fn run_script(&self, query: &str, user_id: &str) -> Result<String> {
+        let res: String = self.state
             .lua_vm
-            .read()
-            .unwrap()
+            .fresh()?
             .execute_with_context(|lua_ctx| {
-                self.register_db_functions(&lua_ctx, user_id)
-                    .map_err(|e| LuaError::RuntimeError(e.to_string()))?;
-                let value: LuaValue = lua_ctx.load(query).eval()?;
+                let env = self.user_env(lua_ctx, user_id)?;
+                let value: LuaValue = lua_ctx.load(query).set_environment(env).eval()?;
                 let out = match value {
                     LuaValue::String(s) => s.to_str()?.to_owned(),
                     LuaValue::Number(n) => n.to_string(),
@@ -62,7 +285,8 @@ impl QueryExecutor {
                     _ => return Err(LuaError::RuntimeError("Unexpected Lua return type".to_string())),
                 };
                 Ok(out)
-            })?;
+            })
+            .inspect_err(metrics::record_lua_error)?;
         Ok(res)
     }
 
@@ -74,10 +298,28 @@ impl QueryExecutor {
         metric: MetricKind,
         scalar: ScalarKind,
     ) -> Result<()> {
-        self.namespace_manager
+        self.state.namespace_manager
+            .read()
+            .unwrap()
+            .create_namespace(name, dimensions, metric, scalar)?;
+        self.state.changes.emit(ChangeKind::CreateNamespace, name, None, None);
+        Ok(())
+    }
+
+    /// Create a namespace whose values and saved vector index are encrypted
+    pub fn create_encrypted_namespace(
+        &self,
+        name: &str,
+        dimensions: usize,
+        metric: MetricKind,
+        scalar: ScalarKind,
+    ) -> Result<()> {
+        self.state.namespace_manager
             .read()
             .unwrap()
-            .create_namespace(name, dimensions, metric, scalar)
+            .create_encrypted_namespace(name, dimensions, metric, scalar)?;
+        self.state.changes.emit(ChangeKind::CreateNamespace, name, None, None);
+        Ok(())
     }
     #[cfg(not(feature = "vector"))]
     pub fn create_namespace_basic(&self, name: &str) -> anyhow::Result<()> {
@@ -86,16 +328,30 @@ impl QueryExecutor {
 
 
     pub fn put(&self, namespace: &str, key: &[u8], value: &[u8]) -> Result<()> {
-        let ns = self
+        let ns = self.state
             .namespace_manager
             .read()
             .unwrap()
             .get_namespace(namespace)?;
-        ns.db.put(key, value)
+        if !self.has_triggers(namespace) {
+            ns.db.put(key, value)?;
+            self.state.changes.emit(ChangeKind::Put, namespace, Some(&String::from_utf8_lossy(key)), None);
+            return Ok(());
+        }
+        self.state.lua_vm.fresh()?.execute_with_context(|lua_ctx| {
+            let value = self.run_before_triggers(lua_ctx, namespace, TriggerEvent::Put, key, Some(value.to_vec()))?
+                .unwrap_or_default();
+            ns.db.put(key, &value)
+                .map_err(|e| LuaError::RuntimeError(format!("Failed to insert value: {}", e)))?;
+            self.state.changes.emit(ChangeKind::Put, namespace, Some(&String::from_utf8_lossy(key)), None);
+            self.run_after_triggers(lua_ctx, namespace, TriggerEvent::Put, key, Some(&value));
+            Ok(())
+        })?;
+        Ok(())
     }
 
     pub fn get(&self, namespace: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let ns = self
+        let ns = self.state
             .namespace_manager
             .read()
             .unwrap()
@@ -104,20 +360,44 @@ impl QueryExecutor {
     }
 
     pub fn delete(&self, namespace: &str, key: &[u8]) -> Result<()> {
-        let ns = self
+        let ns = self.state
             .namespace_manager
             .read()
             .unwrap()
             .get_namespace(namespace)?;
-        ns.db.delete(key)
+        if !self.has_triggers(namespace) {
+            ns.db.delete(key)?;
+            self.state.changes.emit(ChangeKind::Delete, namespace, Some(&String::from_utf8_lossy(key)), None);
+            return Ok(());
+        }
+        self.state.lua_vm.fresh()?.execute_with_context(|lua_ctx| {
+            self.run_before_triggers(lua_ctx, namespace, TriggerEvent::Delete, key, None)?;
+            ns.db.delete(key)
+                .map_err(|e| LuaError::RuntimeError(format!("Failed to delete value: {}", e)))?;
+            self.state.changes.emit(ChangeKind::Delete, namespace, Some(&String::from_utf8_lossy(key)), None);
+            self.run_after_triggers(lua_ctx, namespace, TriggerEvent::Delete, key, None);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Open a system namespace such as `_agents`, creating it on first use
+    pub fn ensure_system_namespace(&self, name: &str) -> Result<()> {
+        self.state.namespace_manager.read().unwrap().system_namespace(name)?;
+        Ok(())
     }
 
     pub fn list_namespaces(&self) -> Vec<String> {
-        self.namespace_manager.read().unwrap().list_namespaces()
+        self.state.namespace_manager.read().unwrap().list_namespaces()
+    }
+
+    /// Vector index and disk usage per namespace
+    pub fn namespace_usage(&self) -> Vec<NamespaceUsage> {
+        self.state.namespace_manager.read().unwrap().usage()
     }
 
     pub fn generate_embedding(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
-        self.embedding.read().unwrap().generate(texts)
+        self.state.embedding.read().unwrap().generate(texts)
     }
 
     pub fn similarity_search(
@@ -126,7 +406,7 @@ impl QueryExecutor {
         vector: &[f32],
         k: usize,
     ) -> Result<Vec<(u64, f32)>> {
-        let ns = self
+        let ns = self.state
             .namespace_manager
             .read()
             .unwrap()
@@ -136,49 +416,991 @@ impl QueryExecutor {
 
     /// Add a vector to a namespace
     pub fn add_vector(&self, namespace: &str, id: u64, vector: &[f32]) -> Result<()> {
-        let ns = self
+        let ns = self.state
             .namespace_manager
             .read()
             .unwrap()
             .get_namespace(namespace)?;
-        ns.vector_db.add(id, vector)
+        ns.vector_db.add(id, vector)?;
+        self.state.changes.emit(ChangeKind::AddVector, namespace, None, Some(id));
+        Ok(())
     }
 
     /// Check if a namespace exists
     pub fn namespace_exists(&self, name: &str) -> bool {
-        self.namespace_manager.read().unwrap().namespace_exists(name)
+        self.state.namespace_manager.read().unwrap().namespace_exists(name)
     }
 
     /// Delete a namespace
     pub fn delete_namespace(&self, name: &str) -> Result<()> {
-        self.namespace_manager.write().unwrap().delete_namespace(name)
+        self.state.namespace_manager.write().unwrap().delete_namespace(name)?;
+        self.state.changes.emit(ChangeKind::DeleteNamespace, name, None, None);
+        Ok(())
+    }
+
+    /// Whether a namespace stores its data encrypted
+    pub fn is_encrypted(&self, name: &str) -> bool {
+        self.state.namespace_manager.read().unwrap().is_encrypted(name)
+    }
+
+    /// Re-encrypt stored values, vector indexes and files under the active key
+    ///
+    /// Returns how many values and files were rewritten.
+    pub fn rotate_encryption_keys(&self) -> Result<usize> {
+        let values = self.state.namespace_manager.read().unwrap().rotate_keys()?;
+        let files = self.state.file_storage.read().unwrap().reencrypt()?;
+        Ok(values + files)
     }
 
     /// Save all data to disk
     pub fn save_all(&self) -> Result<()> {
-        self.namespace_manager.read().unwrap().save_all()?;
-        self.auth_manager.read().unwrap().flush()?;
+        self.state.namespace_manager.read().unwrap().save_all()?;
+        self.state.auth_manager.read().unwrap().flush()?;
         Ok(())
     }
 
     /// Save a specific namespace
     pub fn save_namespace(&self, name: &str) -> Result<()> {
-        self.namespace_manager.read().unwrap().save_namespace(name)
+        self.state.namespace_manager.read().unwrap().save_namespace(name)
+    }
+
+    /// Check whether a user holds a permission
+    pub fn is_authorized(&self, user_id: &str, permission: &str) -> bool {
+        self.state.auth_manager.read().unwrap().is_authorized(user_id, permission)
+    }
+
+    /// Check whether a user holds a permission in a namespace
+    pub fn is_authorized_for(&self, user_id: &str, permission: &str, namespace: &str) -> bool {
+        self.state.auth_manager.read().unwrap().is_authorized_for(user_id, permission, namespace)
+    }
+
+    /// Namespaces `user_id` may list: all of them with an unscoped `list_namespaces`
+    /// grant, otherwise those its scoped grants match
+    pub fn visible_namespaces(&self, user_id: &str) -> Vec<String> {
+        let auth = self.state.auth_manager.read().unwrap();
+        self.list_namespaces()
+            .into_iter()
+            .filter(|ns| auth.is_authorized_for(user_id, "list_namespaces", ns))
+            .collect()
+    }
+
+    /// Shared handle to the auth manager, e.g. for authenticating API keys
+    pub fn auth_manager(&self) -> Arc<RwLock<AuthManager>> {
+        self.state.auth_manager.clone()
+    }
+
+    /// Shared handle to the audit log, for front ends that authorize requests themselves
+    pub fn audit(&self) -> AuditLog {
+        self.state.audit.clone()
+    }
+
+    /// Shared handle to the change feed
+    pub fn changes(&self) -> ChangeFeed {
+        self.state.changes.clone()
+    }
+
+    /// Shared handle to the namespace manager, for subsystems built on the executor
+    pub(crate) fn namespace_manager(&self) -> Arc<RwLock<NamespaceManager>> {
+        self.state.namespace_manager.clone()
+    }
+
+    // ============================================================
+    // AUDITED ACCESS
+    // ============================================================
+    //
+    // The helpers above trust their caller and are not audited. The methods below
+    // act for a user: they check the user's permissions, and record the outcome
+    // of every mutation and every denial in the audit log.
+
+    /// Record a denied operation and return the error to report
+    fn deny(&self, user_id: &str, operation: &str, namespace: Option<&str>, key: Option<&str>, message: String) -> anyhow::Error {
+        self.state.audit.record(user_id, operation, namespace, key, AuditOutcome::Denied, Some(&message));
+        LiathError::Unauthorized(message).into()
+    }
+
+    /// Record the outcome of an authorized mutation and pass it through
+    fn audited<T>(&self, user_id: &str, operation: &str, namespace: Option<&str>, key: Option<&str>, result: Result<T>) -> Result<T> {
+        match &result {
+            Ok(_) => self.state.audit.record(user_id, operation, namespace, key, AuditOutcome::Ok, None),
+            Err(e) => self.state.audit.record(user_id, operation, namespace, key, AuditOutcome::Failed, Some(&format!("{:#}", e))),
+        }
+        result
+    }
+
+    /// Check a namespace permission for `user_id`, recording a denial
+    fn authorize_for(&self, user_id: &str, operation: &str, permission: &str, namespace: &str, key: Option<&str>) -> Result<()> {
+        if self.is_authorized_for(user_id, permission, namespace) {
+            return Ok(());
+        }
+        let message = format!("'{}' lacks the '{}' permission on '{}'", user_id, permission, namespace);
+        Err(self.deny(user_id, operation, Some(namespace), key, message))
+    }
+
+    /// Count `amount` of `resource` against `user_id`'s daily quota
+    ///
+    /// A refusal carries a `LimitExceeded`, and nothing is counted.
+    pub fn charge(&self, user_id: &str, resource: Resource, amount: u64) -> Result<()> {
+        Ok(self.state.auth_manager.read().unwrap().charge(user_id, resource, amount)?)
+    }
+
+    /// Give back `user_id`'s charge when the write it paid for failed
+    fn refund_on_error<T>(&self, user_id: &str, resource: Resource, amount: u64, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.state.auth_manager.read().unwrap().refund(user_id, resource, amount);
+        }
+        result
+    }
+
+    /// Bindings that check and audit on behalf of `user_id`
+    fn gate(&self, user_id: &str) -> Gate {
+        Gate {
+            auth_manager: self.state.auth_manager.clone(),
+            audit: self.state.audit.clone(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    /// Write a value as `user_id`, who needs `insert` on the namespace
+    pub fn put_as(&self, namespace: &str, key: &[u8], value: &[u8], user_id: &str) -> Result<()> {
+        let audit_key = String::from_utf8_lossy(key);
+        self.authorize_for(user_id, "put", "insert", namespace, Some(&audit_key))?;
+        let bytes = (key.len() + value.len()) as u64;
+        self.charge(user_id, Resource::StorageBytes, bytes)?;
+        let result = self.refund_on_error(user_id, Resource::StorageBytes, bytes, self.put(namespace, key, value));
+        self.audited(user_id, "put", Some(namespace), Some(&audit_key), result)
+    }
+
+    /// Read a value as `user_id`, who needs `select` on the namespace
+    pub fn get_as(&self, namespace: &str, key: &[u8], user_id: &str) -> Result<Option<Vec<u8>>> {
+        self.authorize_for(user_id, "get", "select", namespace, Some(&String::from_utf8_lossy(key)))?;
+        self.get(namespace, key)
+    }
+
+    /// Delete a value as `user_id`, who needs `delete` on the namespace
+    pub fn delete_as(&self, namespace: &str, key: &[u8], user_id: &str) -> Result<()> {
+        let audit_key = String::from_utf8_lossy(key);
+        self.authorize_for(user_id, "delete", "delete", namespace, Some(&audit_key))?;
+        self.audited(user_id, "delete", Some(namespace), Some(&audit_key), self.delete(namespace, key))
+    }
+
+    /// Create a namespace as `user_id`, who needs `create_namespace` on its name
+    ///
+    /// With `encrypted` unset the namespace is still encrypted if its name
+    /// matches a configured pattern.
+    pub fn create_namespace_as(
+        &self,
+        name: &str,
+        dimensions: usize,
+        metric: MetricKind,
+        scalar: ScalarKind,
+        encrypted: bool,
+        user_id: &str,
+    ) -> Result<()> {
+        self.authorize_for(user_id, "create_namespace", "create_namespace", name, None)?;
+        let result = if encrypted {
+            self.create_encrypted_namespace(name, dimensions, metric, scalar)
+        } else {
+            self.create_namespace(name, dimensions, metric, scalar)
+        };
+        self.audited(user_id, "create_namespace", Some(name), None, result)
+    }
+
+    /// Delete a namespace as `user_id`, who needs `delete_namespace` on its name
+    pub fn delete_namespace_as(&self, name: &str, user_id: &str) -> Result<()> {
+        self.authorize_for(user_id, "delete_namespace", "delete_namespace", name, None)?;
+        self.audited(user_id, "delete_namespace", Some(name), None, self.delete_namespace(name))
+    }
+
+    /// Audit entries matching `filter`, oldest first; requires `read_audit`
+    pub fn audit_log(&self, filter: &AuditFilter, user_id: &str) -> Result<Vec<AuditEntry>> {
+        if !self.is_authorized(user_id, audit::READ_AUDIT_PERMISSION) {
+            let message = format!("'{}' may not read the audit log", user_id);
+            return Err(self.deny(user_id, "read_audit", None, None, message));
+        }
+        self.state.audit.query(filter)
+    }
+
+    // ============================================================
+    // DECLARATIVE QUERIES
+    // ============================================================
+
+    /// Run a declarative `SELECT` (or `EXPLAIN SELECT`) as `user_id`
+    ///
+    /// Requires the `select` permission on every namespace read, plus
+    /// `similarity_search` for `NEAR`.
+    /// `EXPLAIN` returns the plan as a single `plan` column, one step per row.
+    pub fn query(&self, query: &str, user_id: &str) -> Result<QueryResult> {
+        let (select, explain) = match QueryParser::parse_sql(query)? {
+            Statement::Select(select) => (select, false),
+            Statement::Explain(select) => (select, true),
+        };
+        let plan = Planner::plan(&select)?;
+
+        let namespaces = plan.namespaces();
+        for namespace in &namespaces {
+            if !self.is_authorized_for(user_id, "select", namespace) {
+                let message = format!("'{}' may not select from '{}'", user_id, namespace);
+                return Err(self.deny(user_id, "query", Some(namespace), None, message));
+            }
+        }
+        if matches!(plan.access(), Access::Near { .. }) && !self.is_authorized_for(user_id, "similarity_search", namespaces[0]) {
+            let message = format!("'{}' may not run similarity searches", user_id);
+            return Err(self.deny(user_id, "query", Some(namespaces[0]), None, message));
+        }
+
+        if explain {
+            return Ok(QueryResult {
+                columns: vec!["plan".to_string()],
+                rows: plan.explain().into_iter().map(|step| vec![serde_json::Value::String(step)]).collect(),
+            });
+        }
+        plan.execute(self)
+    }
+
+    // ============================================================
+    // STORED PROCEDURES
+    // ============================================================
+
+    /// Register a new version of a stored procedure owned by `user_id`
+    ///
+    /// Only the current owner or an admin may replace an existing procedure.
+    pub fn register_procedure(&self, name: &str, code: &str, description: Option<&str>, user_id: &str) -> Result<Procedure> {
+        if !self.is_authorized(user_id, "register_procedure") {
+            let message = format!("'{}' may not register procedures", user_id);
+            return Err(self.deny(user_id, "register_procedure", None, Some(name), message));
+        }
+        let validation = LuaValidator::new().with_globals(&["args"]).validate(code);
+        if !validation.valid {
+            let messages: Vec<String> = validation.errors.iter().map(|e| e.message.clone()).collect();
+            return Err(LiathError::InvalidInput(format!(
+                "Procedure '{}' failed validation: {}", name, messages.join("; ")
+            )).into());
+        }
+        let result = self.state.procedures.register(name, code, description, user_id, |current| {
+            if current.owner == user_id || self.state.auth_manager.read().unwrap().is_admin(user_id) {
+                return Ok(());
+            }
+            Err(LiathError::Unauthorized(format!(
+                "'{}' may not replace procedure '{}' owned by '{}'", user_id, name, current.owner
+            )).into())
+        });
+        if let Some(LiathError::Unauthorized(message)) = result.as_ref().err().and_then(|e| e.downcast_ref()) {
+            return Err(self.deny(user_id, "register_procedure", None, Some(name), message.clone()));
+        }
+        self.audited(user_id, "register_procedure", None, Some(name), result)
+    }
+
+    /// Get the current version of a stored procedure
+    pub fn get_procedure(&self, name: &str) -> Result<Option<Procedure>> {
+        self.state.procedures.get(name)
+    }
+
+    /// Get a specific version of a stored procedure
+    pub fn get_procedure_version(&self, name: &str, version: u32) -> Result<Option<Procedure>> {
+        self.state.procedures.get_version(name, version)
+    }
+
+    /// All versions of a stored procedure, oldest first
+    pub fn procedure_history(&self, name: &str) -> Result<Vec<Procedure>> {
+        self.state.procedures.history(name)
+    }
+
+    /// Current version of every stored procedure
+    pub fn list_procedures(&self) -> Result<Vec<Procedure>> {
+        self.state.procedures.list()
+    }
+
+    /// Delete a stored procedure and its history
+    pub fn delete_procedure(&self, name: &str, user_id: &str) -> Result<()> {
+        if !self.is_authorized(user_id, "delete_procedure") {
+            let message = format!("'{}' may not delete procedures", user_id);
+            return Err(self.deny(user_id, "delete_procedure", None, Some(name), message));
+        }
+        self.audited(user_id, "delete_procedure", None, Some(name), self.state.procedures.delete(name))
+    }
+
+    /// Call a stored procedure with JSON arguments and return its result as JSON
+    ///
+    /// The caller needs `call_procedure` or `call_procedure:<name>`; the body then
+    /// runs with the permissions of the procedure's owner.
+    #[instrument(skip(self, args))]
+    pub async fn call_procedure(&self, name: &str, args: serde_json::Value, user_id: &str) -> Result<serde_json::Value> {
+        let procedure = self.authorize_procedure_call(name, user_id)?;
+        let result = self.state
+            .lua_vm
+            .fresh()?
+            .execute_with_context(|lua_ctx| {
+                let args = json_to_lua_value(lua_ctx, &args)?;
+                let value = self.run_procedure(lua_ctx, &procedure, args)?;
+                lua_value_to_json(value)
+            })
+            .inspect_err(metrics::record_lua_error)?;
+        Ok(result)
+    }
+
+    fn authorize_procedure_call(&self, name: &str, user_id: &str) -> Result<Procedure> {
+        let procedure = self.state.procedures.get(name)?
+            .ok_or_else(|| LiathError::InvalidInput(format!("Procedure '{}' not found", name)))?;
+        if !self.is_authorized(user_id, "call_procedure") && !self.is_authorized(user_id, &procedure.call_permission()) {
+            let message = format!("'{}' may not call procedure '{}'", user_id, name);
+            return Err(self.deny(user_id, "call_procedure", None, Some(name), message));
+        }
+        Ok(procedure)
+    }
+
+    /// Run a procedure body with DB functions bound to its owner
+    fn run_procedure<'lua>(&self, lua_ctx: LuaContext<'lua>, procedure: &Procedure, args: LuaValue<'lua>) -> Result<LuaValue<'lua>, LuaError> {
+        let _depth = ProcedureDepthGuard::enter()?;
+        let chunk_name = format!("procedure:{}", procedure.name);
+        self.run_as_owner(lua_ctx, &chunk_name, &procedure.code, &procedure.owner, "args", args)
+    }
+
+    /// A fresh environment with DB functions and the `liath` stdlib bound to `user_id`
+    ///
+    /// Globals a chunk sets stay in its own environment; nothing is written to
+    /// the VM's globals, so code run as one user never sees another's bindings.
+    fn user_env<'lua>(&self, lua_ctx: LuaContext<'lua>, user_id: &str) -> Result<LuaTable<'lua>, LuaError> {
+        let env = lua_ctx.create_table()?;
+        let meta = lua_ctx.create_table()?;
+        meta.set("__index", self.user_bindings(lua_ctx, user_id)?)?;
+        env.set_metatable(Some(meta));
+        Ok(env)
+    }
+
+    /// The DB functions and stdlib bound to `user_id`, built once per VM and user
+    fn user_bindings<'lua>(&self, lua_ctx: LuaContext<'lua>, user_id: &str) -> Result<LuaTable<'lua>, LuaError> {
+        let cache = registry_table(lua_ctx, USER_BINDINGS)?;
+        if let Some(bindings) = cache.get::<_, Option<LuaTable>>(user_id)? {
+            return Ok(bindings);
+        }
+        let bindings = lua_ctx.create_table()?;
+        let meta = lua_ctx.create_table()?;
+        meta.set("__index", lua_ctx.globals())?;
+        bindings.set_metatable(Some(meta));
+        self.register_db_functions_into(&lua_ctx, &bindings, user_id)?;
+        // The stdlib calls DB functions as globals, so it is loaded over the same bindings
+        let stdlib: LuaValue = lua_ctx.load(LIATH_STDLIB).set_name("liath").set_environment(bindings.clone()).eval()?;
+        bindings.set("liath", stdlib)?;
+        cache.set(user_id, bindings.clone())?;
+        Ok(bindings)
+    }
+
+    /// Run stored code in its own environment with DB functions bound to `owner`
+    ///
+    /// `input` is passed both as the chunk argument (`...`) and as the global `input_name`.
+    fn run_as_owner<'lua>(
+        &self,
+        lua_ctx: LuaContext<'lua>,
+        chunk_name: &str,
+        code: &str,
+        owner: &str,
+        input_name: &str,
+        input: LuaValue<'lua>,
+    ) -> Result<LuaValue<'lua>, LuaError> {
+        let env = self.user_env(lua_ctx, owner)?;
+        env.set(input_name, input.clone())?;
+
+        lua_ctx
+            .load(code)
+            .set_name(chunk_name)
+            .set_environment(env)
+            .call(input)
+    }
+
+    // ============================================================
+    // USERS AND API KEYS
+    // ============================================================
+
+    fn check_manage_users(&self, user_id: &str, operation: &str, target: Option<&str>) -> Result<()> {
+        if !self.is_authorized(user_id, "manage_users") {
+            let message = format!("'{}' may not manage users", user_id);
+            return Err(self.deny(user_id, operation, None, target, message));
+        }
+        Ok(())
+    }
+
+    /// Create a user, or replace the permissions of an existing one
+    pub fn add_user(&self, new_user: &str, permissions: Vec<String>, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "add_user", Some(new_user))?;
+        self.state.auth_manager.write().unwrap().add_user(new_user, permissions);
+        self.audited(user_id, "add_user", None, Some(new_user), Ok(()))
+    }
+
+    /// Delete a user and revoke their API keys
+    pub fn remove_user(&self, member: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "remove_user", Some(member))?;
+        let result = self.state.auth_manager.write().unwrap().remove_user(member);
+        self.audited(user_id, "remove_user", None, Some(member), result)
+    }
+
+    /// Grant `member` a permission, e.g. `insert` or `select@docs_*`
+    pub fn grant_permission(&self, member: &str, permission: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "grant_permission", Some(member))?;
+        let result = self.state.auth_manager.write().unwrap().add_permission(member, permission.to_string());
+        self.audited(user_id, "grant_permission", None, Some(member), result)
+    }
+
+    /// Revoke a permission granted directly to `member`
+    pub fn revoke_permission(&self, member: &str, permission: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "revoke_permission", Some(member))?;
+        let result = self.state.auth_manager.write().unwrap().remove_permission(member, permission);
+        self.audited(user_id, "revoke_permission", None, Some(member), result)
+    }
+
+    /// Give `member` their own rate limit and quotas, or with `None` the defaults
+    pub fn set_limits(&self, member: &str, limits: Option<Limits>, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "set_limits", Some(member))?;
+        let result = self.state.auth_manager.write().unwrap().set_limits(member, limits);
+        self.audited(user_id, "set_limits", None, Some(member), result)
+    }
+
+    /// All users with their permissions
+    pub fn list_users(&self, user_id: &str) -> Result<Vec<UserInfo>> {
+        self.check_manage_users(user_id, "list_users", None)?;
+        Ok(self.state.auth_manager.read().unwrap().list_users())
+    }
+
+    /// Issue an API key for `owner`; returns the key's info and its bearer token
+    pub fn create_api_key(&self, owner: &str, description: Option<&str>, user_id: &str) -> Result<(ApiKeyInfo, String)> {
+        self.check_manage_users(user_id, "create_api_key", Some(owner))?;
+        let result = self.state.auth_manager.write().unwrap().create_api_key(owner, description);
+        self.audited(user_id, "create_api_key", None, Some(owner), result)
+    }
+
+    /// Revoke an API key by its ID
+    pub fn revoke_api_key(&self, key_id: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "revoke_api_key", Some(key_id))?;
+        let result = self.state.auth_manager.write().unwrap().revoke_api_key(key_id);
+        self.audited(user_id, "revoke_api_key", None, Some(key_id), result)
+    }
+
+    /// API keys, optionally only those issued for `owner`
+    pub fn list_api_keys(&self, owner: Option<&str>, user_id: &str) -> Result<Vec<ApiKeyInfo>> {
+        self.check_manage_users(user_id, "list_api_keys", owner)?;
+        Ok(self.state.auth_manager.read().unwrap().list_api_keys(owner))
+    }
+
+    /// Create or replace a role from grants such as `select@docs_*` or `!delete`
+    pub fn define_role(&self, name: &str, grants: Vec<String>, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "define_role", Some(name))?;
+        let result = self.state.auth_manager.write().unwrap().define_role(name, grants);
+        self.audited(user_id, "define_role", None, Some(name), result)
+    }
+
+    /// Delete a role and unassign it from every user
+    pub fn remove_role(&self, name: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "remove_role", Some(name))?;
+        let result = self.state.auth_manager.write().unwrap().remove_role(name);
+        self.audited(user_id, "remove_role", None, Some(name), result)
+    }
+
+    /// All roles with their grants
+    pub fn list_roles(&self, user_id: &str) -> Result<Vec<RoleInfo>> {
+        self.check_manage_users(user_id, "list_roles", None)?;
+        Ok(self.state.auth_manager.read().unwrap().list_roles())
+    }
+
+    /// Give `member` the grants of a role
+    pub fn assign_role(&self, member: &str, role: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "assign_role", Some(member))?;
+        let result = self.state.auth_manager.write().unwrap().assign_role(member, role);
+        self.audited(user_id, "assign_role", None, Some(member), result)
+    }
+
+    /// Take a role away from `member`
+    pub fn unassign_role(&self, member: &str, role: &str, user_id: &str) -> Result<()> {
+        self.check_manage_users(user_id, "unassign_role", Some(member))?;
+        let result = self.state.auth_manager.write().unwrap().unassign_role(member, role);
+        self.audited(user_id, "unassign_role", None, Some(member), result)
+    }
+
+    // ============================================================
+    // SCANS
+    // ============================================================
+
+    /// Lazily iterate over the entries of a namespace whose key starts with `prefix`
+    ///
+    /// With a `cursor` from an earlier page or iterator, the scan resumes right after it.
+    pub fn scan_iter(&self, namespace: &str, prefix: &str, cursor: Option<&str>, user_id: &str) -> Result<ScanIter> {
+        if !self.is_authorized_for(user_id, "select", namespace) {
+            let message = format!("'{}' may not select from '{}'", user_id, namespace);
+            return Err(self.deny(user_id, "scan", Some(namespace), None, message));
+        }
+        let after = cursor.map(|c| scan::decode_cursor(c, prefix)).transpose()?;
+        let ns = self.state.namespace_manager.read().unwrap().get_namespace(namespace)?;
+        Ok(ScanIter::new(ns.db.scan_prefix_after(prefix.as_bytes(), after.as_deref())))
+    }
+
+    /// Fetch one page of a prefix scan
+    pub fn scan_page(&self, namespace: &str, prefix: &str, limit: usize, cursor: Option<&str>, user_id: &str) -> Result<ScanPage> {
+        self.scan_iter(namespace, prefix, cursor, user_id)?.page(limit)
+    }
+
+    // ============================================================
+    // CHANGE FEED
+    // ============================================================
+
+    /// Subscribe to the changes matching `filter` as `user_id`
+    ///
+    /// A filter naming a namespace needs `select` on it; without one, only changes
+    /// to namespaces the user may select from are delivered.
+    pub fn subscribe(&self, filter: ChangeFilter, user_id: &str) -> Result<Subscription> {
+        if let Some(namespace) = &filter.namespace {
+            if !self.is_authorized_for(user_id, "select", namespace) {
+                let message = format!("'{}' may not watch '{}'", user_id, namespace);
+                return Err(self.deny(user_id, "watch", Some(namespace), None, message));
+            }
+        }
+        let auth_manager = self.state.auth_manager.clone();
+        let user_id = user_id.to_string();
+        self.state.changes.subscribe(filter, move |namespace| {
+            auth_manager.read().unwrap().is_authorized_for(&user_id, "select", namespace)
+        })
+    }
+
+    // ============================================================
+    // BULK INGEST
+    // ============================================================
+
+    /// Write a batch of bulk records to a namespace as `user_id`, who needs `insert`
+    ///
+    /// Each record comes with its line number for error reports. `reserve` makes room
+    /// for that many more vectors up front, e.g. the expected size of the whole ingest;
+    /// otherwise the index grows just enough for the batch.
+    pub fn bulk_ingest(&self, namespace: &str, records: Vec<(usize, BulkRecord)>, reserve: usize, user_id: &str) -> Result<BulkReport> {
+        self.authorize_for(user_id, "bulk_ingest", "insert", namespace, None)?;
+        let tokens = records.iter()
+            .filter(|(_, r)| r.vector.is_none())
+            .filter_map(|(_, r)| r.text.as_deref())
+            .map(estimate_tokens)
+            .sum();
+        let bytes = records.iter().map(|(_, r)| (r.key.len() + r.stored_value().len()) as u64).sum();
+        self.charge(user_id, Resource::StorageBytes, bytes)?;
+        self.refund_on_error(user_id, Resource::StorageBytes, bytes, self.charge(user_id, Resource::EmbeddingTokens, tokens))?;
+        let result = self.write_bulk(namespace, records, reserve);
+        let result = self.refund_on_error(user_id, Resource::EmbeddingTokens, tokens, result);
+        let result = self.refund_on_error(user_id, Resource::StorageBytes, bytes, result);
+        match &result {
+            Ok(report) => {
+                let detail = format!("{} written, {} failed", report.written, report.errors.len());
+                self.state.audit.record(user_id, "bulk_ingest", Some(namespace), None, AuditOutcome::Ok, Some(&detail));
+            }
+            Err(e) => {
+                self.state.audit.record(user_id, "bulk_ingest", Some(namespace), None, AuditOutcome::Failed, Some(&format!("{:#}", e)));
+            }
+        }
+        result
+    }
+
+    fn write_bulk(&self, namespace: &str, records: Vec<(usize, BulkRecord)>, reserve: usize) -> Result<BulkReport> {
+        let ns = self.state.namespace_manager.read().unwrap().get_namespace(namespace)?;
+        let (lines, mut records): (Vec<usize>, Vec<BulkRecord>) = records.into_iter().unzip();
+        let mut vectors: Vec<Option<Vec<f32>>> = records.iter_mut().map(|r| r.vector.take()).collect();
+        let mut failures: Vec<Option<String>> = vec![None; records.len()];
+
+        // Embed every text that came without a vector in one call
+        let pending: Vec<usize> = (0..records.len())
+            .filter(|&i| vectors[i].is_none() && records[i].text.is_some())
+            .collect();
+        if !pending.is_empty() {
+            let texts = pending.iter().map(|&i| records[i].text.as_deref().unwrap_or_default()).collect();
+            match self.generate_embedding(texts) {
+                Ok(embeddings) => {
+                    for (&i, vector) in pending.iter().zip(embeddings) {
+                        vectors[i] = Some(vector);
+                    }
+                }
+                Err(e) => {
+                    for &i in &pending {
+                        failures[i] = Some(format!("Embedding error: {:#}", e));
+                    }
+                }
+            }
+        }
+
+        let dimensions = ns.vector_db.dimensions();
+        for (vector, failure) in vectors.iter().zip(failures.iter_mut()) {
+            match vector {
+                Some(vector) if failure.is_none() && vector.len() != dimensions => {
+                    *failure = Some(format!("Vector has {} dimensions, expected {}", vector.len(), dimensions));
+                }
+                _ => {}
+            }
+        }
+
+        let mut values: Vec<Vec<u8>> = records.iter().map(|record| record.stored_value().into_bytes()).collect();
+        let write = |lua_ctx: Option<LuaContext>, values: &mut Vec<Vec<u8>>, failures: &mut Vec<Option<String>>| -> Result<()> {
+            if let Some(lua_ctx) = lua_ctx {
+                for i in 0..records.len() {
+                    if failures[i].is_some() {
+                        continue;
+                    }
+                    let value = std::mem::take(&mut values[i]);
+                    match self.run_before_triggers(lua_ctx, namespace, TriggerEvent::Put, records[i].key.as_bytes(), Some(value)) {
+                        Ok(value) => values[i] = value.unwrap_or_default(),
+                        Err(e) => failures[i] = Some(e.to_string()),
+                    }
+                }
+            }
+
+            let indexed = (0..records.len()).filter(|&i| failures[i].is_none() && vectors[i].is_some()).count();
+            let needed = ns.vector_db.size() + indexed.max(reserve);
+            if ns.vector_db.capacity() < needed {
+                ns.vector_db.reserve(needed)?;
+            }
+
+            let mut writes: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+            for (i, record) in records.iter().enumerate() {
+                if failures[i].is_some() {
+                    continue;
+                }
+                if let Some(vector) = &vectors[i] {
+                    let id = record.vector_id();
+                    let added = (|| {
+                        if ns.vector_db.contains(id) {
+                            ns.vector_db.remove(id)?;
+                        }
+                        ns.vector_db.add(id, vector)
+                    })();
+                    if let Err(e) = added {
+                        failures[i] = Some(format!("{:#}", e));
+                        continue;
+                    }
+                    writes.push((format!("_vidx:{}", id).into_bytes(), record.key.clone().into_bytes()));
+                }
+                if let Some(metadata) = &record.metadata {
+                    writes.push((format!("{}{}", bulk::METADATA_PREFIX, record.key).into_bytes(), metadata.to_string().into_bytes()));
+                }
+                writes.push((record.key.clone().into_bytes(), values[i].clone()));
+            }
+            let refs: Vec<(&[u8], &[u8])> = writes.iter().map(|(k, v)| (k.as_slice(), v.as_slice())).collect();
+            ns.db.batch_put(refs)?;
+            for (i, record) in records.iter().enumerate() {
+                if failures[i].is_none() {
+                    if vectors[i].is_some() {
+                        self.state.changes.emit(ChangeKind::AddVector, namespace, None, Some(record.vector_id()));
+                    }
+                    self.state.changes.emit(ChangeKind::Put, namespace, Some(&record.key), None);
+                }
+            }
+
+            if let Some(lua_ctx) = lua_ctx {
+                for i in 0..records.len() {
+                    if failures[i].is_none() {
+                        self.run_after_triggers(lua_ctx, namespace, TriggerEvent::Put, records[i].key.as_bytes(), Some(&values[i]));
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        if self.has_triggers(namespace) {
+            self.state.lua_vm.fresh()?.execute_with_context(|lua_ctx| {
+                write(Some(lua_ctx), &mut values, &mut failures)
+                    .map_err(|e| LuaError::RuntimeError(format!("{:#}", e)))
+            })?;
+        } else {
+            write(None, &mut values, &mut failures)?;
+        }
+
+        let mut report = BulkReport::default();
+        for ((line, record), failure) in lines.into_iter().zip(records).zip(failures) {
+            match failure {
+                None => report.written += 1,
+                Some(error) => report.errors.push(BulkError { line, key: Some(record.key), error }),
+            }
+        }
+        Ok(report)
+    }
+
+    // ============================================================
+    // LUA MODULES
+    // ============================================================
+
+    /// Store a new version of a Lua module
+    pub fn put_module(&self, name: &str, code: &str, description: Option<&str>, user_id: &str) -> Result<LuaModule> {
+        if !self.is_authorized(user_id, "manage_modules") {
+            let message = format!("'{}' may not manage modules", user_id);
+            return Err(self.deny(user_id, "put_module", None, Some(name), message));
+        }
+        let validation = LuaValidator::new().validate(code);
+        if !validation.valid {
+            let messages: Vec<String> = validation.errors.iter().map(|e| e.message.clone()).collect();
+            return Err(LiathError::InvalidInput(format!(
+                "Module '{}' failed validation: {}", name, messages.join("; ")
+            )).into());
+        }
+        let result = self.state.modules.put(name, code, description, user_id);
+        self.audited(user_id, "put_module", None, Some(name), result)
+    }
+
+    /// Get the current version of a Lua module
+    pub fn get_module(&self, name: &str) -> Result<Option<LuaModule>> {
+        self.state.modules.get(name)
+    }
+
+    /// Get a specific version of a Lua module
+    pub fn get_module_version(&self, name: &str, version: u32) -> Result<Option<LuaModule>> {
+        self.state.modules.get_version(name, version)
+    }
+
+    /// All versions of a Lua module, oldest first
+    pub fn module_history(&self, name: &str) -> Result<Vec<LuaModule>> {
+        self.state.modules.history(name)
+    }
+
+    /// Current version of every stored Lua module
+    pub fn list_modules(&self) -> Result<Vec<LuaModule>> {
+        self.state.modules.list()
+    }
+
+    /// Delete a Lua module and its history
+    pub fn delete_module(&self, name: &str, user_id: &str) -> Result<()> {
+        if !self.is_authorized(user_id, "manage_modules") {
+            let message = format!("'{}' may not manage modules", user_id);
+            return Err(self.deny(user_id, "delete_module", None, Some(name), message));
+        }
+        self.audited(user_id, "delete_module", None, Some(name), self.state.modules.delete(name))
+    }
+
+    /// Load a module for `require` as `user_id`, stored modules first and then bundled ones
+    ///
+    /// The module runs in its own environment with DB functions bound to `user_id`,
+    /// so its functions act as the user who required it rather than whoever
+    /// required it first. Loaded modules are cached in the VM by name,
+    /// version and user, so storing a new version takes effect on the next
+    /// `require` while repeated calls reuse the loaded value.
+    fn require_module<'lua>(&self, lua_ctx: LuaContext<'lua>, name: &str, version: Option<u32>, user_id: &str) -> Result<LuaValue<'lua>, LuaError> {
+        let stored = match version {
+            Some(v) => self.state.modules.get_version(name, v),
+            None => self.state.modules.get(name),
+        }
+        .map_err(|e| LuaError::RuntimeError(format!("Module error: {}", e)))?;
+        let (code, cache_key) = match (stored, version) {
+            (Some(module), _) => (module.code, format!("{}@{} as {}", name, module.version, user_id)),
+            (None, None) => match modules::bundled_module(name) {
+                Some(code) => (code.to_string(), format!("{}@bundled as {}", name, user_id)),
+                None => return Err(LuaError::RuntimeError(format!("Module '{}' not found", name))),
+            },
+            (None, Some(v)) => {
+                return Err(LuaError::RuntimeError(format!("Module '{}' has no version {}", name, v)));
+            }
+        };
+
+        let cache = registry_table(lua_ctx, MODULE_CACHE)?;
+        match cache.get::<_, LuaValue>(cache_key.as_str())? {
+            LuaValue::Nil => {}
+            LuaValue::Boolean(false) => {
+                return Err(LuaError::RuntimeError(format!("Circular require of module '{}'", name)));
+            }
+            loaded => return Ok(loaded),
+        }
+
+        // `false` marks the module as loading until its chunk returns
+        cache.set(cache_key.as_str(), false)?;
+        let result = self.user_env(lua_ctx, user_id).and_then(|env| {
+            lua_ctx
+                .load(&code)
+                .set_name(format!("module:{}", name))
+                .set_environment(env)
+                .call::<_, LuaValue>(name)
+        });
+        match result {
+            Ok(value) => {
+                let value = if let LuaValue::Nil = value { LuaValue::Boolean(true) } else { value };
+                cache.set(cache_key.as_str(), value.clone())?;
+                Ok(value)
+            }
+            Err(e) => {
+                cache.set(cache_key.as_str(), LuaValue::Nil)?;
+                Err(e)
+            }
+        }
+    }
+
+    // ============================================================
+    // TRIGGERS
+    // ============================================================
+
+    /// Attach a trigger to a namespace, replacing any trigger with the same name
+    ///
+    /// The trigger body runs with the permissions of `user_id`.
+    pub fn create_trigger(
+        &self,
+        namespace: &str,
+        name: &str,
+        event: TriggerEvent,
+        timing: TriggerTiming,
+        code: &str,
+        user_id: &str,
+    ) -> Result<()> {
+        if !self.is_authorized_for(user_id, "manage_triggers", namespace) {
+            let message = format!("'{}' may not manage triggers on '{}'", user_id, namespace);
+            return Err(self.deny(user_id, "create_trigger", Some(namespace), Some(name), message));
+        }
+        let validation = LuaValidator::new().with_globals(&["event"]).validate(code);
+        if !validation.valid {
+            let messages: Vec<String> = validation.errors.iter().map(|e| e.message.clone()).collect();
+            return Err(LiathError::InvalidInput(format!(
+                "Trigger '{}' failed validation: {}", name, messages.join("; ")
+            )).into());
+        }
+        let trigger = Trigger {
+            name: name.to_string(),
+            event,
+            timing,
+            code: code.to_string(),
+            owner: user_id.to_string(),
+        };
+        let result = self.state.namespace_manager.read().unwrap().set_trigger(namespace, trigger);
+        self.audited(user_id, "create_trigger", Some(namespace), Some(name), result)
     }
 
-    fn register_db_functions(&self, lua_ctx: &LuaContext, user_id: &str) -> Result<(), LuaError> {
+    /// Remove a trigger from a namespace
+    pub fn drop_trigger(&self, namespace: &str, name: &str, user_id: &str) -> Result<()> {
+        if !self.is_authorized_for(user_id, "manage_triggers", namespace) {
+            let message = format!("'{}' may not manage triggers on '{}'", user_id, namespace);
+            return Err(self.deny(user_id, "drop_trigger", Some(namespace), Some(name), message));
+        }
+        let result = self.state.namespace_manager.read().unwrap().remove_trigger(namespace, name);
+        self.audited(user_id, "drop_trigger", Some(namespace), Some(name), result)
+    }
+
+    /// Triggers attached to a namespace
+    pub fn list_triggers(&self, namespace: &str) -> Vec<Trigger> {
+        self.state.namespace_manager.read().unwrap().triggers(namespace)
+    }
+
+    fn has_triggers(&self, namespace: &str) -> bool {
+        !self.state.namespace_manager.read().unwrap().triggers(namespace).is_empty()
+    }
+
+    /// Fire the namespace's before-triggers for a write and return the (possibly replaced) value
+    ///
+    /// Each trigger receives an `event` table with `namespace`, `key`, `value`, `event`
+    /// and `timing`; key and value are passed as raw bytes. A before-trigger rejects the
+    /// write by returning `false` or raising an error, and replaces the value by
+    /// returning a string.
+    ///
+    /// Writes made by a trigger into a namespace whose triggers are already running on
+    /// this thread do not fire them again, so a trigger can write to its own namespace.
+    fn run_before_triggers(
+        &self,
+        lua_ctx: LuaContext,
+        namespace: &str,
+        event: TriggerEvent,
+        key: &[u8],
+        value: Option<Vec<u8>>,
+    ) -> Result<Option<Vec<u8>>, LuaError> {
+        let Some((triggers, _guard)) = self.enter_triggers(namespace, event, TriggerTiming::Before, key)? else {
+            return Ok(value);
+        };
+        let mut value = value;
+        for trigger in triggers {
+            let result = self.fire_trigger(lua_ctx, &trigger, namespace, event, TriggerTiming::Before, key, value.as_deref())?;
+            match result {
+                LuaValue::Boolean(false) => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Write to '{}' rejected by trigger '{}'", namespace, trigger.name
+                    )));
+                }
+                LuaValue::String(s) if event != TriggerEvent::Delete => {
+                    value = Some(s.as_bytes().to_vec());
+                }
+                _ => {}
+            }
+        }
+        Ok(value)
+    }
+
+    /// Fire the namespace's after-triggers for a write that has been committed
+    ///
+    /// The write stands whatever they do, so a failing trigger is logged and the
+    /// others still run.
+    fn run_after_triggers(&self, lua_ctx: LuaContext, namespace: &str, event: TriggerEvent, key: &[u8], value: Option<&[u8]>) {
+        let (triggers, _guard) = match self.enter_triggers(namespace, event, TriggerTiming::After, key) {
+            Ok(Some(entered)) => entered,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("After-triggers on '{}' did not run: {}", namespace, e);
+                return;
+            }
+        };
+        for trigger in triggers {
+            if let Err(e) = self.fire_trigger(lua_ctx, &trigger, namespace, event, TriggerTiming::After, key, value) {
+                tracing::warn!(
+                    "Trigger '{}' on '{}' failed after writing '{}': {}",
+                    trigger.name, namespace, String::from_utf8_lossy(key), e
+                );
+            }
+        }
+    }
+
+    /// The triggers to fire, with the guard marking them as running
+    ///
+    /// `None` if there are none, or if the namespace's triggers are already running.
+    fn enter_triggers(
+        &self,
+        namespace: &str,
+        event: TriggerEvent,
+        timing: TriggerTiming,
+        key: &[u8],
+    ) -> Result<Option<(Vec<Trigger>, TriggerGuard)>, LuaError> {
+        let triggers = self.state.namespace_manager.read().unwrap().matching_triggers(namespace, event, timing);
+        if triggers.is_empty() {
+            return Ok(None);
+        }
+        match TriggerGuard::enter(namespace)? {
+            Some(guard) => Ok(Some((triggers, guard))),
+            None => {
+                tracing::debug!(
+                    "Skipping {} {} triggers on '{}' for '{}': they are already running",
+                    timing, event, namespace, String::from_utf8_lossy(key)
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fire_trigger<'lua>(
+        &self,
+        lua_ctx: LuaContext<'lua>,
+        trigger: &Trigger,
+        namespace: &str,
+        event: TriggerEvent,
+        timing: TriggerTiming,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<LuaValue<'lua>, LuaError> {
+        let info = lua_ctx.create_table()?;
+        info.set("namespace", namespace)?;
+        info.set("key", lua_ctx.create_string(key)?)?;
+        info.set("value", value.map(|value| lua_ctx.create_string(value)).transpose()?)?;
+        info.set("event", event.to_string())?;
+        info.set("timing", timing.to_string())?;
+
+        let chunk_name = format!("trigger:{}:{}", namespace, trigger.name);
+        self.run_as_owner(lua_ctx, &chunk_name, &trigger.code, &trigger.owner, "event", LuaValue::Table(info))
+    }
+
+    fn register_db_functions_into(&self, lua_ctx: &LuaContext, target: &LuaTable, user_id: &str) -> Result<(), LuaError> {
         // These are cloned as needed in closures below
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(user_id);
 
         let user_id_str = user_id.to_string();
+        let lua = *lua_ctx;
+        let recording = profile::is_recording();
+        let mut bindings = Bindings::new(target).with_wrapper(move |name, function| {
+            let function = if recording { profile::wrap(lua, name, function)? } else { function };
+            metrics::wrap(lua, name, function)
+        });
 
         // Namespace operations
-        let user_id = user_id_str.clone();
-        lua_ctx.globals().set("create_namespace", lua_ctx.create_function_mut(move |_, (name, dimensions, metric, scalar): (String, usize, String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "create_namespace") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let changes = self.state.changes.clone();
+        bindings.set("create_namespace", lua_ctx.create_function(move |_, (name, dimensions, metric, scalar, options): (String, usize, String, String, Option<LuaTable>)| {
+            gate.check("create_namespace", "create_namespace", &name, None)?;
             let metric = match metric.as_str() {
                 "cosine" => MetricKind::Cos,
                 "euclidean" => MetricKind::L2sq,
@@ -189,29 +1411,51 @@ impl QueryExecutor {
                 "f16" => ScalarKind::F16,
                 _ => return Err(LuaError::RuntimeError("Invalid scalar kind".to_string())),
             };
-            namespace_manager.write().unwrap().create_namespace(&name, dimensions, metric, scalar)
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to create namespace: {}", e)))
+            let encrypted = match &options {
+                Some(options) => options.get::<_, Option<bool>>("encrypted")?.unwrap_or(false),
+                None => false,
+            };
+            let result = {
+                let manager = namespace_manager.write().unwrap();
+                if encrypted {
+                    manager.create_encrypted_namespace(&name, dimensions, metric, scalar)
+                } else {
+                    manager.create_namespace(&name, dimensions, metric, scalar)
+                }
+            }
+            .map_err(|e| LuaError::RuntimeError(format!("Failed to create namespace: {}", e)));
+            if result.is_ok() {
+                changes.emit(ChangeKind::CreateNamespace, &name, None, None);
+            }
+            gate.record("create_namespace", Some(&name), None, result)
         })?)?;
 
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("delete_namespace", lua_ctx.create_function_mut(move |_, name: String| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "delete_namespace") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let changes = self.state.changes.clone();
+        bindings.set("delete_namespace", lua_ctx.create_function(move |_, name: String| {
+            gate.check("delete_namespace", "delete_namespace", &name, None)?;
+            let result = namespace_manager.write().unwrap().delete_namespace(&name)
+                .map_err(|e| LuaError::RuntimeError(format!("Failed to delete namespace: {}", e)));
+            if result.is_ok() {
+                changes.emit(ChangeKind::DeleteNamespace, &name, None, None);
             }
-            namespace_manager.write().unwrap().delete_namespace(&name)
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to delete namespace: {}", e)))
+            gate.record("delete_namespace", Some(&name), None, result)
         })?)?;
 
         let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("list_namespaces", lua_ctx.create_function_mut(move |lua_ctx, ()| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "list_namespaces") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
+        let namespace_manager = self.state.namespace_manager.clone();
+        let auth_manager = self.state.auth_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("list_namespaces", lua_ctx.create_function(move |lua_ctx, ()| {
+            if !auth_manager.read().unwrap().is_authorized_anywhere(&user_id, "list_namespaces") {
+                gate.deny("list_namespaces", "list_namespaces", None, None)?;
             }
-            let namespaces = namespace_manager.read().unwrap().list_namespaces();
+            let auth = auth_manager.read().unwrap();
+            let namespaces: Vec<String> = namespace_manager.read().unwrap().list_namespaces()
+                .into_iter()
+                .filter(|ns| auth.is_authorized_for(&user_id, "list_namespaces", ns))
+                .collect();
             let lua_namespaces = lua_ctx.create_table()?;
             for (i, namespace) in namespaces.iter().enumerate() {
                 lua_namespaces.set(i + 1, namespace.clone())?;
@@ -220,13 +1464,10 @@ impl QueryExecutor {
         })?)?;
 
         // Database operations
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("select", lua_ctx.create_function_mut(move |_, (namespace, key): (String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "select") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("select", lua_ctx.create_function(move |_, (namespace, key): (String, String)| {
+            gate.check("select", "select", &namespace, Some(&key))?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
             let value = ns.db.get(key.as_bytes())
@@ -234,57 +1475,78 @@ impl QueryExecutor {
             Ok(value.map(|v| String::from_utf8_lossy(&v).into_owned()))
         })?)?;
 
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("insert", lua_ctx.create_function_mut(move |_, (namespace, key, value): (String, String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "insert") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-            ns.db.put(key.as_bytes(), value.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to insert value: {}", e)))?;
-            Ok(())
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let executor = self.downgrade();
+        bindings.set("insert", lua_ctx.create_function(move |lua_ctx, (namespace, key, value): (String, String, String)| {
+            let executor = executor.upgrade()?;
+            gate.check("insert", "insert", &namespace, Some(&key))?;
+            let bytes = (key.len() + value.len()) as u64;
+            gate.charge(Resource::StorageBytes, bytes)?;
+            let result = (|| {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+                let value = executor.run_before_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(value.into_bytes()))?
+                    .unwrap_or_default();
+                ns.db.put(key.as_bytes(), &value)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to insert value: {}", e)))?;
+                executor.state.changes.emit(ChangeKind::Put, &namespace, Some(&key), None);
+                executor.run_after_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(&value));
+                Ok(())
+            })();
+            let result = gate.refund_on_error(Resource::StorageBytes, bytes, result);
+            gate.record("insert", Some(&namespace), Some(&key), result)
         })?)?;
 
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("update", lua_ctx.create_function_mut(move |_, (namespace, key, value): (String, String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "update") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-            ns.db.put(key.as_bytes(), value.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to update value: {}", e)))?;
-            Ok(())
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let executor = self.downgrade();
+        bindings.set("update", lua_ctx.create_function(move |lua_ctx, (namespace, key, value): (String, String, String)| {
+            let executor = executor.upgrade()?;
+            gate.check("update", "update", &namespace, Some(&key))?;
+            let bytes = (key.len() + value.len()) as u64;
+            gate.charge(Resource::StorageBytes, bytes)?;
+            let result = (|| {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+                let value = executor.run_before_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(value.into_bytes()))?
+                    .unwrap_or_default();
+                ns.db.put(key.as_bytes(), &value)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to update value: {}", e)))?;
+                executor.state.changes.emit(ChangeKind::Put, &namespace, Some(&key), None);
+                executor.run_after_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(&value));
+                Ok(())
+            })();
+            let result = gate.refund_on_error(Resource::StorageBytes, bytes, result);
+            gate.record("update", Some(&namespace), Some(&key), result)
         })?)?;
 
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("delete", lua_ctx.create_function_mut(move |_, (namespace, key): (String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "delete") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-            ns.db.delete(key.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to delete value: {}", e)))?;
-            Ok(())
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let executor = self.downgrade();
+        bindings.set("delete", lua_ctx.create_function(move |lua_ctx, (namespace, key): (String, String)| {
+            let executor = executor.upgrade()?;
+            gate.check("delete", "delete", &namespace, Some(&key))?;
+            let result = (|| {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+                executor.run_before_triggers(lua_ctx, &namespace, TriggerEvent::Delete, key.as_bytes(), None)?;
+                ns.db.delete(key.as_bytes())
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to delete value: {}", e)))?;
+                executor.state.changes.emit(ChangeKind::Delete, &namespace, Some(&key), None);
+                executor.run_after_triggers(lua_ctx, &namespace, TriggerEvent::Delete, key.as_bytes(), None);
+                Ok(())
+            })();
+            gate.record("delete", Some(&namespace), Some(&key), result)
         })?)?;
 
         // Embedding operations
-        let user_id = user_id_str.clone();
-        let embedding = self.embedding.clone();
-        let auth_manager = self.auth_manager.clone();
-        let embedding_semaphore = self.embedding_semaphore.clone();
-        lua_ctx.globals().set("generate_embedding", lua_ctx.create_function_mut(move |lua_ctx, texts: Vec<String>| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "generate_embedding") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let embedding = self.state.embedding.clone();
+        let gate = self.gate(&user_id_str);
+        let embedding_semaphore = self.state.embedding_semaphore.clone();
+        bindings.set("generate_embedding", lua_ctx.create_function(move |lua_ctx, texts: Vec<String>| {
+            gate.check_global("generate_embedding", "generate_embedding")?;
+            gate.charge(Resource::EmbeddingTokens, texts.iter().map(|t| estimate_tokens(t)).sum())?;
             let _permit = embedding_semaphore.try_acquire()
                 .map_err(|_| LuaError::RuntimeError("Failed to acquire embedding semaphore".to_string()))?;
             
@@ -303,25 +1565,22 @@ impl QueryExecutor {
         })?)?;
 
         // File operations
-        let user_id = user_id_str.clone();
-        let file_storage = self.file_storage.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("upload_file", lua_ctx.create_function_mut(move |_, (_file_name, content): (String, Vec<u8>)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "upload_file") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let file_id = file_storage.read().unwrap().store(&content)
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to store file: {}", e)))?;
-            Ok(file_id)
+        let file_storage = self.state.file_storage.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("upload_file", lua_ctx.create_function(move |_, (_file_name, content): (String, Vec<u8>)| {
+            gate.check_global("upload_file", "upload_file")?;
+            let result = (|| -> Result<String, LuaError> {
+                let file_id = file_storage.read().unwrap().store(&content)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to store file: {}", e)))?;
+                Ok(file_id)
+            })();
+            gate.record("upload_file", None, None, result)
         })?)?;
 
-        let user_id = user_id_str.clone();
-        let file_storage = self.file_storage.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("retrieve_file", lua_ctx.create_function_mut(move |lua_ctx, file_id: String| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "retrieve_file") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let file_storage = self.state.file_storage.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("retrieve_file", lua_ctx.create_function(move |lua_ctx, file_id: String| {
+            gate.check_global("retrieve_file", "retrieve_file")?;
             let content = file_storage.read().unwrap().retrieve(&file_id)
                 .map_err(|e| LuaError::RuntimeError(format!("Failed to retrieve file: {}", e)))?;
             let lua_content = lua_ctx.create_string(&content)?;
@@ -329,13 +1588,10 @@ impl QueryExecutor {
         })?)?;
 
         // Vector search operations
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("similarity_search", lua_ctx.create_function_mut(move |lua_ctx, (namespace, vector, k): (String, Vec<f32>, usize)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "similarity_search") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("similarity_search", lua_ctx.create_function(move |lua_ctx, (namespace, vector, k): (String, Vec<f32>, usize)| {
+            gate.check("similarity_search", "similarity_search", &namespace, None)?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
             let results = ns.vector_db.search(&vector, k)
@@ -352,26 +1608,23 @@ impl QueryExecutor {
         })?)?;
 
         // LuaRocks package management
-        let user_id = user_id_str.clone();
-        let lua_vm = self.lua_vm.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("install_package", lua_ctx.create_function_mut(move |_, package_name: String| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "install_package") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            lua_vm.read().unwrap().install_package(&package_name)
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to install package: {}", e)))?;
-            Ok(())
+        let lua_vm = self.state.lua_vm.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("install_package", lua_ctx.create_function(move |_, package_name: String| {
+            gate.check_global("install_package", "install_package")?;
+            let result = (|| -> Result<(), LuaError> {
+                lua_vm.fresh()?.install_package(&package_name)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to install package: {}", e)))?;
+                Ok(())
+            })();
+            gate.record("install_package", None, Some(&package_name), result)
         })?)?;
 
-        let user_id = user_id_str.clone();
-        let lua_vm = self.lua_vm.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("list_packages", lua_ctx.create_function_mut(move |lua_ctx, ()| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "list_packages") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let packages = lua_vm.read().unwrap().list_installed_packages()
+        let lua_vm = self.state.lua_vm.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("list_packages", lua_ctx.create_function(move |lua_ctx, ()| {
+            gate.check_global("list_packages", "list_packages")?;
+            let packages = lua_vm.fresh()?.list_installed_packages()
                 .map_err(|e| LuaError::RuntimeError(format!("Failed to list packages: {}", e)))?;
             let lua_packages = lua_ctx.create_table()?;
             for (i, package) in packages.iter().enumerate() {
@@ -385,63 +1638,77 @@ impl QueryExecutor {
         // ============================================================
 
         // add_vector(namespace, id, vector) - Add a vector to the index
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("add_vector", lua_ctx.create_function_mut(move |_, (namespace, id, vector): (String, u64, Vec<f32>)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "insert") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-            ns.vector_db.add(id, &vector)
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to add vector: {}", e)))?;
-            Ok(())
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let changes = self.state.changes.clone();
+        bindings.set("add_vector", lua_ctx.create_function(move |_, (namespace, id, vector): (String, u64, Vec<f32>)| {
+            gate.check("add_vector", "insert", &namespace, None)?;
+            let result = (|| -> Result<(), LuaError> {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+                ns.vector_db.add(id, &vector)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to add vector: {}", e)))?;
+                changes.emit(ChangeKind::AddVector, &namespace, None, Some(id));
+                Ok(())
+            })();
+            gate.record("add_vector", Some(&namespace), Some(&id.to_string()), result)
         })?)?;
 
         // store_document(namespace, id, key, text) - Store text with auto-embedding
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let embedding = self.embedding.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("store_document", lua_ctx.create_function_mut(move |_, (namespace, id, key, text): (String, u64, String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "insert") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-
-            // Generate embedding
-            let embeddings = embedding.read().unwrap().generate(vec![text.as_str()])
-                .map_err(|e| LuaError::RuntimeError(format!("Embedding error: {}", e)))?;
-            let vector = embeddings.into_iter().next()
-                .ok_or_else(|| LuaError::RuntimeError("Failed to generate embedding".to_string()))?;
-
-            // Store text
-            ns.db.put(key.as_bytes(), text.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to store text: {}", e)))?;
-
-            // Store vector
-            ns.vector_db.add(id, &vector)
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to add vector: {}", e)))?;
-
-            // Store ID -> key mapping for semantic search lookup
-            let mapping_key = format!("_vidx:{}", id);
-            ns.db.put(mapping_key.as_bytes(), key.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to store mapping: {}", e)))?;
-
-            Ok(id)
+        let namespace_manager = self.state.namespace_manager.clone();
+        let embedding = self.state.embedding.clone();
+        let gate = self.gate(&user_id_str);
+        let executor = self.downgrade();
+        bindings.set("store_document", lua_ctx.create_function(move |lua_ctx, (namespace, id, key, text): (String, u64, String, String)| {
+            let executor = executor.upgrade()?;
+            gate.check("store_document", "insert", &namespace, Some(&key))?;
+            let (tokens, bytes) = (estimate_tokens(&text), (key.len() + text.len()) as u64);
+            gate.charge(Resource::EmbeddingTokens, tokens)?;
+            gate.refund_on_error(Resource::EmbeddingTokens, tokens, gate.charge(Resource::StorageBytes, bytes))?;
+            let result = (|| -> Result<u64, LuaError> {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+                let text = executor.run_before_triggers(lua_ctx, &namespace, TriggerEvent::StoreDocument, key.as_bytes(), Some(text.into_bytes()))?
+                    .unwrap_or_default();
+                let text = String::from_utf8(text)
+                    .map_err(|_| LuaError::RuntimeError(format!("A trigger replaced the text of '{}' with bytes that are not UTF-8", key)))?;
+
+                // Generate embedding
+                let embeddings = embedding.read().unwrap().generate(vec![text.as_str()])
+                    .map_err(|e| LuaError::RuntimeError(format!("Embedding error: {}", e)))?;
+                let vector = embeddings.into_iter().next()
+                    .ok_or_else(|| LuaError::RuntimeError("Failed to generate embedding".to_string()))?;
+
+                // Store text
+                ns.db.put(key.as_bytes(), text.as_bytes())
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to store text: {}", e)))?;
+
+                // Store vector
+                ns.vector_db.add(id, &vector)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to add vector: {}", e)))?;
+
+                // Store ID -> key mapping for semantic search lookup
+                let mapping_key = format!("_vidx:{}", id);
+                ns.db.put(mapping_key.as_bytes(), key.as_bytes())
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to store mapping: {}", e)))?;
+                executor.state.changes.emit(ChangeKind::Put, &namespace, Some(&key), None);
+                executor.state.changes.emit(ChangeKind::AddVector, &namespace, None, Some(id));
+
+                executor.run_after_triggers(lua_ctx, &namespace, TriggerEvent::StoreDocument, key.as_bytes(), Some(text.as_bytes()));
+                Ok(id)
+            })();
+            let result = gate.refund_on_error(Resource::StorageBytes, bytes, result);
+            let result = gate.refund_on_error(Resource::EmbeddingTokens, tokens, result);
+            gate.record("store_document", Some(&namespace), Some(&key), result)
         })?)?;
 
         // semantic_search(namespace, query_text, k) - Search by text query
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let embedding = self.embedding.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("semantic_search", lua_ctx.create_function_mut(move |lua_ctx, (namespace, query, k): (String, String, usize)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "similarity_search") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let embedding = self.state.embedding.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("semantic_search", lua_ctx.create_function(move |lua_ctx, (namespace, query, k): (String, String, usize)| {
+            gate.check("semantic_search", "similarity_search", &namespace, None)?;
+            gate.charge(Resource::EmbeddingTokens, estimate_tokens(&query))?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
 
@@ -480,47 +1747,53 @@ impl QueryExecutor {
         // ============================================================
 
         // json_encode(table) - Encode Lua table to JSON string
-        lua_ctx.globals().set("json_encode", lua_ctx.create_function(|_, value: LuaValue| {
+        bindings.set("json_encode", lua_ctx.create_function(|_, value: LuaValue| {
             let json = lua_value_to_json(value)?;
             serde_json::to_string(&json)
                 .map_err(|e| LuaError::RuntimeError(format!("JSON encode error: {}", e)))
         })?)?;
 
         // json_decode(string) - Decode JSON string to Lua table
-        lua_ctx.globals().set("json_decode", lua_ctx.create_function(|lua_ctx, json_str: String| {
+        bindings.set("json_decode", lua_ctx.create_function(|lua_ctx, json_str: String| {
             let value: serde_json::Value = serde_json::from_str(&json_str)
                 .map_err(|e| LuaError::RuntimeError(format!("JSON decode error: {}", e)))?;
             json_to_lua_value(lua_ctx, &value)
         })?)?;
 
         // insert_json(namespace, key, table) - Store Lua table as JSON
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("insert_json", lua_ctx.create_function_mut(move |_, (namespace, key, value): (String, String, LuaValue)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "insert") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-
-            let json = lua_value_to_json(value)?;
-            let json_str = serde_json::to_string(&json)
-                .map_err(|e| LuaError::RuntimeError(format!("JSON encode error: {}", e)))?;
-
-            ns.db.put(key.as_bytes(), json_str.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Failed to insert: {}", e)))?;
-            Ok(())
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let executor = self.downgrade();
+        bindings.set("insert_json", lua_ctx.create_function(move |lua_ctx, (namespace, key, value): (String, String, LuaValue)| {
+            let executor = executor.upgrade()?;
+            gate.check("insert_json", "insert", &namespace, Some(&key))?;
+            let result = (|| -> Result<(), LuaError> {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+
+                let json = lua_value_to_json(value)?;
+                let json_str = serde_json::to_string(&json)
+                    .map_err(|e| LuaError::RuntimeError(format!("JSON encode error: {}", e)))?;
+
+                let json = executor.run_before_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(json_str.into_bytes()))?
+                    .unwrap_or_default();
+                let bytes = (key.len() + json.len()) as u64;
+                gate.charge(Resource::StorageBytes, bytes)?;
+                let written = ns.db.put(key.as_bytes(), &json)
+                    .map_err(|e| LuaError::RuntimeError(format!("Failed to insert: {}", e)));
+                gate.refund_on_error(Resource::StorageBytes, bytes, written)?;
+                executor.state.changes.emit(ChangeKind::Put, &namespace, Some(&key), None);
+                executor.run_after_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(&json));
+                Ok(())
+            })();
+            gate.record("insert_json", Some(&namespace), Some(&key), result)
         })?)?;
 
         // select_json(namespace, key) - Retrieve as Lua table
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("select_json", lua_ctx.create_function_mut(move |lua_ctx, (namespace, key): (String, String)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "select") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("select_json", lua_ctx.create_function(move |lua_ctx, (namespace, key): (String, String)| {
+            gate.check("select_json", "select", &namespace, Some(&key))?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
 
@@ -543,9 +1816,9 @@ impl QueryExecutor {
         // ============================================================
 
         // save() - Persist all data to disk
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager_save = self.auth_manager.clone();
-        lua_ctx.globals().set("save", lua_ctx.create_function_mut(move |_, ()| {
+        let namespace_manager = self.state.namespace_manager.clone();
+        let auth_manager_save = self.state.auth_manager.clone();
+        bindings.set("save", lua_ctx.create_function(move |_, ()| {
             namespace_manager.read().unwrap().save_all()
                 .map_err(|e| LuaError::RuntimeError(format!("Save error: {}", e)))?;
             auth_manager_save.read().unwrap().flush()
@@ -554,18 +1827,18 @@ impl QueryExecutor {
         })?)?;
 
         // namespace_exists(name) - Check if namespace exists
-        let namespace_manager = self.namespace_manager.clone();
-        lua_ctx.globals().set("namespace_exists", lua_ctx.create_function_mut(move |_, name: String| {
+        let namespace_manager = self.state.namespace_manager.clone();
+        bindings.set("namespace_exists", lua_ctx.create_function(move |_, name: String| {
             Ok(namespace_manager.read().unwrap().namespace_exists(&name))
         })?)?;
 
         // uuid() - Generate a UUID
-        lua_ctx.globals().set("uuid", lua_ctx.create_function(|_, ()| {
+        bindings.set("uuid", lua_ctx.create_function(|_, ()| {
             Ok(uuid::Uuid::new_v4().to_string())
         })?)?;
 
         // timestamp() - Current Unix timestamp
-        lua_ctx.globals().set("timestamp", lua_ctx.create_function(|_, ()| {
+        bindings.set("timestamp", lua_ctx.create_function(|_, ()| {
             let ts = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
@@ -574,7 +1847,7 @@ impl QueryExecutor {
         })?)?;
 
         // sleep(ms) - Sleep for milliseconds (useful for rate limiting)
-        lua_ctx.globals().set("sleep", lua_ctx.create_function(|_, ms: u64| {
+        bindings.set("sleep", lua_ctx.create_function(|_, ms: u64| {
             std::thread::sleep(std::time::Duration::from_millis(ms));
             Ok(())
         })?)?;
@@ -585,42 +1858,53 @@ impl QueryExecutor {
 
         // batch_insert(namespace, items) - Batch insert key-value pairs
         // items = { {key="k1", value="v1"}, {key="k2", value="v2"}, ... }
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("batch_insert", lua_ctx.create_function_mut(move |_, (namespace, items): (String, LuaTable)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "insert") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-
-            let mut batch_items: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-            for pair in items.sequence_values::<LuaTable>() {
-                let item = pair?;
-                let key: String = item.get("key")?;
-                let value: String = item.get("value")?;
-                batch_items.push((key.into_bytes(), value.into_bytes()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        let executor = self.downgrade();
+        bindings.set("batch_insert", lua_ctx.create_function(move |lua_ctx, (namespace, items): (String, LuaTable)| {
+            let executor = executor.upgrade()?;
+            gate.check("batch_insert", "insert", &namespace, None)?;
+            let result = (|| -> Result<usize, LuaError> {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+
+                let mut batch_items: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+                for pair in items.sequence_values::<LuaTable>() {
+                    let item = pair?;
+                    let key: String = item.get("key")?;
+                    let value: String = item.get("value")?;
+                    let value = executor.run_before_triggers(lua_ctx, &namespace, TriggerEvent::Put, key.as_bytes(), Some(value.into_bytes()))?
+                        .unwrap_or_default();
+                    batch_items.push((key.into_bytes(), value));
+                }
 
-            let refs: Vec<(&[u8], &[u8])> = batch_items.iter()
-                .map(|(k, v)| (k.as_slice(), v.as_slice()))
-                .collect();
+                let bytes = batch_items.iter().map(|(k, v)| (k.len() + v.len()) as u64).sum();
+                gate.charge(Resource::StorageBytes, bytes)?;
+                let refs: Vec<(&[u8], &[u8])> = batch_items.iter()
+                    .map(|(k, v)| (k.as_slice(), v.as_slice()))
+                    .collect();
+
+                let written = ns.db.batch_put(refs)
+                    .map_err(|e| LuaError::RuntimeError(format!("Batch insert error: {}", e)));
+                gate.refund_on_error(Resource::StorageBytes, bytes, written)?;
+                for (key, _) in &batch_items {
+                    executor.state.changes.emit(ChangeKind::Put, &namespace, Some(&String::from_utf8_lossy(key)), None);
+                }
 
-            ns.db.batch_put(refs)
-                .map_err(|e| LuaError::RuntimeError(format!("Batch insert error: {}", e)))?;
+                for (key, value) in &batch_items {
+                    executor.run_after_triggers(lua_ctx, &namespace, TriggerEvent::Put, key, Some(value));
+                }
 
-            Ok(batch_items.len())
+                Ok(batch_items.len())
+            })();
+            gate.record("batch_insert", Some(&namespace), None, result)
         })?)?;
 
         // batch_select(namespace, keys) - Batch get values
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("batch_select", lua_ctx.create_function_mut(move |lua_ctx, (namespace, keys): (String, Vec<String>)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "select") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("batch_select", lua_ctx.create_function(move |lua_ctx, (namespace, keys): (String, Vec<String>)| {
+            gate.check("batch_select", "select", &namespace, None)?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
 
@@ -637,97 +1921,129 @@ impl QueryExecutor {
         })?)?;
 
         // scan(namespace, prefix, limit) - Scan keys with prefix
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("scan", lua_ctx.create_function_mut(move |lua_ctx, (namespace, prefix, limit): (String, String, Option<usize>)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "select") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("scan", lua_ctx.create_function(move |lua_ctx, (namespace, prefix, limit): (String, String, Option<usize>)| {
+            gate.check("scan", "select", &namespace, None)?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
 
             let limit = limit.unwrap_or(100);
             let results = lua_ctx.create_table()?;
-            let mut count = 0;
-
-            for result in ns.db.iter() {
-                if count >= limit {
-                    break;
-                }
+            for (i, result) in ns.db.scan_prefix(prefix.as_bytes()).take(limit).enumerate() {
                 let (key, value) = result
                     .map_err(|e| LuaError::RuntimeError(format!("Scan error: {}", e)))?;
-                let key_str = String::from_utf8_lossy(&key);
-                if key_str.starts_with(&prefix) {
-                    let entry = lua_ctx.create_table()?;
-                    entry.set("key", key_str.into_owned())?;
-                    entry.set("value", String::from_utf8_lossy(&value).into_owned())?;
-                    results.set(count + 1, entry)?;
-                    count += 1;
-                }
+                let entry = lua_ctx.create_table()?;
+                entry.set("key", String::from_utf8_lossy(&key).into_owned())?;
+                entry.set("value", String::from_utf8_lossy(&value).into_owned())?;
+                results.set(i + 1, entry)?;
             }
             Ok(results)
         })?)?;
 
-        // ============================================================
-        // AGENT MEMORY OPERATIONS
-        // ============================================================
+        // iter(namespace, prefix, [cursor]) - Lazy iterator for generic for loops
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("iter", lua_ctx.create_function(move |lua_ctx, (namespace, prefix, cursor): (String, String, Option<String>)| {
+            let executor = executor.upgrade()?;
+            let mut entries = executor.scan_iter(&namespace, &prefix, cursor.as_deref(), &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Scan error: {:#}", e)))?;
+            lua_ctx.create_function_mut(move |_, _: rlua::MultiValue| {
+                match entries.next() {
+                    Some(Ok(entry)) => Ok((Some(entry.key), Some(entry.value))),
+                    Some(Err(e)) => Err(LuaError::RuntimeError(format!("Scan error: {}", e))),
+                    None => Ok((None, None)),
+                }
+            })
+        })?)?;
 
-        // memory_store(namespace, content, tags) - Store content with embedding
+        // scan_page(namespace, prefix, [limit], [cursor]) - One page with a cursor for the next
         let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let embedding = self.embedding.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("memory_store", lua_ctx.create_function_mut(move |_, (namespace, content, tags): (String, String, Option<Vec<String>>)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "insert") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
+        let executor = self.downgrade();
+        bindings.set("scan_page", lua_ctx.create_function(move |lua_ctx, (namespace, prefix, limit, cursor): (String, String, Option<usize>, Option<String>)| {
+            let executor = executor.upgrade()?;
+            let page = executor
+                .scan_page(&namespace, &prefix, limit.unwrap_or(scan::DEFAULT_PAGE_SIZE), cursor.as_deref(), &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Scan error: {:#}", e)))?;
+            let entries = lua_ctx.create_table()?;
+            for (i, entry) in page.entries.into_iter().enumerate() {
+                let row = lua_ctx.create_table()?;
+                row.set("key", entry.key)?;
+                row.set("value", entry.value)?;
+                entries.set(i + 1, row)?;
             }
-            let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
-                .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
-
-            // Generate ID
-            let id = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos() as u64;
-
-            // Generate embedding
-            let embeddings = embedding.read().unwrap().generate(vec![content.as_str()])
-                .map_err(|e| LuaError::RuntimeError(format!("Embedding error: {}", e)))?;
-            let vector = embeddings.into_iter().next()
-                .ok_or_else(|| LuaError::RuntimeError("Failed to generate embedding".to_string()))?;
-
-            // Store content
-            let content_key = format!("mem:{}:content", id);
-            ns.db.put(content_key.as_bytes(), content.as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Store error: {}", e)))?;
-
-            // Store metadata with tags
-            let meta = serde_json::json!({
-                "id": id,
-                "tags": tags.unwrap_or_default(),
-                "created_at": SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
-            });
-            let meta_key = format!("mem:{}:meta", id);
-            ns.db.put(meta_key.as_bytes(), meta.to_string().as_bytes())
-                .map_err(|e| LuaError::RuntimeError(format!("Store error: {}", e)))?;
+            let result = lua_ctx.create_table()?;
+            result.set("entries", entries)?;
+            result.set("cursor", page.cursor)?;
+            Ok(result)
+        })?)?;
 
-            // Store vector
-            ns.vector_db.add(id, &vector)
-                .map_err(|e| LuaError::RuntimeError(format!("Vector error: {}", e)))?;
+        // ============================================================
+        // AGENT MEMORY OPERATIONS
+        // ============================================================
 
-            Ok(id)
+        // memory_store(namespace, content, tags) - Store content with embedding
+        let namespace_manager = self.state.namespace_manager.clone();
+        let embedding = self.state.embedding.clone();
+        let gate = self.gate(&user_id_str);
+        let changes = self.state.changes.clone();
+        bindings.set("memory_store", lua_ctx.create_function(move |_, (namespace, content, tags): (String, String, Option<Vec<String>>)| {
+            gate.check("memory_store", "insert", &namespace, None)?;
+            let (tokens, bytes) = (estimate_tokens(&content), content.len() as u64);
+            gate.charge(Resource::EmbeddingTokens, tokens)?;
+            gate.refund_on_error(Resource::EmbeddingTokens, tokens, gate.charge(Resource::StorageBytes, bytes))?;
+            let result = (|| -> Result<u64, LuaError> {
+                let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
+                    .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
+
+                // Generate ID
+                let id = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos() as u64;
+
+                // Generate embedding
+                let embeddings = embedding.read().unwrap().generate(vec![content.as_str()])
+                    .map_err(|e| LuaError::RuntimeError(format!("Embedding error: {}", e)))?;
+                let vector = embeddings.into_iter().next()
+                    .ok_or_else(|| LuaError::RuntimeError("Failed to generate embedding".to_string()))?;
+
+                // Store content
+                let content_key = format!("mem:{}:content", id);
+                ns.db.put(content_key.as_bytes(), content.as_bytes())
+                    .map_err(|e| LuaError::RuntimeError(format!("Store error: {}", e)))?;
+
+                // Store metadata with tags
+                let meta = serde_json::json!({
+                    "id": id,
+                    "tags": tags.unwrap_or_default(),
+                    "created_at": SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+                });
+                let meta_key = format!("mem:{}:meta", id);
+                ns.db.put(meta_key.as_bytes(), meta.to_string().as_bytes())
+                    .map_err(|e| LuaError::RuntimeError(format!("Store error: {}", e)))?;
+
+                // Store vector
+                ns.vector_db.add(id, &vector)
+                    .map_err(|e| LuaError::RuntimeError(format!("Vector error: {}", e)))?;
+                changes.emit(ChangeKind::Put, &namespace, Some(&content_key), None);
+                changes.emit(ChangeKind::Put, &namespace, Some(&meta_key), None);
+                changes.emit(ChangeKind::AddVector, &namespace, None, Some(id));
+
+                Ok(id)
+            })();
+            let result = gate.refund_on_error(Resource::StorageBytes, bytes, result);
+            let result = gate.refund_on_error(Resource::EmbeddingTokens, tokens, result);
+            gate.record("memory_store", Some(&namespace), None, result)
         })?)?;
 
         // memory_recall(namespace, query, k) - Recall similar memories
-        let user_id = user_id_str.clone();
-        let namespace_manager = self.namespace_manager.clone();
-        let embedding = self.embedding.clone();
-        let auth_manager = self.auth_manager.clone();
-        lua_ctx.globals().set("memory_recall", lua_ctx.create_function_mut(move |lua_ctx, (namespace, query, k): (String, String, usize)| {
-            if !auth_manager.read().unwrap().is_authorized(&user_id, "select") {
-                return Err(LuaError::RuntimeError("Unauthorized".to_string()));
-            }
+        let namespace_manager = self.state.namespace_manager.clone();
+        let embedding = self.state.embedding.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("memory_recall", lua_ctx.create_function(move |lua_ctx, (namespace, query, k): (String, String, usize)| {
+            gate.check("memory_recall", "select", &namespace, None)?;
+            gate.charge(Resource::EmbeddingTokens, estimate_tokens(&query))?;
             let ns = namespace_manager.read().unwrap().get_namespace(&namespace)
                 .map_err(|e| LuaError::RuntimeError(format!("Namespace error: {}", e)))?;
 
@@ -777,10 +2093,154 @@ impl QueryExecutor {
             Ok(lua_results)
         })?)?;
 
-        Ok(())
+        // sql(query) - Run a declarative query; returns an array of rows keyed by column
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("sql", lua_ctx.create_function(move |lua_ctx, query: String| {
+            let executor = executor.upgrade()?;
+            let result = executor.query(&query, &user_id)
+                .map_err(|e| LuaError::RuntimeError(e.to_string()))?;
+            json_to_lua_value(lua_ctx, &serde_json::Value::Array(result.to_objects()))
+        })?)?;
+
+        // Stored procedures
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("register_procedure", lua_ctx.create_function(move |_, (name, code, description): (String, String, Option<String>)| {
+            let executor = executor.upgrade()?;
+            let procedure = executor.register_procedure(&name, &code, description.as_deref(), &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Procedure error: {}", e)))?;
+            Ok(procedure.version)
+        })?)?;
+
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("call", lua_ctx.create_function(move |lua_ctx, (name, args): (String, LuaValue)| {
+            let executor = executor.upgrade()?;
+            let procedure = executor.authorize_procedure_call(&name, &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Procedure error: {}", e)))?;
+            executor.run_procedure(lua_ctx, &procedure, args)
+        })?)?;
+
+        let procedures = self.state.procedures.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("list_procedures", lua_ctx.create_function(move |lua_ctx, ()| {
+            gate.check_global("list_procedures", "call_procedure")?;
+            let list = procedures.list()
+                .map_err(|e| LuaError::RuntimeError(format!("Procedure error: {}", e)))?;
+            let lua_list = lua_ctx.create_table()?;
+            for (i, procedure) in list.into_iter().enumerate() {
+                let entry = lua_ctx.create_table()?;
+                entry.set("name", procedure.name)?;
+                entry.set("version", procedure.version)?;
+                entry.set("description", procedure.description)?;
+                entry.set("owner", procedure.owner)?;
+                lua_list.set(i + 1, entry)?;
+            }
+            Ok(lua_list)
+        })?)?;
+
+        // Modules (require is re-entrant: modules may require other modules)
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("require", lua_ctx.create_function(move |lua_ctx, (name, version): (String, Option<u32>)| {
+            let executor = executor.upgrade()?;
+            executor.require_module(lua_ctx, &name, version, &user_id)
+        })?)?;
+
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("module_put", lua_ctx.create_function(move |_, (name, code, description): (String, String, Option<String>)| {
+            let executor = executor.upgrade()?;
+            let module = executor.put_module(&name, &code, description.as_deref(), &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Module error: {}", e)))?;
+            Ok(module.version)
+        })?)?;
+
+        let modules = self.state.modules.clone();
+        bindings.set("list_modules", lua_ctx.create_function(move |lua_ctx, ()| {
+            let list = modules.list()
+                .map_err(|e| LuaError::RuntimeError(format!("Module error: {}", e)))?;
+            let lua_list = lua_ctx.create_table()?;
+            for (i, module) in list.into_iter().enumerate() {
+                let entry = lua_ctx.create_table()?;
+                entry.set("name", module.name)?;
+                entry.set("version", module.version)?;
+                entry.set("description", module.description)?;
+                entry.set("owner", module.owner)?;
+                lua_list.set(i + 1, entry)?;
+            }
+            Ok(lua_list)
+        })?)?;
+
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("module_delete", lua_ctx.create_function(move |_, name: String| {
+            let executor = executor.upgrade()?;
+            executor.delete_module(&name, &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Module error: {}", e)))
+        })?)?;
+
+        // Triggers
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("create_trigger", lua_ctx.create_function(move |_, (namespace, name, event, timing, code): (String, String, String, String, String)| {
+            let executor = executor.upgrade()?;
+            let event: TriggerEvent = event.parse()
+                .map_err(|e| LuaError::RuntimeError(format!("Trigger error: {}", e)))?;
+            let timing: TriggerTiming = timing.parse()
+                .map_err(|e| LuaError::RuntimeError(format!("Trigger error: {}", e)))?;
+            executor.create_trigger(&namespace, &name, event, timing, &code, &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Trigger error: {}", e)))
+        })?)?;
+
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("drop_trigger", lua_ctx.create_function(move |_, (namespace, name): (String, String)| {
+            let executor = executor.upgrade()?;
+            executor.drop_trigger(&namespace, &name, &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Trigger error: {}", e)))
+        })?)?;
+
+        let namespace_manager = self.state.namespace_manager.clone();
+        let gate = self.gate(&user_id_str);
+        bindings.set("list_triggers", lua_ctx.create_function(move |lua_ctx, namespace: String| {
+            gate.check("list_triggers", "manage_triggers", &namespace, None)?;
+            let triggers = namespace_manager.read().unwrap().triggers(&namespace);
+            let lua_list = lua_ctx.create_table()?;
+            for (i, trigger) in triggers.into_iter().enumerate() {
+                let entry = lua_ctx.create_table()?;
+                entry.set("name", trigger.name)?;
+                entry.set("event", trigger.event.to_string())?;
+                entry.set("timing", trigger.timing.to_string())?;
+                entry.set("owner", trigger.owner)?;
+                lua_list.set(i + 1, entry)?;
+            }
+            Ok(lua_list)
+        })?)?;
+
+        let user_id = user_id_str.clone();
+        let executor = self.downgrade();
+        bindings.set("delete_procedure", lua_ctx.create_function(move |_, name: String| {
+            let executor = executor.upgrade()?;
+            executor.delete_procedure(&name, &user_id)
+                .map_err(|e| LuaError::RuntimeError(format!("Procedure error: {}", e)))
+        })?)?;
+
+        bindings.finish()
     }
 }
 
+/// A table kept in the Lua registry under `name`, created on first use
+fn registry_table<'lua>(lua_ctx: LuaContext<'lua>, name: &str) -> Result<LuaTable<'lua>, LuaError> {
+    if let Some(table) = lua_ctx.named_registry_value::<Option<LuaTable>>(name)? {
+        return Ok(table);
+    }
+    let table = lua_ctx.create_table()?;
+    lua_ctx.set_named_registry_value(name, table.clone())?;
+    Ok(table)
+}
+
 // ============================================================
 // JSON CONVERSION HELPERS
 // ============================================================
@@ -1042,4 +2502,24 @@ mod tests {
             panic!("Expected LuaValue::Table");
         }
     }
+
+    #[tokio::test]
+    async fn test_bindings_do_not_keep_the_executor_alive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut auth_manager = AuthManager::new();
+        auth_manager.add_user("admin", vec!["*".to_string()]);
+        let executor = QueryExecutor::new(
+            NamespaceManager::new(temp_dir.path().to_path_buf()).unwrap(),
+            EmbeddingWrapper::new().unwrap(),
+            LuaVM::new(std::path::PathBuf::from("luarocks")).unwrap(),
+            FileStorage::new(temp_dir.path().join("files")).unwrap(),
+            auth_manager,
+            1,
+        );
+        assert_eq!(executor.execute("return 1", "admin").await.unwrap(), "1");
+
+        let state = Arc::downgrade(&executor.state);
+        drop(executor);
+        assert!(state.upgrade().is_none());
+    }
 }
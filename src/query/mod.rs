@@ -1,5 +1,23 @@
+pub mod ast;
+pub mod audit;
+pub mod bulk;
+pub mod changes;
+pub(crate) mod eval;
 pub mod executor;
+pub mod modules;
 pub mod parser;
+pub mod planner;
+pub mod procedures;
+pub mod profile;
+pub mod scan;
 
+pub use audit::{AuditConfig, AuditEntry, AuditFilter, AuditLog, AuditOutcome};
+pub use bulk::{BulkError, BulkRecord, BulkReport};
+pub use changes::{Change, ChangeFeed, ChangeFilter, ChangeKind, Subscription};
 pub use executor::QueryExecutor;
-pub use parser::QueryParser;
\ No newline at end of file
+pub use modules::{LuaModule, ModuleStore};
+pub use parser::QueryParser;
+pub use planner::{Planner, QueryPlan, QueryResult};
+pub use procedures::{Procedure, ProcedureStore};
+pub use profile::{FunctionProfile, Profile};
+pub use scan::{ScanEntry, ScanIter, ScanPage};
@@ -0,0 +1,206 @@
+//! Lua modules: named, versioned libraries kept in the database
+//!
+//! Modules live in the `_modules` system namespace, laid out like procedures: the
+//! current version of each module under `mod:<name>` and every version under
+//! `version:<name>:<version>`. Scripts load them with the sandboxed `require`,
+//! which also resolves the modules bundled with Liath (such as `liath`).
+
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use crate::core::{Namespace, NamespaceManager};
+
+/// System namespace holding module sources
+pub const MODULES_NAMESPACE: &str = "_modules";
+
+/// Modules compiled into Liath, resolvable by `require` without being stored
+pub const BUNDLED_MODULES: &[(&str, &str)] = &[
+    ("liath", crate::lua::LIATH_STDLIB),
+];
+
+/// Source of a bundled module
+pub fn bundled_module(name: &str) -> Option<&'static str> {
+    BUNDLED_MODULES.iter().find(|(n, _)| *n == name).map(|(_, code)| *code)
+}
+
+/// A stored module version
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LuaModule {
+    pub name: String,
+    pub version: u32,
+    pub code: String,
+    pub description: Option<String>,
+    pub owner: String,
+    pub created_at: u64,
+}
+
+/// Persistent store for module sources
+#[derive(Clone)]
+pub struct ModuleStore {
+    namespace_manager: Arc<RwLock<NamespaceManager>>,
+}
+
+impl ModuleStore {
+    pub fn new(namespace_manager: Arc<RwLock<NamespaceManager>>) -> Self {
+        Self { namespace_manager }
+    }
+
+    fn namespace(&self) -> Result<Namespace> {
+        self.namespace_manager.read().unwrap().system_namespace(MODULES_NAMESPACE)
+    }
+
+    fn current_key(name: &str) -> String {
+        format!("mod:{}", name)
+    }
+
+    fn version_key(name: &str, version: u32) -> String {
+        format!("version:{}:{:08}", name, version)
+    }
+
+    /// Check that a module name is usable; dots separate parts as in `utils.text`
+    pub fn validate_name(name: &str) -> Result<()> {
+        let valid = !name.is_empty() && name.split('.').all(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        });
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "Invalid module name '{}': use letters, digits, '_' or '-', with '.' between parts",
+                name
+            ));
+        }
+        if bundled_module(name).is_some() {
+            return Err(anyhow::anyhow!("Module name '{}' is reserved for a bundled module", name));
+        }
+        Ok(())
+    }
+
+    /// Store a new version of a module and return it
+    pub fn put(&self, name: &str, code: &str, description: Option<&str>, owner: &str) -> Result<LuaModule> {
+        Self::validate_name(name)?;
+        let ns = self.namespace()?;
+
+        let version = match self.get(name)? {
+            Some(current) => current.version + 1,
+            None => 1,
+        };
+        let module = LuaModule {
+            name: name.to_string(),
+            version,
+            code: code.to_string(),
+            description: description.map(String::from),
+            owner: owner.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let value = serde_json::to_vec(&module)
+            .context("Failed to serialize module")?;
+        let version_key = Self::version_key(name, version);
+        let current_key = Self::current_key(name);
+        ns.db.batch_put(vec![
+            (version_key.as_bytes(), value.as_slice()),
+            (current_key.as_bytes(), value.as_slice()),
+        ])?;
+
+        tracing::info!("Stored module '{}' version {}", name, version);
+        Ok(module)
+    }
+
+    /// Get the current version of a module
+    pub fn get(&self, name: &str) -> Result<Option<LuaModule>> {
+        let ns = self.namespace()?;
+        match ns.db.get(Self::current_key(name).as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)
+                .context(format!("Failed to deserialize module '{}'", name))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a specific version of a module
+    pub fn get_version(&self, name: &str, version: u32) -> Result<Option<LuaModule>> {
+        let ns = self.namespace()?;
+        match ns.db.get(Self::version_key(name, version).as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)
+                .context(format!("Failed to deserialize module '{}' version {}", name, version))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All versions of a module, oldest first
+    pub fn history(&self, name: &str) -> Result<Vec<LuaModule>> {
+        let ns = self.namespace()?;
+        let prefix = format!("version:{}:", name);
+        let mut versions = Vec::new();
+        for result in ns.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = result?;
+            versions.push(serde_json::from_slice(&value)
+                .context(format!("Failed to deserialize module '{}'", name))?);
+        }
+        Ok(versions)
+    }
+
+    /// Current version of every stored module, ordered by name
+    pub fn list(&self) -> Result<Vec<LuaModule>> {
+        let ns = self.namespace()?;
+        let mut modules = Vec::new();
+        for result in ns.db.scan_prefix(b"mod:") {
+            let (_, value) = result?;
+            modules.push(serde_json::from_slice(&value)
+                .context("Failed to deserialize module")?);
+        }
+        Ok(modules)
+    }
+
+    /// Delete a module and its version history
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let ns = self.namespace()?;
+        if ns.db.get(Self::current_key(name).as_bytes())?.is_none() {
+            return Err(anyhow::anyhow!("Module '{}' not found", name));
+        }
+        for module in self.history(name)? {
+            ns.db.delete(Self::version_key(name, module.version).as_bytes())?;
+        }
+        ns.db.delete(Self::current_key(name).as_bytes())?;
+        tracing::info!("Deleted module '{}'", name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_module_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = NamespaceManager::new(temp_dir.path().to_path_buf()).unwrap();
+        let store = ModuleStore::new(Arc::new(RwLock::new(manager)));
+
+        assert_eq!(store.put("text.utils", "return {}", None, "admin").unwrap().version, 1);
+        assert_eq!(store.put("text.utils", "return {v = 2}", Some("v2"), "admin").unwrap().version, 2);
+        assert_eq!(store.get("text.utils").unwrap().unwrap().code, "return {v = 2}");
+        assert_eq!(store.get_version("text.utils", 1).unwrap().unwrap().code, "return {}");
+        assert_eq!(store.history("text.utils").unwrap().len(), 2);
+        assert_eq!(store.list().unwrap().len(), 1);
+
+        store.delete("text.utils").unwrap();
+        assert!(store.get("text.utils").unwrap().is_none());
+        assert!(store.history("text.utils").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_module_name_validation() {
+        assert!(ModuleStore::validate_name("utils").is_ok());
+        assert!(ModuleStore::validate_name("text.utils").is_ok());
+        assert!(ModuleStore::validate_name("text..utils").is_err());
+        assert!(ModuleStore::validate_name("a/b").is_err());
+        assert!(ModuleStore::validate_name("liath").is_err());
+        assert!(bundled_module("liath").is_some());
+    }
+}
@@ -1,4 +1,6 @@
 use anyhow::Result;
+use crate::error::LiathError;
+use super::ast::*;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum QueryType {
@@ -29,7 +31,12 @@ impl QueryParser {
         }
 
         let query_type = match parts[0].to_lowercase().as_str() {
-            "select" => QueryType::Select,
+            // Declarative SELECTs are classified by what they do
+            "select" | "explain" => match Self::parse_sql(query) {
+                Ok(Statement::Select(select)) | Ok(Statement::Explain(select)) if select.join.is_some() => QueryType::Join,
+                Ok(Statement::Select(select)) | Ok(Statement::Explain(select)) if select.is_aggregate() => QueryType::Aggregate,
+                _ => QueryType::Select,
+            },
             "insert" => QueryType::Insert,
             "update" => QueryType::Update,
             "delete" => QueryType::Delete,
@@ -51,4 +58,623 @@ impl QueryParser {
 
         Ok((query_type, args))
     }
-}
\ No newline at end of file
+
+    /// Parse a statement of the declarative query language
+    ///
+    /// ```text
+    /// [EXPLAIN] SELECT items FROM ns [alias]
+    ///     [[LEFT] JOIN ns [alias] ON condition]
+    ///     [WHERE condition] [GROUP BY exprs] [HAVING condition]
+    ///     [ORDER BY expr [ASC|DESC], ...] [LIMIT n [OFFSET m]]
+    /// ```
+    pub fn parse_sql(query: &str) -> Result<Statement> {
+        let tokens = tokenize(query)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let statement = parser.statement()?;
+        parser.eat(&Token::Semicolon);
+        parser.expect_end()?;
+        Ok(statement)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// `"name"` or `` `name` ``; never treated as a keyword
+    QuotedIdent(String),
+    Number(f64),
+    String(String),
+    Comma,
+    Dot,
+    LParen,
+    RParen,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Semicolon,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "{}", s),
+            Token::QuotedIdent(s) => write!(f, "\"{}\"", s),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::String(s) => write!(f, "'{}'", s),
+            Token::Comma => write!(f, ","),
+            Token::Dot => write!(f, "."),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Star => write!(f, "*"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Slash => write!(f, "/"),
+            Token::Eq => write!(f, "="),
+            Token::NotEq => write!(f, "!="),
+            Token::Lt => write!(f, "<"),
+            Token::LtEq => write!(f, "<="),
+            Token::Gt => write!(f, ">"),
+            Token::GtEq => write!(f, ">="),
+            Token::Semicolon => write!(f, ";"),
+        }
+    }
+}
+
+fn syntax_error(message: String) -> anyhow::Error {
+    LiathError::Query(message).into()
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\'' => {
+                let (s, next) = read_quoted(&chars, i, '\'')?;
+                tokens.push(Token::String(s));
+                i = next;
+            }
+            '"' | '`' => {
+                let (s, next) = read_quoted(&chars, i, c)?;
+                tokens.push(Token::QuotedIdent(s));
+                i = next;
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let n = text.parse()
+                    .map_err(|_| syntax_error(format!("Invalid number '{}'", text)))?;
+                tokens.push(Token::Number(n));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            _ => {
+                let next = chars.get(i + 1).copied();
+                let (token, len) = match (c, next) {
+                    ('!', Some('=')) | ('<', Some('>')) => (Token::NotEq, 2),
+                    ('<', Some('=')) => (Token::LtEq, 2),
+                    ('>', Some('=')) => (Token::GtEq, 2),
+                    ('=', Some('=')) => (Token::Eq, 2),
+                    ('=', _) => (Token::Eq, 1),
+                    ('<', _) => (Token::Lt, 1),
+                    ('>', _) => (Token::Gt, 1),
+                    (',', _) => (Token::Comma, 1),
+                    ('.', _) => (Token::Dot, 1),
+                    ('(', _) => (Token::LParen, 1),
+                    (')', _) => (Token::RParen, 1),
+                    ('*', _) => (Token::Star, 1),
+                    ('+', _) => (Token::Plus, 1),
+                    ('-', _) => (Token::Minus, 1),
+                    ('/', _) => (Token::Slash, 1),
+                    (';', _) => (Token::Semicolon, 1),
+                    _ => return Err(syntax_error(format!("Unexpected character '{}'", c))),
+                };
+                tokens.push(token);
+                i += len;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Read a quoted string starting at `start`; a doubled quote escapes itself
+fn read_quoted(chars: &[char], start: usize, quote: char) -> Result<(String, usize)> {
+    let mut s = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                s.push(quote);
+                i += 2;
+                continue;
+            }
+            return Ok((s, i + 1));
+        }
+        s.push(chars[i]);
+        i += 1;
+    }
+    Err(syntax_error(format!("Unterminated {}-quoted string", quote)))
+}
+
+/// Words that end an expression or a table reference and so cannot be aliases
+const RESERVED: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "GROUP", "BY", "HAVING", "ORDER", "LIMIT", "OFFSET", "JOIN",
+    "LEFT", "INNER", "ON", "AS", "AND", "OR", "NOT", "IN", "IS", "NULL", "LIKE", "ASC", "DESC",
+    "TRUE", "FALSE", "EXPLAIN",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", token)))
+        }
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected("end of query")),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> anyhow::Error {
+        match self.peek() {
+            Some(token) => syntax_error(format!("Expected {} but found '{}'", expected, token)),
+            None => syntax_error(format!("Expected {} but the query ended", expected)),
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    /// An identifier that is not a reserved word, or any quoted identifier
+    fn identifier(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(s)) if !is_reserved(s) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            Some(Token::QuotedIdent(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    fn optional_alias(&mut self) -> Result<Option<String>> {
+        if self.eat_keyword("AS") {
+            return self.identifier().map(Some);
+        }
+        match self.peek() {
+            Some(Token::Ident(s)) if !is_reserved(s) => self.identifier().map(Some),
+            Some(Token::QuotedIdent(_)) => self.identifier().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn unsigned_integer(&mut self, what: &str) -> Result<usize> {
+        match self.next() {
+            Some(Token::Number(n)) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected(what))
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        if self.eat_keyword("EXPLAIN") {
+            return Ok(Statement::Explain(self.select()?));
+        }
+        Ok(Statement::Select(self.select()?))
+    }
+
+    fn select(&mut self) -> Result<Select> {
+        self.expect_keyword("SELECT")?;
+
+        let mut items = Vec::new();
+        loop {
+            if self.eat(&Token::Star) {
+                items.push(SelectItem::Wildcard);
+            } else {
+                let expr = self.expr()?;
+                let alias = self.optional_alias()?;
+                items.push(SelectItem::Expr { expr, alias });
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+
+        self.expect_keyword("FROM")?;
+        let from = self.table_ref()?;
+
+        let join = if self.peek_keyword("JOIN") || self.peek_keyword("INNER") || self.peek_keyword("LEFT") {
+            let kind = if self.eat_keyword("LEFT") {
+                self.eat_keyword("OUTER");
+                JoinKind::Left
+            } else {
+                self.eat_keyword("INNER");
+                JoinKind::Inner
+            };
+            self.expect_keyword("JOIN")?;
+            let table = self.table_ref()?;
+            self.expect_keyword("ON")?;
+            let on = self.expr()?;
+            Some(Join { kind, table, on })
+        } else {
+            None
+        };
+
+        let filter = if self.eat_keyword("WHERE") { Some(self.expr()?) } else { None };
+
+        let mut group_by = Vec::new();
+        if self.eat_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                group_by.push(self.expr()?);
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let having = if self.eat_keyword("HAVING") { Some(self.expr()?) } else { None };
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let expr = self.expr()?;
+                let descending = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order_by.push(OrderBy { expr, descending });
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let mut limit = None;
+        let mut offset = None;
+        if self.eat_keyword("LIMIT") {
+            limit = Some(self.unsigned_integer("a row count after LIMIT")?);
+            if self.eat_keyword("OFFSET") {
+                offset = Some(self.unsigned_integer("a row count after OFFSET")?);
+            }
+        }
+
+        Ok(Select { items, from, join, filter, group_by, having, order_by, limit, offset })
+    }
+
+    fn table_ref(&mut self) -> Result<TableRef> {
+        let namespace = self.identifier()?;
+        let alias = self.optional_alias()?;
+        Ok(TableRef { namespace, alias })
+    }
+
+    // Precedence, lowest first: OR, AND, NOT, comparison, + -, * /, unary -
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut left = self.and_expr()?;
+        while self.eat_keyword("OR") {
+            let right = self.and_expr()?;
+            left = binary(left, BinaryOp::Or, right);
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr> {
+        let mut left = self.not_expr()?;
+        while self.eat_keyword("AND") {
+            let right = self.not_expr()?;
+            left = binary(left, BinaryOp::And, right);
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+
+        let op = match self.peek() {
+            Some(Token::Eq) => Some(BinaryOp::Eq),
+            Some(Token::NotEq) => Some(BinaryOp::NotEq),
+            Some(Token::Lt) => Some(BinaryOp::Lt),
+            Some(Token::LtEq) => Some(BinaryOp::LtEq),
+            Some(Token::Gt) => Some(BinaryOp::Gt),
+            Some(Token::GtEq) => Some(BinaryOp::GtEq),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.pos += 1;
+            let right = self.additive()?;
+            return Ok(binary(left, op, right));
+        }
+
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull { expr: Box::new(left), negated });
+        }
+
+        let negated = self.eat_keyword("NOT");
+        if self.eat_keyword("LIKE") {
+            let like = binary(left, BinaryOp::Like, self.additive()?);
+            return Ok(if negated { Expr::Not(Box::new(like)) } else { like });
+        }
+        if self.eat_keyword("IN") {
+            self.expect(&Token::LParen)?;
+            let mut list = Vec::new();
+            loop {
+                list.push(self.expr()?);
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::InList { expr: Box::new(left), list, negated });
+        }
+        if negated {
+            return Err(self.unexpected("LIKE or IN after NOT"));
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Minus) => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.multiplicative()?;
+            left = binary(left, op, right);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Mul,
+                Some(Token::Slash) => BinaryOp::Div,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.unary()?;
+            left = binary(left, op, right);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Minus) {
+            return Ok(match self.unary()? {
+                Expr::Literal(Literal::Number(n)) => Expr::Literal(Literal::Number(-n)),
+                expr => Expr::Neg(Box::new(expr)),
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(Literal::Number(n))),
+            Some(Token::String(s)) => Ok(Expr::Literal(Literal::String(s))),
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(word)) if self.peek() == Some(&Token::LParen) => self.call(&word),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("NULL") => Ok(Expr::Literal(Literal::Null)),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("TRUE") => Ok(Expr::Literal(Literal::Bool(true))),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("FALSE") => Ok(Expr::Literal(Literal::Bool(false))),
+            Some(Token::Ident(word)) if !is_reserved(&word) => self.column(word),
+            Some(Token::QuotedIdent(word)) => self.column(word),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("an expression"))
+            }
+        }
+    }
+
+    fn column(&mut self, first: String) -> Result<Expr> {
+        let mut path = vec![first];
+        while self.eat(&Token::Dot) {
+            path.push(self.identifier()?);
+        }
+        Ok(Expr::Column(path))
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr> {
+        self.expect(&Token::LParen)?;
+
+        if name.eq_ignore_ascii_case("NEAR") {
+            let text = match self.next() {
+                Some(Token::String(s)) => s,
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("a quoted search text as the first argument of NEAR"));
+                }
+            };
+            self.expect(&Token::Comma)?;
+            let k = self.unsigned_integer("a result count as the second argument of NEAR")?;
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::Near { text, k });
+        }
+
+        let func = Aggregate::from_name(name)
+            .ok_or_else(|| syntax_error(format!("Unknown function '{}'", name)))?;
+        let arg = if func == Aggregate::Count && self.eat(&Token::Star) {
+            None
+        } else {
+            let arg = self.expr()?;
+            if arg.contains_aggregate() {
+                return Err(syntax_error(format!("Aggregates cannot be nested in {}", func)));
+            }
+            Some(Box::new(arg))
+        };
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Aggregate { func, arg })
+    }
+}
+
+fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+    Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+}
+
+fn is_reserved(word: &str) -> bool {
+    RESERVED.iter().any(|r| r.eq_ignore_ascii_case(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(query: &str) -> Select {
+        match QueryParser::parse_sql(query).unwrap() {
+            Statement::Select(select) => select,
+            other => panic!("expected SELECT, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let s = select("SELECT name, age AS years FROM users WHERE age >= 30 AND key LIKE 'user:%' ORDER BY age DESC LIMIT 10 OFFSET 5");
+        assert_eq!(s.items.len(), 2);
+        assert_eq!(s.items[1], SelectItem::Expr {
+            expr: Expr::Column(vec!["age".to_string()]),
+            alias: Some("years".to_string()),
+        });
+        assert_eq!(s.from, TableRef { namespace: "users".to_string(), alias: None });
+        assert_eq!(s.filter.as_ref().unwrap().conjuncts().len(), 2);
+        assert!(s.order_by[0].descending);
+        assert_eq!((s.limit, s.offset), (Some(10), Some(5)));
+
+        let s = select(r#"select * from "agent:1:memory" m where m.tags.first = 'x' or not m.score < -1.5"#);
+        assert_eq!(s.items, vec![SelectItem::Wildcard]);
+        assert_eq!(s.from.namespace, "agent:1:memory");
+        assert_eq!(s.from.name(), "m");
+        assert_eq!(s.filter.unwrap().to_string(), "((m.tags.first = 'x') OR NOT (m.score < -1.5))");
+    }
+
+    #[test]
+    fn test_parse_join_aggregate_near() {
+        let s = select("SELECT u.name, COUNT(*) AS n, SUM(o.total) FROM orders o LEFT JOIN users u ON o.user_id = u.key GROUP BY u.name HAVING COUNT(*) > 1");
+        let join = s.join.as_ref().unwrap();
+        assert_eq!(join.kind, JoinKind::Left);
+        assert_eq!(join.table.name(), "u");
+        assert!(s.is_aggregate());
+        assert_eq!(s.group_by.len(), 1);
+
+        let s = select("SELECT key, distance FROM docs WHERE NEAR('vector databases', 5) AND lang IN ('en', 'de')");
+        let terms = s.filter.as_ref().unwrap().conjuncts();
+        assert_eq!(terms[0], &Expr::Near { text: "vector databases".to_string(), k: 5 });
+
+        assert!(matches!(QueryParser::parse_sql("EXPLAIN SELECT * FROM t").unwrap(), Statement::Explain(_)));
+        assert_eq!(QueryParser::parse("SELECT COUNT(*) FROM t").unwrap().0, QueryType::Aggregate);
+        assert_eq!(QueryParser::parse("SELECT * FROM a JOIN b ON a.key = b.key").unwrap().0, QueryType::Join);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "SELECT FROM t",
+            "SELECT * FROM",
+            "SELECT * FROM t WHERE",
+            "SELECT * FROM t LIMIT -1",
+            "SELECT * FROM t extra junk",
+            "SELECT FOO(x) FROM t",
+            "SELECT SUM(COUNT(*)) FROM t",
+            "SELECT * FROM t WHERE NEAR(text, 5)",
+            "SELECT 'unterminated FROM t",
+        ] {
+            let err = QueryParser::parse_sql(bad).unwrap_err();
+            assert!(err.to_string().starts_with("Query error:"), "{}: {}", bad, err);
+        }
+    }
+}
@@ -0,0 +1,697 @@
+//! Planning and execution of declarative queries
+//!
+//! The planner picks how to read the `FROM` namespace from the `WHERE` clause:
+//! `NEAR(text, k)` becomes a vector search, `key = ...` / `key IN (...)` point
+//! lookups, and `key LIKE 'prefix%'` a prefix scan. Joins on `other.key = ...`
+//! look up the joined record by key; any other join condition falls back to a
+//! nested loop over the joined namespace.
+
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::core::Namespace;
+use crate::error::LiathError;
+use super::ast::*;
+use super::eval::{eval, sort_order, truthy, Context, Record, Row, Scope};
+use super::executor::QueryExecutor;
+
+/// Key prefix of the vector id -> key mapping written by `store_document`
+const VECTOR_MAPPING_PREFIX: &str = "_vidx:";
+
+/// Rows returned by a query
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    /// Rows as JSON objects keyed by column name
+    pub fn to_objects(&self) -> Vec<Value> {
+        self.rows
+            .iter()
+            .map(|row| {
+                let map = self.columns.iter().cloned().zip(row.iter().cloned()).collect();
+                Value::Object(map)
+            })
+            .collect()
+    }
+}
+
+/// How the `FROM` namespace is read
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    /// Scan all keys starting with the prefix (all keys if empty)
+    Scan { prefix: String },
+    /// Point lookups of specific keys
+    Keys(Vec<String>),
+    /// The `k` nearest records to the embedding of `text`
+    Near { text: String, k: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JoinStrategy {
+    /// Look up the joined record by the key this expression evaluates to
+    KeyLookup(Expr),
+    /// Compare against every record of the joined namespace
+    NestedLoop,
+}
+
+#[derive(Debug, Clone)]
+struct JoinPlan {
+    kind: JoinKind,
+    table: TableRef,
+    on: Expr,
+    strategy: JoinStrategy,
+}
+
+/// An executable query
+#[derive(Debug, Clone)]
+pub struct QueryPlan {
+    from: TableRef,
+    access: Access,
+    join: Option<JoinPlan>,
+    filter: Option<Expr>,
+    aggregate: bool,
+    group_by: Vec<Expr>,
+    having: Option<Expr>,
+    order_by: Vec<OrderBy>,
+    columns: Vec<(String, Expr)>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+/// Turns a parsed `SELECT` into a [`QueryPlan`]
+pub struct Planner;
+
+fn plan_error(message: String) -> anyhow::Error {
+    LiathError::Query(message).into()
+}
+
+impl Planner {
+    pub fn plan(select: &Select) -> Result<QueryPlan> {
+        let from_name = select.from.name().to_string();
+        if let Some(join) = &select.join {
+            if join.table.name() == from_name {
+                return Err(plan_error(format!(
+                    "Both sides of the join are named '{}'; give one an alias", from_name
+                )));
+            }
+        }
+
+        // NEAR may only appear as a top-level AND term of WHERE
+        let mut near = None;
+        let mut filter_terms = Vec::new();
+        if let Some(filter) = &select.filter {
+            if filter.contains_aggregate() {
+                return Err(plan_error("Aggregates are not allowed in WHERE; use HAVING".to_string()));
+            }
+            for term in filter.conjuncts() {
+                match term {
+                    Expr::Near { text, k } if near.is_none() => near = Some((text.clone(), *k)),
+                    Expr::Near { .. } => return Err(plan_error("Only one NEAR is allowed per query".to_string())),
+                    t if t.contains_near() => {
+                        return Err(plan_error("NEAR must be a top-level AND condition of WHERE".to_string()));
+                    }
+                    t => filter_terms.push(t.clone()),
+                }
+            }
+        }
+        let mut elsewhere: Vec<&Expr> = select.group_by.iter().chain(select.having.iter()).collect();
+        elsewhere.extend(select.order_by.iter().map(|o| &o.expr));
+        elsewhere.extend(select.join.iter().map(|j| &j.on));
+        elsewhere.extend(select.items.iter().filter_map(|item| match item {
+            SelectItem::Expr { expr, .. } => Some(expr),
+            SelectItem::Wildcard => None,
+        }));
+        if elsewhere.iter().any(|e| e.contains_near()) {
+            return Err(plan_error("NEAR must be a top-level AND condition of WHERE".to_string()));
+        }
+        if let Some(join) = &select.join {
+            if join.on.contains_aggregate() {
+                return Err(plan_error("Aggregates are not allowed in JOIN conditions".to_string()));
+            }
+        }
+        if select.group_by.iter().any(Expr::contains_aggregate) {
+            return Err(plan_error("Aggregates are not allowed in GROUP BY".to_string()));
+        }
+
+        let access = match near {
+            Some((text, k)) => Access::Near { text, k },
+            None => choose_access(&filter_terms, &from_name),
+        };
+
+        let join = select.join.as_ref().map(|join| JoinPlan {
+            kind: join.kind,
+            table: join.table.clone(),
+            on: join.on.clone(),
+            strategy: join_strategy(&join.on, join.table.name()),
+        });
+
+        let aggregate = select.is_aggregate();
+        let columns = expand_columns(select, &access);
+        if aggregate {
+            for (name, expr) in &columns {
+                let grouped = expr.contains_aggregate()
+                    || matches!(expr, Expr::Literal(_))
+                    || select.group_by.contains(expr);
+                if !grouped {
+                    return Err(plan_error(format!(
+                        "Column '{}' must appear in GROUP BY or be used in an aggregate", name
+                    )));
+                }
+            }
+        } else if select.order_by.iter().any(|o| o.expr.contains_aggregate()) {
+            return Err(plan_error("ORDER BY uses an aggregate but the query has no GROUP BY".to_string()));
+        }
+
+        // ORDER BY may refer to select-list aliases
+        let order_by = select.order_by.iter().map(|o| {
+            let expr = match &o.expr {
+                Expr::Column(path) if path.len() == 1 => select.items.iter()
+                    .find_map(|item| match item {
+                        SelectItem::Expr { expr, alias: Some(alias) } if alias == &path[0] => Some(expr.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| o.expr.clone()),
+                other => other.clone(),
+            };
+            OrderBy { expr, descending: o.descending }
+        }).collect();
+
+        let filter = filter_terms.into_iter().reduce(|left, right| Expr::Binary {
+            left: Box::new(left),
+            op: BinaryOp::And,
+            right: Box::new(right),
+        });
+
+        Ok(QueryPlan {
+            from: select.from.clone(),
+            access,
+            join,
+            filter,
+            aggregate,
+            group_by: select.group_by.clone(),
+            having: select.having.clone(),
+            order_by,
+            columns,
+            limit: select.limit,
+            offset: select.offset.unwrap_or(0),
+        })
+    }
+}
+
+fn is_key_of(expr: &Expr, table: &str) -> bool {
+    match expr {
+        Expr::Column(path) => match path.as_slice() {
+            [key] => key == "key",
+            [t, key] => t == table && key == "key",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn string_literal(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Literal(Literal::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Pick point lookups or the longest key prefix the filter guarantees
+fn choose_access(terms: &[Expr], table: &str) -> Access {
+    let mut prefix = String::new();
+    for term in terms {
+        match term {
+            Expr::Binary { left, op: BinaryOp::Eq, right } => {
+                let key = if is_key_of(left, table) {
+                    string_literal(right)
+                } else if is_key_of(right, table) {
+                    string_literal(left)
+                } else {
+                    None
+                };
+                if let Some(key) = key {
+                    return Access::Keys(vec![key]);
+                }
+            }
+            Expr::InList { expr, list, negated: false } if is_key_of(expr, table) => {
+                let keys: Option<Vec<String>> = list.iter().map(string_literal).collect();
+                if let Some(keys) = keys {
+                    return Access::Keys(keys);
+                }
+            }
+            Expr::Binary { left, op: BinaryOp::Like, right } if is_key_of(left, table) => {
+                if let Some(pattern) = string_literal(right) {
+                    let literal_prefix: String = pattern.chars().take_while(|c| *c != '%' && *c != '_').collect();
+                    if literal_prefix.len() > prefix.len() {
+                        prefix = literal_prefix;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Access::Scan { prefix }
+}
+
+fn references_table(expr: &Expr, table: &str) -> bool {
+    match expr {
+        Expr::Column(path) => path.len() > 1 && path[0] == table,
+        Expr::Not(e) | Expr::Neg(e) | Expr::IsNull { expr: e, .. } => references_table(e, table),
+        Expr::Binary { left, right, .. } => references_table(left, table) || references_table(right, table),
+        Expr::InList { expr, list, .. } => {
+            references_table(expr, table) || list.iter().any(|e| references_table(e, table))
+        }
+        Expr::Aggregate { arg, .. } => arg.as_ref().is_some_and(|a| references_table(a, table)),
+        Expr::Literal(_) | Expr::Near { .. } => false,
+    }
+}
+
+fn join_strategy(on: &Expr, table: &str) -> JoinStrategy {
+    let is_joined_key = |e: &Expr| matches!(e, Expr::Column(path) if path.len() == 2 && path[0] == table && path[1] == "key");
+    for term in on.conjuncts() {
+        if let Expr::Binary { left, op: BinaryOp::Eq, right } = term {
+            if is_joined_key(left) && !references_table(right, table) {
+                return JoinStrategy::KeyLookup((**right).clone());
+            }
+            if is_joined_key(right) && !references_table(left, table) {
+                return JoinStrategy::KeyLookup((**left).clone());
+            }
+        }
+    }
+    JoinStrategy::NestedLoop
+}
+
+/// Output column names and expressions, with `*` expanded
+fn expand_columns(select: &Select, access: &Access) -> Vec<(String, Expr)> {
+    let tables: Vec<&str> = std::iter::once(select.from.name())
+        .chain(select.join.iter().map(|j| j.table.name()))
+        .collect();
+    let mut columns = Vec::new();
+    for item in &select.items {
+        match item {
+            SelectItem::Wildcard => {
+                if tables.len() == 1 {
+                    columns.push(("key".to_string(), Expr::Column(vec!["key".to_string()])));
+                    columns.push(("value".to_string(), Expr::Column(vec!["value".to_string()])));
+                    if matches!(access, Access::Near { .. }) {
+                        columns.push(("distance".to_string(), Expr::Column(vec!["distance".to_string()])));
+                    }
+                } else {
+                    for table in &tables {
+                        for column in ["key", "value"] {
+                            columns.push((
+                                format!("{}.{}", table, column),
+                                Expr::Column(vec![table.to_string(), column.to_string()]),
+                            ));
+                        }
+                    }
+                }
+            }
+            SelectItem::Expr { expr, alias } => {
+                let name = match (alias, expr) {
+                    (Some(alias), _) => alias.clone(),
+                    (None, Expr::Column(path)) if path.len() > 1 && tables.contains(&path[0].as_str()) => path[1..].join("."),
+                    (None, expr) => expr.to_string(),
+                };
+                columns.push((name, expr.clone()));
+            }
+        }
+    }
+    columns
+}
+
+impl QueryPlan {
+    /// How the `FROM` namespace is read
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Namespaces the query reads
+    pub fn namespaces(&self) -> Vec<&str> {
+        std::iter::once(self.from.namespace.as_str())
+            .chain(self.join.iter().map(|j| j.table.namespace.as_str()))
+            .collect()
+    }
+
+    /// Human-readable description of the plan, one step per line
+    pub fn explain(&self) -> Vec<String> {
+        let mut steps = vec![match &self.access {
+            Access::Scan { prefix } if prefix.is_empty() => format!("Scan {} (all keys)", self.from.namespace),
+            Access::Scan { prefix } => format!("Scan {} (prefix '{}')", self.from.namespace, prefix),
+            Access::Keys(keys) => format!("Get {} keys {:?}", self.from.namespace, keys),
+            Access::Near { text, k } => format!(
+                "Vector search {} for {} nearest to '{}'", self.from.namespace, k, text
+            ),
+        }];
+        if let Some(join) = &self.join {
+            let kind = match join.kind {
+                JoinKind::Inner => "Join",
+                JoinKind::Left => "Left join",
+            };
+            steps.push(match &join.strategy {
+                JoinStrategy::KeyLookup(expr) => format!(
+                    "{} {} by key lookup of {}", kind, join.table.namespace, expr
+                ),
+                JoinStrategy::NestedLoop => format!(
+                    "{} {} by nested loop on {}", kind, join.table.namespace, join.on
+                ),
+            });
+        }
+        if let Some(filter) = &self.filter {
+            steps.push(format!("Filter {}", filter));
+        }
+        if self.aggregate {
+            let groups: Vec<String> = self.group_by.iter().map(|e| e.to_string()).collect();
+            steps.push(if groups.is_empty() {
+                "Aggregate all rows".to_string()
+            } else {
+                format!("Group by {}", groups.join(", "))
+            });
+            if let Some(having) = &self.having {
+                steps.push(format!("Having {}", having));
+            }
+        }
+        if !self.order_by.is_empty() {
+            let keys: Vec<String> = self.order_by.iter()
+                .map(|o| format!("{}{}", o.expr, if o.descending { " DESC" } else { "" }))
+                .collect();
+            steps.push(format!("Sort by {}", keys.join(", ")));
+        }
+        match (self.limit, self.offset) {
+            (Some(limit), 0) => steps.push(format!("Limit {}", limit)),
+            (Some(limit), offset) => steps.push(format!("Limit {} offset {}", limit, offset)),
+            (None, 0) => {}
+            (None, offset) => steps.push(format!("Offset {}", offset)),
+        }
+        let names: Vec<&str> = self.columns.iter().map(|(name, _)| name.as_str()).collect();
+        steps.push(format!("Project {}", names.join(", ")));
+        steps
+    }
+
+    fn scope(&self) -> Scope {
+        Scope {
+            tables: std::iter::once(self.from.name().to_string())
+                .chain(self.join.iter().map(|j| j.table.name().to_string()))
+                .collect(),
+        }
+    }
+
+    /// Run the plan; authorization is the caller's responsibility
+    pub(crate) fn execute(&self, executor: &QueryExecutor) -> Result<QueryResult> {
+        let namespace_manager = executor.namespace_manager();
+        let from = namespace_manager.read().unwrap().get_namespace(&self.from.namespace)?;
+        let joined = match &self.join {
+            Some(join) => Some(namespace_manager.read().unwrap().get_namespace(&join.table.namespace)?),
+            None => None,
+        };
+        let scope = self.scope();
+
+        // Records of the joined namespace, read once for nested loops
+        let join_records = match (&self.join, &joined) {
+            (Some(JoinPlan { strategy: JoinStrategy::NestedLoop, .. }), Some(ns)) => Some(scan(ns, "").collect::<Result<Vec<_>>>()?),
+            _ => None,
+        };
+
+        // Without sorting or grouping, stop reading once enough rows match
+        let wanted = match (self.aggregate || !self.order_by.is_empty(), self.limit) {
+            (false, Some(limit)) => Some(limit + self.offset),
+            _ => None,
+        };
+
+        let mut rows: Vec<Row> = Vec::new();
+        for record in self.read(executor, &from)? {
+            let record = record?;
+            let candidates = match (&self.join, &joined) {
+                (Some(join), Some(ns)) => self.join_rows(join, ns, record, join_records.as_deref(), &scope)?,
+                _ => vec![vec![Some(record)]],
+            };
+            for row in candidates {
+                if self.matches(&self.filter, &row, &scope)? {
+                    rows.push(row);
+                }
+            }
+            if wanted.is_some_and(|wanted| rows.len() >= wanted) {
+                break;
+            }
+        }
+
+        if self.aggregate {
+            self.finish_grouped(rows, &scope)
+        } else {
+            self.finish_rows(rows, &scope)
+        }
+    }
+
+    fn read(&self, executor: &QueryExecutor, ns: &Namespace) -> Result<Box<dyn Iterator<Item = Result<Record>>>> {
+        Ok(match &self.access {
+            Access::Scan { prefix } => Box::new(scan(ns, prefix)),
+            Access::Keys(keys) => {
+                let mut records = Vec::new();
+                for key in keys {
+                    if let Some(value) = ns.db.get(key.as_bytes())? {
+                        records.push(Ok(Record::new(key.as_bytes(), &value)));
+                    }
+                }
+                Box::new(records.into_iter())
+            }
+            Access::Near { text, k } => {
+                let vector = executor.generate_embedding(vec![text.as_str()])?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Failed to generate embedding"))?;
+                let mut records = Vec::new();
+                for (id, distance) in ns.vector_db.search(&vector, *k)? {
+                    let mapping_key = format!("{}{}", VECTOR_MAPPING_PREFIX, id);
+                    let key = match ns.db.get(mapping_key.as_bytes())? {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    if let Some(value) = ns.db.get(&key)? {
+                        let mut record = Record::new(&key, &value);
+                        record.distance = Some(distance);
+                        records.push(Ok(record));
+                    }
+                }
+                Box::new(records.into_iter())
+            }
+        })
+    }
+
+    fn join_rows(&self, join: &JoinPlan, ns: &Namespace, record: Record, all: Option<&[Record]>, scope: &Scope) -> Result<Vec<Row>> {
+        let mut rows = Vec::new();
+        match &join.strategy {
+            JoinStrategy::KeyLookup(expr) => {
+                let left: Row = vec![Some(record.clone())];
+                let key = match eval(expr, &Context::Row(&left), scope)? {
+                    Value::String(s) => Some(s),
+                    Value::Null => None,
+                    other => Some(other.to_string()),
+                };
+                if let Some(key) = key {
+                    if let Some(value) = ns.db.get(key.as_bytes())? {
+                        let row = vec![Some(record.clone()), Some(Record::new(key.as_bytes(), &value))];
+                        if self.matches(&Some(join.on.clone()), &row, scope)? {
+                            rows.push(row);
+                        }
+                    }
+                }
+            }
+            JoinStrategy::NestedLoop => {
+                for other in all.unwrap_or_default() {
+                    let row = vec![Some(record.clone()), Some(other.clone())];
+                    if self.matches(&Some(join.on.clone()), &row, scope)? {
+                        rows.push(row);
+                    }
+                }
+            }
+        }
+        if rows.is_empty() && join.kind == JoinKind::Left {
+            rows.push(vec![Some(record), None]);
+        }
+        Ok(rows)
+    }
+
+    fn matches(&self, condition: &Option<Expr>, row: &Row, scope: &Scope) -> Result<bool> {
+        match condition {
+            Some(condition) => Ok(truthy(&eval(condition, &Context::Row(row), scope)?)),
+            None => Ok(true),
+        }
+    }
+
+    fn finish_rows(&self, rows: Vec<Row>, scope: &Scope) -> Result<QueryResult> {
+        let mut rows = rows;
+        if !self.order_by.is_empty() {
+            let mut keyed = Vec::with_capacity(rows.len());
+            for row in rows {
+                let keys = self.order_by.iter()
+                    .map(|o| eval(&o.expr, &Context::Row(&row), scope))
+                    .collect::<Result<Vec<_>>>()?;
+                keyed.push((keys, row));
+            }
+            keyed.sort_by(|(a, _), (b, _)| self.compare_keys(a, b));
+            rows = keyed.into_iter().map(|(_, row)| row).collect();
+        }
+
+        let mut result = self.empty_result();
+        for row in rows.iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)) {
+            result.rows.push(self.project(&Context::Row(row), scope)?);
+        }
+        Ok(result)
+    }
+
+    fn finish_grouped(&self, rows: Vec<Row>, scope: &Scope) -> Result<QueryResult> {
+        // Groups in order of first appearance
+        let mut groups: Vec<Vec<Row>> = Vec::new();
+        if self.group_by.is_empty() {
+            groups.push(rows);
+        } else {
+            let mut index: HashMap<String, usize> = HashMap::new();
+            for row in rows {
+                let key = self.group_by.iter()
+                    .map(|e| eval(e, &Context::Row(&row), scope))
+                    .collect::<Result<Vec<_>>>()?;
+                let key = Value::Array(key).to_string();
+                match index.get(&key) {
+                    Some(&i) => groups[i].push(row),
+                    None => {
+                        index.insert(key, groups.len());
+                        groups.push(vec![row]);
+                    }
+                }
+            }
+        }
+
+        let mut kept = Vec::new();
+        for group in groups {
+            let ctx = Context::Group(&group);
+            if let Some(having) = &self.having {
+                if !truthy(&eval(having, &ctx, scope)?) {
+                    continue;
+                }
+            }
+            let keys = self.order_by.iter()
+                .map(|o| eval(&o.expr, &ctx, scope))
+                .collect::<Result<Vec<_>>>()?;
+            let projected = self.project(&ctx, scope)?;
+            kept.push((keys, projected));
+        }
+        if !self.order_by.is_empty() {
+            kept.sort_by(|(a, _), (b, _)| self.compare_keys(a, b));
+        }
+
+        let mut result = self.empty_result();
+        result.rows = kept.into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(_, row)| row)
+            .collect();
+        Ok(result)
+    }
+
+    fn compare_keys(&self, a: &[Value], b: &[Value]) -> Ordering {
+        for ((x, y), order) in a.iter().zip(b).zip(&self.order_by) {
+            let ordering = sort_order(x, y);
+            let ordering = if order.descending { ordering.reverse() } else { ordering };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn empty_result(&self) -> QueryResult {
+        QueryResult {
+            columns: self.columns.iter().map(|(name, _)| name.clone()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn project(&self, ctx: &Context, scope: &Scope) -> Result<Vec<Value>> {
+        self.columns.iter().map(|(_, expr)| eval(expr, ctx, scope)).collect()
+    }
+}
+
+/// Records of a namespace whose keys start with `prefix`, skipping internal keys
+fn scan(ns: &Namespace, prefix: &str) -> impl Iterator<Item = Result<Record>> {
+    ns.db.scan_prefix(prefix.as_bytes()).filter_map(|entry| match entry {
+        Ok((key, _)) if key.starts_with(VECTOR_MAPPING_PREFIX.as_bytes()) => None,
+        Ok((key, value)) => Some(Ok(Record::new(&key, &value))),
+        Err(e) => Some(Err(e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryParser;
+
+    fn plan(query: &str) -> Result<QueryPlan> {
+        match QueryParser::parse_sql(query)? {
+            Statement::Select(select) | Statement::Explain(select) => Planner::plan(&select),
+        }
+    }
+
+    #[test]
+    fn test_plan_access_paths() {
+        assert_eq!(plan("SELECT * FROM t").unwrap().access(), &Access::Scan { prefix: String::new() });
+        assert_eq!(
+            plan("SELECT * FROM t WHERE age > 3 AND key LIKE 'user:%'").unwrap().access(),
+            &Access::Scan { prefix: "user:".to_string() }
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE t.key = 'a'").unwrap().access(),
+            &Access::Keys(vec!["a".to_string()])
+        );
+        assert_eq!(
+            plan("SELECT * FROM t WHERE key IN ('a', 'b')").unwrap().access(),
+            &Access::Keys(vec!["a".to_string(), "b".to_string()])
+        );
+        // OR cannot narrow the scan
+        assert_eq!(
+            plan("SELECT * FROM t WHERE key = 'a' OR key = 'b'").unwrap().access(),
+            &Access::Scan { prefix: String::new() }
+        );
+        assert_eq!(
+            plan("SELECT key FROM docs WHERE NEAR('rust', 3) AND key LIKE 'a%'").unwrap().access(),
+            &Access::Near { text: "rust".to_string(), k: 3 }
+        );
+    }
+
+    #[test]
+    fn test_plan_explain() {
+        let steps = plan("SELECT u.name AS name, COUNT(*) AS n FROM orders o JOIN users u ON u.key = o.user GROUP BY u.name ORDER BY n DESC LIMIT 3").unwrap().explain();
+        assert_eq!(steps, vec![
+            "Scan orders (all keys)",
+            "Join users by key lookup of o.user",
+            "Group by u.name",
+            "Sort by COUNT(*) DESC",
+            "Limit 3",
+            "Project name, n",
+        ]);
+
+        let steps = plan("SELECT * FROM a JOIN b ON a.x = b.y").unwrap().explain();
+        assert_eq!(steps[1], "Join b by nested loop on (a.x = b.y)");
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        for bad in [
+            "SELECT * FROM t WHERE COUNT(*) > 1",
+            "SELECT * FROM t WHERE NOT NEAR('x', 1)",
+            "SELECT * FROM t WHERE NEAR('x', 1) OR a = 1",
+            "SELECT * FROM t WHERE NEAR('x', 1) AND NEAR('y', 1)",
+            "SELECT name, COUNT(*) FROM t",
+            "SELECT * FROM t GROUP BY name",
+            "SELECT name FROM t ORDER BY COUNT(*)",
+            "SELECT * FROM t JOIN t ON t.key = t.key",
+        ] {
+            let err = plan(bad).unwrap_err();
+            assert!(err.to_string().starts_with("Query error:"), "{}: {}", bad, err);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Stored procedures: named, versioned Lua scripts kept in the database
+//!
+//! Procedures live in the `_procedures` system namespace. The current version of
+//! each procedure is stored under `proc:<name>` and every registered version is
+//! kept under `version:<name>:<version>` so the history can be inspected.
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use crate::core::{Namespace, NamespaceManager};
+
+/// System namespace holding procedure definitions
+pub const PROCEDURES_NAMESPACE: &str = "_procedures";
+
+/// A stored procedure version
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Procedure {
+    pub name: String,
+    pub version: u32,
+    pub code: String,
+    pub description: Option<String>,
+    /// User that registered this version; the procedure body runs with their permissions
+    pub owner: String,
+    pub created_at: u64,
+}
+
+impl Procedure {
+    /// Permission that allows calling only this procedure
+    pub fn call_permission(&self) -> String {
+        format!("call_procedure:{}", self.name)
+    }
+}
+
+/// Persistent store for procedure definitions
+#[derive(Clone)]
+pub struct ProcedureStore {
+    namespace_manager: Arc<RwLock<NamespaceManager>>,
+    /// Held while a version number is chosen and written
+    register_lock: Arc<Mutex<()>>,
+}
+
+impl ProcedureStore {
+    pub fn new(namespace_manager: Arc<RwLock<NamespaceManager>>) -> Self {
+        Self { namespace_manager, register_lock: Arc::new(Mutex::new(())) }
+    }
+
+    fn namespace(&self) -> Result<Namespace> {
+        self.namespace_manager.read().unwrap().system_namespace(PROCEDURES_NAMESPACE)
+    }
+
+    fn current_key(name: &str) -> String {
+        format!("proc:{}", name)
+    }
+
+    fn version_key(name: &str, version: u32) -> String {
+        format!("version:{}:{:08}", name, version)
+    }
+
+    /// Check that a procedure name is usable as a key and as an MCP tool name
+    pub fn validate_name(name: &str) -> Result<()> {
+        let mut chars = name.chars();
+        let valid_start = chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false);
+        if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(anyhow::anyhow!(
+                "Invalid procedure name '{}': use letters, digits, '_' or '-', starting with a letter or '_'",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Register a new version of a procedure and return it
+    ///
+    /// `may_replace` is asked whether `owner` may replace the current version, if
+    /// there is one; its error is returned as is.
+    pub fn register(
+        &self,
+        name: &str,
+        code: &str,
+        description: Option<&str>,
+        owner: &str,
+        may_replace: impl FnOnce(&Procedure) -> Result<()>,
+    ) -> Result<Procedure> {
+        Self::validate_name(name)?;
+        let ns = self.namespace()?;
+
+        let _lock = self.register_lock.lock().unwrap();
+        let version = match self.get(name)? {
+            Some(current) => {
+                may_replace(&current)?;
+                current.version + 1
+            }
+            None => 1,
+        };
+        let procedure = Procedure {
+            name: name.to_string(),
+            version,
+            code: code.to_string(),
+            description: description.map(String::from),
+            owner: owner.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let value = serde_json::to_vec(&procedure)
+            .context("Failed to serialize procedure")?;
+        let version_key = Self::version_key(name, version);
+        let current_key = Self::current_key(name);
+        ns.db.batch_put(vec![
+            (version_key.as_bytes(), value.as_slice()),
+            (current_key.as_bytes(), value.as_slice()),
+        ])?;
+
+        tracing::info!("Registered procedure '{}' version {}", name, version);
+        Ok(procedure)
+    }
+
+    /// Get the current version of a procedure
+    pub fn get(&self, name: &str) -> Result<Option<Procedure>> {
+        let ns = self.namespace()?;
+        match ns.db.get(Self::current_key(name).as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)
+                .context(format!("Failed to deserialize procedure '{}'", name))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a specific version of a procedure
+    pub fn get_version(&self, name: &str, version: u32) -> Result<Option<Procedure>> {
+        let ns = self.namespace()?;
+        match ns.db.get(Self::version_key(name, version).as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)
+                .context(format!("Failed to deserialize procedure '{}' version {}", name, version))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All versions of a procedure, oldest first
+    pub fn history(&self, name: &str) -> Result<Vec<Procedure>> {
+        let ns = self.namespace()?;
+        let prefix = format!("version:{}:", name);
+        let mut versions = Vec::new();
+        for result in ns.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = result?;
+            versions.push(serde_json::from_slice(&value)
+                .context(format!("Failed to deserialize procedure '{}'", name))?);
+        }
+        Ok(versions)
+    }
+
+    /// Current version of every registered procedure, ordered by name
+    pub fn list(&self) -> Result<Vec<Procedure>> {
+        let ns = self.namespace()?;
+        let mut procedures = Vec::new();
+        for result in ns.db.scan_prefix(b"proc:") {
+            let (_, value) = result?;
+            procedures.push(serde_json::from_slice(&value)
+                .context("Failed to deserialize procedure")?);
+        }
+        Ok(procedures)
+    }
+
+    /// Delete a procedure and its version history
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let ns = self.namespace()?;
+        if ns.db.get(Self::current_key(name).as_bytes())?.is_none() {
+            return Err(anyhow::anyhow!("Procedure '{}' not found", name));
+        }
+        for procedure in self.history(name)? {
+            ns.db.delete(Self::version_key(name, procedure.version).as_bytes())?;
+        }
+        ns.db.delete(Self::current_key(name).as_bytes())?;
+        tracing::info!("Deleted procedure '{}'", name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(temp_dir: &TempDir) -> ProcedureStore {
+        let manager = NamespaceManager::new(temp_dir.path().to_path_buf()).unwrap();
+        ProcedureStore::new(Arc::new(RwLock::new(manager)))
+    }
+
+    #[test]
+    fn test_procedure_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir);
+
+        let v1 = store.register("answer", "return 1", Some("first"), "admin", |_| Ok(())).unwrap();
+        assert_eq!(v1.version, 1);
+        let v2 = store.register("answer", "return 2", None, "alice", |_| Ok(())).unwrap();
+        assert_eq!(v2.version, 2);
+
+        let current = store.get("answer").unwrap().unwrap();
+        assert_eq!(current.code, "return 2");
+        assert_eq!(current.owner, "alice");
+        assert_eq!(store.get_version("answer", 1).unwrap().unwrap().code, "return 1");
+
+        let history = store.history("answer").unwrap();
+        assert_eq!(history.iter().map(|p| p.version).collect::<Vec<_>>(), vec![1, 2]);
+
+        store.register("other", "return 3", None, "admin", |_| Ok(())).unwrap();
+        let names: Vec<String> = store.list().unwrap().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["answer", "other"]);
+
+        store.delete("answer").unwrap();
+        assert!(store.get("answer").unwrap().is_none());
+        assert!(store.history("answer").unwrap().is_empty());
+        assert!(store.delete("answer").is_err());
+    }
+
+    #[test]
+    fn test_procedure_replacement() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir);
+
+        store.register("answer", "return 1", None, "alice", |_| Ok(())).unwrap();
+        let err = store.register("answer", "return 2", None, "bob", |current| {
+            Err(anyhow::anyhow!("owned by {}", current.owner))
+        }).unwrap_err();
+        assert_eq!(err.to_string(), "owned by alice");
+        assert_eq!(store.get("answer").unwrap().unwrap().version, 1);
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || store.register("answer", "return 3", None, "alice", |_| Ok(())).unwrap().version)
+            })
+            .collect();
+        let mut versions: Vec<u32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        versions.sort();
+        assert_eq!(versions, (2..=9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_procedure_name_validation() {
+        assert!(ProcedureStore::validate_name("rag_answer").is_ok());
+        assert!(ProcedureStore::validate_name("rag-answer2").is_ok());
+        assert!(ProcedureStore::validate_name("").is_err());
+        assert!(ProcedureStore::validate_name("1abc").is_err());
+        assert!(ProcedureStore::validate_name("a:b").is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Opt-in profiling of Lua execution
+//!
+//! While a profile is recording on the current thread, every database function bound
+//! into Lua is wrapped to count its calls, time them, and measure the bytes it read
+//! from and wrote to storage. Timings are inclusive: a procedure call also counts the
+//! time of the database functions it calls.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+use rlua::{Context as LuaContext, Error as LuaError, Function as LuaFunction, MultiValue};
+use serde::{Serialize, Deserialize};
+use crate::core::IoStats;
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// Statistics for one database function
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// Where the time of one execution went
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    /// Wall time of the whole execution
+    pub total_us: u64,
+    /// Time spent in Lua itself, outside database functions
+    pub lua_us: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Per-function statistics, slowest first
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Profile {
+    /// Render the profile as a plain-text table
+    pub fn format_table(&self) -> String {
+        let mut out = format!(
+            "total {:.3} ms (lua {:.3} ms), read {} B, written {} B\n",
+            self.total_us as f64 / 1000.0,
+            self.lua_us as f64 / 1000.0,
+            self.bytes_read,
+            self.bytes_written
+        );
+        let _ = writeln!(
+            out,
+            "{:<24} {:>6} {:>11} {:>11} {:>10} {:>10}",
+            "function", "calls", "total ms", "max ms", "read B", "written B"
+        );
+        for f in &self.functions {
+            let _ = writeln!(
+                out,
+                "{:<24} {:>6} {:>11.3} {:>11.3} {:>10} {:>10}",
+                f.name,
+                f.calls,
+                f.total_us as f64 / 1000.0,
+                f.max_us as f64 / 1000.0,
+                f.bytes_read,
+                f.bytes_written
+            );
+        }
+        out
+    }
+}
+
+struct Recorder {
+    depth: usize,
+    db_time: Duration,
+    functions: HashMap<&'static str, FunctionProfile>,
+}
+
+/// Records a profile on the current thread until `finish` is called
+pub(crate) struct ProfileGuard {
+    started: Instant,
+    io: IoStats,
+}
+
+impl ProfileGuard {
+    /// Start recording; returns `None` if a profile is already recording on this thread
+    pub(crate) fn start() -> Option<Self> {
+        RECORDER.with(|recorder| {
+            let mut recorder = recorder.borrow_mut();
+            if recorder.is_some() {
+                return None;
+            }
+            *recorder = Some(Recorder {
+                depth: 0,
+                db_time: Duration::ZERO,
+                functions: HashMap::new(),
+            });
+            Some(Self { started: Instant::now(), io: IoStats::current() })
+        })
+    }
+
+    /// Stop recording and return the profile
+    pub(crate) fn finish(self) -> Profile {
+        let total = self.started.elapsed();
+        let io = IoStats::current().since(self.io);
+        let recorder = RECORDER.with(|recorder| recorder.borrow_mut().take());
+        let (db_time, functions) = recorder
+            .map(|r| (r.db_time, r.functions.into_values().collect()))
+            .unwrap_or_default();
+
+        let mut functions: Vec<FunctionProfile> = functions;
+        functions.sort_by(|a, b| b.total_us.cmp(&a.total_us).then_with(|| a.name.cmp(&b.name)));
+        Profile {
+            total_us: total.as_micros() as u64,
+            lua_us: total.saturating_sub(db_time).as_micros() as u64,
+            bytes_read: io.bytes_read,
+            bytes_written: io.bytes_written,
+            functions,
+        }
+    }
+}
+
+impl Drop for ProfileGuard {
+    fn drop(&mut self) {
+        // Stop recording even if the execution failed before `finish`
+        RECORDER.with(|recorder| recorder.borrow_mut().take());
+    }
+}
+
+/// Whether a profile is recording on the current thread
+pub(crate) fn is_recording() -> bool {
+    RECORDER.with(|recorder| recorder.borrow().is_some())
+}
+
+/// Wrap a bound database function so its calls are recorded under `name`
+pub(crate) fn wrap<'lua>(
+    lua_ctx: LuaContext<'lua>,
+    name: &'static str,
+    function: LuaFunction<'lua>,
+) -> Result<LuaFunction<'lua>, LuaError> {
+    let key = lua_ctx.create_registry_value(function)?;
+    lua_ctx.create_function(move |lua_ctx, args: MultiValue| {
+        let function: LuaFunction = lua_ctx.registry_value(&key)?;
+        let io = IoStats::current();
+        let started = Instant::now();
+        enter();
+        let result = function.call::<_, MultiValue>(args);
+        leave(name, started.elapsed(), IoStats::current().since(io));
+        result
+    })
+}
+
+fn enter() {
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            recorder.depth += 1;
+        }
+    });
+}
+
+fn leave(name: &'static str, elapsed: Duration, io: IoStats) {
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            recorder.depth = recorder.depth.saturating_sub(1);
+            if recorder.depth == 0 {
+                recorder.db_time += elapsed;
+            }
+            let micros = elapsed.as_micros() as u64;
+            let entry = recorder.functions.entry(name).or_insert_with(|| FunctionProfile {
+                name: name.to_string(),
+                ..Default::default()
+            });
+            entry.calls += 1;
+            entry.total_us += micros;
+            entry.max_us = entry.max_us.max(micros);
+            entry.bytes_read += io.bytes_read;
+            entry.bytes_written += io.bytes_written;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_wrapped_calls() {
+        let lua = rlua::Lua::new();
+        let guard = ProfileGuard::start().unwrap();
+        assert!(ProfileGuard::start().is_none());
+
+        let add = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b)).unwrap();
+        let add = wrap(&lua, "add", add).unwrap();
+        lua.globals().set("add", add).unwrap();
+        let sum: i64 = lua.load("return add(1, 2) + add(3, 4)").eval().unwrap();
+        assert_eq!(sum, 10);
+
+        let profile = guard.finish();
+        assert!(!is_recording());
+        assert_eq!(profile.functions.len(), 1);
+        assert_eq!(profile.functions[0].name, "add");
+        assert_eq!(profile.functions[0].calls, 2);
+        assert!(profile.format_table().contains("add"));
+    }
+}
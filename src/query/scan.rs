@@ -0,0 +1,138 @@
+//! Lazy and paged scans over a namespace
+//!
+//! A scan walks the keys under a prefix in key order without loading them all. Pages
+//! end with an opaque cursor naming the last key returned; passing it back resumes
+//! the scan right after that key, so pages stay consistent while keys are added.
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use crate::core::KvIter;
+use crate::error::LiathError;
+
+/// Default number of entries in a page
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// One key-value pair from a scan
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScanEntry {
+    pub key: String,
+    pub value: String,
+}
+
+/// A page of scan results
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScanPage {
+    pub entries: Vec<ScanEntry>,
+    /// Pass back to fetch the next page; `None` once the scan is complete
+    pub cursor: Option<String>,
+}
+
+/// Encode the last key seen as a cursor token
+pub fn encode_cursor(key: &[u8]) -> String {
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a cursor token, checking that it belongs to a scan of `prefix`
+pub fn decode_cursor(cursor: &str, prefix: &str) -> Result<Vec<u8>> {
+    let invalid = || LiathError::InvalidInput(format!("Invalid scan cursor '{}'", cursor));
+    if !cursor.len().is_multiple_of(2) {
+        return Err(invalid().into());
+    }
+    let key = (0..cursor.len())
+        .step_by(2)
+        .map(|i| cursor.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    if !key.starts_with(prefix.as_bytes()) {
+        return Err(LiathError::InvalidInput(format!(
+            "Scan cursor does not belong to prefix '{}'", prefix
+        )).into());
+    }
+    Ok(key)
+}
+
+/// Lazily yields the entries under a prefix, tracking where to resume
+pub struct ScanIter {
+    inner: KvIter<'static>,
+    last_key: Option<Vec<u8>>,
+}
+
+impl ScanIter {
+    pub(crate) fn new(inner: KvIter<'static>) -> Self {
+        Self { inner, last_key: None }
+    }
+
+    /// Cursor resuming after the last entry yielded, if any
+    pub fn cursor(&self) -> Option<String> {
+        self.last_key.as_deref().map(encode_cursor)
+    }
+
+    /// Collect up to `limit` entries; the page carries a cursor if more remain
+    pub fn page(mut self, limit: usize) -> Result<ScanPage> {
+        let mut entries = Vec::new();
+        while entries.len() < limit {
+            match self.next() {
+                Some(entry) => entries.push(entry?),
+                None => return Ok(ScanPage { entries, cursor: None }),
+            }
+        }
+        // Only hand out a cursor if there is something after it
+        let cursor = match self.inner.next() {
+            Some(_) => self.cursor(),
+            None => None,
+        };
+        Ok(ScanPage { entries, cursor })
+    }
+}
+
+impl Iterator for ScanIter {
+    type Item = Result<ScanEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = match self.inner.next()? {
+            Ok(pair) => pair,
+            Err(e) => return Some(Err(e)),
+        };
+        let entry = ScanEntry {
+            key: String::from_utf8_lossy(&key).into_owned(),
+            value: String::from_utf8_lossy(&value).into_owned(),
+        };
+        self.last_key = Some(key);
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::FjallWrapper;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = encode_cursor(b"user:42");
+        assert_eq!(decode_cursor(&cursor, "user:").unwrap(), b"user:42");
+        assert!(decode_cursor(&cursor, "doc:").is_err());
+        assert!(decode_cursor("zz", "").is_err());
+        assert!(decode_cursor("abc", "").is_err());
+    }
+
+    #[test]
+    fn test_pages_resume_after_cursor() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = FjallWrapper::new(temp_dir.path()).unwrap();
+        for i in 0..5 {
+            db.put(format!("item:{}", i).as_bytes(), b"v").unwrap();
+        }
+        db.put(b"other", b"v").unwrap();
+
+        let first = ScanIter::new(db.scan_prefix_after(b"item:", None)).page(2).unwrap();
+        assert_eq!(first.entries.len(), 2);
+        let after = decode_cursor(first.cursor.as_deref().unwrap(), "item:").unwrap();
+
+        let rest = ScanIter::new(db.scan_prefix_after(b"item:", Some(&after))).page(10).unwrap();
+        let keys: Vec<&str> = rest.entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, vec!["item:2", "item:3", "item:4"]);
+        assert!(rest.cursor.is_none());
+    }
+}
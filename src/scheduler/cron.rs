@@ -0,0 +1,263 @@
+//! Minimal cron expression support (UTC)
+//!
+//! Supports the standard five fields `minute hour day-of-month month day-of-week`
+//! with `*`, lists (`1,15`), ranges (`1-5`) and steps (`*/10`, `0-30/5`), plus the
+//! `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` shortcuts.
+
+use anyhow::Result;
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Day-of-month field does not restrict days
+    any_day_of_month: bool,
+    /// Day-of-week field does not restrict days
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow::anyhow!(
+                "Invalid cron expression '{}': expected 5 fields (minute hour day month weekday)",
+                expression
+            ));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7)
+            .map_err(|e| anyhow::anyhow!("Invalid day-of-week in '{}': {}", expression, e))?;
+        // Both 0 and 7 mean Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+
+        let days_of_month = parse_field(fields[2], 1, 31)
+            .map_err(|e| anyhow::anyhow!("Invalid day-of-month in '{}': {}", expression, e))?;
+
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)
+                .map_err(|e| anyhow::anyhow!("Invalid minute in '{}': {}", expression, e))?,
+            hours: parse_field(fields[1], 0, 23)
+                .map_err(|e| anyhow::anyhow!("Invalid hour in '{}': {}", expression, e))?,
+            days_of_month,
+            months: parse_field(fields[3], 1, 12)
+                .map_err(|e| anyhow::anyhow!("Invalid month in '{}': {}", expression, e))?,
+            days_of_week,
+            any_day_of_month: !restricts_days(fields[2], days_of_month, ALL_DAYS_OF_MONTH),
+            any_day_of_week: !restricts_days(fields[4], days_of_week, ALL_DAYS_OF_WEEK),
+        })
+    }
+
+    /// First matching time strictly after `after` (seconds since the Unix epoch)
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let mut t = (after / 60 + 1) * 60;
+        // Enough to cover any valid expression; impossible ones (e.g. Feb 30) give up
+        for _ in 0..100_000 {
+            let days = (t / 86_400) as i64;
+            let (year, month, day) = civil_from_days(days);
+            let hour = (t % 86_400) / 3600;
+            let minute = (t % 3600) / 60;
+
+            if self.months & (1 << month) == 0 {
+                let (y, m) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                t = days_from_civil(y, m, 1) as u64 * 86_400;
+                continue;
+            }
+            if !self.day_matches(day, weekday(days)) {
+                t = (days as u64 + 1) * 86_400;
+                continue;
+            }
+            if self.hours & (1 << hour) == 0 {
+                t = (t / 3600 + 1) * 3600;
+                continue;
+            }
+            if self.minutes & (1 << minute) == 0 {
+                t += 60;
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+
+    fn day_matches(&self, day: u32, weekday: u32) -> bool {
+        let dom = self.days_of_month & (1 << day) != 0;
+        let dow = self.days_of_week & (1 << weekday) != 0;
+        // Standard cron: if both fields are restricted, either may match
+        if self.any_day_of_month || self.any_day_of_week {
+            dom && dow
+        } else {
+            dom || dow
+        }
+    }
+}
+
+/// Bits for days 1-31 of a month
+const ALL_DAYS_OF_MONTH: u64 = 0xFFFF_FFFE;
+
+/// Bits for Sunday (0) to Saturday (6)
+const ALL_DAYS_OF_WEEK: u64 = 0x7F;
+
+/// Whether a day field counts as restricted for the day-of-month/day-of-week OR rule
+///
+/// As in Vixie cron, a field starting with `*` (like `*/2`) does not, and neither
+/// does one that allows every day.
+fn restricts_days(field: &str, mask: u64, all: u64) -> bool {
+    !field.starts_with('*') && mask & all != all
+}
+
+/// Parse one cron field into a bitmask of allowed values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse()
+                    .map_err(|_| anyhow::anyhow!("invalid step '{}'", step))?;
+                if step == 0 {
+                    return Err(anyhow::anyhow!("step must be positive"));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a, min, max)?, parse_value(b, min, max)?)
+        } else {
+            let v = parse_value(range, min, max)?;
+            // "5/10" means "from 5 to the end, every 10"
+            (v, if step > 1 { max } else { v })
+        };
+        if start > end {
+            return Err(anyhow::anyhow!("range {}-{} is reversed", start, end));
+        }
+
+        let mut v = start;
+        while v <= end {
+            mask |= 1 << v;
+            v += step;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(s: &str, min: u32, max: u32) -> Result<u32> {
+    let v: u32 = s.parse().map_err(|_| anyhow::anyhow!("invalid value '{}'", s))?;
+    if v < min || v > max {
+        return Err(anyhow::anyhow!("{} is outside {}-{}", v, min, max));
+    }
+    Ok(v)
+}
+
+/// Day of week for days since the epoch, 0 = Sunday
+fn weekday(days: i64) -> u32 {
+    // 1970-01-01 was a Thursday
+    (days + 4).rem_euclid(7) as u32
+}
+
+/// Convert days since the epoch to (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert (year, month, day) to days since the epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-15 10:30:00 UTC, a Monday
+    const MONDAY_1030: u64 = 1_705_314_600;
+
+    #[test]
+    fn test_civil_roundtrip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_737), (2024, 1, 15));
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(weekday(19_737), 1);
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let every_5 = CronSchedule::parse("*/5 * * * *").unwrap();
+        assert_eq!(every_5.next_after(MONDAY_1030), Some(MONDAY_1030 + 300));
+
+        let daily = CronSchedule::parse("@daily").unwrap();
+        assert_eq!(daily.next_after(MONDAY_1030), Some(days_from_civil(2024, 1, 16) as u64 * 86_400));
+
+        // 09:00 on weekdays: next is Tuesday
+        let weekdays = CronSchedule::parse("0 9 * * 1-5").unwrap();
+        assert_eq!(weekdays.next_after(MONDAY_1030), Some(days_from_civil(2024, 1, 16) as u64 * 86_400 + 9 * 3600));
+
+        // Leap day
+        let leap = CronSchedule::parse("0 0 29 2 *").unwrap();
+        assert_eq!(leap.next_after(MONDAY_1030), Some(days_from_civil(2024, 2, 29) as u64 * 86_400));
+
+        assert_eq!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(MONDAY_1030), None);
+    }
+
+    #[test]
+    fn test_cron_day_fields() {
+        let midnight = |year, month, day| days_from_civil(year, month, day) as u64 * 86_400;
+
+        // Both restricted: either may match, so the next Monday wins
+        let either = CronSchedule::parse("0 0 1,15 * 1").unwrap();
+        assert_eq!(either.next_after(MONDAY_1030), Some(midnight(2024, 1, 22)));
+
+        // A stepped `*` does not restrict, so both must match: an odd-numbered Monday
+        let odd_mondays = CronSchedule::parse("0 0 */2 * 1").unwrap();
+        assert_eq!(odd_mondays.next_after(MONDAY_1030), Some(midnight(2024, 1, 29)));
+
+        // Neither does a field allowing every day
+        let mondays = CronSchedule::parse("0 0 1-31 * 1").unwrap();
+        assert_eq!(mondays.next_after(MONDAY_1030), Some(midnight(2024, 1, 22)));
+        let the_first = CronSchedule::parse("0 0 1 * 0-7").unwrap();
+        assert_eq!(the_first.next_after(MONDAY_1030), Some(midnight(2024, 2, 1)));
+    }
+
+    #[test]
+    fn test_cron_parse_errors() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+        assert!(CronSchedule::parse("0 0 * * 7").is_ok());
+    }
+}
@@ -0,0 +1,185 @@
+//! Job definitions and their persistence in the `_scheduler` system namespace
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use crate::core::{Namespace, NamespaceManager};
+use super::cron::CronSchedule;
+
+/// System namespace holding job definitions and run status
+pub const SCHEDULER_NAMESPACE: &str = "_scheduler";
+
+/// When a job runs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Every `seconds` seconds
+    Interval { seconds: u64 },
+    /// Cron expression, evaluated in UTC
+    Cron { expression: String },
+}
+
+impl Schedule {
+    /// Interval schedule from a duration like `30s`, `5m`, `1h` or `2d`
+    pub fn every(duration: &str) -> Result<Self> {
+        let seconds = parse_duration(duration)?.as_secs();
+        if seconds == 0 {
+            return Err(anyhow::anyhow!("Interval must be positive"));
+        }
+        Ok(Schedule::Interval { seconds })
+    }
+
+    /// Cron schedule, validated up front
+    pub fn cron(expression: &str) -> Result<Self> {
+        CronSchedule::parse(expression)?;
+        Ok(Schedule::Cron { expression: expression.to_string() })
+    }
+
+    /// Check that the schedule can produce run times
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Schedule::Interval { seconds: 0 } => Err(anyhow::anyhow!("Interval must be positive")),
+            Schedule::Interval { .. } => Ok(()),
+            Schedule::Cron { expression } => CronSchedule::parse(expression).map(|_| ()),
+        }
+    }
+
+    /// Next run time after `now` (seconds since the Unix epoch)
+    pub fn next_run(&self, now: u64) -> Option<u64> {
+        match self {
+            Schedule::Interval { seconds } => Some(now + seconds),
+            Schedule::Cron { expression } => CronSchedule::parse(expression).ok()?.next_after(now),
+        }
+    }
+}
+
+/// Parse a duration like `30s`, `5m`, `1h` or `2d`; a bare number is seconds
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let (number, unit) = duration.split_at(duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len()));
+    let n: u64 = number.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid interval '{}': expected e.g. 30s, 5m, 1h", duration))?;
+    let seconds = match unit {
+        "" | "s" => n,
+        "m" => n * 60,
+        "h" => n * 3600,
+        "d" => n * 86_400,
+        _ => return Err(anyhow::anyhow!("Invalid interval unit '{}': use s, m, h or d", unit)),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// What a job does when it runs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobAction {
+    /// Run a Lua script
+    Lua { code: String },
+    /// Call a stored procedure
+    Procedure {
+        name: String,
+        #[serde(default)]
+        args: serde_json::Value,
+    },
+    /// Call a Rust callback registered with `Scheduler::register_callback`
+    Callback { name: String },
+}
+
+/// A scheduled job
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Job {
+    pub name: String,
+    pub schedule: Schedule,
+    pub action: JobAction,
+    /// User the job runs as
+    pub owner: String,
+    pub enabled: bool,
+    pub created_at: u64,
+}
+
+/// Outcome of the most recent run of a job
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct JobStatus {
+    pub last_run_at: Option<u64>,
+    pub last_success: Option<bool>,
+    pub last_error: Option<String>,
+    pub last_result: Option<serde_json::Value>,
+    pub last_duration_ms: Option<u64>,
+    pub run_count: u64,
+    pub next_run_at: Option<u64>,
+}
+
+/// Persistent store for jobs and their status
+#[derive(Clone)]
+pub struct JobStore {
+    namespace_manager: Arc<RwLock<NamespaceManager>>,
+}
+
+impl JobStore {
+    pub fn new(namespace_manager: Arc<RwLock<NamespaceManager>>) -> Self {
+        Self { namespace_manager }
+    }
+
+    fn namespace(&self) -> Result<Namespace> {
+        self.namespace_manager.read().unwrap().system_namespace(SCHEDULER_NAMESPACE)
+    }
+
+    pub fn put_job(&self, job: &Job) -> Result<()> {
+        let value = serde_json::to_vec(job).context("Failed to serialize job")?;
+        self.namespace()?.db.put(format!("job:{}", job.name).as_bytes(), &value)
+    }
+
+    pub fn get_job(&self, name: &str) -> Result<Option<Job>> {
+        match self.namespace()?.db.get(format!("job:{}", name).as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)
+                .context(format!("Failed to deserialize job '{}'", name))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All jobs, ordered by name
+    pub fn list_jobs(&self) -> Result<Vec<Job>> {
+        let mut jobs = Vec::new();
+        for result in self.namespace()?.db.scan_prefix(b"job:") {
+            let (_, value) = result?;
+            jobs.push(serde_json::from_slice(&value).context("Failed to deserialize job")?);
+        }
+        Ok(jobs)
+    }
+
+    pub fn delete_job(&self, name: &str) -> Result<()> {
+        let ns = self.namespace()?;
+        ns.db.delete(format!("job:{}", name).as_bytes())?;
+        ns.db.delete(format!("status:{}", name).as_bytes())
+    }
+
+    pub fn put_status(&self, name: &str, status: &JobStatus) -> Result<()> {
+        let value = serde_json::to_vec(status).context("Failed to serialize job status")?;
+        self.namespace()?.db.put(format!("status:{}", name).as_bytes(), &value)
+    }
+
+    pub fn get_status(&self, name: &str) -> Result<JobStatus> {
+        match self.namespace()?.db.get(format!("status:{}", name).as_bytes())? {
+            Some(data) => serde_json::from_slice(&data)
+                .context(format!("Failed to deserialize status of job '{}'", name)),
+            None => Ok(JobStatus::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_every() {
+        assert_eq!(Schedule::every("30s").unwrap(), Schedule::Interval { seconds: 30 });
+        assert_eq!(Schedule::every("5m").unwrap(), Schedule::Interval { seconds: 300 });
+        assert_eq!(Schedule::every("2h").unwrap(), Schedule::Interval { seconds: 7200 });
+        assert_eq!(Schedule::every("45").unwrap(), Schedule::Interval { seconds: 45 });
+        assert!(Schedule::every("0s").is_err());
+        assert!(Schedule::every("5w").is_err());
+        assert!(Schedule::every("m").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Background job scheduler
+//!
+//! Jobs run a Lua script, a stored procedure, or a registered Rust callback on an
+//! interval or cron schedule. Definitions and last-run status are persisted in the
+//! `_scheduler` system namespace, so schedules survive restarts.
+//!
+//! Jobs only run while the scheduler is driven. `Scheduler::start` (or
+//! `EmbeddedLiath::start_scheduler`) runs it on a background thread until the
+//! returned handle is dropped; `liath server` does this automatically. The
+//! executor's Lua VM is not `Send`, so to drive it yourself use
+//! `tokio::task::spawn_local(scheduler.run())` inside a `LocalSet`, or call
+//! `run_due()` periodically.
+
+mod cron;
+mod job;
+
+pub use cron::CronSchedule;
+pub use job::{parse_duration, Job, JobAction, JobStatus, JobStore, Schedule, SCHEDULER_NAMESPACE};
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use tokio::sync::oneshot;
+use crate::error::LiathError;
+use crate::query::QueryExecutor;
+
+/// Rust function a job can run
+pub type JobCallback = Arc<dyn Fn(&QueryExecutor) -> Result<serde_json::Value> + Send + Sync>;
+
+/// Keeps a scheduler started with [`Scheduler::start`] running
+///
+/// Dropping the handle stops the scheduler, after any job in progress finishes.
+pub struct SchedulerHandle {
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs scheduled jobs against a `QueryExecutor`
+#[derive(Clone)]
+pub struct Scheduler {
+    executor: QueryExecutor,
+    store: JobStore,
+    callbacks: Arc<RwLock<HashMap<String, JobCallback>>>,
+    tick: Duration,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl Scheduler {
+    pub fn new(executor: QueryExecutor) -> Self {
+        Self {
+            store: JobStore::new(executor.namespace_manager()),
+            executor,
+            callbacks: Arc::new(RwLock::new(HashMap::new())),
+            tick: Duration::from_secs(1),
+        }
+    }
+
+    /// Set how often `run()` checks for due jobs (default: 1 second)
+    pub fn with_tick(mut self, tick: Duration) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// Register a Rust callback that `JobAction::Callback` jobs can refer to by name
+    pub fn register_callback<F>(&self, name: &str, callback: F)
+    where
+        F: Fn(&QueryExecutor) -> Result<serde_json::Value> + Send + Sync + 'static,
+    {
+        self.callbacks.write().unwrap().insert(name.to_string(), Arc::new(callback));
+    }
+
+    fn authorize(&self, user_id: &str) -> Result<()> {
+        if !self.executor.is_authorized(user_id, "manage_jobs") {
+            return Err(LiathError::Unauthorized(format!("'{}' may not manage jobs", user_id)).into());
+        }
+        Ok(())
+    }
+
+    /// Create or replace a job that runs as `user_id`
+    pub fn add_job(&self, name: &str, schedule: Schedule, action: JobAction, user_id: &str) -> Result<Job> {
+        self.authorize(user_id)?;
+        if name.is_empty() || name.contains(':') {
+            return Err(LiathError::InvalidInput(format!("Invalid job name '{}'", name)).into());
+        }
+        schedule.validate()?;
+
+        let now = now_secs();
+        let job = Job {
+            name: name.to_string(),
+            schedule,
+            action,
+            owner: user_id.to_string(),
+            enabled: true,
+            created_at: now,
+        };
+        self.store.put_job(&job)?;
+
+        let mut status = self.store.get_status(name)?;
+        status.next_run_at = job.schedule.next_run(now);
+        self.store.put_status(name, &status)?;
+
+        tracing::info!("Scheduled job '{}'", name);
+        Ok(job)
+    }
+
+    /// Delete a job and its status
+    pub fn remove_job(&self, name: &str, user_id: &str) -> Result<()> {
+        self.authorize(user_id)?;
+        if self.store.get_job(name)?.is_none() {
+            return Err(LiathError::InvalidInput(format!("Job '{}' not found", name)).into());
+        }
+        self.store.delete_job(name)?;
+        tracing::info!("Removed job '{}'", name);
+        Ok(())
+    }
+
+    /// Pause or resume a job
+    pub fn set_enabled(&self, name: &str, enabled: bool, user_id: &str) -> Result<()> {
+        self.authorize(user_id)?;
+        let mut job = self.store.get_job(name)?
+            .ok_or_else(|| LiathError::InvalidInput(format!("Job '{}' not found", name)))?;
+        job.enabled = enabled;
+        self.store.put_job(&job)?;
+
+        if enabled {
+            let mut status = self.store.get_status(name)?;
+            status.next_run_at = job.schedule.next_run(now_secs());
+            self.store.put_status(name, &status)?;
+        }
+        Ok(())
+    }
+
+    /// A job and its status
+    pub fn job(&self, name: &str) -> Result<Option<(Job, JobStatus)>> {
+        match self.store.get_job(name)? {
+            Some(job) => Ok(Some((job, self.store.get_status(name)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// All jobs with their status, ordered by name
+    pub fn list_jobs(&self) -> Result<Vec<(Job, JobStatus)>> {
+        self.store
+            .list_jobs()?
+            .into_iter()
+            .map(|job| {
+                let status = self.store.get_status(&job.name)?;
+                Ok((job, status))
+            })
+            .collect()
+    }
+
+    /// Run a job now, regardless of its schedule, and record the outcome
+    pub async fn run_job(&self, name: &str, user_id: &str) -> Result<JobStatus> {
+        self.authorize(user_id)?;
+        let job = self.store.get_job(name)?
+            .ok_or_else(|| LiathError::InvalidInput(format!("Job '{}' not found", name)))?;
+        self.execute(&job).await
+    }
+
+    /// Run every enabled job whose next run time has passed; returns the names run
+    ///
+    /// A job whose status cannot be read or saved is logged and skipped, so it
+    /// does not hold up the others.
+    pub async fn run_due(&self) -> Result<Vec<String>> {
+        let now = now_secs();
+        let mut ran = Vec::new();
+        for job in self.store.list_jobs()? {
+            if !job.enabled {
+                continue;
+            }
+            let status = match self.store.get_status(&job.name) {
+                Ok(status) => status,
+                Err(e) => {
+                    tracing::error!("Failed to read the status of job '{}': {:#}", job.name, e);
+                    continue;
+                }
+            };
+            match status.next_run_at {
+                Some(next) if next <= now => match self.execute(&job).await {
+                    Ok(_) => ran.push(job.name),
+                    Err(e) => tracing::error!("Failed to run job '{}': {:#}", job.name, e),
+                },
+                _ => {}
+            }
+        }
+        Ok(ran)
+    }
+
+    /// Run due jobs on a background thread until the returned handle is dropped
+    pub fn start(&self) -> Result<SchedulerHandle> {
+        let (stop, stopped) = oneshot::channel();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let scheduler = self.clone();
+        let thread = std::thread::Builder::new().name("liath-scheduler".to_string()).spawn(move || {
+            let local = tokio::task::LocalSet::new();
+            local.block_on(&runtime, async move {
+                tokio::select! {
+                    _ = scheduler.run() => {}
+                    _ = stopped => {}
+                }
+            });
+        })?;
+        Ok(SchedulerHandle { stop: Some(stop), thread: Some(thread) })
+    }
+
+    /// Run due jobs forever, checking every tick
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.tick);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_due().await {
+                tracing::error!("Scheduler error: {}", e);
+            }
+        }
+    }
+
+    async fn execute(&self, job: &Job) -> Result<JobStatus> {
+        let started = Instant::now();
+        let started_at = now_secs();
+
+        let result = match &job.action {
+            JobAction::Lua { code } => self.executor
+                .execute(code, &job.owner)
+                .await
+                .map(serde_json::Value::String),
+            JobAction::Procedure { name, args } => self.executor
+                .call_procedure(name, args.clone(), &job.owner)
+                .await,
+            JobAction::Callback { name } => {
+                let callback = self.callbacks.read().unwrap().get(name).cloned();
+                match callback {
+                    Some(callback) => callback(&self.executor),
+                    None => Err(anyhow::anyhow!("No callback registered as '{}'", name)),
+                }
+            }
+        };
+
+        let mut status = self.store.get_status(&job.name)?;
+        status.last_run_at = Some(started_at);
+        status.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        status.run_count += 1;
+        status.next_run_at = job.schedule.next_run(now_secs());
+        match result {
+            Ok(value) => {
+                status.last_success = Some(true);
+                status.last_error = None;
+                status.last_result = Some(value);
+            }
+            Err(e) => {
+                tracing::warn!("Job '{}' failed: {}", job.name, e);
+                status.last_success = Some(false);
+                status.last_error = Some(e.to_string());
+                status.last_result = None;
+            }
+        }
+        self.store.put_status(&job.name, &status)?;
+        Ok(status)
+    }
+}
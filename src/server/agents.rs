@@ -0,0 +1,570 @@
+//! REST resources for the agent API
+//!
+//! Handlers forward an [`AgentOp`] to the worker, which owns the database and
+//! runs it against [`crate::agent`]. Each operation checks the caller's
+//! permission on the namespace it touches (`agent_{id}_memory`,
+//! `agent_{id}_conv_{cid}`, `agent_{id}_tool_{tool}`, or `_agents` for the
+//! registry) and audits mutations, as the MCP agent tools do.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use crate::agent::{Agent, AgentMetadata, Conversation, MemoryEntry, Message, Role};
+use crate::auth::{estimate_tokens, Resource};
+use crate::query::audit::AuditOutcome;
+use crate::query::QueryExecutor;
+use crate::EmbeddedLiath;
+use super::api::{auth_error, AppState, AuthUser, SuccessResponse, WorkerMsg};
+use super::openapi::api_types;
+
+const AGENTS_NAMESPACE: &str = "_agents";
+
+// ========== Request/Response Types ==========
+
+api_types! {
+    #[derive(Deserialize)]
+    pub(super) struct CreateAgentRequest {
+        id: String,
+        description: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct AgentResponse {
+        #[serde(flatten)]
+        metadata: AgentMetadata,
+        conversations: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct StoreMemoryRequest {
+        pub(super) content: String,
+        #[serde(default)]
+        pub(super) tags: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct RecallParams {
+        /// Text to recall semantically similar memories for
+        pub(super) query: Option<String>,
+        /// Comma-separated tags, used when `query` is absent
+        pub(super) tags: Option<String>,
+        #[serde(default = "default_k")]
+        pub(super) k: usize,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct CreateConversationRequest {
+        /// ID for the conversation; a UUID is generated when absent
+        id: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct ConversationResponse {
+        id: String,
+        agent_id: String,
+        message_count: u64,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct AddMessageRequest {
+        /// `user`, `assistant`, `system` or `tool`
+        role: String,
+        /// Name of the tool, required when `role` is `tool`
+        tool: Option<String>,
+        content: String,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct MessagesParams {
+        /// Return only the most recent messages
+        last: Option<usize>,
+        /// Return the messages most similar to this text
+        query: Option<String>,
+        #[serde(default = "default_k")]
+        k: usize,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct MessageResponse {
+        id: u64,
+        role: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool: Option<String>,
+        content: String,
+        timestamp: u64,
+    }
+
+        /// Id of a created memory or message
+        #[derive(Serialize)]
+        pub(super) struct IdResponse {
+            id: u64,
+        }
+
+        #[derive(Serialize)]
+        pub(super) struct ToolStateResponse {
+            key: String,
+            value: Value,
+        }
+}
+
+impl From<Message> for MessageResponse {
+    fn from(message: Message) -> Self {
+        let tool = match &message.role {
+            Role::Tool(name) => Some(name.clone()),
+            _ => None,
+        };
+        Self {
+            id: message.id,
+            role: message.role.as_str().to_string(),
+            tool,
+            content: message.content,
+            timestamp: message.timestamp,
+        }
+    }
+}
+
+api_types! {
+    #[derive(Deserialize)]
+    pub(super) struct ToolStateRequest {
+        value: Value,
+    }
+}
+
+fn default_k() -> usize { 5 }
+
+// ========== Worker Operations ==========
+
+/// An agent API call, run by the worker
+pub(super) enum AgentOp {
+    ListAgents,
+    CreateAgent(CreateAgentRequest),
+    GetAgent { agent_id: String },
+    DeleteAgent { agent_id: String },
+    StoreMemory { agent_id: String, request: StoreMemoryRequest },
+    RecallMemories { agent_id: String, params: RecallParams },
+    GetMemory { agent_id: String, memory_id: u64 },
+    ForgetMemory { agent_id: String, memory_id: u64 },
+    ListConversations { agent_id: String },
+    CreateConversation { agent_id: String, request: CreateConversationRequest },
+    GetConversation { agent_id: String, conversation_id: String },
+    AddMessage { agent_id: String, conversation_id: String, request: AddMessageRequest },
+    ListMessages { agent_id: String, conversation_id: String, params: MessagesParams },
+    GetToolState { agent_id: String, tool: String, key: String },
+    PutToolState { agent_id: String, tool: String, key: String, value: Value },
+    DeleteToolState { agent_id: String, tool: String, key: String },
+}
+
+/// Status and JSON body of a successful call, or status and error message
+pub(super) type AgentReply = Result<(StatusCode, Value), (StatusCode, String)>;
+
+fn internal(error: anyhow::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+}
+
+fn not_found(message: String) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, message)
+}
+
+fn to_json<T: Serialize>(status: StatusCode, body: T) -> AgentReply {
+    serde_json::to_value(body)
+        .map(|value| (status, value))
+        .map_err(|e| internal(e.into()))
+}
+
+/// The authenticated caller, checked and audited against the executor's auth state
+struct Caller<'a> {
+    executor: &'a QueryExecutor,
+    user_id: &'a str,
+}
+
+impl Caller<'_> {
+    fn authorize(&self, permission: &str, namespace: &str) -> Result<(), (StatusCode, String)> {
+        if self.executor.is_authorized_for(self.user_id, permission, namespace) {
+            return Ok(());
+        }
+        let message = format!("'{}' lacks the '{}' permission on '{}'", self.user_id, permission, namespace);
+        self.executor.audit().record(self.user_id, permission, Some(namespace), None, AuditOutcome::Denied, Some(&message));
+        Err((StatusCode::FORBIDDEN, message))
+    }
+
+    /// Audit a mutation made through the agent API rather than the executor
+    fn record<T>(&self, operation: &str, namespace: &str, key: Option<&str>, result: &anyhow::Result<T>) {
+        let (outcome, detail) = match result {
+            Ok(_) => (AuditOutcome::Ok, None),
+            Err(e) => (AuditOutcome::Failed, Some(e.to_string())),
+        };
+        self.executor.audit().record(self.user_id, operation, Some(namespace), key, outcome, detail.as_deref());
+    }
+}
+
+fn load_agent(db: &Arc<EmbeddedLiath>, agent_id: &str) -> Result<Agent, (StatusCode, String)> {
+    Agent::load(agent_id, db.clone())
+        .map_err(internal)?
+        .ok_or_else(|| not_found(format!("Agent not found: {}", agent_id)))
+}
+
+fn memory_namespace(agent_id: &str) -> String {
+    format!("agent_{}_memory", agent_id)
+}
+
+fn conversation_namespace(agent_id: &str, conversation_id: &str) -> String {
+    format!("agent_{}_conv_{}", agent_id, conversation_id)
+}
+
+fn tool_namespace(agent_id: &str, tool: &str) -> String {
+    format!("agent_{}_tool_{}", agent_id, tool)
+}
+
+fn parse_role(role: &str, tool: Option<String>) -> Result<Role, (StatusCode, String)> {
+    match (role, tool) {
+        ("user", None) => Ok(Role::User),
+        ("assistant", None) => Ok(Role::Assistant),
+        ("system", None) => Ok(Role::System),
+        ("tool", Some(name)) => Ok(Role::Tool(name)),
+        ("tool", None) => Err((StatusCode::BAD_REQUEST, "Messages with role 'tool' need a 'tool' name".to_string())),
+        (_, Some(_)) if matches!(role, "user" | "assistant" | "system") => {
+            Err((StatusCode::BAD_REQUEST, "Only messages with role 'tool' take a 'tool' name".to_string()))
+        }
+        (other, _) => Err((StatusCode::BAD_REQUEST, format!(
+            "Unknown role '{}': expected user, assistant, system or tool", other
+        ))),
+    }
+}
+
+/// Run `op` for `user_id`
+pub(super) fn execute(db: &Arc<EmbeddedLiath>, executor: &QueryExecutor, user_id: &str, op: AgentOp) -> AgentReply {
+    let caller = Caller { executor, user_id };
+    match op {
+        AgentOp::ListAgents => {
+            caller.authorize("select", AGENTS_NAMESPACE)?;
+            to_json(StatusCode::OK, Agent::list_agents(db).map_err(internal)?)
+        }
+        AgentOp::CreateAgent(request) => {
+            caller.authorize("insert", AGENTS_NAMESPACE)?;
+            if request.id.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "Agent id must not be empty".to_string()));
+            }
+            if Agent::exists(&request.id, db).map_err(internal)? {
+                return Err((StatusCode::CONFLICT, format!("Agent already exists: {}", request.id)));
+            }
+            let agent = match &request.description {
+                Some(description) => Agent::new_with_description(&request.id, description, db.clone()),
+                None => Agent::new(&request.id, db.clone()),
+            };
+            let metadata = agent.metadata()
+                .and_then(|m| m.ok_or_else(|| anyhow::anyhow!("Failed to register agent '{}'", request.id)));
+            caller.record("agent_create", AGENTS_NAMESPACE, Some(&request.id), &metadata);
+            to_json(StatusCode::CREATED, metadata.map_err(internal)?)
+        }
+        AgentOp::GetAgent { agent_id } => {
+            caller.authorize("select", AGENTS_NAMESPACE)?;
+            let agent = load_agent(db, &agent_id)?;
+            let metadata = agent.metadata().map_err(internal)?
+                .ok_or_else(|| not_found(format!("Agent not found: {}", agent_id)))?;
+            let conversations = agent.conversations().map_err(internal)?
+                .into_iter()
+                .filter(|cid| executor.is_authorized_for(user_id, "select", &conversation_namespace(&agent_id, cid)))
+                .collect();
+            to_json(StatusCode::OK, AgentResponse { metadata, conversations })
+        }
+        AgentOp::DeleteAgent { agent_id } => {
+            caller.authorize("delete", AGENTS_NAMESPACE)?;
+            load_agent(db, &agent_id)?;
+            let result = Agent::delete(&agent_id, db);
+            caller.record("agent_delete", AGENTS_NAMESPACE, Some(&agent_id), &result);
+            result.map_err(internal)?;
+            to_json(StatusCode::OK, SuccessResponse { success: true, message: format!("Agent '{}' deleted", agent_id) })
+        }
+        AgentOp::StoreMemory { agent_id, request } => {
+            let namespace = memory_namespace(&agent_id);
+            caller.authorize("insert", &namespace)?;
+            let agent = load_agent(db, &agent_id)?;
+            let tags: Vec<&str> = request.tags.iter().map(String::as_str).collect();
+            let result = agent.memory().and_then(|memory| memory.store(&request.content, &tags));
+            caller.record("agent_store_memory", &namespace, None, &result);
+            to_json(StatusCode::CREATED, IdResponse { id: result.map_err(internal)? })
+        }
+        AgentOp::RecallMemories { agent_id, params } => {
+            caller.authorize("select", &memory_namespace(&agent_id))?;
+            let memory = load_agent(db, &agent_id)?.memory().map_err(internal)?;
+            let entries: Vec<MemoryEntry> = match (&params.query, &params.tags) {
+                (Some(query), _) => memory.recall(query, params.k),
+                (None, Some(tags)) => {
+                    let tags: Vec<&str> = tags.split(',').map(str::trim).filter(|t| !t.is_empty()).collect();
+                    memory.recall_by_tags(&tags, params.k)
+                }
+                (None, None) => {
+                    return Err((StatusCode::BAD_REQUEST, "Pass 'query' or 'tags' to recall memories".to_string()));
+                }
+            }.map_err(internal)?;
+            to_json(StatusCode::OK, entries)
+        }
+        AgentOp::GetMemory { agent_id, memory_id } => {
+            caller.authorize("select", &memory_namespace(&agent_id))?;
+            let memory = load_agent(db, &agent_id)?.memory().map_err(internal)?;
+            let entry = memory.get(memory_id).map_err(internal)?
+                .ok_or_else(|| not_found(format!("Memory not found: {}", memory_id)))?;
+            to_json(StatusCode::OK, entry)
+        }
+        AgentOp::ForgetMemory { agent_id, memory_id } => {
+            let namespace = memory_namespace(&agent_id);
+            caller.authorize("delete", &namespace)?;
+            let memory = load_agent(db, &agent_id)?.memory().map_err(internal)?;
+            if memory.get(memory_id).map_err(internal)?.is_none() {
+                return Err(not_found(format!("Memory not found: {}", memory_id)));
+            }
+            let result = memory.forget(memory_id);
+            caller.record("agent_forget_memory", &namespace, Some(&memory_id.to_string()), &result);
+            result.map_err(internal)?;
+            to_json(StatusCode::OK, SuccessResponse { success: true, message: format!("Memory {} forgotten", memory_id) })
+        }
+        AgentOp::ListConversations { agent_id } => {
+            let agent = load_agent(db, &agent_id)?;
+            let conversations: Vec<String> = agent.conversations().map_err(internal)?
+                .into_iter()
+                .filter(|cid| executor.is_authorized_for(user_id, "select", &conversation_namespace(&agent_id, cid)))
+                .collect();
+            to_json(StatusCode::OK, conversations)
+        }
+        AgentOp::CreateConversation { agent_id, request } => {
+            let agent = load_agent(db, &agent_id)?;
+            let conversation_id = request.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            if conversation_id.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "Conversation id must not be empty".to_string()));
+            }
+            let namespace = conversation_namespace(&agent_id, &conversation_id);
+            caller.authorize("insert", &namespace)?;
+            if agent.conversation(Some(&conversation_id)).is_ok() {
+                return Err((StatusCode::CONFLICT, format!("Conversation already exists: {}", conversation_id)));
+            }
+            let result = Conversation::create_with_id(&conversation_id, &agent_id, db.clone());
+            caller.record("agent_create_conversation", &namespace, None, &result);
+            let conversation = result.map_err(internal)?;
+            to_json(StatusCode::CREATED, ConversationResponse {
+                id: conversation.id().to_string(),
+                agent_id,
+                message_count: conversation.message_count(),
+            })
+        }
+        AgentOp::GetConversation { agent_id, conversation_id } => {
+            caller.authorize("select", &conversation_namespace(&agent_id, &conversation_id))?;
+            let conversation = load_agent(db, &agent_id)?
+                .conversation(Some(&conversation_id))
+                .map_err(|_| not_found(format!("Conversation not found: {}", conversation_id)))?;
+            to_json(StatusCode::OK, ConversationResponse {
+                id: conversation_id,
+                agent_id,
+                message_count: conversation.message_count(),
+            })
+        }
+        AgentOp::AddMessage { agent_id, conversation_id, request } => {
+            let namespace = conversation_namespace(&agent_id, &conversation_id);
+            caller.authorize("insert", &namespace)?;
+            let role = parse_role(&request.role, request.tool)?;
+            let conversation = load_agent(db, &agent_id)?
+                .conversation(Some(&conversation_id))
+                .map_err(|_| not_found(format!("Conversation not found: {}", conversation_id)))?;
+            let result = conversation.add_message(role, &request.content);
+            caller.record("agent_add_message", &namespace, None, &result);
+            to_json(StatusCode::CREATED, IdResponse { id: result.map_err(internal)? })
+        }
+        AgentOp::ListMessages { agent_id, conversation_id, params } => {
+            caller.authorize("select", &conversation_namespace(&agent_id, &conversation_id))?;
+            let conversation = load_agent(db, &agent_id)?
+                .conversation(Some(&conversation_id))
+                .map_err(|_| not_found(format!("Conversation not found: {}", conversation_id)))?;
+            let messages = match (params.last, &params.query) {
+                (_, Some(query)) => conversation.search(query, params.k),
+                (Some(n), None) => conversation.last_n(n),
+                (None, None) => conversation.messages(),
+            }.map_err(internal)?;
+            to_json(StatusCode::OK, messages.into_iter().map(MessageResponse::from).collect::<Vec<_>>())
+        }
+        AgentOp::GetToolState { agent_id, tool, key } => {
+            caller.authorize("select", &tool_namespace(&agent_id, &tool))?;
+            let state = load_agent(db, &agent_id)?.tool_state(&tool).map_err(internal)?;
+            let value: Value = state.get(&key).map_err(internal)?
+                .ok_or_else(|| not_found(format!("Key not found: {}", key)))?;
+            to_json(StatusCode::OK, ToolStateResponse { key, value })
+        }
+        AgentOp::PutToolState { agent_id, tool, key, value } => {
+            let namespace = tool_namespace(&agent_id, &tool);
+            caller.authorize("insert", &namespace)?;
+            let state = load_agent(db, &agent_id)?.tool_state(&tool).map_err(internal)?;
+            let result = state.set(&key, &value);
+            caller.record("agent_set_tool_state", &namespace, Some(&key), &result);
+            result.map_err(internal)?;
+            to_json(StatusCode::OK, ToolStateResponse { key, value })
+        }
+        AgentOp::DeleteToolState { agent_id, tool, key } => {
+            let namespace = tool_namespace(&agent_id, &tool);
+            caller.authorize("delete", &namespace)?;
+            let state = load_agent(db, &agent_id)?.tool_state(&tool).map_err(internal)?;
+            if !state.exists(&key).map_err(internal)? {
+                return Err(not_found(format!("Key not found: {}", key)));
+            }
+            let result = state.delete(&key);
+            caller.record("agent_delete_tool_state", &namespace, Some(&key), &result);
+            result.map_err(internal)?;
+            to_json(StatusCode::OK, SuccessResponse { success: true, message: format!("Deleted '{}'", key) })
+        }
+    }
+}
+
+// ========== Handlers ==========
+
+async fn dispatch(state: &AppState, user_id: String, op: AgentOp) -> Response {
+    match state.call(|resp| WorkerMsg::Agent { op, user_id, resp }).await {
+        Ok(Ok((status, body))) => (status, Json(body)).into_response(),
+        Ok(Err((status, message))) => auth_error(status, message),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub(super) async fn list_agents(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::ListAgents).await
+}
+
+pub(super) async fn create_agent(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Json(request): Json<CreateAgentRequest>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::CreateAgent(request)).await
+}
+
+pub(super) async fn get_agent(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(agent_id): Path<String>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::GetAgent { agent_id }).await
+}
+
+pub(super) async fn delete_agent(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(agent_id): Path<String>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::DeleteAgent { agent_id }).await
+}
+
+pub(super) async fn store_memory(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(agent_id): Path<String>,
+    Json(request): Json<StoreMemoryRequest>,
+) -> Response {
+    if let Err(e) = state.charge(&user_id, Resource::EmbeddingTokens, estimate_tokens(&request.content)) {
+        return e.into_response();
+    }
+    dispatch(&state, user_id, AgentOp::StoreMemory { agent_id, request }).await
+}
+
+pub(super) async fn recall_memories(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(agent_id): Path<String>,
+    Query(params): Query<RecallParams>,
+) -> Response {
+    if let Some(query) = &params.query {
+        if let Err(e) = state.charge(&user_id, Resource::EmbeddingTokens, estimate_tokens(query)) {
+            return e.into_response();
+        }
+    }
+    dispatch(&state, user_id, AgentOp::RecallMemories { agent_id, params }).await
+}
+
+pub(super) async fn get_memory(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, memory_id)): Path<(String, u64)>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::GetMemory { agent_id, memory_id }).await
+}
+
+pub(super) async fn forget_memory(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, memory_id)): Path<(String, u64)>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::ForgetMemory { agent_id, memory_id }).await
+}
+
+pub(super) async fn list_conversations(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(agent_id): Path<String>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::ListConversations { agent_id }).await
+}
+
+pub(super) async fn create_conversation(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(agent_id): Path<String>,
+    request: Option<Json<CreateConversationRequest>>,
+) -> Response {
+    let request = request.map(|Json(request)| request).unwrap_or(CreateConversationRequest { id: None });
+    dispatch(&state, user_id, AgentOp::CreateConversation { agent_id, request }).await
+}
+
+pub(super) async fn get_conversation(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, conversation_id)): Path<(String, String)>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::GetConversation { agent_id, conversation_id }).await
+}
+
+pub(super) async fn add_message(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, conversation_id)): Path<(String, String)>,
+    Json(request): Json<AddMessageRequest>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::AddMessage { agent_id, conversation_id, request }).await
+}
+
+pub(super) async fn list_messages(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, conversation_id)): Path<(String, String)>,
+    Query(params): Query<MessagesParams>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::ListMessages { agent_id, conversation_id, params }).await
+}
+
+pub(super) async fn get_tool_state(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, tool, key)): Path<(String, String, String)>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::GetToolState { agent_id, tool, key }).await
+}
+
+pub(super) async fn put_tool_state(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, tool, key)): Path<(String, String, String)>,
+    Json(request): Json<ToolStateRequest>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::PutToolState { agent_id, tool, key, value: request.value }).await
+}
+
+pub(super) async fn delete_tool_state(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path((agent_id, tool, key)): Path<(String, String, String)>,
+) -> Response {
+    dispatch(&state, user_id, AgentOp::DeleteToolState { agent_id, tool, key }).await
+}
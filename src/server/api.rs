@@ -1,146 +1,316 @@
 use axum::{
-    extract::{Path, State},
-    routing::{delete, get, post, put},
-    Json, Router,
+    body::Body,
+    extract::{
+        ws::{close_code, rejection::WebSocketUpgradeRejection, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        MatchedPath, Path, Query, RawPathParams, Request, State,
+    },
+    handler::Handler,
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+    routing::{get, on, MethodFilter, MethodRouter},
+    Extension, Json, Router,
 };
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, oneshot};
-use crate::query::QueryExecutor;
+use crate::auth::{estimate_tokens, AuthManager, LimitExceeded, Resource};
+use crate::error::LiathError;
+use crate::metrics;
+use crate::EmbeddedLiath;
+use crate::query::audit::{self, AuditEntry, AuditFilter, AuditLog, AuditOutcome};
+use crate::query::bulk::{self, BulkError, BulkRecord, BulkReport};
+use crate::query::{ChangeFilter, Procedure, Profile, QueryExecutor, QueryResult, ScanEntry, ScanIter, Subscription};
+use crate::scheduler::{Job, JobAction, JobStatus, Schedule, Scheduler};
+use super::agents::{self, AgentOp, AgentReply};
+use super::openapi::{self, api_types};
 
 // ========== Request/Response Types ==========
 
-#[derive(Deserialize)]
-struct QueryRequest {
-    query: String,
-    user_id: String,
-}
+api_types! {
+    #[derive(Deserialize)]
+    pub(super) struct QueryRequest {
+        query: String,
+        /// Profile the execution and return the profile with the result
+        #[serde(default)]
+        explain: bool,
+    }
 
-#[derive(Serialize)]
-struct QueryResponse {
-    result: String,
-}
+    #[derive(Serialize)]
+    pub(super) struct QueryResponse {
+        result: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        profile: Option<Profile>,
+    }
 
-#[derive(Serialize)]
-struct HealthResponse {
-    status: String,
-    version: String,
-    uptime_secs: u64,
-}
+    #[derive(Deserialize)]
+    pub(super) struct SqlRequest {
+        query: String,
+    }
 
-#[derive(Serialize)]
-struct MetricsResponse {
-    namespaces: usize,
-    requests_total: u64,
-    uptime_secs: u64,
-}
+    #[derive(Serialize)]
+    pub(super) struct SqlResponse {
+        success: bool,
+        columns: Vec<String>,
+        rows: Vec<Vec<serde_json::Value>>,
+        error: Option<String>,
+    }
 
-#[derive(Serialize)]
-struct NamespacesResponse {
-    namespaces: Vec<String>,
-}
+    #[derive(Serialize)]
+    pub(super) struct HealthResponse {
+        status: String,
+        version: String,
+        uptime_secs: u64,
+    }
 
-#[derive(Deserialize)]
-struct CreateNamespaceRequest {
-    name: String,
-    #[serde(default = "default_dimensions")]
-    dimensions: usize,
-    #[serde(default = "default_metric")]
-    metric: String,
-}
+    #[derive(Serialize)]
+    pub(super) struct MetricsResponse {
+        namespaces: usize,
+        requests_total: u64,
+        uptime_secs: u64,
+        /// Requests waiting for a worker
+        queue_depth: usize,
+        queue_capacity: usize,
+        workers: usize,
+        busy_workers: usize,
+    }
 
-fn default_dimensions() -> usize { 384 }
-fn default_metric() -> String { "cosine".to_string() }
+    #[derive(Serialize)]
+    pub(super) struct NamespacesResponse {
+        namespaces: Vec<String>,
+    }
 
-#[derive(Serialize)]
-struct SuccessResponse {
-    success: bool,
-    message: String,
-}
+    #[derive(Deserialize)]
+    pub(super) struct CreateNamespaceRequest {
+        name: String,
+        #[serde(default = "default_dimensions")]
+        dimensions: usize,
+        #[serde(default = "default_metric")]
+        metric: String,
+        /// Encrypt values and the saved vector index
+        #[serde(default)]
+        encrypted: bool,
+    }
 
-#[derive(Deserialize)]
-struct KvPutRequest {
-    value: String,
-}
+    #[derive(Serialize)]
+    pub(super) struct SuccessResponse {
+        pub(super) success: bool,
+        pub(super) message: String,
+    }
 
-#[derive(Serialize)]
-struct KvGetResponse {
-    key: String,
-    value: Option<String>,
-}
+    #[derive(Deserialize)]
+    pub(super) struct KvPutRequest {
+        value: String,
+    }
 
-#[derive(Deserialize)]
-struct SemanticSearchRequest {
-    query: String,
-    #[serde(default = "default_k")]
-    k: usize,
-}
+    #[derive(Serialize)]
+    pub(super) struct KvGetResponse {
+        key: String,
+        value: Option<String>,
+    }
 
-fn default_k() -> usize { 5 }
+    #[derive(Deserialize)]
+    pub(super) struct ScanQuery {
+        #[serde(default)]
+        prefix: String,
+        cursor: Option<String>,
+        /// Stop after this many entries and end the stream with a cursor line
+        limit: Option<usize>,
+    }
 
-#[derive(Serialize)]
-struct SemanticSearchResult {
-    id: u64,
-    content: String,
-    distance: f32,
-}
+    #[derive(Deserialize)]
+    pub(super) struct BulkQuery {
+        /// Records written per batch
+        batch_size: Option<usize>,
+        /// Vectors to reserve index capacity for before the first batch
+        #[serde(default)]
+        expected: usize,
+    }
 
-#[derive(Serialize)]
-struct SemanticSearchResponse {
-    results: Vec<SemanticSearchResult>,
-}
+    #[derive(Deserialize)]
+    pub(super) struct WatchQuery {
+        namespace: Option<String>,
+        prefix: Option<String>,
+        /// Resume after this sequence number; SSE clients may send `Last-Event-ID` instead
+        since: Option<u64>,
+    }
 
-#[derive(Deserialize)]
-struct EmbedRequest {
-    texts: Vec<String>,
-}
+    /// Running totals of a bulk ingest
+    #[derive(Serialize, Default)]
+    pub(super) struct BulkProgress {
+        pub(super) processed: usize,
+        pub(super) written: usize,
+        pub(super) failed: usize,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct AuditQuery {
+        /// Milliseconds since the epoch, or a duration like `1h` meaning that long ago
+        since: Option<String>,
+        until: Option<String>,
+        user: Option<String>,
+        limit: Option<usize>,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct AuditResponse {
+        entries: Vec<AuditEntry>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct SemanticSearchRequest {
+        query: String,
+        #[serde(default = "default_k")]
+        k: usize,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct SemanticSearchResult {
+        id: u64,
+        content: String,
+        distance: f32,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct SemanticSearchResponse {
+        results: Vec<SemanticSearchResult>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct EmbedRequest {
+        texts: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct EmbedResponse {
+        embeddings: Vec<Vec<f32>>,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct ProceduresResponse {
+        procedures: Vec<Procedure>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct RegisterProcedureRequest {
+        code: String,
+        description: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct CallProcedureRequest {
+        #[serde(default)]
+        args: serde_json::Value,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct CallProcedureResponse {
+        success: bool,
+        result: Option<serde_json::Value>,
+        error: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct JobInfo {
+        #[serde(flatten)]
+        job: Job,
+        status: JobStatus,
+    }
 
-#[derive(Serialize)]
-struct EmbedResponse {
-    embeddings: Vec<Vec<f32>>,
+    #[derive(Serialize)]
+    pub(super) struct JobsResponse {
+        jobs: Vec<JobInfo>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct PutJobRequest {
+        schedule: Schedule,
+        action: JobAction,
+    }
+
+    #[derive(Serialize)]
+    pub(super) struct RunJobResponse {
+        success: bool,
+        status: Option<JobStatus>,
+        error: Option<String>,
+    }
 }
 
+fn default_dimensions() -> usize { 384 }
+fn default_metric() -> String { "cosine".to_string() }
+
+/// Messages buffered between the worker and a streaming response
+pub(super) const SCAN_STREAM_BUFFER: usize = 256;
+
+fn default_k() -> usize { 5 }
+
 // ========== Worker Message ==========
 
-enum WorkerMsg {
+pub(super) enum WorkerMsg {
     Execute {
         query: String,
         user_id: String,
-        resp: oneshot::Sender<String>,
+        explain: bool,
+        resp: oneshot::Sender<(String, Option<Profile>)>,
     },
-    GetNamespaceCount {
-        resp: oneshot::Sender<usize>,
+    Sql {
+        query: String,
+        user_id: String,
+        resp: oneshot::Sender<Result<QueryResult, String>>,
     },
     ListNamespaces {
+        user_id: String,
         resp: oneshot::Sender<Vec<String>>,
     },
     CreateNamespace {
         name: String,
         dimensions: usize,
         metric: String,
+        encrypted: bool,
+        user_id: String,
         resp: oneshot::Sender<Result<(), String>>,
     },
     DeleteNamespace {
         name: String,
+        user_id: String,
         resp: oneshot::Sender<Result<(), String>>,
     },
     KvGet {
         namespace: String,
         key: String,
+        user_id: String,
         resp: oneshot::Sender<Result<Option<String>, String>>,
     },
     KvPut {
         namespace: String,
         key: String,
         value: String,
-        resp: oneshot::Sender<Result<(), String>>,
+        user_id: String,
+        resp: oneshot::Sender<anyhow::Result<()>>,
     },
     KvDelete {
         namespace: String,
         key: String,
+        user_id: String,
         resp: oneshot::Sender<Result<(), String>>,
     },
+    KvScan {
+        namespace: String,
+        prefix: String,
+        cursor: Option<String>,
+        limit: Option<usize>,
+        user_id: String,
+        items: mpsc::Sender<ScanItem>,
+    },
+    BulkIngest {
+        namespace: String,
+        records: Vec<(usize, BulkRecord)>,
+        reserve: usize,
+        user_id: String,
+        resp: oneshot::Sender<Result<BulkReport, String>>,
+    },
     SemanticSearch {
         namespace: String,
         query: String,
@@ -151,27 +321,261 @@ enum WorkerMsg {
         texts: Vec<String>,
         resp: oneshot::Sender<Result<Vec<Vec<f32>>, String>>,
     },
+    /// Add vectors in order, stopping at the first that fails
+    #[cfg(feature = "grpc")]
+    AddVectors {
+        namespace: String,
+        vectors: Vec<(u64, Vec<f32>)>,
+        resp: oneshot::Sender<Result<usize, String>>,
+    },
+    #[cfg(feature = "grpc")]
+    SearchVectors {
+        namespace: String,
+        vector: Vec<f32>,
+        k: usize,
+        resp: oneshot::Sender<Result<Vec<(u64, f32)>, String>>,
+    },
+    Audit {
+        filter: AuditFilter,
+        user_id: String,
+        resp: oneshot::Sender<Result<Vec<AuditEntry>, String>>,
+    },
+    ListProcedures {
+        resp: oneshot::Sender<Result<Vec<Procedure>, String>>,
+    },
+    GetProcedure {
+        name: String,
+        resp: oneshot::Sender<Result<Option<Procedure>, String>>,
+    },
+    RegisterProcedure {
+        name: String,
+        code: String,
+        description: Option<String>,
+        user_id: String,
+        resp: oneshot::Sender<Result<Procedure, String>>,
+    },
+    DeleteProcedure {
+        name: String,
+        user_id: String,
+        resp: oneshot::Sender<Result<(), String>>,
+    },
+    CallProcedure {
+        name: String,
+        args: serde_json::Value,
+        user_id: String,
+        resp: oneshot::Sender<Result<serde_json::Value, String>>,
+    },
+    ListJobs {
+        resp: oneshot::Sender<Result<Vec<(Job, JobStatus)>, String>>,
+    },
+    PutJob {
+        name: String,
+        schedule: Schedule,
+        action: JobAction,
+        user_id: String,
+        resp: oneshot::Sender<Result<(), String>>,
+    },
+    DeleteJob {
+        name: String,
+        user_id: String,
+        resp: oneshot::Sender<Result<(), String>>,
+    },
+    SetJobEnabled {
+        name: String,
+        enabled: bool,
+        user_id: String,
+        resp: oneshot::Sender<Result<(), String>>,
+    },
+    RunJob {
+        name: String,
+        user_id: String,
+        resp: oneshot::Sender<Result<JobStatus, String>>,
+    },
+    Agent {
+        op: AgentOp,
+        user_id: String,
+        resp: oneshot::Sender<AgentReply>,
+    },
+    /// Answer MCP messages as `user_id`, replying to the requests among them
+    #[cfg(feature = "mcp")]
+    Mcp {
+        messages: Vec<serde_json::Value>,
+        user_id: String,
+        subscriptions: crate::mcp::Subscriptions,
+        resp: oneshot::Sender<Vec<serde_json::Value>>,
+    },
+}
+
+impl WorkerMsg {
+    /// Whether the handler stopped waiting, e.g. because the request timed out
+    fn is_abandoned(&self) -> bool {
+        match self {
+            WorkerMsg::Execute { resp, .. } => resp.is_closed(),
+            WorkerMsg::Sql { resp, .. } => resp.is_closed(),
+            WorkerMsg::ListNamespaces { resp, .. } => resp.is_closed(),
+            WorkerMsg::CreateNamespace { resp, .. } => resp.is_closed(),
+            WorkerMsg::DeleteNamespace { resp, .. } => resp.is_closed(),
+            WorkerMsg::KvGet { resp, .. } => resp.is_closed(),
+            WorkerMsg::KvPut { resp, .. } => resp.is_closed(),
+            WorkerMsg::KvDelete { resp, .. } => resp.is_closed(),
+            WorkerMsg::BulkIngest { resp, .. } => resp.is_closed(),
+            WorkerMsg::SemanticSearch { resp, .. } => resp.is_closed(),
+            WorkerMsg::GenerateEmbeddings { resp, .. } => resp.is_closed(),
+            #[cfg(feature = "grpc")]
+            WorkerMsg::AddVectors { resp, .. } => resp.is_closed(),
+            #[cfg(feature = "grpc")]
+            WorkerMsg::SearchVectors { resp, .. } => resp.is_closed(),
+            WorkerMsg::Audit { resp, .. } => resp.is_closed(),
+            WorkerMsg::ListProcedures { resp, .. } => resp.is_closed(),
+            WorkerMsg::GetProcedure { resp, .. } => resp.is_closed(),
+            WorkerMsg::RegisterProcedure { resp, .. } => resp.is_closed(),
+            WorkerMsg::DeleteProcedure { resp, .. } => resp.is_closed(),
+            WorkerMsg::CallProcedure { resp, .. } => resp.is_closed(),
+            WorkerMsg::ListJobs { resp, .. } => resp.is_closed(),
+            WorkerMsg::PutJob { resp, .. } => resp.is_closed(),
+            WorkerMsg::DeleteJob { resp, .. } => resp.is_closed(),
+            WorkerMsg::SetJobEnabled { resp, .. } => resp.is_closed(),
+            WorkerMsg::RunJob { resp, .. } => resp.is_closed(),
+            WorkerMsg::Agent { resp, .. } => resp.is_closed(),
+            #[cfg(feature = "mcp")]
+            WorkerMsg::Mcp { resp, .. } => resp.is_closed(),
+            WorkerMsg::KvScan { items, .. } => items.is_closed(),
+        }
+    }
 }
 
 // ========== App State ==========
 
+/// Why a request never got a worker's reply, or was refused before it did
+pub(super) enum DispatchError {
+    /// The queue is full (503)
+    Busy,
+    /// The workers are gone (503)
+    Stopped,
+    /// No reply within the request timeout (504)
+    TimedOut(Duration),
+    /// The caller's rate limit or a daily quota ran out (429)
+    Limited(LimitExceeded),
+}
+
+impl From<LimitExceeded> for DispatchError {
+    fn from(refusal: LimitExceeded) -> Self {
+        DispatchError::Limited(refusal)
+    }
+}
+
+impl std::fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DispatchError::Busy => write!(f, "Server is busy, retry later"),
+            DispatchError::Stopped => write!(f, "Request workers are not running"),
+            DispatchError::TimedOut(timeout) => write!(f, "Request timed out after {}s", timeout.as_secs_f64()),
+            DispatchError::Limited(refusal) => write!(f, "{}", refusal),
+        }
+    }
+}
+
+impl IntoResponse for DispatchError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            DispatchError::TimedOut(_) => StatusCode::GATEWAY_TIMEOUT,
+            DispatchError::Busy | DispatchError::Stopped => StatusCode::SERVICE_UNAVAILABLE,
+            DispatchError::Limited(_) => StatusCode::TOO_MANY_REQUESTS,
+        };
+        let mut response = auth_error(status, self.to_string());
+        let retry_after = match &self {
+            DispatchError::Busy => Some(1),
+            DispatchError::Limited(refusal) => Some(refusal.retry_after_secs()),
+            DispatchError::Stopped | DispatchError::TimedOut(_) => None,
+        };
+        if let Some(secs) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, header::HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
 #[derive(Clone)]
-struct AppState {
+pub(super) struct AppState {
     tx: mpsc::Sender<WorkerMsg>,
+    pub(super) query_executor: QueryExecutor,
+    pub(super) auth: Arc<RwLock<AuthManager>>,
+    pub(super) audit: AuditLog,
     start_time: u64,
-    requests: Arc<std::sync::atomic::AtomicU64>,
+    pub(super) requests: Arc<std::sync::atomic::AtomicU64>,
+    request_timeout: Duration,
+    workers: usize,
+    busy: Arc<std::sync::atomic::AtomicUsize>,
 }
 
 impl AppState {
-    fn new(tx: mpsc::Sender<WorkerMsg>) -> Self {
+    fn new(tx: mpsc::Sender<WorkerMsg>, query_executor: QueryExecutor, config: &ServerConfig) -> Self {
         let start_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
         Self {
             tx,
+            auth: query_executor.auth_manager(),
+            audit: query_executor.audit(),
+            query_executor,
             start_time,
             requests: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            request_timeout: config.request_timeout,
+            workers: config.workers,
+            busy: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+        }
+    }
+
+    /// Queue `msg` for a worker without waiting for room
+    ///
+    /// A full queue is refused so clients back off instead of piling up.
+    pub(super) fn submit(&self, msg: WorkerMsg) -> Result<(), DispatchError> {
+        self.tx.try_send(msg).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => DispatchError::Busy,
+            mpsc::error::TrySendError::Closed(_) => DispatchError::Stopped,
+        })
+    }
+
+    /// Queue the message built by `msg` and wait for the worker's reply
+    ///
+    /// Gives up after the request timeout; the worker skips the message if it
+    /// was still queued.
+    pub(super) async fn call<T>(&self, msg: impl FnOnce(oneshot::Sender<T>) -> WorkerMsg) -> Result<T, DispatchError> {
+        let (tx, rx) = oneshot::channel();
+        let deadline = tokio::time::Instant::now() + self.request_timeout;
+        self.submit(msg(tx))?;
+        self.reply(rx, deadline).await
+    }
+
+    /// Like [`call`](Self::call), but wait for room in a full queue
+    ///
+    /// For streams that have already answered the client, where refusing one batch
+    /// would abort the whole stream. The wait counts against the request timeout.
+    pub(super) async fn call_queued<T>(&self, msg: impl FnOnce(oneshot::Sender<T>) -> WorkerMsg) -> Result<T, DispatchError> {
+        let (tx, rx) = oneshot::channel();
+        let deadline = tokio::time::Instant::now() + self.request_timeout;
+        match tokio::time::timeout_at(deadline, self.tx.send(msg(tx))).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => return Err(DispatchError::Stopped),
+            Err(_) => return Err(DispatchError::TimedOut(self.request_timeout)),
+        }
+        self.reply(rx, deadline).await
+    }
+
+    /// Count `amount` of `resource` against the caller's daily quota
+    ///
+    /// For work the workers do without metering it, such as embedding the query
+    /// of a semantic search.
+    pub(super) fn charge(&self, user_id: &str, resource: Resource, amount: u64) -> Result<(), DispatchError> {
+        Ok(self.auth.read().unwrap().charge(user_id, resource, amount)?)
+    }
+
+    async fn reply<T>(&self, rx: oneshot::Receiver<T>, deadline: tokio::time::Instant) -> Result<T, DispatchError> {
+        match tokio::time::timeout_at(deadline, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(DispatchError::Stopped),
+            Err(_) => Err(DispatchError::TimedOut(self.request_timeout)),
         }
     }
 
@@ -184,23 +588,184 @@ impl AppState {
     }
 }
 
+// ========== Authentication ==========
+
+/// The user an API key resolved to, set on every authenticated request
+#[derive(Clone)]
+pub(super) struct AuthUser(pub(super) String);
+
+/// Permission a route needs beyond a valid key
+///
+/// Routes not listed here run through the query executor, procedures or the
+/// scheduler, which check the caller's permissions themselves. Routes with a
+/// namespace in their path are checked against that namespace.
+pub(super) fn required_permission(method: &Method, path: &str) -> Option<&'static str> {
+    match (method.as_str(), path) {
+        ("GET", "/namespaces") => Some("list_namespaces"),
+        ("POST", "/namespaces") => Some("create_namespace"),
+        ("DELETE", "/namespaces/:name") => Some("delete_namespace"),
+        ("GET", "/kv/:namespace") | ("GET", "/kv/:namespace/:key") => Some("select"),
+        ("PUT", "/kv/:namespace/:key") => Some("insert"),
+        ("DELETE", "/kv/:namespace/:key") => Some("delete"),
+        ("POST", "/bulk/:namespace") => Some("insert"),
+        ("POST", "/semantic/:namespace") => Some("similarity_search"),
+        ("POST", "/embed") => Some("generate_embedding"),
+        ("GET", "/audit") => Some(audit::READ_AUDIT_PERMISSION),
+        ("GET", "/procedures") | ("GET", "/procedures/:name") => Some("call_procedure"),
+        ("GET", "/jobs") => Some("manage_jobs"),
+        _ => None,
+    }
+}
+
+/// Daily quota a route spends, refused up front once it is used up
+///
+/// The amount is only known once the request runs, so a request may still go
+/// over what is left of the quota and be refused then.
+pub(super) fn quota_resource(method: &Method, path: &str) -> Option<Resource> {
+    match (method.as_str(), path) {
+        ("POST", "/embed") | ("POST", "/semantic/:namespace") => Some(Resource::EmbeddingTokens),
+        ("PUT", "/kv/:namespace/:key") | ("POST", "/bulk/:namespace") => Some(Resource::StorageBytes),
+        _ => None,
+    }
+}
+
+pub(super) fn auth_error(status: StatusCode, message: String) -> Response {
+    (status, Json(SuccessResponse { success: false, message })).into_response()
+}
+
+/// Refuse a request with 403 and record the denial in the audit log
+fn forbidden(state: &AppState, user_id: &str, operation: &str, namespace: Option<&str>, key: Option<&str>, message: String) -> Response {
+    state.audit.record(user_id, operation, namespace, key, AuditOutcome::Denied, Some(&message));
+    auth_error(StatusCode::FORBIDDEN, message)
+}
+
+/// Resolve the bearer token to a user, check the route's permission and count
+/// the request against the user's limits
+async fn authorize(
+    State(state): State<AppState>,
+    matched_path: Option<MatchedPath>,
+    params: Option<RawPathParams>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let query_token = matched_path
+        .as_ref()
+        .filter(|path| path.as_str() == "/watch")
+        .and_then(|_| request.uri().query())
+        .and_then(access_token);
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .or(query_token.as_deref());
+    let Some(token) = token else {
+        metrics::increment(metrics::AUTH_DENIALS, &[("operation", "authenticate")]);
+        return auth_error(StatusCode::UNAUTHORIZED, "Missing API key: send 'Authorization: Bearer <key>'".to_string());
+    };
+
+    let user_id = {
+        let auth = state.auth.read().unwrap();
+        let Some(user_id) = auth.authenticate(token) else {
+            metrics::increment(metrics::AUTH_DENIALS, &[("operation", "authenticate")]);
+            return auth_error(StatusCode::UNAUTHORIZED, "Invalid API key".to_string());
+        };
+        let path = matched_path.as_ref().map(|p| p.as_str()).unwrap_or_default();
+        if let Some(permission) = required_permission(request.method(), path) {
+            let namespace = params.iter().flat_map(|p| p.iter()).find_map(|(name, value)| {
+                (name == "namespace" || (name == "name" && path.starts_with("/namespaces/"))).then_some(value)
+            });
+            let key = params.iter().flat_map(|p| p.iter()).find_map(|(name, value)| (name == "key").then_some(value));
+            let denied = match namespace {
+                Some(namespace) if !auth.is_authorized_for(&user_id, permission, namespace) => {
+                    Some(format!("'{}' lacks the '{}' permission on '{}'", user_id, permission, namespace))
+                }
+                Some(_) => None,
+                // The handler checks the namespace named in the body or filters the listing
+                None if path == "/namespaces" && auth.is_authorized_anywhere(&user_id, permission) => None,
+                None if path != "/namespaces" && auth.is_authorized(&user_id, permission) => None,
+                None => Some(format!("'{}' lacks the '{}' permission", user_id, permission)),
+            };
+            if let Some(message) = denied {
+                return forbidden(&state, &user_id, permission, namespace, key, message);
+            }
+        }
+        let admitted = auth.admit(&user_id).and_then(|()| match quota_resource(request.method(), path) {
+            Some(resource) => auth.check_quota(&user_id, resource),
+            None => Ok(()),
+        });
+        if let Err(refusal) = admitted {
+            return DispatchError::from(refusal).into_response();
+        }
+        user_id
+    };
+
+    request.extensions_mut().insert(AuthUser(user_id));
+    next.run(request).await
+}
+
+/// Count and time every request by its route pattern
+async fn record_request(matched_path: Option<MatchedPath>, request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let route = matched_path.as_ref().map_or("unmatched", |path| path.as_str()).to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let labels = [("method", method.as_str()), ("route", route.as_str())];
+    metrics::observe_duration(metrics::HTTP_DURATION, &labels, started.elapsed());
+    let status = response.status();
+    metrics::increment(metrics::HTTP_REQUESTS, &[labels[0], labels[1], ("status", status.as_str())]);
+    response
+}
+
+/// The `access_token` query parameter, for clients that cannot set headers
+///
+/// Browsers open WebSockets and `EventSource` streams without custom headers, so
+/// `/watch` also takes the key from the URL.
+fn access_token(query: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct TokenQuery {
+        access_token: Option<String>,
+    }
+    Query::<TokenQuery>::try_from_uri(&format!("/?{}", query).parse().ok()?)
+        .ok()
+        .and_then(|Query(q)| q.access_token)
+}
+
 // ========== Handlers ==========
 
-async fn execute_query(State(state): State<AppState>, Json(payload): Json<QueryRequest>) -> Json<QueryResponse> {
+async fn execute_query(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Json(payload): Json<QueryRequest>,
+) -> Result<Json<QueryResponse>, DispatchError> {
     state.requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
-    let (tx, rx) = oneshot::channel();
-    let _ = state
-        .tx
-        .send(WorkerMsg::Execute {
-            query: payload.query,
-            user_id: payload.user_id,
-            resp: tx,
-        })
-        .await;
+    let (result, profile) = state.call(|resp| WorkerMsg::Execute {
+        query: payload.query,
+        user_id,
+        explain: payload.explain,
+        resp,
+    }).await?;
+    Ok(Json(QueryResponse { result, profile }))
+}
+
+async fn execute_sql(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Json(payload): Json<SqlRequest>,
+) -> Result<Json<SqlResponse>, DispatchError> {
+    state.requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
-    let result = rx.await.unwrap_or_else(|e| format!("Recv error: {}", e));
-    Json(QueryResponse { result })
+    let result = state.call(|resp| WorkerMsg::Sql {
+        query: payload.query,
+        user_id,
+        resp,
+    }).await?;
+    Ok(match result {
+        Ok(result) => Json(SqlResponse { success: true, columns: result.columns, rows: result.rows, error: None }),
+        Err(e) => Json(SqlResponse { success: false, columns: Vec::new(), rows: Vec::new(), error: Some(e) }),
+    })
 }
 
 async fn health(State(state): State<AppState>) -> Json<HealthResponse> {
@@ -211,45 +776,85 @@ async fn health(State(state): State<AppState>) -> Json<HealthResponse> {
     })
 }
 
-async fn metrics(State(state): State<AppState>) -> Json<MetricsResponse> {
-    // Get namespace count from worker
-    let (tx, rx) = oneshot::channel();
-    let namespace_count = if state.tx.send(WorkerMsg::GetNamespaceCount { resp: tx }).await.is_ok() {
-        rx.await.unwrap_or(0)
-    } else {
-        0
-    };
+async fn openapi_json() -> Json<&'static serde_json::Value> {
+    Json(openapi::document())
+}
 
+async fn docs() -> axum::response::Html<&'static str> {
+    axum::response::Html(openapi::docs_page())
+}
+
+async fn metrics(State(state): State<AppState>) -> Json<MetricsResponse> {
+    // Answered here rather than by a worker, so metrics stay available under load
     Json(MetricsResponse {
-        namespaces: namespace_count,
+        namespaces: state.query_executor.list_namespaces().len(),
         requests_total: state.requests.load(std::sync::atomic::Ordering::Relaxed),
         uptime_secs: state.uptime(),
+        queue_depth: state.tx.max_capacity() - state.tx.capacity(),
+        queue_capacity: state.tx.max_capacity(),
+        workers: state.workers,
+        busy_workers: state.busy.load(std::sync::atomic::Ordering::Relaxed),
     })
 }
 
-async fn list_namespaces(State(state): State<AppState>) -> Json<NamespacesResponse> {
-    let (tx, rx) = oneshot::channel();
-    let namespaces = if state.tx.send(WorkerMsg::ListNamespaces { resp: tx }).await.is_ok() {
-        rx.await.unwrap_or_default()
-    } else {
-        Vec::new()
-    };
-    Json(NamespacesResponse { namespaces })
+/// The Prometheus text exposition of the server's metrics
+async fn metrics_prometheus(State(state): State<AppState>) -> Response {
+    let usage = state.query_executor.namespace_usage();
+    let labels: Vec<[(&str, &str); 1]> = usage.iter().map(|u| [("namespace", u.name.as_str())]).collect();
+
+    let mut exposition = metrics::Exposition::new();
+    exposition.value("liath_uptime_seconds", "Seconds since the server started", state.uptime() as f64);
+    exposition.value("liath_namespaces", "Namespaces, system namespaces included", usage.len() as f64);
+    exposition.value("liath_queue_depth", "Requests waiting for a worker", (state.tx.max_capacity() - state.tx.capacity()) as f64);
+    exposition.value("liath_queue_capacity", "Requests that may wait for a worker", state.tx.max_capacity() as f64);
+    exposition.value("liath_workers", "Worker threads", state.workers as f64);
+    exposition.value("liath_busy_workers", "Workers handling a request", state.busy.load(std::sync::atomic::Ordering::Relaxed) as f64);
+    exposition.gauge(
+        "liath_vector_index_size",
+        "Vectors in a namespace's index",
+        labels.iter().zip(&usage).map(|(l, u)| (&l[..], u.vectors as f64)),
+    );
+    exposition.gauge(
+        "liath_vector_index_capacity",
+        "Vectors a namespace's index has room for",
+        labels.iter().zip(&usage).map(|(l, u)| (&l[..], u.vector_capacity as f64)),
+    );
+    exposition.gauge(
+        "liath_disk_usage_bytes",
+        "Bytes a namespace's Fjall keyspace occupies on disk",
+        labels.iter().zip(&usage).map(|(l, u)| (&l[..], u.disk_bytes as f64)),
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")], exposition.finish()).into_response()
+}
+
+async fn list_namespaces(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+) -> Result<Json<NamespacesResponse>, DispatchError> {
+    let namespaces = state.call(|resp| WorkerMsg::ListNamespaces { user_id, resp }).await?;
+    Ok(Json(NamespacesResponse { namespaces }))
 }
 
 async fn create_namespace(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Json(payload): Json<CreateNamespaceRequest>,
-) -> Json<SuccessResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::CreateNamespace {
+) -> Response {
+    if !state.auth.read().unwrap().is_authorized_for(&user_id, "create_namespace", &payload.name) {
+        let message = format!("'{}' lacks the 'create_namespace' permission on '{}'", user_id, payload.name);
+        return forbidden(&state, &user_id, "create_namespace", Some(&payload.name), None, message);
+    }
+    let result = state.call(|resp| WorkerMsg::CreateNamespace {
         name: payload.name.clone(),
         dimensions: payload.dimensions,
         metric: payload.metric,
-        resp: tx,
+        encrypted: payload.encrypted,
+        user_id,
+        resp,
     }).await;
 
-    match rx.await {
+    match result {
         Ok(Ok(())) => Json(SuccessResponse {
             success: true,
             message: format!("Created namespace '{}'", payload.name),
@@ -258,276 +863,1023 @@ async fn create_namespace(
             success: false,
             message: e,
         }),
-        Err(_) => Json(SuccessResponse {
-            success: false,
-            message: "Worker communication error".to_string(),
-        }),
+        Err(e) => return e.into_response(),
     }
+    .into_response()
 }
 
 async fn delete_namespace_handler(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Path(name): Path<String>,
-) -> Json<SuccessResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::DeleteNamespace {
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::DeleteNamespace {
         name: name.clone(),
-        resp: tx,
-    }).await;
+        user_id,
+        resp,
+    }).await?;
 
-    match rx.await {
-        Ok(Ok(())) => Json(SuccessResponse {
+    Ok(match result {
+        Ok(()) => Json(SuccessResponse {
             success: true,
             message: format!("Deleted namespace '{}'", name),
         }),
-        Ok(Err(e)) => Json(SuccessResponse {
+        Err(e) => Json(SuccessResponse {
             success: false,
             message: e,
         }),
-        Err(_) => Json(SuccessResponse {
-            success: false,
-            message: "Worker communication error".to_string(),
-        }),
-    }
+    })
 }
 
 async fn kv_get(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Path((namespace, key)): Path<(String, String)>,
-) -> Json<KvGetResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::KvGet {
+) -> Result<Json<KvGetResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::KvGet {
         namespace,
         key: key.clone(),
-        resp: tx,
-    }).await;
+        user_id,
+        resp,
+    }).await?;
+
+    Ok(Json(KvGetResponse { key, value: result.ok().flatten() }))
+}
+
+/// Stream the entries under a prefix as NDJSON
+///
+/// Each line is `{"key", "value"}`. If `limit` cuts the scan short, the last line is
+/// `{"cursor"}` to resume from; a failure ends the stream with `{"error"}`.
+async fn kv_scan(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(namespace): Path<String>,
+    Query(params): Query<ScanQuery>,
+) -> Response {
+    let (tx, rx) = mpsc::channel(SCAN_STREAM_BUFFER);
+    if let Err(e) = state.submit(WorkerMsg::KvScan {
+        namespace,
+        prefix: params.prefix,
+        cursor: params.cursor,
+        limit: params.limit,
+        user_id,
+        items: tx,
+    }) {
+        return e.into_response();
+    }
+    ndjson_response(rx, |item| {
+        let line = match item {
+            ScanItem::Entry(entry) => serde_json::json!(entry),
+            ScanItem::Cursor(cursor) => serde_json::json!({ "cursor": cursor }),
+            ScanItem::Error(error) => serde_json::json!({ "error": error }),
+        };
+        format!("{}\n", line)
+    })
+}
+
+/// Stream what is sent to `rx` as an NDJSON response body, one `line` per item
+fn ndjson_response<T: Send + 'static>(rx: mpsc::Receiver<T>, line: fn(T) -> String) -> Response {
+    let stream = futures::stream::unfold(rx, move |mut rx| async move {
+        rx.recv().await.map(|item| (Ok::<_, std::convert::Infallible>(line(item)), rx))
+    });
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(stream)).into_response()
+}
+
+/// One message of a streamed scan
+pub(super) enum ScanItem {
+    Entry(ScanEntry),
+    /// Resumes the scan where `limit` cut it short; ends the stream
+    Cursor(Option<String>),
+    /// Ends the stream
+    Error(String),
+}
+
+/// Send scan entries to a stream until the scan, limit or receiver ends
+async fn stream_scan(mut entries: ScanIter, limit: Option<usize>, items: mpsc::Sender<ScanItem>) {
+    let mut sent = 0;
+    loop {
+        let cursor = entries.cursor();
+        let (item, last) = match entries.next() {
+            None => return,
+            Some(_) if limit.is_some_and(|limit| sent >= limit) => (ScanItem::Cursor(cursor), true),
+            Some(Ok(entry)) => (ScanItem::Entry(entry), false),
+            Some(Err(e)) => (ScanItem::Error(e.to_string()), true),
+        };
+        if items.send(item).await.is_err() || last {
+            return;
+        }
+        sent += 1;
+    }
+}
+
+/// Ingest NDJSON records into a namespace, streaming the progress back as NDJSON
+///
+/// Records are written in batches as they arrive. Every failed record yields
+/// `{"line", "key", "error"}` and every batch `{"processed", "written", "failed"}`.
+/// The stream ends with the totals and `"done": true`, or with `{"error"}` and the
+/// totals so far if the ingest had to stop.
+async fn bulk_ingest(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(namespace): Path<String>,
+    Query(params): Query<BulkQuery>,
+    body: Body,
+) -> Response {
+    if !state.query_executor.namespace_exists(&namespace) {
+        return auth_error(StatusCode::NOT_FOUND, format!("Namespace '{}' does not exist", namespace));
+    }
+    let batch_size = params.batch_size.unwrap_or(bulk::DEFAULT_BATCH_SIZE).clamp(1, bulk::MAX_BATCH_SIZE);
+    let (tx, rx) = mpsc::channel(SCAN_STREAM_BUFFER);
+    tokio::spawn(async move {
+        let mut progress = BulkProgress::default();
+        let result = ingest_records(&state, &namespace, &user_id, batch_size, params.expected, body, &mut progress, &tx).await;
+        let mut line = serde_json::json!(progress);
+        match result {
+            Ok(()) => line["done"] = true.into(),
+            Err(error) => line["error"] = error.into(),
+        }
+        let _ = tx.send(format!("{}\n", line)).await;
+    });
+    ndjson_response(rx, std::convert::identity)
+}
+
+/// Read bulk records from the request body and hand them to the workers batch by batch
+#[allow(clippy::too_many_arguments)]
+async fn ingest_records(
+    state: &AppState,
+    namespace: &str,
+    user_id: &str,
+    batch_size: usize,
+    mut reserve: usize,
+    body: Body,
+    progress: &mut BulkProgress,
+    lines: &mpsc::Sender<String>,
+) -> Result<(), String> {
+    let emit = |line: serde_json::Value| async move {
+        lines.send(format!("{}\n", line)).await.map_err(|_| "Client disconnected".to_string())
+    };
+    let mut chunks = body.into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut batch: Vec<(usize, BulkRecord)> = Vec::new();
+    let mut line_number = 0;
+    let mut ended = false;
+
+    while !ended {
+        match chunks.next().await {
+            Some(chunk) => {
+                let chunk = chunk.map_err(|e| format!("Failed to read request body: {}", e))?;
+                buffer.extend_from_slice(&chunk);
+            }
+            None => {
+                ended = true;
+                buffer.push(b'\n');
+            }
+        }
+        // Only complete lines are parsed; the rest waits for the next chunk
+        let Some(end) = buffer.iter().rposition(|&b| b == b'\n') else { continue };
+        let rest = buffer.split_off(end + 1);
+        let complete = std::mem::replace(&mut buffer, rest);
 
-    match rx.await {
-        Ok(Ok(value)) => Json(KvGetResponse { key, value }),
-        Ok(Err(_)) | Err(_) => Json(KvGetResponse { key, value: None }),
+        for line in complete[..end].split(|&b| b == b'\n') {
+            line_number += 1;
+            let line = String::from_utf8_lossy(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            match BulkRecord::parse(line.trim()) {
+                Ok(record) => batch.push((line_number, record)),
+                Err(e) => {
+                    progress.processed += 1;
+                    progress.failed += 1;
+                    emit(serde_json::json!(BulkError { line: line_number, key: None, error: format!("{:#}", e) })).await?;
+                }
+            }
+            if batch.len() >= batch_size {
+                let report = write_batch(state, namespace, user_id, std::mem::take(&mut batch), std::mem::take(&mut reserve), progress).await?;
+                emit_batch(&report, progress, lines).await?;
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let report = write_batch(state, namespace, user_id, batch, reserve, progress).await?;
+        emit_batch(&report, progress, lines).await?;
+    }
+    Ok(())
+}
+
+/// Have a worker write one batch and add its outcome to the running totals
+pub(super) async fn write_batch(
+    state: &AppState,
+    namespace: &str,
+    user_id: &str,
+    records: Vec<(usize, BulkRecord)>,
+    reserve: usize,
+    progress: &mut BulkProgress,
+) -> Result<BulkReport, String> {
+    let count = records.len();
+    let report = state.call_queued(|resp| WorkerMsg::BulkIngest {
+        namespace: namespace.to_string(),
+        records,
+        reserve,
+        user_id: user_id.to_string(),
+        resp,
+    }).await.map_err(|e| e.to_string())??;
+
+    progress.processed += count;
+    progress.written += report.written;
+    progress.failed += report.errors.len();
+    Ok(report)
+}
+
+/// Report the failures of a batch and the running totals as NDJSON
+async fn emit_batch(report: &BulkReport, progress: &BulkProgress, lines: &mpsc::Sender<String>) -> Result<(), String> {
+    let mut output = String::new();
+    for error in &report.errors {
+        output.push_str(&format!("{}\n", serde_json::json!(error)));
+    }
+    output.push_str(&format!("{}\n", serde_json::json!(progress)));
+    lines.send(output).await.map_err(|_| "Client disconnected".to_string())
+}
+
+/// Stream changes as server-sent events, or over a WebSocket if the client upgrades
+///
+/// Every change is one JSON message. SSE events carry the sequence number as their
+/// id, so a reconnecting `EventSource` resumes where it stopped.
+async fn watch(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Query(params): Query<WatchQuery>,
+    websocket: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    headers: HeaderMap,
+) -> Response {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let filter = ChangeFilter {
+        namespace: params.namespace,
+        prefix: params.prefix,
+        since: params.since.or(last_event_id),
+    };
+    let subscription = match state.query_executor.subscribe(filter, &user_id) {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            let status = match e.downcast_ref::<LiathError>() {
+                Some(LiathError::Unauthorized(_)) => StatusCode::FORBIDDEN,
+                Some(LiathError::InvalidInput(_)) => StatusCode::GONE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return auth_error(status, format!("{:#}", e));
+        }
+    };
+
+    match websocket {
+        Ok(websocket) => return websocket.on_upgrade(move |socket| watch_websocket(subscription, socket)),
+        Err(rejection) if headers.contains_key(header::UPGRADE) => return rejection.into_response(),
+        Err(_) => {}
+    }
+
+    let events = futures::stream::unfold(Some(subscription), |subscription| async move {
+        let mut subscription = subscription?;
+        let event = match subscription.next().await {
+            Ok(Some(change)) => Event::default()
+                .id(change.sequence.to_string())
+                .event(change.kind.to_string())
+                .data(serde_json::to_string(&change).unwrap_or_default()),
+            Ok(None) => return None,
+            Err(e) => return Some((Ok::<_, std::convert::Infallible>(Event::default().event("error").data(format!("{:#}", e))), None)),
+        };
+        Some((Ok(event), Some(subscription)))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// Push changes to a WebSocket client until either side closes
+async fn watch_websocket(mut subscription: Subscription, socket: WebSocket) {
+    let (mut sender, mut receiver) = socket.split();
+    let close = |code, reason: &'static str| Message::Close(Some(CloseFrame { code, reason: reason.into() }));
+
+    // Pings are answered by the socket itself; other client messages are ignored
+    let result = loop {
+        tokio::select! {
+            change = subscription.next() => match change {
+                Ok(Some(change)) => {
+                    if let Err(e) = sender.send(Message::Text(serde_json::to_string(&change).unwrap_or_default())).await {
+                        break Err(e);
+                    }
+                }
+                Ok(None) => break sender.send(close(close_code::AWAY, "Server shutting down")).await,
+                Err(e) => {
+                    let _ = sender.send(Message::Text(serde_json::json!({ "error": format!("{:#}", e) }).to_string())).await;
+                    break sender.send(close(close_code::ERROR, "Change feed failed")).await;
+                }
+            },
+            message = receiver.next() => match message {
+                Some(Ok(Message::Close(_))) | None => break Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break Err(e),
+            },
+        }
+    };
+    if let Err(e) = result {
+        tracing::debug!("WebSocket closed: {}", e);
     }
 }
 
 async fn kv_put(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Path((namespace, key)): Path<(String, String)>,
     Json(payload): Json<KvPutRequest>,
-) -> Json<SuccessResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::KvPut {
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::KvPut {
         namespace,
         key: key.clone(),
         value: payload.value,
-        resp: tx,
-    }).await;
+        user_id,
+        resp,
+    }).await?;
 
-    match rx.await {
-        Ok(Ok(())) => Json(SuccessResponse {
+    Ok(match result {
+        Ok(()) => Json(SuccessResponse {
             success: true,
             message: format!("Stored key '{}'", key),
         }),
-        Ok(Err(e)) => Json(SuccessResponse {
-            success: false,
-            message: e,
-        }),
-        Err(_) => Json(SuccessResponse {
-            success: false,
-            message: "Worker communication error".to_string(),
-        }),
-    }
+        Err(e) => match e.downcast::<LimitExceeded>() {
+            Ok(refusal) => return Err(refusal.into()),
+            Err(e) => Json(SuccessResponse {
+                success: false,
+                message: e.to_string(),
+            }),
+        },
+    })
 }
 
 async fn kv_delete(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Path((namespace, key)): Path<(String, String)>,
-) -> Json<SuccessResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::KvDelete {
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::KvDelete {
         namespace,
         key: key.clone(),
-        resp: tx,
-    }).await;
+        user_id,
+        resp,
+    }).await?;
 
-    match rx.await {
-        Ok(Ok(())) => Json(SuccessResponse {
+    Ok(match result {
+        Ok(()) => Json(SuccessResponse {
             success: true,
             message: format!("Deleted key '{}'", key),
         }),
-        Ok(Err(e)) => Json(SuccessResponse {
+        Err(e) => Json(SuccessResponse {
             success: false,
             message: e,
         }),
-        Err(_) => Json(SuccessResponse {
-            success: false,
-            message: "Worker communication error".to_string(),
-        }),
-    }
+    })
 }
 
 async fn semantic_search_handler(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Path(namespace): Path<String>,
     Json(payload): Json<SemanticSearchRequest>,
-) -> Json<SemanticSearchResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::SemanticSearch {
+) -> Result<Json<SemanticSearchResponse>, DispatchError> {
+    state.charge(&user_id, Resource::EmbeddingTokens, estimate_tokens(&payload.query))?;
+    let result = state.call(|resp| WorkerMsg::SemanticSearch {
         namespace,
         query: payload.query,
         k: payload.k,
-        resp: tx,
-    }).await;
+        resp,
+    }).await?;
 
-    match rx.await {
-        Ok(Ok(results)) => Json(SemanticSearchResponse {
+    Ok(match result {
+        Ok(results) => Json(SemanticSearchResponse {
             results: results.into_iter().map(|(id, content, distance)| {
                 SemanticSearchResult { id, content, distance }
             }).collect(),
         }),
-        _ => Json(SemanticSearchResponse { results: Vec::new() }),
-    }
+        Err(_) => Json(SemanticSearchResponse { results: Vec::new() }),
+    })
 }
 
 async fn embed_handler(
     State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
     Json(payload): Json<EmbedRequest>,
-) -> Json<EmbedResponse> {
-    let (tx, rx) = oneshot::channel();
-    let _ = state.tx.send(WorkerMsg::GenerateEmbeddings {
+) -> Result<Json<EmbedResponse>, DispatchError> {
+    state.charge(&user_id, Resource::EmbeddingTokens, payload.texts.iter().map(|t| estimate_tokens(t)).sum())?;
+    let result = state.call(|resp| WorkerMsg::GenerateEmbeddings {
         texts: payload.texts,
-        resp: tx,
-    }).await;
+        resp,
+    }).await?;
+
+    Ok(Json(EmbedResponse { embeddings: result.unwrap_or_default() }))
+}
+
+/// Audit entries filtered by time range and user, oldest first
+async fn audit_handler(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Query(params): Query<AuditQuery>,
+) -> Response {
+    let bound = |value: Option<String>| value.map(|v| audit::parse_time_bound(&v)).transpose();
+    let (since_ms, until_ms) = match (bound(params.since), bound(params.until)) {
+        (Ok(since_ms), Ok(until_ms)) => (since_ms, until_ms),
+        (Err(e), _) | (_, Err(e)) => return auth_error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let filter = AuditFilter {
+        since_ms,
+        until_ms,
+        user_id: params.user,
+        limit: params.limit.unwrap_or(audit::DEFAULT_QUERY_LIMIT),
+    };
 
-    match rx.await {
-        Ok(Ok(embeddings)) => Json(EmbedResponse { embeddings }),
-        _ => Json(EmbedResponse { embeddings: Vec::new() }),
+    match state.call(|resp| WorkerMsg::Audit { filter, user_id, resp }).await {
+        Ok(Ok(entries)) => Json(AuditResponse { entries }).into_response(),
+        Ok(Err(e)) => auth_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+        Err(e) => e.into_response(),
     }
 }
 
+async fn list_procedures(State(state): State<AppState>) -> Result<Json<ProceduresResponse>, DispatchError> {
+    let procedures = state.call(|resp| WorkerMsg::ListProcedures { resp }).await?;
+    Ok(Json(ProceduresResponse { procedures: procedures.unwrap_or_default() }))
+}
+
+async fn get_procedure(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Option<Procedure>>, DispatchError> {
+    let procedure = state.call(|resp| WorkerMsg::GetProcedure { name, resp }).await?;
+    Ok(Json(procedure.ok().flatten()))
+}
+
+async fn register_procedure(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+    Json(payload): Json<RegisterProcedureRequest>,
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::RegisterProcedure {
+        name: name.clone(),
+        code: payload.code,
+        description: payload.description,
+        user_id,
+        resp,
+    }).await?;
+
+    Ok(match result {
+        Ok(procedure) => Json(SuccessResponse {
+            success: true,
+            message: format!("Registered procedure '{}' version {}", name, procedure.version),
+        }),
+        Err(e) => Json(SuccessResponse {
+            success: false,
+            message: e,
+        }),
+    })
+}
+
+async fn delete_procedure(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::DeleteProcedure {
+        name: name.clone(),
+        user_id,
+        resp,
+    }).await?;
+
+    Ok(match result {
+        Ok(()) => Json(SuccessResponse {
+            success: true,
+            message: format!("Deleted procedure '{}'", name),
+        }),
+        Err(e) => Json(SuccessResponse {
+            success: false,
+            message: e,
+        }),
+    })
+}
+
+async fn call_procedure(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+    Json(payload): Json<CallProcedureRequest>,
+) -> Result<Json<CallProcedureResponse>, DispatchError> {
+    state.requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let result = state.call(|resp| WorkerMsg::CallProcedure {
+        name,
+        args: payload.args,
+        user_id,
+        resp,
+    }).await?;
+
+    Ok(match result {
+        Ok(value) => Json(CallProcedureResponse { success: true, result: Some(value), error: None }),
+        Err(e) => Json(CallProcedureResponse { success: false, result: None, error: Some(e) }),
+    })
+}
+
+async fn list_jobs(State(state): State<AppState>) -> Result<Json<JobsResponse>, DispatchError> {
+    let jobs = state.call(|resp| WorkerMsg::ListJobs { resp }).await?.unwrap_or_default();
+    Ok(Json(JobsResponse {
+        jobs: jobs.into_iter().map(|(job, status)| JobInfo { job, status }).collect(),
+    }))
+}
+
+fn job_response(result: Result<Result<(), String>, DispatchError>, message: String) -> Result<Json<SuccessResponse>, DispatchError> {
+    Ok(match result? {
+        Ok(()) => Json(SuccessResponse { success: true, message }),
+        Err(e) => Json(SuccessResponse { success: false, message: e }),
+    })
+}
+
+async fn put_job(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+    Json(payload): Json<PutJobRequest>,
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::PutJob {
+        name: name.clone(),
+        schedule: payload.schedule,
+        action: payload.action,
+        user_id,
+        resp,
+    }).await;
+    job_response(result, format!("Scheduled job '{}'", name))
+}
+
+async fn delete_job(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::DeleteJob {
+        name: name.clone(),
+        user_id,
+        resp,
+    }).await;
+    job_response(result, format!("Removed job '{}'", name))
+}
+
+async fn enable_job(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::SetJobEnabled {
+        name: name.clone(),
+        enabled: true,
+        user_id,
+        resp,
+    }).await;
+    job_response(result, format!("Enabled job '{}'", name))
+}
+
+async fn disable_job(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+) -> Result<Json<SuccessResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::SetJobEnabled {
+        name: name.clone(),
+        enabled: false,
+        user_id,
+        resp,
+    }).await;
+    job_response(result, format!("Disabled job '{}'", name))
+}
+
+async fn run_job(
+    State(state): State<AppState>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    Path(name): Path<String>,
+) -> Result<Json<RunJobResponse>, DispatchError> {
+    let result = state.call(|resp| WorkerMsg::RunJob {
+        name,
+        user_id,
+        resp,
+    }).await?;
+
+    Ok(match result {
+        Ok(status) => Json(RunJobResponse { success: true, status: Some(status), error: None }),
+        Err(e) => Json(RunJobResponse { success: false, status: None, error: Some(e) }),
+    })
+}
+
 // ========== Server ==========
 
+/// Sizing and limits of the server's worker pool
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Threads running queries, scripts and storage calls
+    pub workers: usize,
+    /// Requests that may wait for a worker; further requests get 503
+    pub queue_size: usize,
+    /// Longest a request may wait and run before it gets 504
+    pub request_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            queue_size: 64,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A method, path and handler described in the OpenAPI document
+pub(super) type Route = (Method, &'static str, MethodRouter<AppState>);
+
+fn route<H, T>(method: Method, path: &'static str, handler: H) -> Route
+where
+    H: Handler<T, AppState>,
+    T: 'static,
+{
+    let filter = MethodFilter::try_from(method.clone()).expect("routes use standard methods");
+    (method, path, on(filter, handler))
+}
+
+/// Routes that need an API key; `openapi::operations` must describe each one
+pub(super) fn routes() -> Vec<Route> {
+    use Method as M;
+    vec![
+        route(M::POST, "/query", execute_query),
+        route(M::POST, "/sql", execute_sql),
+        route(M::GET, "/metrics", metrics),
+        route(M::GET, "/metrics/prometheus", metrics_prometheus),
+        route(M::GET, "/namespaces", list_namespaces),
+        route(M::POST, "/namespaces", create_namespace),
+        route(M::DELETE, "/namespaces/:name", delete_namespace_handler),
+        route(M::GET, "/kv/:namespace", kv_scan),
+        route(M::GET, "/kv/:namespace/:key", kv_get),
+        route(M::PUT, "/kv/:namespace/:key", kv_put),
+        route(M::DELETE, "/kv/:namespace/:key", kv_delete),
+        route(M::POST, "/bulk/:namespace", bulk_ingest),
+        route(M::POST, "/semantic/:namespace", semantic_search_handler),
+        route(M::POST, "/embed", embed_handler),
+        route(M::GET, "/audit", audit_handler),
+        route(M::GET, "/watch", watch),
+        route(M::GET, "/procedures", list_procedures),
+        route(M::GET, "/procedures/:name", get_procedure),
+        route(M::PUT, "/procedures/:name", register_procedure),
+        route(M::POST, "/procedures/:name", call_procedure),
+        route(M::DELETE, "/procedures/:name", delete_procedure),
+        route(M::GET, "/jobs", list_jobs),
+        route(M::PUT, "/jobs/:name", put_job),
+        route(M::DELETE, "/jobs/:name", delete_job),
+        route(M::POST, "/jobs/:name/run", run_job),
+        route(M::POST, "/jobs/:name/enable", enable_job),
+        route(M::POST, "/jobs/:name/disable", disable_job),
+        route(M::GET, "/agents", agents::list_agents),
+        route(M::POST, "/agents", agents::create_agent),
+        route(M::GET, "/agents/:agent", agents::get_agent),
+        route(M::DELETE, "/agents/:agent", agents::delete_agent),
+        route(M::GET, "/agents/:agent/memories", agents::recall_memories),
+        route(M::POST, "/agents/:agent/memories", agents::store_memory),
+        route(M::GET, "/agents/:agent/memories/:memory", agents::get_memory),
+        route(M::DELETE, "/agents/:agent/memories/:memory", agents::forget_memory),
+        route(M::GET, "/agents/:agent/conversations", agents::list_conversations),
+        route(M::POST, "/agents/:agent/conversations", agents::create_conversation),
+        route(M::GET, "/agents/:agent/conversations/:conversation", agents::get_conversation),
+        route(M::GET, "/agents/:agent/conversations/:conversation/messages", agents::list_messages),
+        route(M::POST, "/agents/:agent/conversations/:conversation/messages", agents::add_message),
+        route(M::GET, "/agents/:agent/tools/:tool/state/:key", agents::get_tool_state),
+        route(M::PUT, "/agents/:agent/tools/:tool/state/:key", agents::put_tool_state),
+        route(M::DELETE, "/agents/:agent/tools/:tool/state/:key", agents::delete_tool_state),
+    ]
+}
+
+/// Routes answered without an API key
+pub(super) fn public_routes() -> Vec<Route> {
+    vec![route(Method::GET, "/health", health), route(Method::GET, "/openapi.json", openapi_json)]
+}
+
 pub async fn run_server(port: u16, query_executor: QueryExecutor) -> anyhow::Result<()> {
-    // channel between axum handlers and the worker
-    let (tx, mut rx) = mpsc::channel::<WorkerMsg>(64);
-
-    // spawn a local task for the worker on the current runtime
-    let local = tokio::task::LocalSet::new();
-    local.spawn_local(async move {
-        while let Some(msg) = rx.recv().await {
-            match msg {
-                WorkerMsg::Execute { query, user_id, resp } => {
-                    let out = query_executor
-                        .execute(&query, &user_id)
+    let scheduler = Scheduler::new(query_executor.clone());
+    run_server_with_scheduler(port, query_executor, scheduler).await
+}
+
+/// Run the server with a caller-provided scheduler (e.g. one with Rust callbacks registered)
+///
+/// The agent endpoints need the database itself and answer 501 here; use
+/// [`run_server_with_db`] to serve them.
+pub async fn run_server_with_scheduler(port: u16, query_executor: QueryExecutor, scheduler: Scheduler) -> anyhow::Result<()> {
+    serve(port, query_executor, scheduler, None, ServerConfig::default()).await
+}
+
+/// Run the server for an embedded database, including the `/agents` endpoints
+pub async fn run_server_with_db(port: u16, db: Arc<EmbeddedLiath>) -> anyhow::Result<()> {
+    run_server_with_config(port, db, ServerConfig::default()).await
+}
+
+/// Run the server for an embedded database with a sized worker pool
+pub async fn run_server_with_config(port: u16, db: Arc<EmbeddedLiath>, config: ServerConfig) -> anyhow::Result<()> {
+    serve(port, db.query_executor(), db.scheduler(), Some(db), config).await
+}
+
+/// What a worker thread needs to answer requests
+#[derive(Clone)]
+struct Worker {
+    query_executor: QueryExecutor,
+    scheduler: Scheduler,
+    db: Option<Arc<EmbeddedLiath>>,
+}
+
+impl Worker {
+    async fn handle(&self, msg: WorkerMsg) {
+        let Worker { query_executor, scheduler, db } = self;
+        match msg {
+            WorkerMsg::Execute { query, user_id, explain, resp } => {
+                let out = if explain {
+                    query_executor
+                        .execute_profiled(&query, &user_id)
                         .await
-                        .unwrap_or_else(|e| format!("Error: {}", e));
-                    let _ = resp.send(out);
-                }
-                WorkerMsg::GetNamespaceCount { resp } => {
-                    let count = query_executor.list_namespaces().len();
-                    let _ = resp.send(count);
-                }
-                WorkerMsg::ListNamespaces { resp } => {
-                    let namespaces = query_executor.list_namespaces();
-                    let _ = resp.send(namespaces);
-                }
-                WorkerMsg::CreateNamespace { name, dimensions, metric, resp } => {
-                    #[cfg(feature = "vector")]
-                    {
-                        use usearch::{MetricKind, ScalarKind};
-                        let metric_kind = match metric.to_lowercase().as_str() {
-                            "euclidean" | "l2" => MetricKind::L2sq,
-                            _ => MetricKind::Cos,
-                        };
-                        let result = query_executor.create_namespace(&name, dimensions, metric_kind, ScalarKind::F32)
-                            .map_err(|e| e.to_string());
-                        let _ = resp.send(result);
-                    }
-                    #[cfg(not(feature = "vector"))]
-                    {
-                        let _ = (dimensions, metric);
-                        let _ = resp.send(Err("Vector feature not enabled".to_string()));
-                    }
-                }
-                WorkerMsg::DeleteNamespace { name, resp } => {
-                    let result = query_executor.delete_namespace(&name)
-                        .map_err(|e| e.to_string());
-                    let _ = resp.send(result);
-                }
-                WorkerMsg::KvGet { namespace, key, resp } => {
-                    let result = query_executor.get(&namespace, key.as_bytes())
-                        .map(|opt| opt.map(|v| String::from_utf8_lossy(&v).to_string()))
+                        .map(|(result, profile)| (result, Some(profile)))
+                } else {
+                    query_executor.execute(&query, &user_id).await.map(|result| (result, None))
+                };
+                let _ = resp.send(out.unwrap_or_else(|e| (format!("Error: {}", e), None)));
+            }
+            WorkerMsg::ListNamespaces { user_id, resp } => {
+                let namespaces = query_executor.visible_namespaces(&user_id);
+                let _ = resp.send(namespaces);
+            }
+            WorkerMsg::CreateNamespace { name, dimensions, metric, encrypted, user_id, resp } => {
+                #[cfg(feature = "vector")]
+                {
+                    use usearch::{MetricKind, ScalarKind};
+                    let metric_kind = match metric.to_lowercase().as_str() {
+                        "euclidean" | "l2" => MetricKind::L2sq,
+                        _ => MetricKind::Cos,
+                    };
+                    let result = query_executor.create_namespace_as(&name, dimensions, metric_kind, ScalarKind::F32, encrypted, &user_id)
                         .map_err(|e| e.to_string());
                     let _ = resp.send(result);
                 }
-                WorkerMsg::KvPut { namespace, key, value, resp } => {
-                    let result = query_executor.put(&namespace, key.as_bytes(), value.as_bytes())
-                        .map_err(|e| e.to_string());
-                    let _ = resp.send(result);
+                #[cfg(not(feature = "vector"))]
+                {
+                    let _ = (dimensions, metric, encrypted, user_id);
+                    let _ = resp.send(Err("Vector feature not enabled".to_string()));
                 }
-                WorkerMsg::KvDelete { namespace, key, resp } => {
-                    let result = query_executor.delete(&namespace, key.as_bytes())
-                        .map_err(|e| e.to_string());
-                    let _ = resp.send(result);
+            }
+            WorkerMsg::DeleteNamespace { name, user_id, resp } => {
+                let result = query_executor.delete_namespace_as(&name, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::KvGet { namespace, key, user_id, resp } => {
+                let result = query_executor.get_as(&namespace, key.as_bytes(), &user_id)
+                    .map(|opt| opt.map(|v| String::from_utf8_lossy(&v).to_string()))
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::KvPut { namespace, key, value, user_id, resp } => {
+                let result = query_executor.put_as(&namespace, key.as_bytes(), value.as_bytes(), &user_id);
+                let _ = resp.send(result);
+            }
+            WorkerMsg::KvDelete { namespace, key, user_id, resp } => {
+                let result = query_executor.delete_as(&namespace, key.as_bytes(), &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::KvScan { namespace, prefix, cursor, limit, user_id, items } => {
+                match query_executor.scan_iter(&namespace, &prefix, cursor.as_deref(), &user_id) {
+                    Ok(entries) => {
+                        tokio::task::spawn_local(stream_scan(entries, limit, items));
+                    }
+                    Err(e) => {
+                        let _ = items.send(ScanItem::Error(format!("{:#}", e))).await;
+                    }
                 }
-                WorkerMsg::SemanticSearch { namespace, query, k, resp } => {
-                    // Generate embedding
-                    let result = match query_executor.generate_embedding(vec![query.as_str()]) {
-                        Ok(embeddings) => {
-                            match embeddings.into_iter().next() {
-                                Some(query_vec) => {
-                                    match query_executor.similarity_search(&namespace, &query_vec, k) {
-                                        Ok(results) => {
-                                            // Get content for each result using ID mapping
-                                            let mut output = Vec::new();
-                                            for (id, distance) in results {
-                                                let mapping_key = format!("_vidx:{}", id);
-                                                let content = if let Ok(Some(key)) = query_executor.get(&namespace, mapping_key.as_bytes()) {
-                                                    if let Ok(Some(data)) = query_executor.get(&namespace, &key) {
-                                                        String::from_utf8_lossy(&data).to_string()
-                                                    } else {
-                                                        String::new()
-                                                    }
+            }
+            WorkerMsg::BulkIngest { namespace, records, reserve, user_id, resp } => {
+                let result = query_executor.bulk_ingest(&namespace, records, reserve, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::SemanticSearch { namespace, query, k, resp } => {
+                // Generate embedding
+                let result = match query_executor.generate_embedding(vec![query.as_str()]) {
+                    Ok(embeddings) => {
+                        match embeddings.into_iter().next() {
+                            Some(query_vec) => {
+                                match query_executor.similarity_search(&namespace, &query_vec, k) {
+                                    Ok(results) => {
+                                        // Get content for each result using ID mapping
+                                        let mut output = Vec::new();
+                                        for (id, distance) in results {
+                                            let mapping_key = format!("_vidx:{}", id);
+                                            let content = if let Ok(Some(key)) = query_executor.get(&namespace, mapping_key.as_bytes()) {
+                                                if let Ok(Some(data)) = query_executor.get(&namespace, &key) {
+                                                    String::from_utf8_lossy(&data).to_string()
                                                 } else {
                                                     String::new()
-                                                };
-                                                output.push((id, content, distance));
-                                            }
-                                            Ok(output)
+                                                }
+                                            } else {
+                                                String::new()
+                                            };
+                                            output.push((id, content, distance));
                                         }
-                                        Err(e) => Err(e.to_string()),
+                                        Ok(output)
                                     }
+                                    Err(e) => Err(e.to_string()),
                                 }
-                                None => Err("Failed to generate embedding".to_string()),
                             }
+                            None => Err("Failed to generate embedding".to_string()),
                         }
-                        Err(e) => Err(e.to_string()),
-                    };
-                    let _ = resp.send(result);
-                }
-                WorkerMsg::GenerateEmbeddings { texts, resp } => {
-                    let text_refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
-                    let result = query_executor.generate_embedding(text_refs)
-                        .map_err(|e| e.to_string());
-                    let _ = resp.send(result);
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
+                let _ = resp.send(result);
+            }
+            WorkerMsg::GenerateEmbeddings { texts, resp } => {
+                let text_refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
+                let result = query_executor.generate_embedding(text_refs)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            #[cfg(feature = "grpc")]
+            WorkerMsg::AddVectors { namespace, vectors, resp } => {
+                let count = vectors.len();
+                let result = vectors.into_iter().enumerate().try_for_each(|(added, (id, vector))| {
+                    query_executor.add_vector(&namespace, id, &vector)
+                        .map_err(|e| format!("Vector {} failed after {} were added: {}", id, added, e))
+                });
+                let _ = resp.send(result.map(|()| count));
+            }
+            #[cfg(feature = "grpc")]
+            WorkerMsg::SearchVectors { namespace, vector, k, resp } => {
+                let result = query_executor.similarity_search(&namespace, &vector, k)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::Audit { filter, user_id, resp } => {
+                let result = query_executor.audit_log(&filter, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::ListProcedures { resp } => {
+                let result = query_executor.list_procedures()
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::GetProcedure { name, resp } => {
+                let result = query_executor.get_procedure(&name)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::RegisterProcedure { name, code, description, user_id, resp } => {
+                let result = query_executor
+                    .register_procedure(&name, &code, description.as_deref(), &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::DeleteProcedure { name, user_id, resp } => {
+                let result = query_executor.delete_procedure(&name, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::Sql { query, user_id, resp } => {
+                let result = query_executor
+                    .query(&query, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::CallProcedure { name, args, user_id, resp } => {
+                let result = query_executor
+                    .call_procedure(&name, args, &user_id)
+                    .await
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::ListJobs { resp } => {
+                let result = scheduler.list_jobs()
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::PutJob { name, schedule, action, user_id, resp } => {
+                let result = scheduler.add_job(&name, schedule, action, &user_id)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::DeleteJob { name, user_id, resp } => {
+                let result = scheduler.remove_job(&name, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::SetJobEnabled { name, enabled, user_id, resp } => {
+                let result = scheduler.set_enabled(&name, enabled, &user_id)
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::RunJob { name, user_id, resp } => {
+                let result = scheduler.run_job(&name, &user_id)
+                    .await
+                    .map_err(|e| e.to_string());
+                let _ = resp.send(result);
+            }
+            WorkerMsg::Agent { op, user_id, resp } => {
+                let result = match db {
+                    Some(db) => agents::execute(db, query_executor, &user_id, op),
+                    None => Err((StatusCode::NOT_IMPLEMENTED, "Agent endpoints need a server started with the database".to_string())),
+                };
+                let _ = resp.send(result);
+            }
+            #[cfg(feature = "mcp")]
+            WorkerMsg::Mcp { messages, user_id, subscriptions, resp } => {
+                use crate::mcp::LiathService;
+                let service = match db {
+                    Some(db) => LiathService::with_db(db.clone(), user_id),
+                    None => LiathService::new(query_executor.clone(), user_id),
+                };
+                let service = service.with_subscriptions(subscriptions);
+                let mut replies = Vec::new();
+                for message in messages {
+                    replies.extend(crate::mcp::handle_message(&service, message).await);
                 }
+                let _ = resp.send(replies);
             }
         }
-    });
+    }
+}
+
+/// Run `task` on a new thread with its own single-threaded runtime
+///
+/// Scripts and storage calls block, so they run on these threads rather than
+/// on the runtime accepting connections.
+fn spawn_runtime_thread<F, Fut>(name: String, task: F) -> anyhow::Result<()>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()>,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    std::thread::Builder::new().name(name).spawn(move || {
+        let local = tokio::task::LocalSet::new();
+        local.block_on(&runtime, task());
+    })?;
+    Ok(())
+}
 
-    let app_state = AppState::new(tx);
-
-    let app = Router::new()
-        .route("/query", post(execute_query))
-        .route("/health", get(health))
-        .route("/metrics", get(metrics))
-        .route("/namespaces", get(list_namespaces))
-        .route("/namespaces", post(create_namespace))
-        .route("/namespaces/{name}", delete(delete_namespace_handler))
-        .route("/kv/{namespace}/{key}", get(kv_get))
-        .route("/kv/{namespace}/{key}", put(kv_put))
-        .route("/kv/{namespace}/{key}", delete(kv_delete))
-        .route("/semantic/{namespace}", post(semantic_search_handler))
-        .route("/embed", post(embed_handler))
-        .with_state(app_state);
+async fn serve(
+    port: u16,
+    query_executor: QueryExecutor,
+    scheduler: Scheduler,
+    db: Option<Arc<EmbeddedLiath>>,
+    config: ServerConfig,
+) -> anyhow::Result<()> {
+    // bounded queue between axum handlers and the worker threads
+    let (tx, rx) = mpsc::channel::<WorkerMsg>(config.queue_size.max(1));
+    let app_state = AppState::new(tx, query_executor.clone(), &config);
+
+    let worker = Worker { query_executor, scheduler: scheduler.clone(), db };
+    let rx = Arc::new(tokio::sync::Mutex::new(rx));
+    for i in 0..config.workers.max(1) {
+        let (worker, rx, busy) = (worker.clone(), rx.clone(), app_state.busy.clone());
+        spawn_runtime_thread(format!("liath-worker-{}", i), move || async move {
+            loop {
+                let Some(msg) = rx.lock().await.recv().await else {
+                    break;
+                };
+                // The handler already answered 504; don't run work nobody will see
+                if msg.is_abandoned() {
+                    continue;
+                }
+                busy.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                worker.handle(msg).await;
+                busy.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+            }
+        })?;
+    }
+    spawn_runtime_thread("liath-scheduler".to_string(), move || scheduler.run())?;
+
+    let mut app = Router::new();
+    for (_, path, handler) in routes() {
+        app = app.route(path, handler);
+    }
+    // MCP over HTTP; the tools check the caller's permissions like the executor does
+    #[cfg(feature = "mcp")]
+    let app = app.merge(super::mcp::router());
+    let mut app = app.route_layer(middleware::from_fn_with_state(app_state.clone(), authorize));
+    for (_, path, handler) in public_routes() {
+        app = app.route(path, handler);
+    }
+    let app = app
+        .route("/docs", get(docs))
+        .with_state(app_state.clone());
+    // gRPC calls authenticate themselves from their metadata
+    #[cfg(feature = "grpc")]
+    let app = app.merge(super::grpc::router(app_state));
+    let app = app.layer(middleware::from_fn(record_request));
 
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
     println!("Liath DB Server listening on {}", addr);
@@ -535,10 +1887,7 @@ pub async fn run_server(port: u16, query_executor: QueryExecutor) -> anyhow::Res
     // axum 0.7 style: use TcpListener and axum::serve
     let listener = tokio::net::TcpListener::bind(&addr).await?;
 
-    // Run the server within the LocalSet so both the server and the local worker can make progress
-    local
-        .run_until(async move { axum::serve(listener, app).await })
-        .await?;
+    axum::serve(listener, app).await?;
 
     Ok(())
 }
@@ -0,0 +1,308 @@
+//! Generates the Rust client in `src/client/generated.rs` from the OpenAPI document
+//!
+//! The generated file is checked in so the `client` feature builds without the
+//! server. `test_generated_client_is_current` fails when the API changed and the
+//! file was not regenerated; run it with `LIATH_UPDATE_CLIENT=1` to rewrite it:
+//!
+//! ```text
+//! LIATH_UPDATE_CLIENT=1 cargo test --features server generated_client
+//! ```
+
+use serde_json::Value;
+use std::fmt::Write as _;
+
+const REF_PREFIX: &str = "#/components/schemas/";
+
+/// Rust source of the client types and methods for `document`
+pub(super) fn rust_client(document: &Value) -> String {
+    let mut out = String::from(concat!(
+        "//! Generated from the server's OpenAPI document by `server::codegen`; do not edit.\n",
+        "//!\n",
+        "//! Regenerate with `LIATH_UPDATE_CLIENT=1 cargo test --features server generated_client`.\n",
+        "\n",
+        "use anyhow::Result;\n",
+        "use reqwest::Method;\n",
+        "use serde::{Deserialize, Serialize};\n",
+        "use super::{ndjson_body, segment, Client};\n",
+    ));
+
+    for (name, schema) in document["components"]["schemas"].as_object().into_iter().flatten() {
+        out.push('\n');
+        if schema.get("enum").is_some() {
+            string_enum(&mut out, name, schema);
+        } else if schema.get("oneOf").is_some() {
+            tagged_enum(&mut out, name, schema);
+        } else {
+            structure(&mut out, name, schema);
+        }
+    }
+
+    out.push_str("\nimpl Client {\n");
+    let mut first = true;
+    for (path, item) in document["paths"].as_object().into_iter().flatten() {
+        for (method, operation) in item.as_object().into_iter().flatten() {
+            let mut rendered = String::new();
+            if method_source(&mut rendered, method, path, operation) {
+                if !first {
+                    out.push('\n');
+                }
+                first = false;
+                out.push_str(&rendered);
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn structure(out: &mut String, name: &str, schema: &Value) {
+    doc_comment(out, "", &schema["description"]);
+    // An `allOf` of references and one object is a struct with flattened parts
+    let mut flattened = Vec::new();
+    let mut object = schema;
+    for part in schema["allOf"].as_array().into_iter().flatten() {
+        match part["$ref"].as_str() {
+            Some(reference) => flattened.push(reference.trim_start_matches(REF_PREFIX)),
+            None => object = part,
+        }
+    }
+
+    let fields = fields(object, "    ", "pub ");
+    let defaultable = flattened.is_empty() && fields.iter().all(|(ty, _)| has_default(ty));
+    let derives = if defaultable { "Debug, Clone, Default, PartialEq, Serialize, Deserialize" } else { "Debug, Clone, PartialEq, Serialize, Deserialize" };
+    let _ = writeln!(out, "#[derive({})]\npub struct {} {{", derives, name);
+    for part in flattened {
+        let _ = writeln!(out, "    #[serde(flatten)]\n    pub {}: {},", snake_case(part), part);
+    }
+    for (_, source) in fields {
+        out.push_str(&source);
+    }
+    out.push_str("}\n");
+}
+
+fn string_enum(out: &mut String, name: &str, schema: &Value) {
+    doc_comment(out, "", &schema["description"]);
+    let _ = writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]\npub enum {} {{", name);
+    for value in schema["enum"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+        let _ = writeln!(out, "    #[serde(rename = \"{}\")]\n    {},", value, camel_case(value));
+    }
+    out.push_str("}\n");
+}
+
+/// A `oneOf` told apart by a tag property, as an internally tagged enum
+fn tagged_enum(out: &mut String, name: &str, schema: &Value) {
+    let tag = schema["discriminator"]["propertyName"].as_str().unwrap_or("type");
+    doc_comment(out, "", &schema["description"]);
+    let _ = writeln!(out, "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n#[serde(tag = \"{}\")]\npub enum {} {{", tag, name);
+    for variant in schema["oneOf"].as_array().into_iter().flatten() {
+        let value = variant["properties"][tag]["enum"][0].as_str().unwrap_or_default();
+        doc_comment(out, "    ", &variant["description"]);
+        let _ = writeln!(out, "    #[serde(rename = \"{}\")]\n    {} {{", value, camel_case(value));
+        let mut variant = variant.clone();
+        variant["properties"].as_object_mut().map(|properties| properties.remove(tag));
+        for (_, source) in fields(&variant, "        ", "") {
+            out.push_str(&source);
+        }
+        out.push_str("    },\n");
+    }
+    out.push_str("}\n");
+}
+
+/// The Rust type and source of each property of an object schema
+fn fields(object: &Value, indent: &str, visibility: &str) -> Vec<(String, String)> {
+    let required: Vec<&str> = object["required"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    let mut fields = Vec::new();
+    for (name, schema) in object["properties"].as_object().into_iter().flatten() {
+        let mut source = String::new();
+        doc_comment(&mut source, indent, &schema["description"]);
+        let mut ty = rust_type(schema);
+        if !required.contains(&name.as_str()) {
+            ty = format!("Option<{}>", ty);
+            let _ = writeln!(source, "{}#[serde(default, skip_serializing_if = \"Option::is_none\")]", indent);
+        } else if schema["nullable"] == true {
+            ty = format!("Option<{}>", ty);
+        }
+        let _ = writeln!(source, "{}{}{}: {},", indent, visibility, field_name(name), ty);
+        fields.push((ty, source));
+    }
+    fields
+}
+
+/// The Rust type of a schema, ignoring whether it is nullable
+fn rust_type(schema: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return reference.trim_start_matches(REF_PREFIX).to_string();
+    }
+    if let Some([inner]) = schema["allOf"].as_array().map(Vec::as_slice) {
+        return rust_type(inner);
+    }
+    let unsigned = schema["minimum"].as_u64() == Some(0);
+    match (schema["type"].as_str(), schema["format"].as_str()) {
+        (Some("string"), _) => "String".to_string(),
+        (Some("boolean"), _) => "bool".to_string(),
+        (Some("integer"), Some("int32")) => if unsigned { "u32" } else { "i32" }.to_string(),
+        (Some("integer"), _) => if unsigned { "u64" } else { "i64" }.to_string(),
+        (Some("number"), Some("float")) => "f32".to_string(),
+        (Some("number"), _) => "f64".to_string(),
+        (Some("array"), _) => format!("Vec<{}>", rust_type(&schema["items"])),
+        _ => "serde_json::Value".to_string(),
+    }
+}
+
+/// Whether a field of this type can take its `Default`
+fn has_default(ty: &str) -> bool {
+    ["Option<", "Vec<", "String", "bool", "u32", "u64", "i32", "i64", "f32", "f64", "serde_json::Value"]
+        .iter()
+        .any(|prefix| ty.starts_with(prefix))
+}
+
+/// Render one client method; routes the client cannot speak are skipped
+fn method_source(out: &mut String, method: &str, path: &str, operation: &Value) -> bool {
+    let Some((_, response)) = operation["responses"].as_object().into_iter().flatten().find(|(status, _)| status.starts_with('2')) else {
+        return false;
+    };
+    let (content_type, schema) = match response["content"].as_object().and_then(|content| content.iter().next()) {
+        Some((content_type, media)) => (content_type.as_str(), &media["schema"]),
+        None => ("application/json", &Value::Null),
+    };
+    let (return_type, reader) = match content_type {
+        "application/json" if schema["nullable"] == true => (format!("Option<{}>", rust_type(schema)), "json"),
+        "application/json" => (rust_type(schema), "json"),
+        "application/x-ndjson" => (format!("Vec<{}>", rust_type(schema)), "ndjson"),
+        "text/plain" => ("String".to_string(), "text"),
+        _ => return false,
+    };
+
+    let mut arguments = vec!["&self".to_string()];
+    let mut path_arguments = Vec::new();
+    let mut query = None;
+    for parameter in operation["parameters"].as_array().into_iter().flatten() {
+        let name = parameter["name"].as_str().unwrap_or_default();
+        if parameter["in"] == "path" {
+            let ty = rust_type(&parameter["schema"]);
+            if ty == "String" {
+                arguments.push(format!("{}: &str", name));
+                path_arguments.push(format!("segment({})", name));
+            } else {
+                arguments.push(format!("{}: {}", name, ty));
+                path_arguments.push(name.to_string());
+            }
+        }
+    }
+    if let Some(reference) = operation.get("x-query") {
+        let ty = rust_type(reference);
+        arguments.push(format!("query: &{}", ty));
+        query = Some(ty);
+    }
+    let mut body = None;
+    if let Some((body_type, media)) = operation["requestBody"]["content"].as_object().and_then(|c| c.iter().next()) {
+        let ty = rust_type(&media["schema"]);
+        if body_type == "application/x-ndjson" {
+            arguments.push(format!("records: &[{}]", ty));
+            body = Some("ndjson");
+        } else {
+            arguments.push(format!("body: &{}", ty));
+            body = Some("json");
+        }
+    }
+
+    let summary = operation["summary"].as_str().unwrap_or_default();
+    let _ = writeln!(out, "    /// {}\n    ///", summary);
+    match operation["x-permission"].as_str() {
+        Some(permission) => {
+            let _ = writeln!(out, "    /// `{} {}`; requires the `{}` permission.", method.to_uppercase(), path, permission);
+        }
+        None => {
+            let _ = writeln!(out, "    /// `{} {}`", method.to_uppercase(), path);
+        }
+    }
+    let id = operation["operationId"].as_str().unwrap_or_default();
+    let _ = writeln!(out, "    pub async fn {}({}) -> Result<{}> {{", id, arguments.join(", "), return_type);
+
+    let path_expression = if path_arguments.is_empty() {
+        format!("\"{}\"", path)
+    } else {
+        let mut pattern = String::new();
+        for segment in path.split('/').skip(1) {
+            pattern.push('/');
+            pattern.push_str(if segment.starts_with('{') { "{}" } else { segment });
+        }
+        let _ = writeln!(out, "        let path = format!(\"{}\", {});", pattern, path_arguments.join(", "));
+        "&path".to_string()
+    };
+    let mut request = format!("self.request(Method::{}, {})", method.to_uppercase(), path_expression);
+    if query.is_some() {
+        request.push_str(".query(query)");
+    }
+    match body {
+        Some("json") => request.push_str(".json(body)"),
+        Some(_) => request = format!("ndjson_body({}, records)?", request),
+        None => {}
+    }
+    let _ = writeln!(out, "        let request = {};", request);
+    let _ = writeln!(out, "        Self::{}(request).await\n    }}", reader);
+    true
+}
+
+fn doc_comment(out: &mut String, indent: &str, description: &Value) {
+    for line in description.as_str().into_iter().flat_map(str::lines) {
+        let _ = writeln!(out, "{}/// {}", indent, line);
+    }
+}
+
+fn field_name(name: &str) -> String {
+    match name {
+        "type" | "ref" | "match" | "fn" | "mod" | "use" | "impl" | "struct" | "enum" | "trait" | "loop" => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
+
+fn camel_case(value: &str) -> String {
+    value
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|c| c.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_generated_client_is_current() {
+        let generated = rust_client(super::super::openapi::document());
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/client/generated.rs");
+        if std::env::var_os("LIATH_UPDATE_CLIENT").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+            return;
+        }
+        let current = std::fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            current == generated,
+            "src/client/generated.rs is out of date; regenerate it with \
+             LIATH_UPDATE_CLIENT=1 cargo test --features server generated_client",
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(snake_case("AgentMetadata"), "agent_metadata");
+        assert_eq!(camel_case("add_vector"), "AddVector");
+        assert_eq!(field_name("type"), "r#type");
+    }
+}
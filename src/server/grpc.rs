@@ -0,0 +1,441 @@
+//! gRPC service, served on the same port as the REST API
+//!
+//! The service is defined in `proto/liath.proto`. Calls send the API key as
+//! `authorization: Bearer <key>` metadata, need the same permissions as the
+//! matching REST routes, and run on the same workers. Vectors and embeddings are
+//! packed floats instead of JSON numbers.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use liath::server::grpc::proto::{liath_client::LiathClient, PutRequest};
+//!
+//! let mut client = LiathClient::connect("http://localhost:8080").await?;
+//! let mut request = tonic::Request::new(PutRequest {
+//!     namespace: "docs".into(),
+//!     key: "greeting".into(),
+//!     value: "hello".into(),
+//! });
+//! request.metadata_mut().insert("authorization", "Bearer lk_...".parse()?);
+//! client.put(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+// `Status` is the error type of every service method, large as it is
+#![allow(clippy::result_large_err)]
+
+use std::pin::Pin;
+use axum::http::StatusCode;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+use crate::agent::MemoryEntry;
+use crate::auth::{estimate_tokens, LimitExceeded, Resource};
+use crate::metrics;
+use crate::query::audit::AuditOutcome;
+use crate::query::bulk::{self, BulkRecord};
+use crate::query::{FunctionProfile, Profile};
+use super::agents::{AgentOp, RecallParams, StoreMemoryRequest};
+use super::api::{self, AppState, BulkProgress, DispatchError, ScanItem, WorkerMsg};
+
+/// Messages and client generated from `proto/liath.proto`
+pub mod proto {
+    tonic::include_proto!("liath.v1");
+}
+
+use proto::liath_server::{Liath, LiathServer};
+
+const DEFAULT_DIMENSIONS: u32 = 384;
+const DEFAULT_K: u32 = 5;
+
+/// Routes serving the gRPC service, for merging into the REST router
+pub(super) fn router(state: AppState) -> axum::Router {
+    tonic::service::Routes::new(LiathServer::new(LiathService { state })).into_axum_router()
+}
+
+struct LiathService {
+    state: AppState,
+}
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+impl From<DispatchError> for Status {
+    fn from(error: DispatchError) -> Self {
+        match error {
+            DispatchError::TimedOut(_) => Status::deadline_exceeded(error.to_string()),
+            DispatchError::Busy | DispatchError::Stopped => Status::unavailable(error.to_string()),
+            DispatchError::Limited(ref refusal) => {
+                let mut status = Status::resource_exhausted(error.to_string());
+                status.metadata_mut().insert("retry-after", refusal.retry_after_secs().into());
+                status
+            }
+        }
+    }
+}
+
+/// A worker's error, which carries no status of its own
+fn failed(message: String) -> Status {
+    Status::unknown(message)
+}
+
+/// A worker's error, unless a limit refused the operation
+fn failed_or_limited(error: anyhow::Error) -> Status {
+    match error.downcast::<LimitExceeded>() {
+        Ok(refusal) => DispatchError::from(refusal).into(),
+        Err(error) => failed(error.to_string()),
+    }
+}
+
+/// The gRPC status for the HTTP status of an agent reply
+fn status(code: StatusCode, message: String) -> Status {
+    match code {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        StatusCode::NOT_IMPLEMENTED => Status::unimplemented(message),
+        _ => Status::internal(message),
+    }
+}
+
+impl LiathService {
+    /// Resolve the bearer token in the call's metadata to a user and count the
+    /// call against the user's rate limit
+    fn authenticate<T>(&self, request: &Request<T>) -> Result<String, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let user_id = match token {
+            None => Err(Status::unauthenticated("Missing API key: send 'authorization: Bearer <key>'")),
+            Some(token) => self.state.auth.read().unwrap().authenticate(token).ok_or_else(|| Status::unauthenticated("Invalid API key")),
+        };
+        if user_id.is_err() {
+            metrics::increment(metrics::AUTH_DENIALS, &[("operation", "authenticate")]);
+        }
+        let user_id = user_id?;
+        self.state.auth.read().unwrap().admit(&user_id).map_err(DispatchError::from)?;
+        Ok(user_id)
+    }
+
+    /// Authenticate the call and check `permission`, on `namespace` if given
+    fn authorize<T>(&self, request: &Request<T>, permission: &str, namespace: Option<&str>) -> Result<String, Status> {
+        let user_id = self.authenticate(request)?;
+        let allowed = {
+            let auth = self.state.auth.read().unwrap();
+            match namespace {
+                Some(namespace) => auth.is_authorized_for(&user_id, permission, namespace),
+                None => auth.is_authorized(&user_id, permission),
+            }
+        };
+        if allowed {
+            return Ok(user_id);
+        }
+        let message = match namespace {
+            Some(namespace) => format!("'{}' lacks the '{}' permission on '{}'", user_id, permission, namespace),
+            None => format!("'{}' lacks the '{}' permission", user_id, permission),
+        };
+        self.state.audit.record(&user_id, permission, namespace, None, AuditOutcome::Denied, Some(&message));
+        Err(Status::permission_denied(message))
+    }
+
+    async fn agent(&self, user_id: String, op: AgentOp) -> Result<serde_json::Value, Status> {
+        match self.state.call(|resp| WorkerMsg::Agent { op, user_id, resp }).await? {
+            Ok((_, body)) => Ok(body),
+            Err((code, message)) => Err(status(code, message)),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Liath for LiathService {
+    async fn list_namespaces(&self, request: Request<proto::ListNamespacesRequest>) -> Result<Response<proto::ListNamespacesResponse>, Status> {
+        let user_id = self.authenticate(&request)?;
+        if !self.state.auth.read().unwrap().is_authorized_anywhere(&user_id, "list_namespaces") {
+            return Err(Status::permission_denied(format!("'{}' lacks the 'list_namespaces' permission", user_id)));
+        }
+        let namespaces = self.state.call(|resp| WorkerMsg::ListNamespaces { user_id, resp }).await?;
+        Ok(Response::new(proto::ListNamespacesResponse { namespaces }))
+    }
+
+    async fn create_namespace(&self, request: Request<proto::CreateNamespaceRequest>) -> Result<Response<proto::CreateNamespaceResponse>, Status> {
+        let user_id = self.authorize(&request, "create_namespace", Some(&request.get_ref().name))?;
+        let request = request.into_inner();
+        self.state.call(|resp| WorkerMsg::CreateNamespace {
+            name: request.name,
+            dimensions: request.dimensions.unwrap_or(DEFAULT_DIMENSIONS) as usize,
+            metric: request.metric.unwrap_or_else(|| "cosine".to_string()),
+            encrypted: request.encrypted,
+            user_id,
+            resp,
+        }).await?.map_err(failed)?;
+        Ok(Response::new(proto::CreateNamespaceResponse {}))
+    }
+
+    async fn delete_namespace(&self, request: Request<proto::DeleteNamespaceRequest>) -> Result<Response<proto::DeleteNamespaceResponse>, Status> {
+        let user_id = self.authorize(&request, "delete_namespace", Some(&request.get_ref().name))?;
+        let name = request.into_inner().name;
+        self.state.call(|resp| WorkerMsg::DeleteNamespace { name, user_id, resp }).await?.map_err(failed)?;
+        Ok(Response::new(proto::DeleteNamespaceResponse {}))
+    }
+
+    async fn get(&self, request: Request<proto::GetRequest>) -> Result<Response<proto::GetResponse>, Status> {
+        let user_id = self.authorize(&request, "select", Some(&request.get_ref().namespace))?;
+        let proto::GetRequest { namespace, key } = request.into_inner();
+        let value = self.state.call(|resp| WorkerMsg::KvGet { namespace, key, user_id, resp }).await?.map_err(failed)?;
+        Ok(Response::new(proto::GetResponse { value }))
+    }
+
+    async fn put(&self, request: Request<proto::PutRequest>) -> Result<Response<proto::PutResponse>, Status> {
+        let user_id = self.authorize(&request, "insert", Some(&request.get_ref().namespace))?;
+        let proto::PutRequest { namespace, key, value } = request.into_inner();
+        self.state.call(|resp| WorkerMsg::KvPut { namespace, key, value, user_id, resp }).await?.map_err(failed_or_limited)?;
+        Ok(Response::new(proto::PutResponse {}))
+    }
+
+    async fn delete(&self, request: Request<proto::DeleteRequest>) -> Result<Response<proto::DeleteResponse>, Status> {
+        let user_id = self.authorize(&request, "delete", Some(&request.get_ref().namespace))?;
+        let proto::DeleteRequest { namespace, key } = request.into_inner();
+        self.state.call(|resp| WorkerMsg::KvDelete { namespace, key, user_id, resp }).await?.map_err(failed)?;
+        Ok(Response::new(proto::DeleteResponse {}))
+    }
+
+    type ScanStream = ResponseStream<proto::ScanResponse>;
+
+    async fn scan(&self, request: Request<proto::ScanRequest>) -> Result<Response<Self::ScanStream>, Status> {
+        let user_id = self.authorize(&request, "select", Some(&request.get_ref().namespace))?;
+        let request = request.into_inner();
+        let (tx, rx) = mpsc::channel(api::SCAN_STREAM_BUFFER);
+        self.state.submit(WorkerMsg::KvScan {
+            namespace: request.namespace,
+            prefix: request.prefix,
+            cursor: request.cursor.filter(|cursor| !cursor.is_empty()),
+            limit: request.limit.map(|limit| limit as usize),
+            user_id,
+            items: tx,
+        })?;
+        let stream = ReceiverStream::new(rx).map(|item| {
+            let item = match item {
+                ScanItem::Entry(entry) => proto::scan_response::Item::Entry(proto::Entry { key: entry.key, value: entry.value }),
+                ScanItem::Cursor(cursor) => proto::scan_response::Item::Cursor(cursor.unwrap_or_default()),
+                ScanItem::Error(error) => return Err(failed(error)),
+            };
+            Ok(proto::ScanResponse { item: Some(item) })
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type BulkIngestStream = ResponseStream<proto::BulkIngestResponse>;
+
+    async fn bulk_ingest(&self, request: Request<proto::BulkIngestRequest>) -> Result<Response<Self::BulkIngestStream>, Status> {
+        let user_id = self.authorize(&request, "insert", Some(&request.get_ref().namespace))?;
+        let request = request.into_inner();
+        let namespace = request.namespace;
+        if !self.state.query_executor.namespace_exists(&namespace) {
+            return Err(Status::not_found(format!("Namespace '{}' does not exist", namespace)));
+        }
+        let batch_size = request.batch_size.map_or(bulk::DEFAULT_BATCH_SIZE, |size| size as usize).clamp(1, bulk::MAX_BATCH_SIZE);
+
+        // Records that cannot be written are reported before any batch is
+        let mut progress = BulkProgress::default();
+        let mut invalid = Vec::new();
+        let mut records = Vec::new();
+        for (index, record) in request.records.into_iter().enumerate() {
+            let key = record.key.clone();
+            match bulk_record(record) {
+                Ok(record) => records.push((index + 1, record)),
+                Err(error) => invalid.push(bulk_error(index + 1, Some(key), error)),
+            }
+        }
+        progress.processed = invalid.len();
+        progress.failed = invalid.len();
+
+        let state = self.state.clone();
+        let (tx, rx) = mpsc::channel(api::SCAN_STREAM_BUFFER);
+        tokio::spawn(async move {
+            for error in invalid {
+                if tx.send(Ok(error)).await.is_err() {
+                    return;
+                }
+            }
+            let mut reserve = records.len();
+            let mut records = records.into_iter().peekable();
+            while records.peek().is_some() {
+                let batch: Vec<_> = records.by_ref().take(batch_size).collect();
+                let report = match api::write_batch(&state, &namespace, &user_id, batch, std::mem::take(&mut reserve), &mut progress).await {
+                    Ok(report) => report,
+                    Err(error) => {
+                        let _ = tx.send(Err(failed(error))).await;
+                        return;
+                    }
+                };
+                for error in report.errors {
+                    if tx.send(Ok(bulk_error(error.line, error.key, error.error))).await.is_err() {
+                        return;
+                    }
+                }
+                if tx.send(Ok(bulk_progress(&progress, false))).await.is_err() {
+                    return;
+                }
+            }
+            let _ = tx.send(Ok(bulk_progress(&progress, true))).await;
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn add_vectors(&self, request: Request<proto::AddVectorsRequest>) -> Result<Response<proto::AddVectorsResponse>, Status> {
+        self.authorize(&request, "insert", Some(&request.get_ref().namespace))?;
+        let request = request.into_inner();
+        let vectors = request.vectors.into_iter().map(|vector| (vector.id, vector.values)).collect();
+        let added = self.state.call(|resp| WorkerMsg::AddVectors { namespace: request.namespace, vectors, resp }).await?.map_err(failed)?;
+        Ok(Response::new(proto::AddVectorsResponse { added: added as u64 }))
+    }
+
+    async fn search_vectors(&self, request: Request<proto::SearchVectorsRequest>) -> Result<Response<proto::SearchVectorsResponse>, Status> {
+        self.authorize(&request, "similarity_search", Some(&request.get_ref().namespace))?;
+        let request = request.into_inner();
+        let results = self.state.call(|resp| WorkerMsg::SearchVectors {
+            namespace: request.namespace,
+            vector: request.vector,
+            k: request.k.unwrap_or(DEFAULT_K) as usize,
+            resp,
+        }).await?.map_err(failed)?;
+        let matches = results.into_iter().map(|(id, distance)| proto::Match { id, distance }).collect();
+        Ok(Response::new(proto::SearchVectorsResponse { matches }))
+    }
+
+    async fn semantic_search(&self, request: Request<proto::SemanticSearchRequest>) -> Result<Response<proto::SemanticSearchResponse>, Status> {
+        let user_id = self.authorize(&request, "similarity_search", Some(&request.get_ref().namespace))?;
+        let request = request.into_inner();
+        self.state.charge(&user_id, Resource::EmbeddingTokens, estimate_tokens(&request.query))?;
+        let results = self.state.call(|resp| WorkerMsg::SemanticSearch {
+            namespace: request.namespace,
+            query: request.query,
+            k: request.k.unwrap_or(DEFAULT_K) as usize,
+            resp,
+        }).await?.map_err(failed)?;
+        let matches = results.into_iter()
+            .map(|(id, content, distance)| proto::SemanticMatch { id, content, distance })
+            .collect();
+        Ok(Response::new(proto::SemanticSearchResponse { matches }))
+    }
+
+    async fn embed(&self, request: Request<proto::EmbedRequest>) -> Result<Response<proto::EmbedResponse>, Status> {
+        let user_id = self.authorize(&request, "generate_embedding", None)?;
+        let texts = request.into_inner().texts;
+        self.state.charge(&user_id, Resource::EmbeddingTokens, texts.iter().map(|t| estimate_tokens(t)).sum())?;
+        let embeddings = self.state.call(|resp| WorkerMsg::GenerateEmbeddings { texts, resp }).await?.map_err(failed)?;
+        let embeddings = embeddings.into_iter().map(|values| proto::Embedding { values }).collect();
+        Ok(Response::new(proto::EmbedResponse { embeddings }))
+    }
+
+    async fn execute(&self, request: Request<proto::ExecuteRequest>) -> Result<Response<proto::ExecuteResponse>, Status> {
+        let user_id = self.authenticate(&request)?;
+        let proto::ExecuteRequest { query, explain } = request.into_inner();
+        self.state.requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let (result, profile) = self.state.call(|resp| WorkerMsg::Execute { query, user_id, explain, resp }).await?;
+        if let Some(error) = result.strip_prefix("Error: ") {
+            return Err(failed(error.to_string()));
+        }
+        Ok(Response::new(proto::ExecuteResponse { result, profile: profile.map(Into::into) }))
+    }
+
+    async fn store_memory(&self, request: Request<proto::StoreMemoryRequest>) -> Result<Response<proto::StoreMemoryResponse>, Status> {
+        let user_id = self.authenticate(&request)?;
+        let proto::StoreMemoryRequest { agent_id, content, tags } = request.into_inner();
+        self.state.charge(&user_id, Resource::EmbeddingTokens, estimate_tokens(&content))?;
+        let op = AgentOp::StoreMemory { agent_id, request: StoreMemoryRequest { content, tags } };
+        let body = self.agent(user_id, op).await?;
+        let id = body["id"].as_u64().ok_or_else(|| Status::internal("Stored memory has no id"))?;
+        Ok(Response::new(proto::StoreMemoryResponse { id }))
+    }
+
+    async fn recall_memories(&self, request: Request<proto::RecallMemoriesRequest>) -> Result<Response<proto::RecallMemoriesResponse>, Status> {
+        let user_id = self.authenticate(&request)?;
+        let request = request.into_inner();
+        if let Some(query) = &request.query {
+            self.state.charge(&user_id, Resource::EmbeddingTokens, estimate_tokens(query))?;
+        }
+        let params = RecallParams {
+            query: request.query,
+            tags: (!request.tags.is_empty()).then(|| request.tags.join(",")),
+            k: request.k.unwrap_or(DEFAULT_K) as usize,
+        };
+        let body = self.agent(user_id, AgentOp::RecallMemories { agent_id: request.agent_id, params }).await?;
+        let entries: Vec<MemoryEntry> = serde_json::from_value(body).map_err(|e| Status::internal(e.to_string()))?;
+        let memories = entries.into_iter().map(|entry| proto::Memory {
+            id: entry.id,
+            content: entry.content,
+            tags: entry.tags,
+            distance: entry.distance,
+            created_at: entry.created_at,
+        }).collect();
+        Ok(Response::new(proto::RecallMemoriesResponse { memories }))
+    }
+
+    async fn forget_memory(&self, request: Request<proto::ForgetMemoryRequest>) -> Result<Response<proto::ForgetMemoryResponse>, Status> {
+        let user_id = self.authenticate(&request)?;
+        let proto::ForgetMemoryRequest { agent_id, memory_id } = request.into_inner();
+        self.agent(user_id, AgentOp::ForgetMemory { agent_id, memory_id }).await?;
+        Ok(Response::new(proto::ForgetMemoryResponse {}))
+    }
+}
+
+/// A bulk record as the NDJSON ingest would have parsed it
+fn bulk_record(record: proto::BulkRecord) -> Result<BulkRecord, String> {
+    let metadata = record.metadata
+        .map(|metadata| serde_json::from_str(&metadata).map_err(|e| format!("Invalid metadata: {}", e)))
+        .transpose()?;
+    let record = BulkRecord {
+        key: record.key,
+        value: record.value.map(serde_json::Value::String),
+        text: record.text,
+        vector: (!record.vector.is_empty()).then_some(record.vector),
+        id: record.id,
+        metadata,
+    };
+    record.validate().map_err(|e| format!("{:#}", e))?;
+    Ok(record)
+}
+
+fn bulk_error(record: usize, key: Option<String>, error: String) -> proto::BulkIngestResponse {
+    let error = proto::BulkError { record: record as u64, key: key.unwrap_or_default(), error };
+    proto::BulkIngestResponse { item: Some(proto::bulk_ingest_response::Item::Error(error)) }
+}
+
+fn bulk_progress(progress: &BulkProgress, done: bool) -> proto::BulkIngestResponse {
+    let progress = proto::BulkProgress {
+        processed: progress.processed as u64,
+        written: progress.written as u64,
+        failed: progress.failed as u64,
+        done,
+    };
+    proto::BulkIngestResponse { item: Some(proto::bulk_ingest_response::Item::Progress(progress)) }
+}
+
+impl From<Profile> for proto::Profile {
+    fn from(profile: Profile) -> Self {
+        Self {
+            total_us: profile.total_us,
+            lua_us: profile.lua_us,
+            bytes_read: profile.bytes_read,
+            bytes_written: profile.bytes_written,
+            functions: profile.functions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<FunctionProfile> for proto::FunctionProfile {
+    fn from(function: FunctionProfile) -> Self {
+        Self {
+            name: function.name,
+            calls: function.calls,
+            total_us: function.total_us,
+            max_us: function.max_us,
+            bytes_read: function.bytes_read,
+            bytes_written: function.bytes_written,
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! MCP over streamable HTTP
+//!
+//! Serves the Model Context Protocol on `/mcp`, so several assistants can share one
+//! running database instead of each spawning `liath mcp` on the data directory.
+//! Clients POST JSON-RPC messages, GET a stream of the messages the server sends on
+//! its own, such as resource updates, and DELETE their session when done. Every request carries the API key
+//! of the user the tools run as.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+    routing::post,
+    Extension, Json, Router,
+};
+use rand::RngCore;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use crate::mcp::Subscriptions;
+use super::api::{auth_error, AppState, AuthUser, WorkerMsg};
+
+/// Header carrying the session ID handed out by `initialize`
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// How long a session without an open stream survives without requests
+const SESSION_IDLE: Duration = Duration::from_secs(60 * 60);
+
+/// Server messages kept for a session until a stream takes them
+const EVENT_BUFFER: usize = 64;
+
+/// Messages for a session, taken by whichever of its streams asks first
+type Outbox = Arc<tokio::sync::Mutex<mpsc::Receiver<Value>>>;
+
+struct Session {
+    user_id: String,
+    last_seen: Instant,
+    outbox: Outbox,
+    subscriptions: Subscriptions,
+}
+
+impl Session {
+    /// Whether a GET stream is open; each holds the outbox
+    fn is_streaming(&self) -> bool {
+        Arc::strong_count(&self.outbox) > 1
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.subscriptions.clear();
+    }
+}
+
+/// Why a request may not use the session it names
+#[derive(Debug)]
+enum Refusal {
+    /// No `Mcp-Session-Id` header (400)
+    MissingSession,
+    /// No such session for the caller (404)
+    UnknownSession(String),
+    /// A page on another site (403)
+    Origin(String),
+}
+
+impl IntoResponse for Refusal {
+    fn into_response(self) -> Response {
+        match self {
+            Refusal::MissingSession => {
+                auth_error(StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header: send 'initialize' first".to_string())
+            }
+            Refusal::UnknownSession(id) => {
+                auth_error(StatusCode::NOT_FOUND, format!("Unknown MCP session '{}': initialize a new one", id))
+            }
+            Refusal::Origin(origin) => auth_error(StatusCode::FORBIDDEN, format!("Origin '{}' may not use this server", origin)),
+        }
+    }
+}
+
+/// Sessions opened by `initialize`, each bound to the user who opened it
+#[derive(Clone, Default)]
+pub(super) struct Sessions(Arc<Mutex<HashMap<String, Session>>>);
+
+impl Sessions {
+    /// Start a session for `user_id`, dropping sessions that went idle
+    fn open(&self, user_id: &str) -> String {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let (events, outbox) = mpsc::channel(EVENT_BUFFER);
+        let session = Session {
+            user_id: user_id.to_string(),
+            last_seen: Instant::now(),
+            outbox: Arc::new(tokio::sync::Mutex::new(outbox)),
+            subscriptions: Subscriptions::new(events),
+        };
+
+        let mut sessions = self.0.lock().unwrap();
+        sessions.retain(|_, session| session.is_streaming() || session.last_seen.elapsed() < SESSION_IDLE);
+        sessions.insert(id.clone(), session);
+        id
+    }
+
+    /// Run `f` on the caller's session, or answer 404 as if it did not exist
+    ///
+    /// Another user's session is reported as unknown, so session IDs cannot be probed.
+    fn with<T>(&self, id: &str, user_id: &str, f: impl FnOnce(&mut Session) -> T) -> Result<T, Refusal> {
+        let mut sessions = self.0.lock().unwrap();
+        match sessions.get_mut(id) {
+            Some(session) if session.user_id == user_id => {
+                session.last_seen = Instant::now();
+                Ok(f(session))
+            }
+            _ => Err(Refusal::UnknownSession(id.to_string())),
+        }
+    }
+
+    fn close(&self, id: &str, user_id: &str) -> Result<(), Refusal> {
+        self.with(id, user_id, |_| ())?;
+        self.0.lock().unwrap().remove(id);
+        Ok(())
+    }
+}
+
+/// The `/mcp` route; merged before authorization, so it needs a valid API key
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/mcp", post(post_messages).get(stream_messages).delete(close_session))
+        .layer(Extension(Sessions::default()))
+}
+
+/// Refuse browsers on other sites, which could otherwise reach a local server
+/// through DNS rebinding
+fn check_origin(headers: &HeaderMap) -> Result<(), Refusal> {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|value| value.to_str().ok()) else {
+        return Ok(());
+    };
+    let origin_host = origin.split_once("://").map_or(origin, |(_, host)| host);
+    let host = headers.get(header::HOST).and_then(|value| value.to_str().ok());
+    if host == Some(origin_host) {
+        Ok(())
+    } else {
+        Err(Refusal::Origin(origin.to_string()))
+    }
+}
+
+/// The session ID the client sent, or 400 without one
+fn session_id(headers: &HeaderMap) -> Result<&str, Refusal> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(Refusal::MissingSession)
+}
+
+fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|accepted| {
+            let accepted = accepted.split(';').next().unwrap_or_default().trim();
+            accepted == media_type || accepted == "*/*"
+        })
+}
+
+fn message_event(message: &Value) -> Result<Event, Infallible> {
+    Ok(Event::default().event("message").data(message.to_string()))
+}
+
+/// Answer a JSON-RPC message or batch
+///
+/// `initialize` opens a session; everything else must name one. Replies come back
+/// as JSON, or as a short event stream for clients that only accept one.
+async fn post_messages(
+    State(state): State<AppState>,
+    Extension(sessions): Extension<Sessions>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(refusal) = check_origin(&headers) {
+        return refusal.into_response();
+    }
+    let message: Value = match serde_json::from_slice(&body) {
+        Ok(message) => message,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(crate::mcp::parse_error(e))).into_response(),
+    };
+    let batch = message.is_array();
+    let messages = match message {
+        Value::Array(messages) if messages.is_empty() => {
+            return auth_error(StatusCode::BAD_REQUEST, "Empty JSON-RPC batch".to_string());
+        }
+        Value::Array(messages) => messages,
+        message => vec![message],
+    };
+
+    let initialize = messages.iter().any(|message| message.get("method").and_then(Value::as_str) == Some("initialize"));
+    let session = if initialize {
+        Ok(sessions.open(&user_id))
+    } else {
+        session_id(&headers).map(str::to_string)
+    };
+    let (session, subscriptions) = match session
+        .and_then(|id| sessions.with(&id, &user_id, |session| session.subscriptions.clone()).map(|s| (id, s)))
+    {
+        Ok(session) => session,
+        Err(refusal) => return refusal.into_response(),
+    };
+
+    // Notifications and responses need no answer
+    if !messages.iter().any(|message| message.get("method").is_some() && message.get("id").is_some()) {
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    let replies = match state.call(|resp| WorkerMsg::Mcp { messages, user_id, subscriptions, resp }).await {
+        Ok(replies) => replies,
+        Err(e) => return e.into_response(),
+    };
+
+    let mut response = if !accepts(&headers, "application/json") && accepts(&headers, "text/event-stream") {
+        let events: Vec<_> = replies.iter().map(message_event).collect();
+        Sse::new(futures::stream::iter(events)).into_response()
+    } else if batch {
+        Json(Value::Array(replies)).into_response()
+    } else {
+        Json(replies.into_iter().next().unwrap_or(Value::Null)).into_response()
+    };
+    if let Ok(value) = HeaderValue::from_str(&session) {
+        response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    response
+}
+
+/// Stream the messages the server sends to a session on its own
+///
+/// Messages wait for a stream to be opened; with several open, each message goes
+/// to one of them.
+async fn stream_messages(
+    Extension(sessions): Extension<Sessions>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    headers: HeaderMap,
+) -> Response {
+    let outbox = match check_origin(&headers)
+        .and_then(|()| session_id(&headers))
+        .and_then(|id| sessions.with(id, &user_id, |session| session.outbox.clone()))
+    {
+        Ok(outbox) => outbox,
+        Err(refusal) => return refusal.into_response(),
+    };
+
+    let events = futures::stream::unfold(outbox, |outbox| async move {
+        let message = outbox.lock().await.recv().await?;
+        Some((message_event(&message), outbox))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// End a session
+async fn close_session(
+    Extension(sessions): Extension<Sessions>,
+    Extension(AuthUser(user_id)): Extension<AuthUser>,
+    headers: HeaderMap,
+) -> Response {
+    match check_origin(&headers)
+        .and_then(|()| session_id(&headers))
+        .and_then(|id| sessions.close(id, &user_id))
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(refusal) => refusal.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sessions_are_bound_to_their_user() {
+        let sessions = Sessions::default();
+        let id = sessions.open("alice");
+        assert_eq!(id.len(), 32);
+        assert!(sessions.with(&id, "alice", |_| ()).is_ok());
+        assert_eq!(sessions.with(&id, "bob", |_| ()).unwrap_err().into_response().status(), StatusCode::NOT_FOUND);
+        assert_eq!(sessions.close(&id, "bob").unwrap_err().into_response().status(), StatusCode::NOT_FOUND);
+        sessions.close(&id, "alice").unwrap();
+        assert!(sessions.with(&id, "alice", |_| ()).is_err());
+    }
+
+    #[test]
+    fn test_origin_must_match_host() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("127.0.0.1:3000"));
+        assert!(check_origin(&headers).is_ok());
+        headers.insert(header::ORIGIN, HeaderValue::from_static("http://127.0.0.1:3000"));
+        assert!(check_origin(&headers).is_ok());
+        headers.insert(header::ORIGIN, HeaderValue::from_static("https://evil.example"));
+        assert_eq!(check_origin(&headers).unwrap_err().into_response().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_accepts() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json, text/event-stream"));
+        assert!(accepts(&headers, "application/json"));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/event-stream;q=1"));
+        assert!(!accepts(&headers, "application/json"));
+        assert!(accepts(&headers, "text/event-stream"));
+    }
+}
@@ -1,3 +1,12 @@
 pub mod api;
+mod agents;
+#[cfg(test)]
+mod codegen;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "mcp")]
+mod mcp;
+mod openapi;
 
-pub use api::run_server;
\ No newline at end of file
+pub use api::{run_server, run_server_with_scheduler, run_server_with_db, run_server_with_config, ServerConfig};
+pub use openapi::document as openapi_document;
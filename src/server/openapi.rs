@@ -0,0 +1,1010 @@
+//! OpenAPI 3 description of the HTTP API
+//!
+//! Request and response types declare themselves through [`api_types!`], which
+//! derives each schema from the struct definition, field types and doc comments
+//! included. [`document`] combines those schemas with the route table below into
+//! the document served at `/openapi.json`; [`docs_page`] renders it as the HTML
+//! page at `/docs`. The Rust client in `liath::client` is generated from the same
+//! document, see `codegen`.
+
+use axum::http::Method;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use crate::agent::AgentMetadata;
+use crate::agent::MemoryEntry;
+use crate::query::audit::{AuditEntry, AuditOutcome};
+use crate::query::bulk::{BulkError, BulkRecord};
+use crate::query::profile::FunctionProfile;
+use crate::query::{Change, ChangeKind, Procedure, Profile};
+use crate::scheduler::{Job, JobAction, JobStatus, Schedule};
+use super::agents::{
+    AddMessageRequest, AgentResponse, ConversationResponse, CreateAgentRequest, CreateConversationRequest,
+    IdResponse, MessageResponse, MessagesParams, RecallParams, StoreMemoryRequest, ToolStateRequest, ToolStateResponse,
+};
+use super::api::{
+    self, AuditQuery, AuditResponse, BulkQuery, CallProcedureRequest, CallProcedureResponse, CreateNamespaceRequest,
+    EmbedRequest, EmbedResponse, HealthResponse, JobsResponse, KvGetResponse, KvPutRequest, MetricsResponse,
+    NamespacesResponse, ProceduresResponse, PutJobRequest, QueryRequest, QueryResponse, RegisterProcedureRequest,
+    RunJobResponse, ScanQuery, SemanticSearchRequest, SemanticSearchResponse, SqlRequest, SqlResponse,
+    SuccessResponse, WatchQuery,
+};
+
+const JSON: &str = "application/json";
+const NDJSON: &str = "application/x-ndjson";
+
+/// A type with a schema in the API document
+pub(super) trait ApiSchema {
+    /// Whether a field of this type may be absent or null
+    const OPTIONAL: bool = false;
+
+    /// The type's schema, or a reference to it for named types
+    fn schema(schemas: &mut Schemas) -> Value;
+}
+
+/// Named schemas collected while the document is built
+#[derive(Default)]
+pub(super) struct Schemas {
+    named: BTreeMap<String, Value>,
+}
+
+impl Schemas {
+    /// Register the schema `build` returns under `name`, once, and refer to it
+    pub(super) fn named(&mut self, name: &str, build: impl FnOnce(&mut Self) -> Value) -> Value {
+        if !self.named.contains_key(name) {
+            // Placed first so a type that contains itself refers instead of recursing
+            self.named.insert(name.to_string(), Value::Null);
+            let schema = build(self);
+            self.named.insert(name.to_string(), schema);
+        }
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+
+    /// The schema a reference points to
+    fn resolve<'a>(&'a self, schema: &'a Value) -> &'a Value {
+        schema["$ref"]
+            .as_str()
+            .and_then(|r| r.strip_prefix("#/components/schemas/"))
+            .and_then(|name| self.named.get(name))
+            .unwrap_or(schema)
+    }
+}
+
+/// A property of an object schema
+pub(super) struct Field {
+    name: &'static str,
+    schema: Value,
+    description: Option<String>,
+    required: bool,
+    flatten: bool,
+}
+
+impl Field {
+    /// A struct field, with its doc comment and serde attributes as `stringify!` renders them
+    pub(super) fn declared<T: ApiSchema>(schemas: &mut Schemas, name: &'static str, attrs: &[&str]) -> Self {
+        let serde: Vec<&str> = attrs
+            .iter()
+            .filter_map(|attr| attr.strip_prefix("serde"))
+            .flat_map(|args| args.trim().trim_start_matches('(').trim_end_matches(')').split(','))
+            .map(str::trim)
+            .collect();
+        let defaulted = serde.iter().any(|arg| arg.starts_with("default") || arg.starts_with("skip_serializing_if"));
+        Self {
+            name,
+            schema: T::schema(schemas),
+            description: doc(attrs),
+            required: !T::OPTIONAL && !defaulted,
+            flatten: serde.contains(&"flatten"),
+        }
+    }
+
+    /// A field described by hand
+    fn of<T: ApiSchema>(schemas: &mut Schemas, name: &'static str, description: &str) -> Self {
+        Self {
+            name,
+            schema: T::schema(schemas),
+            description: Some(description.to_string()).filter(|d| !d.is_empty()),
+            required: !T::OPTIONAL,
+            flatten: false,
+        }
+    }
+}
+
+/// An object schema; flattened fields become `allOf` parts
+pub(super) fn object(description: Option<String>, fields: Vec<Field>) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    let mut flattened = Vec::new();
+    for field in fields {
+        if field.flatten {
+            flattened.push(field.schema);
+            continue;
+        }
+        if field.required {
+            required.push(field.name);
+        }
+        properties.insert(field.name.to_string(), describe(field.schema, field.description.as_deref()));
+    }
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    if !flattened.is_empty() {
+        flattened.push(schema);
+        schema = json!({ "allOf": flattened });
+    }
+    describe(schema, description.as_deref())
+}
+
+/// The text of `doc` attributes as `stringify!` renders them, one line each
+pub(super) fn doc(attrs: &[&str]) -> Option<String> {
+    let lines: Vec<&str> = attrs
+        .iter()
+        .filter_map(|attr| attr.strip_prefix("doc"))
+        .filter_map(|rest| rest.trim_start().strip_prefix('='))
+        .map(|literal| {
+            let literal = literal.trim().trim_start_matches('r').trim_matches('#');
+            let text = literal.strip_prefix('"').and_then(|l| l.strip_suffix('"')).unwrap_or(literal);
+            text.strip_prefix(' ').unwrap_or(text)
+        })
+        .collect();
+    let text = lines.join("\n").trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn describe(mut schema: Value, description: Option<&str>) -> Value {
+    let Some(description) = description else { return schema };
+    if schema.get("$ref").is_some() {
+        // Siblings of `$ref` are ignored, so the reference is wrapped
+        return json!({ "allOf": [schema], "description": description });
+    }
+    schema["description"] = description.into();
+    schema
+}
+
+/// Implement [`ApiSchema`] for a struct from a copy of its definition
+///
+/// For types defined outside the server; the server's own types use [`api_types!`].
+macro_rules! api_schema {
+    ($(#[$attr:meta])* $name:ident { $($(#[$field_attr:meta])* $field:ident : $ty:ty),* $(,)? }) => {
+        impl $crate::server::openapi::ApiSchema for $name {
+            fn schema(schemas: &mut $crate::server::openapi::Schemas) -> ::serde_json::Value {
+                schemas.named(stringify!($name), |schemas| {
+                    let fields = vec![$(
+                        $crate::server::openapi::Field::declared::<$ty>(schemas, stringify!($field), &[$(stringify!($field_attr)),*]),
+                    )*];
+                    $crate::server::openapi::object($crate::server::openapi::doc(&[$(stringify!($attr)),*]), fields)
+                })
+            }
+        }
+    };
+}
+
+/// Define request and response structs along with their schemas
+macro_rules! api_types {
+    ($(
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    )*) => {$(
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $ty),*
+        }
+
+        $crate::server::openapi::api_schema!($(#[$attr])* $name { $($(#[$field_attr])* $field: $ty),* });
+    )*};
+}
+
+pub(super) use api_schema;
+pub(super) use api_types;
+
+macro_rules! primitive_schemas {
+    ($($ty:ty => $schema:tt),* $(,)?) => {$(
+        impl ApiSchema for $ty {
+            fn schema(_: &mut Schemas) -> Value {
+                json!($schema)
+            }
+        }
+    )*};
+}
+
+primitive_schemas! {
+    String => { "type": "string" },
+    bool => { "type": "boolean" },
+    u32 => { "type": "integer", "format": "int32", "minimum": 0 },
+    u64 => { "type": "integer", "format": "int64", "minimum": 0 },
+    usize => { "type": "integer", "format": "int64", "minimum": 0 },
+    f32 => { "type": "number", "format": "float" },
+    Value => {},
+}
+
+impl<T: ApiSchema> ApiSchema for Option<T> {
+    const OPTIONAL: bool = true;
+
+    fn schema(schemas: &mut Schemas) -> Value {
+        let schema = T::schema(schemas);
+        if schema.get("$ref").is_some() {
+            return json!({ "allOf": [schema], "nullable": true });
+        }
+        let mut schema = schema;
+        schema["nullable"] = true.into();
+        schema
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn schema(schemas: &mut Schemas) -> Value {
+        json!({ "type": "array", "items": T::schema(schemas) })
+    }
+}
+
+// ========== Types From Outside the Server ==========
+
+api_schema! {
+    /// A version of a stored procedure
+    Procedure {
+        name: String,
+        version: u32,
+        code: String,
+        description: Option<String>,
+        /// User that registered this version; the procedure body runs with their permissions
+        owner: String,
+        created_at: u64,
+    }
+}
+
+api_schema! {
+    /// Where the time of one execution went
+    Profile {
+        /// Wall time of the whole execution
+        total_us: u64,
+        /// Time spent in Lua itself, outside database functions
+        lua_us: u64,
+        bytes_read: u64,
+        bytes_written: u64,
+        /// Per-function statistics, slowest first
+        functions: Vec<FunctionProfile>,
+    }
+}
+
+api_schema! {
+    /// Calls of one database function during an execution
+    FunctionProfile {
+        name: String,
+        calls: u64,
+        total_us: u64,
+        max_us: u64,
+        bytes_read: u64,
+        bytes_written: u64,
+    }
+}
+
+api_schema! {
+    /// One audited operation
+    AuditEntry {
+        /// Milliseconds since the Unix epoch
+        timestamp_ms: u64,
+        user_id: String,
+        /// Operation name, such as `insert` or `register_procedure`
+        operation: String,
+        namespace: Option<String>,
+        /// Key the operation touched, unless keys are hashed
+        key: Option<String>,
+        /// Hex SHA-256 of the key when `hash_keys` is set
+        key_hash: Option<String>,
+        outcome: AuditOutcome,
+        /// Error message for denied and failed operations
+        detail: Option<String>,
+    }
+}
+
+api_schema! {
+    /// A scheduled job
+    Job {
+        name: String,
+        schedule: Schedule,
+        action: JobAction,
+        /// User the job runs as
+        owner: String,
+        enabled: bool,
+        created_at: u64,
+    }
+}
+
+api_schema! {
+    /// Outcome of the most recent run of a job
+    JobStatus {
+        last_run_at: Option<u64>,
+        last_success: Option<bool>,
+        last_error: Option<String>,
+        last_result: Option<Value>,
+        last_duration_ms: Option<u64>,
+        run_count: u64,
+        next_run_at: Option<u64>,
+    }
+}
+
+api_schema! {
+    /// One change to the database
+    Change {
+        sequence: u64,
+        /// Milliseconds since the Unix epoch
+        timestamp_ms: u64,
+        kind: ChangeKind,
+        namespace: String,
+        /// Key written or deleted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+        /// Id of the vector added
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+    }
+}
+
+api_schema! {
+    /// Metadata for a registered agent
+    AgentMetadata {
+        id: String,
+        created_at: u64,
+        description: Option<String>,
+    }
+}
+
+api_schema! {
+    /// An entry in an agent's long-term memory
+    MemoryEntry {
+        id: u64,
+        content: String,
+        tags: Vec<String>,
+        /// Distance to the recall query; 0 when not recalled by similarity
+        distance: f32,
+        created_at: u64,
+    }
+}
+
+api_schema! {
+    /// One record of a bulk ingest
+    BulkRecord {
+        key: String,
+        /// Stored under `key`: strings as they are, other JSON serialized. Defaults to `text`
+        #[serde(default)]
+        value: Option<Value>,
+        /// Embedded and indexed unless `vector` is given
+        #[serde(default)]
+        text: Option<String>,
+        #[serde(default)]
+        vector: Option<Vec<f32>>,
+        /// Vector id; derived from the key when absent
+        #[serde(default)]
+        id: Option<u64>,
+        #[serde(default)]
+        metadata: Option<Value>,
+    }
+}
+
+api_schema! {
+    /// A record that could not be written
+    BulkError {
+        /// Line of the record in the request, counting from 1
+        line: usize,
+        key: Option<String>,
+        error: String,
+    }
+}
+
+/// A string schema with a fixed set of values
+fn string_enum(description: &str, values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values, "description": description })
+}
+
+/// One of several objects, told apart by their `type` property
+fn tagged(description: &str, variants: Vec<(&str, Value)>) -> Value {
+    let variants: Vec<Value> = variants
+        .into_iter()
+        .map(|(tag, mut variant)| {
+            variant["properties"]["type"] = json!({ "type": "string", "enum": [tag] });
+            let mut required = vec![json!("type")];
+            required.extend(variant["required"].as_array().cloned().unwrap_or_default());
+            variant["required"] = required.into();
+            variant
+        })
+        .collect();
+    json!({ "oneOf": variants, "discriminator": { "propertyName": "type" }, "description": description })
+}
+
+impl ApiSchema for AuditOutcome {
+    fn schema(schemas: &mut Schemas) -> Value {
+        schemas.named("AuditOutcome", |_| string_enum("How an audited operation ended", &["ok", "denied", "failed"]))
+    }
+}
+
+impl ApiSchema for ChangeKind {
+    fn schema(schemas: &mut Schemas) -> Value {
+        schemas.named("ChangeKind", |_| {
+            string_enum("What a change did", &["put", "delete", "add_vector", "create_namespace", "delete_namespace"])
+        })
+    }
+}
+
+impl ApiSchema for Schedule {
+    fn schema(schemas: &mut Schemas) -> Value {
+        schemas.named("Schedule", |schemas| {
+            let interval = object(Some("Every `seconds` seconds".into()), vec![Field::of::<u64>(schemas, "seconds", "")]);
+            let cron = object(
+                Some("Cron expression, evaluated in UTC".into()),
+                vec![Field::of::<String>(schemas, "expression", "")],
+            );
+            tagged("When a job runs", vec![("interval", interval), ("cron", cron)])
+        })
+    }
+}
+
+impl ApiSchema for JobAction {
+    fn schema(schemas: &mut Schemas) -> Value {
+        schemas.named("JobAction", |schemas| {
+            let lua = object(Some("Run a Lua script".into()), vec![Field::of::<String>(schemas, "code", "")]);
+            let procedure = object(
+                Some("Call a stored procedure".into()),
+                vec![Field::of::<String>(schemas, "name", ""), Field::of::<Option<Value>>(schemas, "args", "")],
+            );
+            let callback = object(
+                Some("Call a Rust callback registered with the scheduler".into()),
+                vec![Field::of::<String>(schemas, "name", "")],
+            );
+            tagged("What a job does when it runs", vec![("lua", lua), ("procedure", procedure), ("callback", callback)])
+        })
+    }
+}
+
+/// One line of a scan stream
+fn scan_line(schemas: &mut Schemas) -> Value {
+    schemas.named("ScanLine", |schemas| {
+        let fields = vec![
+            Field::of::<Option<String>>(schemas, "key", ""),
+            Field::of::<Option<String>>(schemas, "value", ""),
+            Field::of::<Option<String>>(schemas, "cursor", "Present on the last line when `limit` cut the scan short"),
+            Field::of::<Option<String>>(schemas, "error", "Present on the last line when the scan failed"),
+        ];
+        object(Some("An entry, the cursor to resume from, or an error".into()), fields)
+    })
+}
+
+/// One line of a bulk ingest's progress stream
+fn bulk_line(schemas: &mut Schemas) -> Value {
+    schemas.named("BulkLine", |schemas| {
+        let fields = vec![
+            Field::of::<Option<usize>>(schemas, "line", "Line of a record that failed"),
+            Field::of::<Option<String>>(schemas, "key", "Key of a record that failed"),
+            Field::of::<Option<String>>(schemas, "error", "Why a record failed, or why the ingest stopped"),
+            Field::of::<Option<usize>>(schemas, "processed", "Records processed so far"),
+            Field::of::<Option<usize>>(schemas, "written", "Records written so far"),
+            Field::of::<Option<usize>>(schemas, "failed", "Records failed so far"),
+            Field::of::<Option<bool>>(schemas, "done", "Set on the last line when every record was processed"),
+        ];
+        object(Some("A failed record, the running totals, or the final totals".into()), fields)
+    })
+}
+
+// ========== Routes ==========
+
+type SchemaFn = fn(&mut Schemas) -> Value;
+
+/// One route of the API, as the document describes it
+struct Operation {
+    tag: &'static str,
+    method: Method,
+    /// Route pattern as the router has it, such as `/kv/:namespace/:key`
+    path: &'static str,
+    id: &'static str,
+    summary: &'static str,
+    public: bool,
+    /// Path parameters that are not strings
+    path_types: Vec<(&'static str, SchemaFn)>,
+    query: Option<SchemaFn>,
+    body: Option<(&'static str, SchemaFn)>,
+    status: u16,
+    response: Option<(&'static str, SchemaFn)>,
+}
+
+impl Operation {
+    fn new(tag: &'static str, method: Method, path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self {
+            tag,
+            method,
+            path,
+            id,
+            summary,
+            public: false,
+            path_types: Vec::new(),
+            query: None,
+            body: None,
+            status: 200,
+            response: None,
+        }
+    }
+
+    /// Answered without an API key
+    fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    fn path_param<T: ApiSchema>(mut self, name: &'static str) -> Self {
+        self.path_types.push((name, T::schema));
+        self
+    }
+
+    /// Query parameters, one per field of `T`
+    fn query<T: ApiSchema>(mut self) -> Self {
+        self.query = Some(T::schema);
+        self
+    }
+
+    fn body<T: ApiSchema>(mut self) -> Self {
+        self.body = Some((JSON, T::schema));
+        self
+    }
+
+    /// A body of one `T` per line
+    fn ndjson_body<T: ApiSchema>(mut self) -> Self {
+        self.body = Some((NDJSON, T::schema));
+        self
+    }
+
+    fn returns<T: ApiSchema>(self) -> Self {
+        self.responds(200, JSON, T::schema)
+    }
+
+    fn creates<T: ApiSchema>(self) -> Self {
+        self.responds(201, JSON, T::schema)
+    }
+
+    fn responds(mut self, status: u16, content_type: &'static str, schema: SchemaFn) -> Self {
+        self.status = status;
+        self.response = Some((content_type, schema));
+        self
+    }
+}
+
+/// Every route the server answers, in the order the document lists them
+fn operations() -> Vec<Operation> {
+    use Method as M;
+    let op = Operation::new;
+    vec![
+        op("Server", M::GET, "/health", "health", "Check that the server is up").public().returns::<HealthResponse>(),
+        op("Server", M::GET, "/metrics", "metrics", "Request, queue and worker counters").returns::<MetricsResponse>(),
+        op("Server", M::GET, "/metrics/prometheus", "metrics_prometheus", "Metrics in the Prometheus text format")
+            .responds(200, "text/plain", String::schema),
+        op("Server", M::GET, "/openapi.json", "openapi", "The OpenAPI document of this API").public().returns::<Value>(),
+        op("Queries", M::POST, "/query", "execute_query", "Run a Lua script").body::<QueryRequest>().returns::<QueryResponse>(),
+        op("Queries", M::POST, "/sql", "execute_sql", "Run a declarative query").body::<SqlRequest>().returns::<SqlResponse>(),
+        op("Namespaces", M::GET, "/namespaces", "list_namespaces", "List the namespaces the caller may read")
+            .returns::<NamespacesResponse>(),
+        op("Namespaces", M::POST, "/namespaces", "create_namespace", "Create a namespace")
+            .body::<CreateNamespaceRequest>()
+            .returns::<SuccessResponse>(),
+        op("Namespaces", M::DELETE, "/namespaces/:name", "delete_namespace", "Delete a namespace and its data")
+            .returns::<SuccessResponse>(),
+        op("Key-Value", M::GET, "/kv/:namespace", "kv_scan", "Stream the entries under a prefix")
+            .query::<ScanQuery>()
+            .responds(200, NDJSON, scan_line),
+        op("Key-Value", M::GET, "/kv/:namespace/:key", "kv_get", "Read a value").returns::<KvGetResponse>(),
+        op("Key-Value", M::PUT, "/kv/:namespace/:key", "kv_put", "Write a value")
+            .body::<KvPutRequest>()
+            .returns::<SuccessResponse>(),
+        op("Key-Value", M::DELETE, "/kv/:namespace/:key", "kv_delete", "Delete a value").returns::<SuccessResponse>(),
+        op("Key-Value", M::POST, "/bulk/:namespace", "bulk_ingest", "Ingest records in batches, streaming the progress")
+            .query::<BulkQuery>()
+            .ndjson_body::<BulkRecord>()
+            .responds(200, NDJSON, bulk_line),
+        op("Vectors", M::POST, "/semantic/:namespace", "semantic_search", "Find the values closest in meaning to a text")
+            .body::<SemanticSearchRequest>()
+            .returns::<SemanticSearchResponse>(),
+        op("Vectors", M::POST, "/embed", "embed", "Embed texts").body::<EmbedRequest>().returns::<EmbedResponse>(),
+        op("Changes", M::GET, "/watch", "watch", "Stream changes as server-sent events, or over a WebSocket")
+            .query::<WatchQuery>()
+            .responds(200, "text/event-stream", Change::schema),
+        op("Audit", M::GET, "/audit", "audit", "Read the audit log").query::<AuditQuery>().returns::<AuditResponse>(),
+        op("Procedures", M::GET, "/procedures", "list_procedures", "List stored procedures").returns::<ProceduresResponse>(),
+        op("Procedures", M::GET, "/procedures/:name", "get_procedure", "Read the latest version of a procedure")
+            .returns::<Option<Procedure>>(),
+        op("Procedures", M::PUT, "/procedures/:name", "register_procedure", "Register a new version of a procedure")
+            .body::<RegisterProcedureRequest>()
+            .returns::<SuccessResponse>(),
+        op("Procedures", M::POST, "/procedures/:name", "call_procedure", "Call a procedure")
+            .body::<CallProcedureRequest>()
+            .returns::<CallProcedureResponse>(),
+        op("Procedures", M::DELETE, "/procedures/:name", "delete_procedure", "Delete a procedure and its versions")
+            .returns::<SuccessResponse>(),
+        op("Jobs", M::GET, "/jobs", "list_jobs", "List scheduled jobs and their status").returns::<JobsResponse>(),
+        op("Jobs", M::PUT, "/jobs/:name", "put_job", "Create or replace a job").body::<PutJobRequest>().returns::<SuccessResponse>(),
+        op("Jobs", M::DELETE, "/jobs/:name", "delete_job", "Delete a job").returns::<SuccessResponse>(),
+        op("Jobs", M::POST, "/jobs/:name/run", "run_job", "Run a job now").returns::<RunJobResponse>(),
+        op("Jobs", M::POST, "/jobs/:name/enable", "enable_job", "Enable a job").returns::<SuccessResponse>(),
+        op("Jobs", M::POST, "/jobs/:name/disable", "disable_job", "Disable a job").returns::<SuccessResponse>(),
+        op("Agents", M::GET, "/agents", "list_agents", "List agents").returns::<Vec<AgentMetadata>>(),
+        op("Agents", M::POST, "/agents", "create_agent", "Register an agent").body::<CreateAgentRequest>().creates::<AgentMetadata>(),
+        op("Agents", M::GET, "/agents/:agent", "get_agent", "Read an agent and its conversations").returns::<AgentResponse>(),
+        op("Agents", M::DELETE, "/agents/:agent", "delete_agent", "Delete an agent").returns::<SuccessResponse>(),
+        op("Agents", M::GET, "/agents/:agent/memories", "recall_memories", "Recall memories by similarity or tags")
+            .query::<RecallParams>()
+            .returns::<Vec<MemoryEntry>>(),
+        op("Agents", M::POST, "/agents/:agent/memories", "store_memory", "Store a memory")
+            .body::<StoreMemoryRequest>()
+            .creates::<IdResponse>(),
+        op("Agents", M::GET, "/agents/:agent/memories/:memory", "get_memory", "Read a memory")
+            .path_param::<u64>("memory")
+            .returns::<MemoryEntry>(),
+        op("Agents", M::DELETE, "/agents/:agent/memories/:memory", "forget_memory", "Forget a memory")
+            .path_param::<u64>("memory")
+            .returns::<SuccessResponse>(),
+        op("Agents", M::GET, "/agents/:agent/conversations", "list_conversations", "List an agent's conversations")
+            .returns::<Vec<String>>(),
+        op("Agents", M::POST, "/agents/:agent/conversations", "create_conversation", "Start a conversation")
+            .body::<CreateConversationRequest>()
+            .creates::<ConversationResponse>(),
+        op("Agents", M::GET, "/agents/:agent/conversations/:conversation", "get_conversation", "Read a conversation")
+            .returns::<ConversationResponse>(),
+        op("Agents", M::GET, "/agents/:agent/conversations/:conversation/messages", "list_messages", "Read messages")
+            .query::<MessagesParams>()
+            .returns::<Vec<MessageResponse>>(),
+        op("Agents", M::POST, "/agents/:agent/conversations/:conversation/messages", "add_message", "Add a message")
+            .body::<AddMessageRequest>()
+            .creates::<IdResponse>(),
+        op("Agents", M::GET, "/agents/:agent/tools/:tool/state/:key", "get_tool_state", "Read a tool's state")
+            .returns::<ToolStateResponse>(),
+        op("Agents", M::PUT, "/agents/:agent/tools/:tool/state/:key", "put_tool_state", "Write a tool's state")
+            .body::<ToolStateRequest>()
+            .returns::<ToolStateResponse>(),
+        op("Agents", M::DELETE, "/agents/:agent/tools/:tool/state/:key", "delete_tool_state", "Delete a tool's state")
+            .returns::<SuccessResponse>(),
+    ]
+}
+
+// ========== Document ==========
+
+/// The OpenAPI document of the HTTP API
+pub fn document() -> &'static Value {
+    static DOCUMENT: LazyLock<Value> = LazyLock::new(build_document);
+    &DOCUMENT
+}
+
+fn build_document() -> Value {
+    let mut schemas = Schemas::default();
+    let error = SuccessResponse::schema(&mut schemas);
+    let mut tags: Vec<&str> = Vec::new();
+    let mut paths = Map::new();
+
+    for op in operations() {
+        if !tags.contains(&op.tag) {
+            tags.push(op.tag);
+        }
+        let mut parameters = Vec::new();
+        for segment in op.path.split('/') {
+            let Some(name) = segment.strip_prefix(':') else { continue };
+            let schema = match op.path_types.iter().find(|(param, _)| *param == name) {
+                Some((_, schema)) => schema(&mut schemas),
+                None => String::schema(&mut schemas),
+            };
+            parameters.push(json!({ "name": name, "in": "path", "required": true, "schema": schema }));
+        }
+        let mut query_type = None;
+        if let Some(query) = op.query {
+            let reference = query(&mut schemas);
+            query_type = Some(reference.clone());
+            let object = schemas.resolve(&reference);
+            let required = object["required"].as_array().cloned().unwrap_or_default();
+            for (name, schema) in object["properties"].as_object().into_iter().flatten() {
+                let mut schema = schema.clone();
+                let description = schema.as_object_mut().and_then(|s| s.remove("description"));
+                let mut parameter = json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&json!(name)),
+                    "schema": schema,
+                });
+                if let Some(description) = description {
+                    parameter["description"] = description;
+                }
+                parameters.push(parameter);
+            }
+        }
+
+        let mut operation = json!({
+            "operationId": op.id,
+            "summary": op.summary,
+            "tags": [op.tag],
+            "responses": {
+                "default": {
+                    "description": "Error",
+                    "headers": {
+                        "Retry-After": {
+                            "description": "Seconds to wait before retrying, sent with 429 and 503",
+                            "schema": { "type": "integer" },
+                        },
+                    },
+                    "content": { JSON: { "schema": error } },
+                },
+            },
+        });
+        if !parameters.is_empty() {
+            operation["parameters"] = parameters.into();
+        }
+        // The type the query parameters come from, so generated clients can take it whole
+        if let Some(reference) = query_type {
+            operation["x-query"] = reference;
+        }
+        if let Some((content_type, schema)) = op.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { content_type: { "schema": schema(&mut schemas) } },
+            });
+        }
+        let mut response = json!({ "description": if op.status == 201 { "Created" } else { "OK" } });
+        if let Some((content_type, schema)) = op.response {
+            response["content"] = json!({ content_type: { "schema": schema(&mut schemas) } });
+        }
+        operation["responses"][op.status.to_string()] = response;
+        if op.public {
+            operation["security"] = json!([]);
+        }
+        if let Some(permission) = api::required_permission(&op.method, op.path) {
+            operation["x-permission"] = permission.into();
+            operation["description"] = format!("Requires the `{}` permission.", permission).into();
+        }
+
+        let path = op
+            .path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        item[op.method.as_str().to_lowercase()] = operation;
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Liath",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Key-value storage, vector search and Lua scripting over HTTP. \
+                Send an API key as `Authorization: Bearer <key>`.",
+        },
+        "tags": tags.iter().map(|tag| json!({ "name": tag })).collect::<Vec<_>>(),
+        "paths": paths,
+        "components": {
+            "schemas": schemas.named,
+            "securitySchemes": { "bearerAuth": { "type": "http", "scheme": "bearer" } },
+        },
+        "security": [{ "bearerAuth": [] }],
+    })
+}
+
+// ========== Docs Page ==========
+
+/// The document rendered as a self-contained HTML page
+pub(super) fn docs_page() -> &'static str {
+    static PAGE: LazyLock<String> = LazyLock::new(|| render_docs(document()));
+    &PAGE
+}
+
+fn render_docs(document: &Value) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Liath HTTP API</title><style>",
+        "body{font-family:system-ui,sans-serif;max-width:60rem;margin:2rem auto;padding:0 1rem;color:#222}",
+        "code,pre{font-family:ui-monospace,monospace;font-size:.9em}pre{background:#f5f5f5;padding:.75rem;overflow:auto}",
+        "section{border-top:1px solid #ddd;padding:.5rem 0}.method{font-weight:bold;text-transform:uppercase;margin-right:.5rem}",
+        "table{border-collapse:collapse}td,th{border:1px solid #ddd;padding:.25rem .5rem;text-align:left}",
+        "</style></head><body>\n",
+    ));
+    html.push_str(&format!(
+        "<h1>Liath HTTP API <small>{}</small></h1>\n<p>{}</p>\n<p>Machine-readable: <a href=\"/openapi.json\">/openapi.json</a></p>\n",
+        escape(document["info"]["version"].as_str().unwrap_or_default()),
+        escape(document["info"]["description"].as_str().unwrap_or_default()),
+    ));
+
+    for tag in document["tags"].as_array().into_iter().flatten() {
+        let tag = tag["name"].as_str().unwrap_or_default();
+        html.push_str(&format!("<h2>{}</h2>\n", escape(tag)));
+        for (path, item) in document["paths"].as_object().into_iter().flatten() {
+            for (method, operation) in item.as_object().into_iter().flatten() {
+                if operation["tags"][0] != tag {
+                    continue;
+                }
+                render_operation(&mut html, method, path, operation);
+            }
+        }
+    }
+
+    html.push_str("<h2>Schemas</h2>\n");
+    for (name, schema) in document["components"]["schemas"].as_object().into_iter().flatten() {
+        html.push_str(&format!(
+            "<section id=\"schema-{0}\"><h3>{0}</h3><pre>{1}</pre></section>\n",
+            escape(name),
+            escape(&serde_json::to_string_pretty(schema).unwrap_or_default()),
+        ));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn render_operation(html: &mut String, method: &str, path: &str, operation: &Value) {
+    html.push_str(&format!(
+        "<section><h3><span class=\"method\">{}</span><code>{}</code></h3><p>{}</p>\n",
+        escape(method),
+        escape(path),
+        escape(operation["summary"].as_str().unwrap_or_default()),
+    ));
+    if let Some(description) = operation["description"].as_str() {
+        html.push_str(&format!("<p>{}</p>\n", escape(description)));
+    }
+    if operation["security"] == json!([]) {
+        html.push_str("<p>No API key needed.</p>\n");
+    }
+    if let Some(parameters) = operation["parameters"].as_array() {
+        html.push_str("<table><tr><th>Parameter</th><th>In</th><th>Type</th><th>Description</th></tr>\n");
+        for parameter in parameters {
+            html.push_str(&format!(
+                "<tr><td><code>{}</code>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(parameter["name"].as_str().unwrap_or_default()),
+                if parameter["required"] == true { " (required)" } else { "" },
+                escape(parameter["in"].as_str().unwrap_or_default()),
+                type_name(&parameter["schema"]),
+                escape(parameter["description"].as_str().unwrap_or_default()),
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    for (content_type, media) in operation["requestBody"]["content"].as_object().into_iter().flatten() {
+        html.push_str(&format!("<p>Body: {} <code>{}</code></p>\n", type_name(&media["schema"]), escape(content_type)));
+    }
+    for (status, response) in operation["responses"].as_object().into_iter().flatten() {
+        if status == "default" {
+            continue;
+        }
+        html.push_str(&format!("<p>{}:", escape(status)));
+        for (content_type, media) in response["content"].as_object().into_iter().flatten() {
+            html.push_str(&format!(" {} <code>{}</code>", type_name(&media["schema"]), escape(content_type)));
+        }
+        html.push_str("</p>\n");
+    }
+    html.push_str("</section>\n");
+}
+
+/// A short name for a schema, linking to named schemas
+fn type_name(schema: &Value) -> String {
+    if let Some(name) = schema["$ref"].as_str().and_then(|r| r.strip_prefix("#/components/schemas/")) {
+        return format!("<a href=\"#schema-{0}\">{0}</a>", escape(name));
+    }
+    if let Some(inner) = schema["allOf"].get(0) {
+        return type_name(inner);
+    }
+    match schema["type"].as_str() {
+        Some("array") => format!("{}[]", type_name(&schema["items"])),
+        Some(name) => name.to_string(),
+        None => "any".to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    /// The property names a type serializes to must be the ones its schema lists
+    fn assert_properties<T: ApiSchema + Serialize>(value: T) {
+        let mut schemas = Schemas::default();
+        let reference = T::schema(&mut schemas);
+        let schema = schemas.resolve(&reference);
+        let mut documented: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+        let serialized = serde_json::to_value(&value).unwrap();
+        let mut actual: Vec<&String> = serialized.as_object().unwrap().keys().collect();
+        documented.sort();
+        actual.sort();
+        assert_eq!(documented, actual, "schema of {}", reference["$ref"]);
+    }
+
+    #[test]
+    fn test_foreign_schemas_match_their_types() {
+        assert_properties(Procedure {
+            name: "p".into(),
+            version: 1,
+            code: String::new(),
+            description: None,
+            owner: "admin".into(),
+            created_at: 0,
+        });
+        assert_properties(Profile::default());
+        assert_properties(FunctionProfile::default());
+        assert_properties(JobStatus::default());
+        assert_properties(AgentMetadata { id: "a".into(), created_at: 0, description: None });
+        assert_properties(MemoryEntry { id: 1, content: String::new(), tags: Vec::new(), distance: 0.0, created_at: 0 });
+        assert_properties(BulkError { line: 1, key: None, error: String::new() });
+        assert_properties(BulkRecord::parse(r#"{"key": "k", "value": 1}"#).unwrap());
+        assert_properties(Change {
+            sequence: 1,
+            timestamp_ms: 0,
+            kind: ChangeKind::Put,
+            namespace: "docs".into(),
+            key: Some("k".into()),
+            id: Some(1),
+        });
+        assert_properties(AuditEntry {
+            timestamp_ms: 0,
+            user_id: "admin".into(),
+            operation: "insert".into(),
+            namespace: None,
+            key: None,
+            key_hash: None,
+            outcome: AuditOutcome::Ok,
+            detail: None,
+        });
+        assert_properties(Job {
+            name: "j".into(),
+            schedule: Schedule::Interval { seconds: 1 },
+            action: JobAction::Lua { code: String::new() },
+            owner: "admin".into(),
+            enabled: true,
+            created_at: 0,
+        });
+    }
+
+    #[test]
+    fn test_document_describes_routes() {
+        let document = document();
+        let query = &document["paths"]["/query"]["post"];
+        assert_eq!(query["operationId"], "execute_query");
+        assert_eq!(query["requestBody"]["content"][JSON]["schema"]["$ref"], "#/components/schemas/QueryRequest");
+
+        let request = &document["components"]["schemas"]["QueryRequest"];
+        assert_eq!(request["required"], json!(["query"]));
+        assert_eq!(request["properties"]["explain"]["description"], "Profile the execution and return the profile with the result");
+
+        let put = &document["paths"]["/kv/{namespace}/{key}"]["put"];
+        assert_eq!(put["x-permission"], "insert");
+        assert_eq!(put["parameters"][1]["name"], "key");
+        assert_eq!(document["paths"]["/health"]["get"]["security"], json!([]));
+
+        let scan = &document["paths"]["/kv/{namespace}"]["get"]["parameters"];
+        assert!(scan.as_array().unwrap().iter().any(|p| p["name"] == "limit" && p["in"] == "query" && p["required"] == false));
+
+        // Every reference resolves
+        let text = document.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(document["components"]["schemas"][name].is_object(), "unresolved schema {}", name);
+        }
+    }
+
+    #[test]
+    fn test_operations_match_routes() {
+        use std::collections::BTreeSet;
+        let documented: BTreeSet<_> = operations().into_iter().map(|op| (op.method.to_string(), op.path, op.public)).collect();
+        let served = |routes: Vec<api::Route>, public: bool| {
+            routes.into_iter().map(move |(method, path, _)| (method.to_string(), path, public))
+        };
+        let served: BTreeSet<_> = served(api::routes(), false).chain(served(api::public_routes(), true)).collect();
+        let missing: Vec<_> = served.difference(&documented).collect();
+        let stale: Vec<_> = documented.difference(&served).collect();
+        assert!(missing.is_empty(), "routes without an operation: {:?}", missing);
+        assert!(stale.is_empty(), "operations without a route: {:?}", stale);
+    }
+
+    #[test]
+    fn test_docs_page_lists_operations() {
+        let page = docs_page();
+        assert!(page.contains("<code>/kv/{namespace}/{key}</code>"));
+        assert!(page.contains("id=\"schema-QueryRequest\""));
+        assert!(!page.contains("<script"));
+    }
+}
@@ -29,6 +29,14 @@ impl UsearchWrapper {
         self.index.add(id, vector).context("Failed to add vector to index")
     }
 
+    pub fn contains(&self, id: u64) -> bool {
+        self.index.contains(id)
+    }
+
+    pub fn remove(&self, id: u64) -> Result<usize> {
+        self.index.remove(id).context("Failed to remove vector from index")
+    }
+
     pub fn search(&self, vector: &[f32], k: usize) -> Result<Vec<(u64, f32)>> {
         let results = self.index.search(vector, k).context("Failed to perform search")?;
         Ok(results.keys.into_iter().zip(results.distances).collect())
@@ -42,6 +50,18 @@ impl UsearchWrapper {
         self.index.load(path).context("Failed to save index")
     }
 
+    /// Serialize the index into memory, e.g. to encrypt it before writing
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; self.index.serialized_length()];
+        self.index.save_to_buffer(&mut buffer).context("Failed to serialize index")?;
+        Ok(buffer)
+    }
+
+    /// Replace the index with one serialized by [`to_bytes`](Self::to_bytes)
+    pub fn load_bytes(&self, buffer: &[u8]) -> Result<()> {
+        self.index.load_from_buffer(buffer).context("Failed to deserialize index")
+    }
+
     pub fn view(&self,path: &str) -> Result<()> {
         self.index.view(path).context("Failed to save index")
     }
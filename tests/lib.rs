@@ -504,6 +504,743 @@ async fn test_query_executor_kv_via_lua() {
     assert_eq!(result.unwrap(), "myvalue");
 }
 
+#[tokio::test]
+async fn test_stored_procedures() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("proc_ns", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+
+    let v1 = liath.register_procedure(
+        "remember",
+        r#"insert("proc_ns", args.key, args.value) return { stored = args.key }"#,
+        Some("Store a value"),
+    ).unwrap();
+    assert_eq!(v1.version, 1);
+
+    // Call from Rust with JSON args
+    let result = liath.call_procedure("remember", serde_json::json!({"key": "k1", "value": "v1"})).await.unwrap();
+    assert_eq!(result, serde_json::json!({"stored": "k1"}));
+    assert_eq!(liath.get("proc_ns", b"k1").unwrap(), Some(b"v1".to_vec()));
+
+    // Call from Lua
+    let result = liath.execute_lua(r#"return call("remember", {key = "k2", value = "v2"}).stored"#).await.unwrap();
+    assert_eq!(result, serde_json::json!("k2"));
+
+    // New versions keep history
+    let v2 = liath.register_procedure("remember", "return 'v2'", None).unwrap();
+    assert_eq!(v2.version, 2);
+    let executor = liath.query_executor();
+    assert_eq!(executor.procedure_history("remember").unwrap().len(), 2);
+
+    // Callers without permission are rejected
+    assert!(executor.call_procedure("remember", serde_json::Value::Null, "nobody").await.is_err());
+
+    // Only the owner or an admin may replace a procedure
+    executor.add_user("bob", vec!["register_procedure".to_string()], "admin").unwrap();
+    let err = executor.register_procedure("remember", "return 'bob'", None, "bob").unwrap_err();
+    assert!(err.to_string().contains("may not replace"), "unexpected error: {}", err);
+    assert_eq!(executor.get_procedure("remember").unwrap().unwrap().version, 2);
+    executor.register_procedure("bobs", "return 1", None, "bob").unwrap();
+    assert_eq!(executor.register_procedure("bobs", "return 2", None, "bob").unwrap().version, 2);
+    assert_eq!(liath.register_procedure("bobs", "return 3", None).unwrap().version, 3);
+
+    // Listing procedures shows their code and owners, so it takes `call_procedure`
+    assert!(executor.execute("return #list_procedures()", "bob").await.is_err());
+    assert_eq!(liath.execute_lua("return #list_procedures()").await.unwrap(), serde_json::json!(2));
+
+    // The store is read-only to scripts; a forged record would run as the owner it names
+    let writer = ["insert", "delete", "delete_namespace"].iter().map(|p| p.to_string()).collect();
+    executor.add_user("mallory", writer, "admin").unwrap();
+    let forged = r#"insert("_procedures", "proc:remember", '{"name":"remember","owner":"admin"}')"#;
+    assert!(executor.execute(forged, "mallory").await.is_err());
+    assert!(executor.execute("delete_namespace('_procedures')", "mallory").await.is_err());
+    assert_eq!(executor.get_procedure("remember").unwrap().unwrap().version, 2);
+
+    // Invalid code is rejected at registration
+    assert!(liath.register_procedure("bad", "return os.execute('ls')", None).is_err());
+
+    // Runaway recursion is stopped
+    liath.register_procedure("loop", "return call('loop')", None).unwrap();
+    let err = liath.call_procedure("loop", serde_json::Value::Null).await.unwrap_err();
+    assert!(err.to_string().contains("depth"), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn test_namespace_triggers() {
+    use liath::{EmbeddedLiath, Config, TriggerEvent, TriggerTiming};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    {
+        let liath = EmbeddedLiath::new(config.clone()).unwrap();
+        liath.create_namespace("notes", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+        liath.create_namespace("mirror", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+
+        // Reject empty values, upper-case everything else
+        liath.create_trigger("notes", "validate", TriggerEvent::Put, TriggerTiming::Before,
+            r#"if event.value == "" then return false end return string.upper(event.value)"#).unwrap();
+        // Mirror writes and count them in the same namespace (must not recurse)
+        liath.create_trigger("notes", "mirror", TriggerEvent::Put, TriggerTiming::After, r#"
+            insert("mirror", event.key, event.value)
+            local n = tonumber(select("notes", "_count") or "0") + 1
+            insert("notes", "_count", tostring(n))
+        "#).unwrap();
+        liath.create_trigger("notes", "unmirror", TriggerEvent::Delete, TriggerTiming::After,
+            r#"delete("mirror", event.key)"#).unwrap();
+
+        liath.put("notes", b"a", b"hello").unwrap();
+        assert_eq!(liath.get("notes", b"a").unwrap(), Some(b"HELLO".to_vec()));
+        assert_eq!(liath.get("mirror", b"a").unwrap(), Some(b"HELLO".to_vec()));
+
+        liath.execute_lua(r#"insert("notes", "b", "world")"#).await.unwrap();
+        assert_eq!(liath.get("mirror", b"b").unwrap(), Some(b"WORLD".to_vec()));
+        assert_eq!(liath.get("notes", b"_count").unwrap(), Some(b"2".to_vec()));
+
+        assert!(liath.put("notes", b"c", b"").is_err());
+        assert_eq!(liath.get("notes", b"c").unwrap(), None);
+
+        // Writes a trigger rejects use no storage quota
+        liath.add_user("writer", vec!["insert".to_string(), "select".to_string()]).unwrap();
+        let executor = liath.query_executor();
+        let usage = || executor.auth_manager().read().unwrap().usage("writer").storage_bytes;
+        assert!(executor.put_as("notes", b"c", b"", "writer").is_err());
+        assert!(executor.execute(r#"insert("notes", "c", "")"#, "writer").await.is_err());
+        assert_eq!(usage(), 0);
+        executor.put_as("notes", b"c", b"ok", "writer").unwrap();
+        assert_eq!(usage(), 3);
+
+        liath.delete("notes", b"a").unwrap();
+        assert_eq!(liath.get("mirror", b"a").unwrap(), None);
+        liath.close().unwrap();
+    }
+
+    // Triggers are persisted with the namespace
+    let liath = EmbeddedLiath::new(config).unwrap();
+    assert_eq!(liath.query_executor().list_triggers("notes").len(), 3);
+    assert_eq!(liath.execute_lua(r#"return #list_triggers("notes")"#).await.unwrap(), serde_json::json!(3));
+    // Listing triggers takes `manage_triggers` on the namespace
+    liath.add_user("reader", vec!["select".to_string()]).unwrap();
+    assert!(liath.query_executor().execute(r#"return #list_triggers("notes")"#, "reader").await.is_err());
+    liath.put("notes", b"d", b"again").unwrap();
+    assert_eq!(liath.get("mirror", b"d").unwrap(), Some(b"AGAIN".to_vec()));
+
+    // Binary values reach triggers as raw bytes
+    liath.create_namespace("blobs", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.create_trigger("blobs", "length", TriggerEvent::Put, TriggerTiming::Before,
+        r#"if #event.value ~= 4 then return false end return event.value"#).unwrap();
+    let blob = [0xff, 0x00, 0xfe, 0x80];
+    liath.put("blobs", b"\xffkey", &blob).unwrap();
+    assert_eq!(liath.get("blobs", b"\xffkey").unwrap(), Some(blob.to_vec()));
+
+    // A failing after-trigger does not undo or fail the write
+    liath.create_trigger("blobs", "broken", TriggerEvent::Put, TriggerTiming::After, r#"error("boom")"#).unwrap();
+    liath.put("blobs", b"k", b"vvvv").unwrap();
+    assert_eq!(liath.get("blobs", b"k").unwrap(), Some(b"vvvv".to_vec()));
+}
+
+#[tokio::test]
+async fn test_lua_modules() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+
+    let v1 = liath.put_module(
+        "text.utils",
+        "local M = {} function M.shout(s) return s:upper() .. '!' end return M",
+        Some("String helpers"),
+    ).unwrap();
+    assert_eq!(v1.version, 1);
+
+    let result = liath.execute_lua(r#"return require("text.utils").shout("hi")"#).await.unwrap();
+    assert_eq!(result, serde_json::json!("HI!"));
+
+    // Repeated requires reuse the cached module
+    let result = liath.execute_lua(r#"return require("text.utils") == require("text.utils")"#).await.unwrap();
+    assert_eq!(result, serde_json::json!(true));
+
+    // A new version takes effect on the next require; older versions stay loadable
+    let version = liath.execute_lua(
+        r#"return module_put("text.utils", "return { shout = function(s) return s .. '?' end }")"#
+    ).await.unwrap();
+    assert_eq!(version, serde_json::json!(2));
+    let result = liath.execute_lua(r#"return require("text.utils").shout("hi") .. require("text.utils", 1).shout("hi")"#).await.unwrap();
+    assert_eq!(result, serde_json::json!("hi?HI!"));
+
+    // Bundled modules resolve without being stored
+    let result = liath.execute_lua(r#"return type(require("liath").kv.get)"#).await.unwrap();
+    assert_eq!(result, serde_json::json!("function"));
+
+    // Modules can require each other, but not in a cycle
+    liath.put_module("cycle.a", "return require('cycle.b')", None).unwrap();
+    liath.put_module("cycle.b", "return require('cycle.a')", None).unwrap();
+    let err = liath.execute_lua(r#"return require("cycle.a")"#).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("Circular require"));
+
+    // Unknown modules, filesystem modules and unprivileged writers are rejected
+    assert!(liath.execute_lua(r#"return require("missing")"#).await.is_err());
+    assert!(liath.put_module("liath", "return {}", None).is_err());
+    assert!(liath.put_module("bad", "return io.open('/etc/passwd')", None).is_err());
+    let executor = liath.query_executor();
+    assert!(executor.put_module("other", "return {}", None, "nobody").is_err());
+    executor.add_user("mallory", vec!["insert".to_string()], "admin").unwrap();
+    let planted = r#"insert("_modules", "mod:text.utils", '{"name":"text.utils","code":"return {}"}')"#;
+    assert!(executor.execute(planted, "mallory").await.is_err());
+
+    let names: Vec<String> = executor.list_modules().unwrap().into_iter().map(|m| m.name).collect();
+    assert_eq!(names, vec!["cycle.a", "cycle.b", "text.utils"]);
+
+    // Modules act as the user who required them, even if another user loaded them first
+    liath.create_namespace("secrets", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.put("secrets", b"k", b"v").unwrap();
+    liath.put_module("peek", "return { get = function(k) return select('secrets', k) end }", None).unwrap();
+    assert_eq!(liath.execute_lua(r#"return require("peek").get("k")"#).await.unwrap(), serde_json::json!("v"));
+    executor.add_user("bob", vec!["register_procedure".to_string()], "admin").unwrap();
+    executor.register_procedure("peek", r#"return require("peek").get("k")"#, None, "bob").unwrap();
+    let err = liath.call_procedure("peek", serde_json::Value::Null).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("Unauthorized"), "unexpected error: {:#}", err);
+}
+
+#[tokio::test]
+async fn test_execute_profiled() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("notes", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+
+    let executor = liath.query_executor();
+    let (result, profile) = executor.execute_profiled(r#"
+        insert("notes", "a", "hello")
+        insert("notes", "b", "world")
+        return select("notes", "a")
+    "#, "admin").await.unwrap();
+    assert_eq!(result, "hello");
+
+    let insert = profile.functions.iter().find(|f| f.name == "insert").unwrap();
+    assert_eq!(insert.calls, 2);
+    assert!(insert.bytes_written >= 10);
+    let select = profile.functions.iter().find(|f| f.name == "select").unwrap();
+    assert_eq!(select.calls, 1);
+    assert_eq!(select.bytes_read, 5);
+    assert!(profile.total_us >= profile.lua_us);
+
+    // A script that calls no database functions has an empty profile
+    let (_, profile) = executor.execute_profiled("return 1", "admin").await.unwrap();
+    assert!(profile.functions.is_empty());
+}
+
+#[tokio::test]
+async fn test_scan_iterators_and_cursors() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("items", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    for i in 0..25 {
+        liath.put("items", format!("item:{:02}", i).as_bytes(), i.to_string().as_bytes()).unwrap();
+    }
+    liath.put("items", b"other", b"x").unwrap();
+
+    // Lazy iteration visits every matching key without a limit
+    let count = liath.execute_lua(r#"
+        local n = 0
+        for key, value in iter("items", "item:") do n = n + 1 end
+        return n
+    "#).await.unwrap();
+    assert_eq!(count, serde_json::json!(25));
+
+    // Paging with cursors covers the same keys exactly once
+    let keys = liath.execute_lua(r#"
+        local keys, cursor = {}, nil
+        repeat
+            local page = scan_page("items", "item:", 10, cursor)
+            for _, entry in ipairs(page.entries) do table.insert(keys, entry.key) end
+            cursor = page.cursor
+        until cursor == nil
+        return #keys .. " " .. keys[1] .. " " .. keys[#keys]
+    "#).await.unwrap();
+    assert_eq!(keys, serde_json::json!("25 item:00 item:24"));
+
+    // The Rust API hands out cursors that resume after the last entry seen
+    let executor = liath.query_executor();
+    let page = executor.scan_page("items", "item:", 20, None, "admin").unwrap();
+    assert_eq!(page.entries.len(), 20);
+    let rest = executor.scan_iter("items", "item:", page.cursor.as_deref(), "admin").unwrap();
+    assert_eq!(rest.count(), 5);
+    assert!(executor.scan_page("items", "other", 10, page.cursor.as_deref(), "admin").is_err());
+}
+
+#[test]
+fn test_bulk_ingest_batches() {
+    use liath::{EmbeddedLiath, Config};
+    use liath::query::BulkRecord;
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("docs", 3, MetricKind::Cos, ScalarKind::F32).unwrap();
+    let executor = liath.query_executor();
+
+    let records = vec![
+        (1, BulkRecord::parse(r#"{"key": "a", "value": "alpha", "vector": [1, 0, 0], "metadata": {"tag": "x"}}"#).unwrap()),
+        (2, BulkRecord::parse(r#"{"key": "b", "value": {"n": 2}, "vector": [0, 1, 0], "id": 7}"#).unwrap()),
+        (3, BulkRecord::parse(r#"{"key": "c", "value": "short", "vector": [1, 0]}"#).unwrap()),
+        (4, BulkRecord::parse(r#"{"key": "d", "value": "plain"}"#).unwrap()),
+    ];
+    let report = executor.bulk_ingest("docs", records.clone(), 100, "admin").unwrap();
+    assert_eq!(report.written, 3);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!((report.errors[0].line, report.errors[0].key.as_deref()), (3, Some("c")));
+
+    assert_eq!(liath.get("docs", b"b").unwrap(), Some(br#"{"n":2}"#.to_vec()));
+    assert_eq!(liath.get("docs", b"_meta:a").unwrap(), Some(br#"{"tag":"x"}"#.to_vec()));
+    assert_eq!(liath.get("docs", b"_vidx:7").unwrap(), Some(b"b".to_vec()));
+    let nearest = executor.similarity_search("docs", &[0.0, 1.0, 0.0], 1).unwrap();
+    assert_eq!(nearest[0].0, 7);
+
+    // Ingesting the same records again replaces their vectors
+    let report = executor.bulk_ingest("docs", records, 0, "admin").unwrap();
+    assert_eq!(report.written, 3);
+    assert_eq!(executor.similarity_search("docs", &[1.0, 0.0, 0.0], 10).unwrap().len(), 2);
+
+    executor.add_user("reader", vec!["select".to_string()], "admin").unwrap();
+    assert!(executor.bulk_ingest("docs", Vec::new(), 0, "reader").is_err());
+}
+
+#[tokio::test]
+async fn test_change_feed_subscription() {
+    use liath::{EmbeddedLiath, Config, ChangeFilter, ChangeKind};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let filter = ChangeFilter { namespace: Some("docs".to_string()), ..Default::default() };
+    let mut changes = liath.subscribe(filter.clone()).unwrap();
+
+    liath.create_namespace("docs", 3, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.put("docs", b"k1", b"v1").unwrap();
+    liath.execute_lua(r#"delete("docs", "k1")"#).await.unwrap();
+
+    let kinds: Vec<ChangeKind> = [
+        changes.next().await.unwrap().unwrap(),
+        changes.next().await.unwrap().unwrap(),
+        changes.next().await.unwrap().unwrap(),
+    ].iter().map(|c| c.kind).collect();
+    assert_eq!(kinds, vec![ChangeKind::CreateNamespace, ChangeKind::Put, ChangeKind::Delete]);
+
+    // A new subscription resumes from a sequence number
+    let since = changes.position() - 1;
+    let mut resumed = liath.subscribe(ChangeFilter { since: Some(since), ..filter }).unwrap();
+    let change = resumed.next().await.unwrap().unwrap();
+    assert_eq!((change.kind, change.key.as_deref()), (ChangeKind::Delete, Some("k1")));
+
+    // Users only see changes to namespaces they may read
+    let executor = liath.query_executor();
+    executor.add_user("outsider", Vec::new(), "admin").unwrap();
+    assert!(executor.subscribe(ChangeFilter { namespace: Some("docs".to_string()), ..Default::default() }, "outsider").is_err());
+}
+
+#[tokio::test]
+async fn test_api_keys_persist() {
+    use liath::{EmbeddedLiath, Config};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    let token = {
+        let liath = EmbeddedLiath::new(config.clone()).unwrap();
+        let executor = liath.query_executor();
+        executor.add_user("agent", vec!["select".to_string()], "admin").unwrap();
+        assert!(executor.create_api_key("agent", None, "agent").is_err());
+        let (info, token) = executor.create_api_key("agent", Some("ci"), "admin").unwrap();
+        assert_eq!(info.user_id, "agent");
+        liath.save().unwrap();
+        token
+    };
+
+    // Users and keys are loaded again on the next start
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let executor = liath.query_executor();
+    assert!(executor.is_authorized("agent", "select"));
+    assert_eq!(executor.auth_manager().read().unwrap().authenticate(&token).as_deref(), Some("agent"));
+
+    let keys = executor.list_api_keys(Some("agent"), "admin").unwrap();
+    executor.revoke_api_key(&keys[0].id, "admin").unwrap();
+    assert!(executor.auth_manager().read().unwrap().authenticate(&token).is_none());
+}
+
+#[tokio::test]
+async fn test_namespace_names_cannot_reach_system_stores() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    let token = {
+        let liath = EmbeddedLiath::new(config.clone()).unwrap();
+        let executor = liath.query_executor();
+        let (_, token) = executor.create_api_key("admin", None, "admin").unwrap();
+
+        // The auth store and namespace metadata live beside namespace directories
+        for name in ["_auth", "_metadata", "", "..", "../escape", "a/b", "a\\b"] {
+            assert!(liath.create_namespace(name, 4, MetricKind::Cos, ScalarKind::F32).is_err(), "created {:?}", name);
+            assert!(executor.create_namespace_as(name, 4, MetricKind::Cos, ScalarKind::F32, false, "admin").is_err());
+        }
+        assert!(executor.delete_namespace_as("_auth", "admin").is_err());
+        assert!(executor.execute("delete_namespace('_auth')", "admin").await.is_err());
+        assert!(!temp_dir.path().parent().unwrap().join("escape").exists());
+        liath.save().unwrap();
+        token
+    };
+
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let auth = liath.query_executor().auth_manager();
+    assert_eq!(auth.read().unwrap().authenticate(&token).as_deref(), Some("admin"));
+}
+
+#[tokio::test]
+async fn test_embedded_user_management() {
+    use liath::{EmbeddedLiath, Config};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        admin_user: "root".to_string(),
+        ..Default::default()
+    };
+
+    {
+        let liath = EmbeddedLiath::new(config.clone()).unwrap();
+        assert_eq!(liath.list_users().unwrap()[0].user_id, "root");
+        liath.add_user("reader", vec!["select".to_string()]).unwrap();
+        liath.grant("reader", "similarity_search@docs_*").unwrap();
+        liath.revoke("reader", "select").unwrap();
+        liath.add_user("temp", vec![]).unwrap();
+        liath.remove_user("temp").unwrap();
+        liath.save().unwrap();
+    }
+
+    // Users survive a restart and no default admin is added to an existing store
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let users = liath.list_users().unwrap();
+    let ids: Vec<&str> = users.iter().map(|u| u.user_id.as_str()).collect();
+    assert_eq!(ids, vec!["reader", "root"]);
+    let executor = liath.query_executor();
+    assert!(!executor.is_authorized("reader", "select"));
+    assert!(executor.is_authorized_for("reader", "similarity_search", "docs_api"));
+    assert!(!executor.is_authorized("admin", "select"));
+}
+
+#[tokio::test]
+async fn test_namespace_scoped_permissions() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    for ns in ["agent_7_memory", "agent_8_memory", "docs_api"] {
+        liath.create_namespace(ns, 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    }
+    let executor = liath.query_executor();
+    executor.define_role("docs_reader", vec!["select@docs_*".to_string(), "list_namespaces@docs_*".to_string()], "admin").unwrap();
+    executor.add_user("agent_7", vec!["*@agent_7_*".to_string(), "!delete@agent_7_*".to_string()], "admin").unwrap();
+    executor.assign_role("agent_7", "docs_reader", "admin").unwrap();
+
+    let ok = executor.execute("insert('agent_7_memory', 'k', 'v'); return select('agent_7_memory', 'k')", "agent_7").await;
+    assert_eq!(ok.unwrap(), "v");
+    assert!(executor.execute("return select('docs_api', 'missing') == nil", "agent_7").await.is_ok());
+    assert!(executor.execute("insert('agent_8_memory', 'k', 'v')", "agent_7").await.is_err());
+    assert!(executor.execute("insert('docs_api', 'k', 'v')", "agent_7").await.is_err());
+    // Deny rules override the wildcard grant
+    assert!(executor.execute("delete('agent_7_memory', 'k')", "agent_7").await.is_err());
+
+    assert!(executor.scan_page("agent_8_memory", "", 10, None, "agent_7").is_err());
+    let mut visible = executor.visible_namespaces("agent_7");
+    visible.sort();
+    assert_eq!(visible, vec!["agent_7_memory".to_string(), "docs_api".to_string()]);
+}
+
+#[tokio::test]
+async fn test_scripts_cannot_leave_code_for_other_users() {
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let liath = EmbeddedLiath::new(Config { data_dir: temp_dir.path().to_path_buf(), ..Default::default() }).unwrap();
+    liath.create_namespace("secrets", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.create_namespace("loot", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.put("secrets", b"k", b"v").unwrap();
+    let executor = liath.query_executor();
+    executor.add_user("mallory", vec!["*@loot".to_string()], "admin").unwrap();
+
+    // Helpers and library functions a script replaces are gone by the next script
+    executor.execute(r#"
+        liath.kv.get = function(ns, key) insert("loot", key, select(ns, key)) return "tampered" end
+        string.upper = liath.kv.get
+        helper = liath.kv.get
+    "#, "mallory").await.unwrap();
+    let result = executor.execute(r#"return liath.kv.get("secrets", "k") .. string.upper("x") .. tostring(helper)"#, "admin").await;
+    assert_eq!(result.unwrap(), "vXnil");
+    assert_eq!(liath.get("loot", b"k").unwrap(), None);
+
+    // The stdlib acts as the user running it
+    assert!(executor.execute(r#"return liath.kv.get("secrets", "k")"#, "mallory").await.is_err());
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    use liath::{AuditFilter, AuditOutcome, EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("docs", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.add_user("writer", vec!["insert".to_string(), "select".to_string()]).unwrap();
+    let executor = liath.query_executor();
+
+    executor.execute("insert('docs', 'k1', 'v')", "writer").await.unwrap();
+    assert!(executor.execute("delete('docs', 'k1')", "writer").await.is_err());
+    executor.put_as("docs", b"k2", b"v", "writer").unwrap();
+
+    let writer = AuditFilter { user_id: Some("writer".to_string()), ..Default::default() };
+    let entries = liath.audit_log(&writer).unwrap();
+    let summary: Vec<(&str, Option<&str>, AuditOutcome)> = entries
+        .iter()
+        .map(|e| (e.operation.as_str(), e.key.as_deref(), e.outcome))
+        .collect();
+    assert_eq!(summary, vec![
+        ("insert", Some("k1"), AuditOutcome::Ok),
+        ("delete", Some("k1"), AuditOutcome::Denied),
+        ("put", Some("k2"), AuditOutcome::Ok),
+    ]);
+    assert!(entries.iter().all(|e| e.namespace.as_deref() == Some("docs")));
+
+    // The log is read-only, even for the admin, and reading it takes `read_audit`
+    assert!(executor.execute("insert('_audit', 'entry:0', 'forged')", "admin").await.is_err());
+    assert!(executor.execute("delete_namespace('_audit')", "admin").await.is_err());
+    assert!(executor.audit_log(&AuditFilter::default(), "writer").is_err());
+    assert!(executor.execute("return select('_audit', 'entry:0') == nil", "writer").await.is_err());
+
+    let denied = liath.audit_log(&AuditFilter::default()).unwrap();
+    assert!(denied.iter().any(|e| e.user_id == "writer" && e.operation == "read_audit" && e.outcome == AuditOutcome::Denied));
+    assert!(denied.iter().any(|e| e.user_id == "admin" && e.namespace.as_deref() == Some("_audit") && e.outcome == AuditOutcome::Denied));
+}
+
+#[tokio::test]
+async fn test_declarative_queries() {
+    use liath::{EmbeddedLiath, Config};
+    use serde_json::json;
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("users", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.create_namespace("orders", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+
+    liath.put("users", b"user:1", br#"{"name": "Ada", "age": 36, "city": "London"}"#).unwrap();
+    liath.put("users", b"user:2", br#"{"name": "Grace", "age": 45, "city": "New York"}"#).unwrap();
+    liath.put("users", b"user:3", br#"{"name": "Alan", "age": 41, "city": "London"}"#).unwrap();
+    liath.put("users", b"note", b"not a user").unwrap();
+    liath.put("orders", b"o1", br#"{"user": "user:1", "total": 10}"#).unwrap();
+    liath.put("orders", b"o2", br#"{"user": "user:1", "total": 25.5}"#).unwrap();
+    liath.put("orders", b"o3", br#"{"user": "user:2", "total": 7}"#).unwrap();
+    liath.put("orders", b"o4", br#"{"user": "user:9", "total": 1}"#).unwrap();
+
+    // Filter, order, limit on JSON fields
+    let result = liath.query("SELECT name, age FROM users WHERE key LIKE 'user:%' AND age > 40 ORDER BY age DESC").unwrap();
+    assert_eq!(result.columns, vec!["name", "age"]);
+    assert_eq!(result.rows, vec![vec![json!("Grace"), json!(45)], vec![json!("Alan"), json!(41)]]);
+
+    let result = liath.query("SELECT key FROM users WHERE key LIKE 'user:%' LIMIT 1 OFFSET 1").unwrap();
+    assert_eq!(result.rows, vec![vec![json!("user:2")]]);
+
+    let result = liath.query("SELECT * FROM users WHERE key = 'note'").unwrap();
+    assert_eq!(result.to_objects(), vec![json!({"key": "note", "value": "not a user"})]);
+
+    // Aggregates
+    let result = liath.query("SELECT city, COUNT(*) AS n, AVG(age) AS avg_age FROM users WHERE age IS NOT NULL GROUP BY city ORDER BY n DESC").unwrap();
+    assert_eq!(result.rows, vec![
+        vec![json!("London"), json!(2), json!(38.5)],
+        vec![json!("New York"), json!(1), json!(45)],
+    ]);
+    let result = liath.query("SELECT COUNT(*), SUM(total), MIN(total), MAX(total) FROM orders").unwrap();
+    assert_eq!(result.rows, vec![vec![json!(4), json!(43.5), json!(1), json!(25.5)]]);
+
+    // Joins on keys
+    let result = liath.query(
+        "SELECT u.name AS name, SUM(o.total) AS spent FROM orders o JOIN users u ON u.key = o.user GROUP BY u.name ORDER BY spent DESC"
+    ).unwrap();
+    assert_eq!(result.rows, vec![vec![json!("Ada"), json!(35.5)], vec![json!("Grace"), json!(7)]]);
+
+    let result = liath.query("SELECT o.key, u.name FROM orders o LEFT JOIN users u ON u.key = o.user WHERE o.total < 5").unwrap();
+    assert_eq!(result.columns, vec!["key", "name"]);
+    assert_eq!(result.rows, vec![vec![json!("o4"), json!(null)]]);
+
+    // EXPLAIN shows the access path
+    let plan = liath.query("EXPLAIN SELECT * FROM users WHERE key LIKE 'user:%'").unwrap();
+    assert_eq!(plan.rows[0], vec![json!("Scan users (prefix 'user:')")]);
+
+    // From Lua
+    let result = liath.execute_lua(r#"
+        local rows = sql("SELECT name FROM users WHERE city = 'London' ORDER BY name")
+        return rows[1].name .. "," .. rows[2].name
+    "#).await.unwrap();
+    assert_eq!(result, json!("Ada,Alan"));
+
+    // Errors and permissions
+    assert!(liath.query("SELECT * FROM missing").is_err());
+    let err = liath.query("SELECT name FROM users GROUP BY city").unwrap_err();
+    assert!(err.to_string().contains("GROUP BY"));
+    assert!(liath.query_executor().query("SELECT * FROM users", "nobody").is_err());
+}
+
+#[tokio::test]
+async fn test_scheduler_jobs() {
+    use liath::{EmbeddedLiath, Config};
+    use liath::scheduler::{JobAction, Schedule};
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    {
+        let liath = EmbeddedLiath::new(config.clone()).unwrap();
+        liath.create_namespace("jobs", 128, MetricKind::Cos, ScalarKind::F32).unwrap();
+        let scheduler = liath.scheduler();
+
+        scheduler.add_job("tick", Schedule::every("1h").unwrap(), JobAction::Lua {
+            code: r#"insert("jobs", "ticked", "yes") return "done""#.to_string(),
+        }, "admin").unwrap();
+
+        // Not due yet
+        assert!(scheduler.run_due().await.unwrap().is_empty());
+
+        let status = scheduler.run_job("tick", "admin").await.unwrap();
+        assert_eq!(status.run_count, 1);
+        assert_eq!(status.last_success, Some(true));
+        assert_eq!(status.last_result, Some(serde_json::json!("done")));
+        assert!(status.next_run_at.unwrap() >= status.last_run_at.unwrap() + 3600);
+        assert_eq!(liath.get("jobs", b"ticked").unwrap(), Some(b"yes".to_vec()));
+
+        // Rust callbacks
+        scheduler.register_callback("count", |qe| {
+            Ok(serde_json::json!(qe.list_namespaces().len()))
+        });
+        scheduler.add_job("count", Schedule::cron("@daily").unwrap(),
+            JobAction::Callback { name: "count".to_string() }, "admin").unwrap();
+        let status = liath.scheduler().run_job("count", "admin").await.unwrap();
+        assert_eq!(status.last_success, Some(true));
+
+        // Failures are recorded, not propagated
+        scheduler.add_job("broken", Schedule::every("5m").unwrap(),
+            JobAction::Lua { code: "error('boom')".to_string() }, "admin").unwrap();
+        let status = scheduler.run_job("broken", "admin").await.unwrap();
+        assert_eq!(status.last_success, Some(false));
+        assert!(status.last_error.unwrap().contains("boom"));
+
+        assert!(scheduler.add_job("bad", Schedule::Cron { expression: "61 * * * *".to_string() },
+            JobAction::Lua { code: "return 1".to_string() }, "admin").is_err());
+        assert!(scheduler.add_job("nope", Schedule::every("1m").unwrap(),
+            JobAction::Lua { code: "return 1".to_string() }, "guest").is_err());
+
+        // The job store is read-only to scripts; a forged job would run as the owner it names
+        liath.add_user("mallory", vec!["insert".to_string(), "delete_namespace".to_string()]).unwrap();
+        let forged = r#"insert("_scheduler", "job:evil", '{"name":"evil","owner":"admin"}')"#;
+        assert!(liath.query_executor().execute(forged, "mallory").await.is_err());
+        assert!(liath.query_executor().put_as("_scheduler", b"job:evil", b"{}", "mallory").is_err());
+        assert!(liath.query_executor().execute("delete_namespace('_scheduler')", "mallory").await.is_err());
+        assert!(scheduler.job("evil").unwrap().is_none());
+
+        scheduler.set_enabled("broken", false, "admin").unwrap();
+        liath.close().unwrap();
+    }
+
+    // Definitions and status survive a restart
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let jobs = liath.scheduler().list_jobs().unwrap();
+    assert_eq!(jobs.len(), 3);
+    let (job, status) = liath.scheduler().job("tick").unwrap().unwrap();
+    assert!(job.enabled);
+    assert_eq!(status.run_count, 1);
+    assert!(!liath.scheduler().job("broken").unwrap().unwrap().0.enabled);
+
+    // A started scheduler runs due jobs in the background until stopped
+    liath.scheduler().add_job("often", Schedule::every("1s").unwrap(), JobAction::Lua {
+        code: r#"insert("jobs", "often", "ran") return 1"#.to_string(),
+    }, "admin").unwrap();
+    let handle = liath.start_scheduler().unwrap();
+    for _ in 0..50 {
+        if liath.get("jobs", b"often").unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    drop(handle);
+    assert_eq!(liath.get("jobs", b"often").unwrap(), Some(b"ran".to_vec()));
+    let runs = liath.scheduler().job("often").unwrap().unwrap().1.run_count;
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(liath.scheduler().job("often").unwrap().unwrap().1.run_count, runs);
+}
+
 // ============================================================
 // AGENT MODULE INTEGRATION TESTS
 // ============================================================
@@ -668,6 +1405,31 @@ fn test_agent_persistence() {
     }
 }
 
+#[test]
+fn test_agent_conversation_listing() {
+    use liath::{EmbeddedLiath, Config};
+    use liath::agent::{Agent, Conversation};
+    use std::sync::Arc;
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let db = Arc::new(EmbeddedLiath::new(config).unwrap());
+    let agent = Agent::new("lister", db.clone());
+    let other = Agent::new("lister2", db.clone());
+
+    assert!(agent.conversations().unwrap().is_empty());
+    Conversation::create_with_id("b", "lister", db.clone()).unwrap();
+    Conversation::create_with_id("a", "lister", db.clone()).unwrap();
+    Conversation::create_with_id("c", "lister2", db.clone()).unwrap();
+
+    assert_eq!(agent.conversations().unwrap(), vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(other.conversations().unwrap(), vec!["c".to_string()]);
+    assert_eq!(agent.conversation(Some("a")).unwrap().message_count(), 0);
+}
+
 #[test]
 fn test_agent_list_and_delete() {
     use liath::{EmbeddedLiath, Config};
@@ -734,4 +1496,568 @@ fn test_semantic_search_with_content_mapping() {
         first_result.1.contains("fox") || first_result.1.contains("Fox"),
         "First result should be about foxes: {}", first_result.1
     );
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_encryption_at_rest() {
+    use liath::{EmbeddedLiath, EncryptionConfig, Config, KeyRing};
+    use usearch::{MetricKind, ScalarKind};
+
+    fn on_disk(dir: &std::path::Path, needle: &[u8]) -> bool {
+        std::fs::read_dir(dir).unwrap().any(|entry| {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                return on_disk(&path, needle);
+            }
+            std::fs::read(&path).unwrap().windows(needle.len()).any(|w| w == needle)
+        })
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let first_key = (1, KeyRing::generate_key());
+    let config = |keys: Vec<(u32, [u8; 32])>| Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        encryption: EncryptionConfig {
+            keys,
+            namespaces: vec!["agent_*".to_string()],
+            encrypt_files: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let file_id = {
+        let liath = EmbeddedLiath::new(config(vec![first_key])).unwrap();
+        liath.create_namespace("agent_7_memory", 2, MetricKind::Cos, ScalarKind::F32).unwrap();
+        liath.create_encrypted_namespace("notes", 2, MetricKind::Cos, ScalarKind::F32).unwrap();
+        assert!(liath.is_encrypted("agent_7_memory") && liath.is_encrypted("notes"));
+
+        liath.put("agent_7_memory", b"m1", b"lives at 12 Elm Street").unwrap();
+        let executor = liath.query_executor();
+        executor.execute("insert('notes', 'n1', 'blood type AB')", "admin").await.unwrap();
+        let file_id = executor
+            .execute("return upload_file('scan.txt', {112, 97, 115, 115, 112, 111, 114, 116})", "admin")
+            .await
+            .unwrap();
+        assert_eq!(executor.execute(&format!("return retrieve_file('{}')", file_id), "admin").await.unwrap(), "passport");
+        liath.close().unwrap();
+        file_id
+    };
+    assert!(!on_disk(temp_dir.path(), b"12 Elm Street"));
+    assert!(!on_disk(temp_dir.path(), b"blood type AB"));
+    assert!(!on_disk(temp_dir.path(), b"passport"));
+
+    // Rotate to a second key, then open with only that key
+    let second_key = (2, KeyRing::generate_key());
+    {
+        let liath = EmbeddedLiath::new(config(vec![first_key, second_key])).unwrap();
+        assert!(liath.rotate_encryption_keys().unwrap() >= 3);
+    }
+    let liath = EmbeddedLiath::new(config(vec![second_key])).unwrap();
+    assert_eq!(liath.get("agent_7_memory", b"m1").unwrap().unwrap(), b"lives at 12 Elm Street");
+    let executor = liath.query_executor();
+    assert_eq!(executor.execute("return select('notes', 'n1')", "admin").await.unwrap(), "blood type AB");
+    assert_eq!(executor.execute(&format!("return retrieve_file('{}')", file_id), "admin").await.unwrap(), "passport");
+}
+
+#[tokio::test]
+async fn test_metrics_record_lua_work() {
+    use liath::{EmbeddedLiath, Config};
+    use liath::metrics::Exposition;
+    use usearch::{MetricKind, ScalarKind};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    liath.create_namespace("metered", 3, MetricKind::Cos, ScalarKind::F32).unwrap();
+    liath.execute_lua(r#"insert("metered", "k", "v"); return select("metered", "k")"#).await.unwrap();
+    assert!(liath.execute_lua("return (").await.is_err());
+
+    let text = Exposition::new().finish();
+    assert!(text.contains("# TYPE liath_db_function_duration_seconds histogram"));
+    assert!(text.contains(r#"liath_db_function_duration_seconds_count{function="insert"}"#));
+    assert!(text.contains(r#"liath_db_function_duration_seconds_bucket{function="select",le="+Inf"}"#));
+    assert!(text.contains(r#"liath_lua_errors_total{type="syntax"}"#));
+}
+
+/// Serve `liath` on a free port and wait until it answers
+#[cfg(all(feature = "server", feature = "client"))]
+async fn spawn_server(liath: liath::EmbeddedLiath) -> liath::client::Client {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    tokio::spawn(liath::server::run_server_with_db(port, std::sync::Arc::new(liath)));
+    let client = liath::client::Client::new(format!("http://127.0.0.1:{}", port));
+    let mut attempts = 0;
+    while client.health().await.is_err() {
+        attempts += 1;
+        assert!(attempts < 100, "Server did not start");
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    client
+}
+
+#[cfg(all(feature = "server", feature = "client"))]
+#[tokio::test]
+async fn test_client_against_server() {
+    use liath::client::{ApiError, BulkQuery, BulkRecord, CreateNamespaceRequest, KvPutRequest, QueryRequest, ScanQuery};
+    use liath::{EmbeddedLiath, Config};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let permissions = ["create_namespace", "list_namespaces", "insert", "select"];
+    liath.add_user("bob", permissions.iter().map(|p| p.to_string()).collect()).unwrap();
+    let (_, token) = liath.create_api_key("bob", None).unwrap();
+
+    let anonymous = spawn_server(liath).await;
+    let client = anonymous.clone().with_api_key(token);
+
+    let spec = anonymous.openapi().await.unwrap();
+    assert_eq!(spec, *liath::server::openapi_document());
+
+    let namespace = CreateNamespaceRequest { name: "docs".to_string(), dimensions: Some(4), ..Default::default() };
+    assert!(client.create_namespace(&namespace).await.unwrap().success);
+    assert!(client.list_namespaces().await.unwrap().namespaces.contains(&"docs".to_string()));
+
+    client.kv_put("docs", "a b/c", &KvPutRequest { value: "first".to_string() }).await.unwrap();
+    assert_eq!(client.kv_get("docs", "a b/c").await.unwrap().value.as_deref(), Some("first"));
+
+    let records: Vec<BulkRecord> = (0..3)
+        .map(|i| BulkRecord { key: format!("bulk{}", i), value: Some(format!("v{}", i).into()), ..Default::default() })
+        .collect();
+    let progress = client.bulk_ingest("docs", &BulkQuery::default(), &records).await.unwrap();
+    let last = progress.last().unwrap();
+    assert_eq!((last.done, last.written), (Some(true), Some(3)));
+
+    let query = ScanQuery { prefix: Some("bulk".to_string()), limit: Some(2), ..Default::default() };
+    let lines = client.kv_scan("docs", &query).await.unwrap();
+    assert_eq!(lines[0].key.as_deref(), Some("bulk0"));
+    assert!(lines[2].cursor.is_some());
+
+    let script = QueryRequest { query: "return select('docs', 'bulk2')".to_string(), ..Default::default() };
+    assert_eq!(client.execute_query(&script).await.unwrap().result, "v2");
+
+    let status = |error: anyhow::Error| error.downcast::<ApiError>().unwrap().status;
+    assert_eq!(status(anonymous.kv_get("docs", "bulk0").await.unwrap_err()), 401);
+    assert_eq!(status(client.kv_delete("docs", "bulk0").await.unwrap_err()), 403);
+}
+
+#[cfg(all(feature = "server", feature = "client"))]
+#[tokio::test]
+async fn test_rate_limits_against_server() {
+    use liath::client::{ApiError, CreateNamespaceRequest, EmbedRequest, KvPutRequest};
+    use liath::{EmbeddedLiath, Config, Limits};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        limits: "daily_embedding_tokens=2".parse().unwrap(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let executor = liath.query_executor();
+    executor.create_namespace("docs", 4, usearch::MetricKind::Cos, usearch::ScalarKind::F32).unwrap();
+    let mut tokens = Vec::new();
+    for (user, limits) in [("bob", "requests_per_second=0.01,burst=2"), ("carol", "daily_storage_bytes=10"), ("dave", "")] {
+        liath.add_user(user, vec!["*".to_string()]).unwrap();
+        if !limits.is_empty() {
+            liath.set_limits(user, Some(limits.parse::<Limits>().unwrap())).unwrap();
+        }
+        tokens.push(liath.create_api_key(user, None).unwrap().1);
+    }
+
+    let anonymous = spawn_server(liath).await;
+    let clients: Vec<_> = tokens.into_iter().map(|token| anonymous.clone().with_api_key(token)).collect();
+    let (bob, carol, dave) = (&clients[0], &clients[1], &clients[2]);
+    let refusal = |error: anyhow::Error| error.downcast::<ApiError>().unwrap();
+
+    // Bob's bucket holds two requests and refills one every 100 seconds
+    bob.list_namespaces().await.unwrap();
+    bob.list_namespaces().await.unwrap();
+    let error = refusal(bob.list_namespaces().await.unwrap_err());
+    assert_eq!((error.status, error.retry_after), (429, Some(100)));
+    assert!(error.message.contains("rate limit"));
+
+    // A write that would go over the quota is refused whole
+    let value = |value: &str| KvPutRequest { value: value.to_string() };
+    carol.kv_put("docs", "a", &value("12345")).await.unwrap();
+    let error = refusal(carol.kv_put("docs", "b", &value("12345")).await.unwrap_err());
+    assert_eq!(error.status, 429);
+    assert!(error.retry_after.unwrap() <= 86_400);
+    assert!(carol.kv_get("docs", "b").await.unwrap().value.is_none());
+    // Carol's own limits replace the default embedding quota
+    let namespace = CreateNamespaceRequest { name: "more".to_string(), dimensions: Some(4), ..Default::default() };
+    assert!(carol.create_namespace(&namespace).await.unwrap().success);
+
+    // Embedding quotas are spent before the texts reach the model
+    let texts = EmbedRequest { texts: vec!["hello world".to_string()] };
+    assert_eq!(refusal(dave.embed(&texts).await.unwrap_err()).status, 429);
+    assert!(dave.kv_put("docs", "c", &value("1")).await.unwrap().success);
+}
+
+#[cfg(all(feature = "server", feature = "client"))]
+#[tokio::test]
+async fn test_watch_over_websocket() {
+    use liath::client::KvPutRequest;
+    use liath::{EmbeddedLiath, Config};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let temp_dir = TempDir::new().unwrap();
+    let liath = EmbeddedLiath::new(Config { data_dir: temp_dir.path().to_path_buf(), ..Default::default() }).unwrap();
+    liath.query_executor().create_namespace("docs", 4, usearch::MetricKind::Cos, usearch::ScalarKind::F32).unwrap();
+    let (_, token) = liath.create_api_key("admin", None).unwrap();
+    let client = spawn_server(liath).await.with_api_key(token.clone());
+    let address = client.base_url().trim_start_matches("http://").to_string();
+
+    // The bundled client has no WebSocket support, so speak the handshake by hand
+    let mut stream = tokio::net::TcpStream::connect(&address).await.unwrap();
+    let handshake = format!(
+        "GET /watch?namespace=docs HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\nAuthorization: Bearer {}\r\n\r\n",
+        address, token
+    );
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(stream.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap().to_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{}", response);
+    assert!(response.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="));
+
+    // Each change arrives as one unmasked text frame
+    client.kv_put("docs", "a", &KvPutRequest { value: "first".to_string() }).await.unwrap();
+    assert_eq!(stream.read_u8().await.unwrap(), 0x81);
+    let length = match stream.read_u8().await.unwrap() {
+        126 => stream.read_u16().await.unwrap() as usize,
+        length => length as usize,
+    };
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload).await.unwrap();
+    let change: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+    assert_eq!(change["namespace"], "docs");
+    assert_eq!(change["key"], "a");
+}
+
+#[cfg(all(feature = "server", feature = "client", feature = "mcp"))]
+#[tokio::test]
+async fn test_mcp_over_http() {
+    use liath::{EmbeddedLiath, Config};
+    use serde_json::{json, Value};
+
+    let temp_dir = TempDir::new().unwrap();
+    let liath = EmbeddedLiath::new(Config { data_dir: temp_dir.path().to_path_buf(), ..Default::default() }).unwrap();
+    liath.query_executor().create_namespace("docs", 4, usearch::MetricKind::Cos, usearch::ScalarKind::F32).unwrap();
+    liath.add_user("bob", vec!["select".to_string()]).unwrap();
+    let (_, admin) = liath.create_api_key("admin", None).unwrap();
+    let (_, bob) = liath.create_api_key("bob", None).unwrap();
+    let url = format!("{}/mcp", spawn_server(liath).await.base_url());
+
+    let http = reqwest::Client::new();
+    let post = |token: &str, session: Option<&str>, body: Value| {
+        let mut request = http
+            .post(&url)
+            .bearer_auth(token)
+            .header("accept", "application/json, text/event-stream")
+            .json(&body);
+        if let Some(session) = session {
+            request = request.header("mcp-session-id", session);
+        }
+        request.send()
+    };
+    let call = |id: u64, tool: &str, arguments: Value| {
+        json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {"name": tool, "arguments": arguments}})
+    };
+    let initialize = json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {"protocolVersion": "2025-03-26"}});
+
+    // Sessions start with initialize and belong to the key's user
+    assert_eq!(http.post(&url).json(&initialize).send().await.unwrap().status(), 401);
+    let response = post(&admin, None, initialize.clone()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let session = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+    let reply: Value = response.json().await.unwrap();
+    assert_eq!(reply["result"]["protocolVersion"], "2025-03-26");
+    let bob_session = post(&bob, None, initialize).await.unwrap().headers()["mcp-session-id"].to_str().unwrap().to_string();
+
+    let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+    assert_eq!(post(&admin, Some(&session), notification).await.unwrap().status(), 202);
+    let put = call(1, "liath_kv_put", json!({"namespace": "docs", "key": "a", "value": "hello"}));
+    assert_eq!(post(&admin, None, put.clone()).await.unwrap().status(), 400);
+    assert_eq!(post(&admin, Some("unknown"), put.clone()).await.unwrap().status(), 404);
+    assert_eq!(post(&bob, Some(&session), put.clone()).await.unwrap().status(), 404);
+
+    // Both assistants share the database, each with their own permissions
+    let reply: Value = post(&admin, Some(&session), put.clone()).await.unwrap().json().await.unwrap();
+    assert_eq!(reply["result"]["content"][0]["text"], "OK");
+    let reply: Value = post(&bob, Some(&bob_session), put).await.unwrap().json().await.unwrap();
+    assert_eq!(reply["result"]["isError"], true);
+    let get = call(2, "liath_kv_get", json!({"namespace": "docs", "key": "a"}));
+    let reply: Value = post(&bob, Some(&bob_session), get.clone()).await.unwrap().json().await.unwrap();
+    assert_eq!(reply["result"]["content"][0]["text"], "hello");
+
+    // Batches answer each request in order
+    let batch = json!([{"jsonrpc": "2.0", "id": 3, "method": "ping"}, {"jsonrpc": "2.0", "id": 4, "method": "tools/list"}]);
+    let replies: Vec<Value> = post(&admin, Some(&session), batch).await.unwrap().json().await.unwrap();
+    assert_eq!((replies[0]["id"].clone(), replies[1]["id"].clone()), (json!(3), json!(4)));
+    assert!(replies[1]["result"]["tools"].as_array().unwrap().len() > 5);
+
+    // Clients that only take event streams get the reply as an event
+    let response = http
+        .post(&url)
+        .bearer_auth(&bob)
+        .header("accept", "text/event-stream")
+        .header("mcp-session-id", &bob_session)
+        .json(&get)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/event-stream"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains("event: message") && body.contains("hello"));
+
+    let delete = http.delete(&url).bearer_auth(&admin).header("mcp-session-id", &session).send().await.unwrap();
+    assert_eq!(delete.status(), 204);
+    assert_eq!(post(&admin, Some(&session), call(5, "liath_list_namespaces", json!({}))).await.unwrap().status(), 404);
+}
+
+#[cfg(all(feature = "server", feature = "client", feature = "mcp"))]
+#[tokio::test]
+async fn test_mcp_resources() {
+    use liath::agent::{Agent, Conversation, Memory};
+    use liath::{EmbeddedLiath, Config};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = Arc::new(EmbeddedLiath::new(Config { data_dir: temp_dir.path().to_path_buf(), ..Default::default() }).unwrap());
+    let executor = db.query_executor();
+    for namespace in ["docs", "bulk"] {
+        executor.create_namespace(namespace, 4, usearch::MetricKind::Cos, usearch::ScalarKind::F32).unwrap();
+    }
+    executor.put("docs", b"a", br#"{"x": 1}"#).unwrap();
+    executor.put("docs", b"b/c", b"plain").unwrap();
+    for i in 0..150 {
+        executor.put("bulk", format!("k{:03}", i).as_bytes(), b"v").unwrap();
+    }
+    // A memory written the way Memory::store lays it out, without embedding it
+    Agent::new("helper", db.clone());
+    Memory::new("helper", db.clone()).unwrap();
+    executor.put("agent_helper_memory", b"content:1", b"likes tea").unwrap();
+    executor.put("agent_helper_memory", b"meta:1", br#"{"id": 1, "tags": ["prefs"], "created_at": 0}"#).unwrap();
+    executor.put("agent_helper_memory", b"_next_id", &2u64.to_le_bytes()).unwrap();
+    Conversation::create_with_id("c1", "helper", db.clone()).unwrap();
+    db.add_user("bob", vec!["select@bulk".to_string(), "list_namespaces@bulk".to_string()]).unwrap();
+    let (_, admin) = db.create_api_key("admin", None).unwrap();
+    let (_, bob) = db.create_api_key("bob", None).unwrap();
+    let served = Arc::try_unwrap(db).ok().unwrap();
+    let url = format!("{}/mcp", spawn_server(served).await.base_url());
+
+    let http = reqwest::Client::new();
+    let session = |token: String| {
+        let (http, url) = (http.clone(), url.clone());
+        async move {
+            let initialize = json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}});
+            let response = http.post(&url).bearer_auth(&token).json(&initialize).send().await.unwrap();
+            let id = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+            (token, id)
+        }
+    };
+    let (admin, bob) = (session(admin).await, session(bob).await);
+    let request = |(token, session): &(String, String), method: &str, params: Value| {
+        let message = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        let request = http.post(&url).bearer_auth(token).header("mcp-session-id", session).json(&message);
+        async move { request.send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+
+    let templates = request(&admin, "resources/templates/list", json!({})).await;
+    assert_eq!(templates["result"]["resourceTemplates"].as_array().unwrap().len(), 3);
+
+    // Pages follow each other through the cursor; agent data is listed once, as agent resources
+    let mut uris = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = request(&admin, "resources/list", json!({"cursor": cursor})).await;
+        let resources = page["result"]["resources"].as_array().unwrap();
+        assert!(resources.len() <= 100);
+        uris.extend(resources.iter().map(|resource| resource["uri"].as_str().unwrap().to_string()));
+        match page["result"]["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(&uris[..4], [
+        "liath://agent/helper/memories",
+        "liath://agent/helper/conversation/c1",
+        "liath://namespace/bulk/key/k000",
+        "liath://namespace/bulk/key/k001",
+    ]);
+    assert_eq!(uris.len(), 2 + 150 + 2);
+    assert_eq!(uris.last().unwrap(), "liath://namespace/docs/key/b%2Fc");
+    let bob_list = request(&bob, "resources/list", json!({})).await;
+    assert_eq!(bob_list["result"]["resources"].as_array().unwrap().len(), 100);
+    assert!(bob_list["result"]["nextCursor"].is_string());
+
+    let read = |who, uri: &str| request(who, "resources/read", json!({"uri": uri}));
+    let contents = read(&admin, "liath://namespace/docs/key/a").await["result"]["contents"][0].clone();
+    assert_eq!((contents["mimeType"].as_str(), contents["text"].as_str()), (Some("application/json"), Some(r#"{"x": 1}"#)));
+    let contents = read(&admin, "liath://namespace/docs/key/b%2Fc").await["result"]["contents"][0].clone();
+    assert_eq!(contents["mimeType"], "text/plain");
+    let memories = read(&admin, "liath://agent/helper/memories").await["result"]["contents"][0]["text"].clone();
+    let memories: Value = serde_json::from_str(memories.as_str().unwrap()).unwrap();
+    assert_eq!((memories[0]["content"].as_str(), memories[0]["tags"].clone()), (Some("likes tea"), json!(["prefs"])));
+    let messages = read(&admin, "liath://agent/helper/conversation/c1").await;
+    assert_eq!(messages["result"]["contents"][0]["text"], "[]");
+    assert!(read(&bob, "liath://namespace/docs/key/a").await["error"].is_object());
+    assert!(read(&admin, "liath://namespace/docs/key/missing").await["error"].is_object());
+
+    // Updates arrive on the session's stream
+    let subscribe = request(&bob, "resources/subscribe", json!({"uri": "liath://namespace/docs/key/a"})).await;
+    assert!(subscribe["error"]["message"].as_str().unwrap().contains("may not watch"));
+    let subscribe = request(&admin, "resources/subscribe", json!({"uri": "liath://namespace/docs/key/a"})).await;
+    assert_eq!(subscribe["result"], json!({}));
+    let mut stream = http
+        .get(&url)
+        .bearer_auth(&admin.0)
+        .header("accept", "text/event-stream")
+        .header("mcp-session-id", &admin.1)
+        .send()
+        .await
+        .unwrap();
+    for (key, value) in [("other", "1"), ("a", "2")] {
+        let put = json!({"name": "liath_kv_put", "arguments": {"namespace": "docs", "key": key, "value": value}});
+        request(&admin, "tools/call", put).await;
+    }
+    let mut received = String::new();
+    while !received.contains("\n\n") {
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), stream.chunk()).await.unwrap().unwrap().unwrap();
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    let data = received.lines().find_map(|line| line.strip_prefix("data: ")).unwrap();
+    let notification: Value = serde_json::from_str(data).unwrap();
+    assert_eq!(notification["method"], "notifications/resources/updated");
+    assert_eq!(notification["params"]["uri"], "liath://namespace/docs/key/a");
+}
+
+#[cfg(all(feature = "server", feature = "client"))]
+#[tokio::test]
+async fn test_remote_executor_matches_local() {
+    use liath::cli::Executor;
+    use liath::client::RemoteExecutor;
+    use liath::{EmbeddedLiath, Config};
+    use usearch::{MetricKind, ScalarKind};
+
+    let open = |dir: &TempDir| {
+        EmbeddedLiath::new(Config { data_dir: dir.path().to_path_buf(), ..Default::default() }).unwrap()
+    };
+    let (local_dir, remote_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let local = Executor::from(open(&local_dir).query_executor());
+    let served = open(&remote_dir);
+    let (_, token) = served.create_api_key("admin", None).unwrap();
+    let remote = Executor::from(RemoteExecutor::new(spawn_server(served).await.with_api_key(token)));
+    assert!(remote.remote_url().unwrap().starts_with("http://127.0.0.1:"));
+
+    for executor in [&local, &remote] {
+        executor.create_namespace("docs", 4, MetricKind::Cos, ScalarKind::F32).await.unwrap();
+        assert!(executor.list_namespaces().await.unwrap().contains(&"docs".to_string()));
+
+        executor.put("docs", b"a", b"1").await.unwrap();
+        executor.put("docs", b"b", b"2").await.unwrap();
+        assert_eq!(executor.get("docs", b"a").await.unwrap(), Some(b"1".to_vec()));
+        executor.delete("docs", b"a").await.unwrap();
+        assert_eq!(executor.get("docs", b"a").await.unwrap(), None);
+
+        assert_eq!(executor.execute("return select('docs', 'b')", "admin").await.unwrap(), "2");
+        assert!(executor.execute("error('boom')", "admin").await.unwrap_err().to_string().contains("boom"));
+        let (result, profile) = executor.execute_profiled("return select('docs', 'b')", "admin").await.unwrap();
+        assert_eq!(result, "2");
+        assert_eq!(profile.functions[0].name, "select");
+
+        let result = executor.query("SELECT key, value FROM docs", "admin").await.unwrap();
+        assert_eq!(result.columns, vec!["key", "value"]);
+        assert_eq!(result.rows.len(), 1);
+    }
+}
+
+#[cfg(all(feature = "grpc", feature = "client"))]
+#[tokio::test]
+async fn test_grpc_against_server() {
+    use liath::server::grpc::proto::{self, liath_client::LiathClient, bulk_ingest_response, scan_response};
+    use liath::{EmbeddedLiath, Config};
+    use tonic::{Code, Request};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let liath = EmbeddedLiath::new(config).unwrap();
+    let permissions = ["create_namespace", "list_namespaces", "insert", "select"];
+    liath.add_user("bob", permissions.iter().map(|p| p.to_string()).collect()).unwrap();
+    let (_, token) = liath.create_api_key("bob", None).unwrap();
+
+    // gRPC shares the port of the REST API
+    let base_url = spawn_server(liath).await.base_url().to_string();
+    let mut client = LiathClient::connect(base_url).await.unwrap();
+    fn with_key<T>(message: T, token: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        request
+    }
+
+    let namespace = proto::CreateNamespaceRequest { name: "docs".to_string(), dimensions: Some(4), ..Default::default() };
+    client.create_namespace(with_key(namespace, &token)).await.unwrap();
+    let namespaces = client.list_namespaces(with_key(proto::ListNamespacesRequest {}, &token)).await.unwrap().into_inner().namespaces;
+    assert!(namespaces.contains(&"docs".to_string()));
+
+    for key in ["a", "b", "c"] {
+        let put = proto::PutRequest { namespace: "docs".to_string(), key: key.to_string(), value: key.to_uppercase() };
+        client.put(with_key(put, &token)).await.unwrap();
+    }
+    let get = proto::GetRequest { namespace: "docs".to_string(), key: "a".to_string() };
+    assert_eq!(client.get(with_key(get, &token)).await.unwrap().into_inner().value.as_deref(), Some("A"));
+
+    let scan = proto::ScanRequest { namespace: "docs".to_string(), limit: Some(2), ..Default::default() };
+    let mut stream = client.scan(with_key(scan, &token)).await.unwrap().into_inner();
+    let mut items = Vec::new();
+    while let Some(response) = stream.message().await.unwrap() {
+        items.push(response.item.unwrap());
+    }
+    assert_eq!(items.len(), 3);
+    assert!(matches!(&items[0], scan_response::Item::Entry(entry) if entry.key == "a" && entry.value == "A"));
+    let scan_response::Item::Cursor(cursor) = &items[2] else { panic!("Scan did not end with a cursor") };
+    let scan = proto::ScanRequest { namespace: "docs".to_string(), cursor: Some(cursor.clone()), ..Default::default() };
+    let mut stream = client.scan(with_key(scan, &token)).await.unwrap().into_inner();
+    let item = stream.message().await.unwrap().unwrap().item.unwrap();
+    assert!(matches!(item, scan_response::Item::Entry(entry) if entry.key == "c"));
+    assert!(stream.message().await.unwrap().is_none());
+
+    let records = vec![
+        proto::BulkRecord { key: "bulk1".to_string(), value: Some("v1".to_string()), ..Default::default() },
+        proto::BulkRecord { key: "bulk2".to_string(), ..Default::default() },
+        proto::BulkRecord { key: "bulk3".to_string(), value: Some("v3".to_string()), ..Default::default() },
+    ];
+    let bulk = proto::BulkIngestRequest { namespace: "docs".to_string(), records, batch_size: Some(1) };
+    let mut stream = client.bulk_ingest(with_key(bulk, &token)).await.unwrap().into_inner();
+    let mut items = Vec::new();
+    while let Some(response) = stream.message().await.unwrap() {
+        items.push(response.item.unwrap());
+    }
+    assert!(matches!(&items[0], bulk_ingest_response::Item::Error(error) if error.record == 2 && error.key == "bulk2"));
+    let Some(bulk_ingest_response::Item::Progress(progress)) = items.last() else { panic!("Ingest did not end with progress") };
+    assert_eq!((progress.processed, progress.written, progress.failed, progress.done), (3, 2, 1, true));
+
+    let execute = proto::ExecuteRequest { query: "return select('docs', 'bulk3')".to_string(), explain: true };
+    let response = client.execute(with_key(execute, &token)).await.unwrap().into_inner();
+    assert_eq!(response.result, "v3");
+    assert_eq!(response.profile.unwrap().functions[0].name, "select");
+    let execute = proto::ExecuteRequest { query: "error('boom')".to_string(), explain: false };
+    assert!(client.execute(with_key(execute, &token)).await.unwrap_err().message().contains("boom"));
+
+    let delete = proto::DeleteRequest { namespace: "docs".to_string(), key: "a".to_string() };
+    assert_eq!(client.delete(with_key(delete.clone(), &token)).await.unwrap_err().code(), Code::PermissionDenied);
+    assert_eq!(client.delete(Request::new(delete)).await.unwrap_err().code(), Code::Unauthenticated);
+}